@@ -0,0 +1,314 @@
+//! Measure the costs of ABY operations and conversions, for sharing assignment.
+//!
+//! For each operation, sharing, and bit-width, this builds an ABY project that evaluates the
+//! operation many times, runs both parties on this machine, and compares the running time to
+//! that of a project with no operations. The per-operation difference, in microseconds, goes into
+//! a cost file in OPA's format, which `circ --cost-model <FILE>` reads.
+//!
+//! To calibrate for a WAN, run this with the loopback interface shaped like the WAN (e.g., with
+//! `tc qdisc add dev lo root netem delay 50ms rate 100mbit`).
+
+use circ::ir::term::*;
+use circ::target::aby::assignment::{ShareType, SharingMap};
+use circ::target::aby::circuit::{AbyCircuit, GateOp, Wire};
+use circ::target::aby::output::write_aby_exec;
+use circ::target::aby::trans::{to_aby_circuit, write_aby_circuit};
+use circ::term;
+
+use fxhash::FxHashMap;
+use serde_json::{json, Map, Value};
+use structopt::StructOpt;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "aby_calibrate",
+    about = "Measure ABY costs for sharing assignment"
+)]
+struct Options {
+    /// Where ABY is installed (passed to CMake as CMAKE_PREFIX_PATH)
+    #[structopt(long, parse(from_os_str))]
+    aby_prefix: PathBuf,
+
+    /// File to write the costs to
+    #[structopt(long, default_value = "costs.json", parse(from_os_str))]
+    out: PathBuf,
+
+    /// Bit-widths to measure
+    #[structopt(long, default_value = "32", use_delimiter = true)]
+    widths: Vec<usize>,
+
+    /// Number of copies of each operation per run
+    #[structopt(long, default_value = "1000")]
+    ops: usize,
+
+    /// Number of runs of each project; the fastest counts
+    #[structopt(long, default_value = "3")]
+    trials: usize,
+
+    /// Directory to build the projects in
+    #[structopt(long, default_value = "aby_calibrate", parse(from_os_str))]
+    work_dir: PathBuf,
+
+    /// First port for the parties to talk on; each run uses the next one
+    #[structopt(long, default_value = "7766")]
+    port: u16,
+}
+
+const ALL: &[ShareType] = &[ShareType::Arithmetic, ShareType::Boolean, ShareType::Yao];
+const BOOLEAN: &[ShareType] = &[ShareType::Boolean, ShareType::Yao];
+
+/// The operations to measure: their names in OPA's cost files, names for their projects, and the
+/// sharings to measure them in
+const BENCHES: &[(&str, &str, &[ShareType])] = &[
+    ("add", "add", ALL),
+    ("sub", "sub", ALL),
+    ("mul", "mul", ALL),
+    ("and", "and", BOOLEAN),
+    ("or", "or", BOOLEAN),
+    ("xor", "xor", BOOLEAN),
+    ("eq", "eq", BOOLEAN),
+    ("ne", "ne", BOOLEAN),
+    ("ge", "ge", BOOLEAN),
+    ("le", "le", BOOLEAN),
+    ("gt", "gt", BOOLEAN),
+    ("lt", "lt", BOOLEAN),
+    ("mux", "mux", ALL),
+    ("shl", "shl", BOOLEAN),
+    ("shr", "shr", BOOLEAN),
+    ("bvudiv", "udiv", BOOLEAN),
+    ("bvurem", "urem", BOOLEAN),
+    ("&&", "land", BOOLEAN),
+    ("||", "lor", BOOLEAN),
+];
+
+/// The inputs of the benchmarks, and their parties
+const INPUTS: &[(&str, u8)] = &[("a_v0", 0), ("b_v0", 1), ("c_v0", 0), ("d_v0", 1)];
+
+fn share_char(s: ShareType) -> &'static str {
+    match s {
+        ShareType::Arithmetic => "a",
+        ShareType::Boolean => "b",
+        ShareType::Yao => "y",
+    }
+}
+
+/// The term for operation `name` on `width`-bit inputs
+fn op_term(name: &str, width: usize) -> Term {
+    let v = |n: &str, s: Sort| leaf_term(Op::Var(n.to_owned(), s));
+    let a = v("a_v0", Sort::BitVector(width));
+    let b = v("b_v0", Sort::BitVector(width));
+    let c = v("c_v0", Sort::Bool);
+    let d = v("d_v0", Sort::Bool);
+    match name {
+        "add" => term![BV_ADD; a, b],
+        "sub" => term![BV_SUB; a, b],
+        "mul" => term![BV_MUL; a, b],
+        "and" => term![BV_AND; a, b],
+        "or" => term![BV_OR; a, b],
+        "xor" => term![BV_XOR; a, b],
+        "eq" => term![Op::Eq; a, b],
+        "ne" => term![Op::Not; term![Op::Eq; a, b]],
+        "ge" => term![BV_UGE; a, b],
+        "le" => term![BV_ULE; a, b],
+        "gt" => term![BV_UGT; a, b],
+        "lt" => term![BV_ULT; a, b],
+        "mux" => term![ITE; c, a, b],
+        "shl" => term![BV_SHL; a, b],
+        "shr" => term![BV_LSHR; a, b],
+        "bvudiv" => term![BV_UDIV; a, b],
+        "bvurem" => term![BV_UREM; a, b],
+        "&&" => term![AND; c, d],
+        "||" => term![OR; c, d],
+        _ => unreachable!(),
+    }
+}
+
+/// A circuit that evaluates `t` with every term in sharing `s`
+fn lower(t: Term, s: ShareType) -> AbyCircuit {
+    let mut md = ComputationMetadata::default();
+    for (name, party) in INPUTS {
+        md.new_input(name.to_string(), Some(*party));
+    }
+    let cs = Computation {
+        outputs: vec![t.clone()],
+        metadata: md,
+        values: None,
+    };
+    let s_map: SharingMap = PostOrderIter::new(t).map(|t| (t, s)).collect();
+    to_aby_circuit(&cs, &s_map)
+}
+
+/// A circuit that converts a `width`-bit input from sharing `from` to sharing `to`
+fn conversion(width: usize, from: ShareType, to: ShareType) -> AbyCircuit {
+    let mut circ = AbyCircuit::default();
+    let a = circ.add(
+        GateOp::In {
+            name: "a_v0".to_owned(),
+            party: Some(0),
+        },
+        from,
+        width,
+    );
+    let c = circ.add(GateOp::Conv(a), to, width);
+    circ.outputs.push(c);
+    circ
+}
+
+/// `circ`, with its non-input gates copied `n` times, outputting the last copy; or, if `n` is
+/// zero, outputting its first input
+fn replicate(circ: &AbyCircuit, n: usize) -> AbyCircuit {
+    let mut out = AbyCircuit::default();
+    let mut map: FxHashMap<Wire, Wire> = FxHashMap::default();
+    for (w, g) in circ.gates.iter().enumerate() {
+        if let GateOp::In { .. } = g.op {
+            map.insert(w, out.add(g.op.clone(), g.share, g.width));
+        }
+    }
+    let first_input = *map.values().min().unwrap();
+    let mut outputs = vec![first_input];
+    for _ in 0..n {
+        let mut copy = map.clone();
+        for (w, g) in circ.gates.iter().enumerate() {
+            if let GateOp::In { .. } = g.op {
+                continue;
+            }
+            let op = g.op.map_wires(|a| copy[&a]);
+            let new = out.add(op, g.share, g.width);
+            copy.insert(w, new);
+        }
+        outputs = circ.outputs.iter().map(|o| copy[o]).collect();
+    }
+    out.outputs = outputs;
+    out
+}
+
+struct Runner {
+    options: Options,
+    next_port: u16,
+}
+
+impl Runner {
+    /// Build `circ` as project `name`, run it, and return the fastest time.
+    fn time(&mut self, name: &str, circ: &AbyCircuit, width: usize) -> Duration {
+        let path = PathBuf::from(name);
+        let lang = "calib".to_owned();
+        let work_dir = &self.options.work_dir;
+        write_aby_circuit(circ, &path, &lang, work_dir);
+        write_aby_exec(&path, &lang, work_dir);
+        let project = work_dir.join(format!("{}_{}", name, lang));
+        let build = project.join("build");
+        run(Command::new("cmake")
+            .arg("-S")
+            .arg(&project)
+            .arg("-B")
+            .arg(&build)
+            .arg("-DCMAKE_BUILD_TYPE=Release")
+            .arg(format!(
+                "-DCMAKE_PREFIX_PATH={}",
+                self.options.aby_prefix.display()
+            )));
+        run(Command::new("make").arg("-C").arg(&build));
+        let exe = build.join(format!("{}_{}_test", name, lang));
+        (0..self.options.trials)
+            .map(|_| self.run_parties(&exe, width))
+            .min()
+            .unwrap()
+    }
+
+    /// Run both parties of `exe`, returning the time until both are done
+    fn run_parties(&mut self, exe: &Path, width: usize) -> Duration {
+        let port = self.next_port.to_string();
+        self.next_port = self.next_port.wrapping_add(1).max(1024);
+        let party = |role: &str| {
+            Command::new(exe)
+                .args(&["-r", role, "-b", &width.to_string(), "-p", &port])
+                .args(&["-i", "a,3,b,2,c,1,d,0"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .expect("Could not run the benchmark")
+        };
+        let start = Instant::now();
+        let server = party("0");
+        let client = party("1");
+        let outs: Vec<Output> = vec![server, client]
+            .into_iter()
+            .map(|p| p.wait_with_output().expect("Could not run the benchmark"))
+            .collect();
+        let elapsed = start.elapsed();
+        for out in &outs {
+            assert!(
+                out.status.success(),
+                "Benchmark {} failed: {}",
+                exe.display(),
+                String::from_utf8_lossy(&out.stderr)
+            );
+        }
+        elapsed
+    }
+
+    /// The cost of one copy of `circ`'s operation, in microseconds
+    fn cost(&mut self, name: &str, circ: &AbyCircuit, width: usize) -> f64 {
+        let n = self.options.ops;
+        let base = self.time(&format!("{}_base", name), &replicate(circ, 0), width);
+        let full = self.time(name, &replicate(circ, n), width);
+        let cost = full.saturating_sub(base).as_secs_f64() * 1e6 / n as f64;
+        println!("{}: {:.3}us", name, cost);
+        cost
+    }
+}
+
+fn run(cmd: &mut Command) {
+    let status = cmd
+        .status()
+        .unwrap_or_else(|e| panic!("Could not run {:?}: {}", cmd, e));
+    assert!(status.success(), "{:?} failed", cmd);
+}
+
+fn main() {
+    env_logger::Builder::from_default_env()
+        .format_level(false)
+        .format_timestamp(None)
+        .init();
+    let options = Options::from_args();
+    let next_port = options.port;
+    let widths = options.widths.clone();
+    let out = options.out.clone();
+    let mut runner = Runner { options, next_port };
+    let mut file = Map::new();
+    for w in widths {
+        let mut costs = Map::new();
+        for (op, file_name, shares) in BENCHES {
+            let mut by_share = Map::new();
+            for s in *shares {
+                let circ = lower(op_term(op, w), *s);
+                let name = format!("{}_{}_{}", file_name, share_char(*s), w);
+                let cost = runner.cost(&name, &circ, w);
+                by_share.insert(share_char(*s).to_owned(), json!({ "1": cost }));
+            }
+            costs.insert(op.to_string(), Value::Object(by_share));
+        }
+        for from in ALL {
+            for to in ALL {
+                if from != to {
+                    let conv = format!("{}2{}", share_char(*from), share_char(*to));
+                    let circ = conversion(w, *from, *to);
+                    let cost = runner.cost(&format!("{}_{}", conv, w), &circ, w);
+                    costs.insert(conv, json!({ "1": cost }));
+                }
+            }
+        }
+        file.insert(w.to_string(), Value::Object(costs));
+    }
+    fs::write(
+        &out,
+        serde_json::to_string_pretty(&Value::Object(file)).unwrap(),
+    )
+    .expect("Could not write the costs");
+    println!("Wrote costs to {}", out.display());
+}
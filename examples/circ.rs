@@ -6,8 +6,6 @@ use bellman::groth16::{
 };
 use bellman::Circuit;
 use bls12_381::{Scalar, Bls12};
-use libspartan::{Instance, NIZKGens, NIZK};
-use merlin::Transcript;
 
 use circ::front::datalog::{self, Datalog};
 use circ::front::zokrates::{self, Zokrates};
@@ -15,18 +13,31 @@ use circ::front::c::{self, C};
 use circ::front::{Mode, FrontEnd};
 use circ::ir::{opt::{opt, Opt}, term::extras::Letified};
 use circ::target::aby::output::write_aby_exec;
-use circ::target::aby::trans::to_aby;
+use circ::target::aby::assignment::{all_boolean_sharing, Assigner};
+use circ::target::aby::assignment::ilp::CostModel;
+use circ::target::aby::mpspdz::to_mpspdz;
+use circ::target::aby::trans::{to_aby, to_aby_circuit};
+use circ::target::bristol::trans::to_bristol;
 use circ::target::ilp::trans::to_ilp;
 use circ::target::r1cs::opt::reduce_linearities;
-use circ::target::r1cs::trans::to_r1cs;
-use circ::target::r1cs::spartan::r1cs_to_spartan;
+use circ::target::r1cs::bellman::{parse_public_inputs, write_public_inputs, Groth16};
+use circ::target::r1cs::proof::ProofSystem;
+use circ::target::r1cs::R1cs;
+use circ::target::r1cs::export::{write_circom_r1cs, write_circom_wtns, write_json};
+use circ::target::r1cs::trans::{to_r1cs_and_wit_gen, to_r1cs_with_profile};
+use circ::target::r1cs::wit::WitnessGenerator;
+use circ::target::r1cs::spartan::{Spartan, SpartanSnark};
 
 use circ::target::smt::find_model;
+use circ::target::smt::underconstrained::underconstrained_vars;
 use env_logger;
+use fxhash::FxHashMap;
+use rug::Integer;
 use good_lp::default_solver;
 use std::fs::File;
-use std::io::Write;
+use std::io::{BufReader, Write};
 use std::path::PathBuf;
+use std::sync::Arc;
 use structopt::clap::arg_enum;
 use std::io::Read;
 use structopt::StructOpt;
@@ -51,6 +62,29 @@ struct Options {
     #[structopt(long, default_value = "2", name = "PARTIES")]
     parties: u8,
 
+    /// Reveal the outputs of an MPC only to these parties (numbered from 1, as in `private<n>`).
+    /// By default, all parties learn them.
+    #[structopt(long, name = "PARTY")]
+    reveal_to: Vec<u8>,
+
+    /// Check memory by permuting access traces for arrays of at least this size (proofs only)
+    #[structopt(long, name = "SIZE")]
+    perm_mem_threshold: Option<usize>,
+
+    /// Use lookup arguments for reads from constant arrays of at least this size (proofs only)
+    #[structopt(long, name = "TABLE_SIZE")]
+    lookup_threshold: Option<usize>,
+
+    /// Only substitute away linear R1CS constraints whose combinations have at most this many
+    /// terms (proofs only)
+    #[structopt(long, name = "LC_SIZE")]
+    lc_size_thresh: Option<usize>,
+
+    /// Print where R1CS constraints come from, and write them as flamegraph stacks to this file
+    /// (proofs only)
+    #[structopt(long, name = "PROFILE", parse(from_os_str))]
+    profile: Option<PathBuf>,
+
     #[structopt(subcommand)]
     backend: Backend,
 }
@@ -89,13 +123,52 @@ enum Backend {
         proof: PathBuf,
         #[structopt(long, default_value = "x", parse(from_os_str))]
         instance: PathBuf,
+        #[structopt(long, default_value = "W", parse(from_os_str))]
+        witness_generator: PathBuf,
+        #[structopt(long, default_value = "C", parse(from_os_str))]
+        constraint_system: PathBuf,
+        #[structopt(long, default_value = "circ", parse(from_os_str))]
+        export: PathBuf,
         #[structopt(long, default_value = "count")]
         action: ProofAction,
+        #[structopt(long, default_value = "groth16")]
+        proof_system: ProofSystemKind,
 
     },
     Smt {},
     Ilp {},
-    Mpc {},
+    Mpc {
+        /// Directory to write the ABY project to
+        #[structopt(long, default_value = "aby", parse(from_os_str))]
+        aby_out: PathBuf,
+        /// Write an MP-SPDZ program to this file, instead of an ABY project (for more than two
+        /// parties)
+        #[structopt(long, parse(from_os_str))]
+        mp_spdz: Option<PathBuf>,
+        /// Costs for assigning sharings: a built-in profile (e.g., `lan`) or an OPA cost file.
+        /// Not used for MP-SPDZ.
+        #[structopt(long, default_value = "lan")]
+        cost_model: String,
+        /// The bit-width whose costs to use
+        #[structopt(long, default_value = "32")]
+        cost_width: usize,
+        /// The cost of a round of communication, in the units of the cost model. If positive,
+        /// sharing assignment minimizes the total time, rather than the cost of the operations.
+        #[structopt(long, default_value = "0")]
+        latency: f64,
+        /// How to assign sharings: `ilp` (optimal), `greedy`, or `partition` (optimal within
+        /// regions)
+        #[structopt(long, default_value = "ilp")]
+        assigner: AssignerKind,
+        /// The maximum number of terms in a region, for the `partition` assigner
+        #[structopt(long, default_value = "500")]
+        region_size: usize,
+    },
+    Bristol {
+        /// File to write the Bristol Fashion circuit to
+        #[structopt(long, default_value = "circ.txt", parse(from_os_str))]
+        bristol_out: PathBuf,
+    },
 
 }
 
@@ -123,7 +196,46 @@ arg_enum! {
         Prove,
         Setup,
         Verify,
-	Spartan,
+        Export,
+    }
+}
+
+arg_enum! {
+    #[derive(PartialEq, Debug)]
+    enum ProofSystemKind {
+        Groth16,
+        Spartan,
+        SpartanSnark,
+    }
+}
+
+impl ProofSystemKind {
+    /// The size of the field that this proof system works over.
+    fn modulus(&self) -> Integer {
+        match self {
+            ProofSystemKind::Groth16 => Groth16::modulus(),
+            ProofSystemKind::Spartan => Spartan::modulus(),
+            ProofSystemKind::SpartanSnark => SpartanSnark::modulus(),
+        }
+    }
+}
+
+arg_enum! {
+    #[derive(PartialEq, Debug)]
+    enum AssignerKind {
+        Ilp,
+        Greedy,
+        Partition,
+    }
+}
+
+impl AssignerKind {
+    fn assigner(&self, region_size: usize) -> Assigner {
+        match self {
+            AssignerKind::Ilp => Assigner::Ilp,
+            AssignerKind::Greedy => Assigner::Greedy,
+            AssignerKind::Partition => Assigner::Partition(region_size),
+        }
     }
 }
 
@@ -160,6 +272,82 @@ fn determine_language(l: &Language, input_path: &PathBuf) -> DeterminedLanguage
     }
 }
 
+fn setup<P: ProofSystem>(r1cs: &R1cs<String>, prover_key: &PathBuf, verifier_key: &PathBuf) {
+    let start = Instant::now();
+    let (pk, vk) = P::setup(r1cs);
+    let setup_ms = start.elapsed().as_millis();
+    println!("r1cs: {}, setup ms: {}", r1cs.constraints().len(), setup_ms);
+    P::write_pk(&pk, File::create(prover_key).unwrap()).unwrap();
+    P::write_vk(&vk, File::create(verifier_key).unwrap()).unwrap();
+}
+
+fn prove<P: ProofSystem>(r1cs: &R1cs<String>, prover_key: &PathBuf, proof: &PathBuf, instance: &PathBuf) {
+    let pk = P::read_pk(File::open(prover_key).unwrap()).unwrap();
+    let start = Instant::now();
+    let pf = P::prove(&pk, r1cs);
+    let prover_ms = start.elapsed().as_millis();
+    let mut pf_bytes = Vec::new();
+    P::write_proof(&pf, &mut pf_bytes).unwrap();
+    println!("prover ms: {}, proof len: {}", prover_ms, pf_bytes.len());
+    std::fs::write(proof, &pf_bytes).unwrap();
+    write_public_inputs(r1cs, File::create(instance).unwrap()).unwrap();
+}
+
+/// Prove with the R1CS and witness generator that setup saved, and the user's input file.
+///
+/// Does not run the front-end.
+fn prove_from_setup(options: &Options) {
+    if let Backend::R1cs {
+        constraint_system,
+        witness_generator,
+        prover_key,
+        proof,
+        instance,
+        proof_system,
+        ..
+    } = &options.backend
+    {
+        let inputs = options
+            .frontend
+            .inputs
+            .clone()
+            .expect("Proving requires an input file (--inputs)");
+        // Datalog has no input files of its own; it uses the same format as ZoKrates.
+        let values: FxHashMap<String, Integer> =
+            match determine_language(&options.frontend.language, &options.path) {
+                DeterminedLanguage::Zokrates | DeterminedLanguage::Datalog => {
+                    zokrates::parse_inputs(inputs).into_iter().collect()
+                }
+                DeterminedLanguage::C => c::parse_inputs(inputs).into_iter().collect(),
+            };
+        let mut r1cs = R1cs::read(File::open(constraint_system).unwrap()).unwrap();
+        let wit_gen = WitnessGenerator::read(File::open(witness_generator).unwrap()).unwrap();
+        let inputs = wit_gen
+            .user_inputs(&values)
+            .unwrap_or_else(|e| panic!("Cannot prove: {}", e));
+        wit_gen.generate_into(&mut r1cs, &inputs);
+        println!("Proving");
+        match proof_system {
+            ProofSystemKind::Groth16 => prove::<Groth16>(&r1cs, prover_key, proof, instance),
+            ProofSystemKind::Spartan => prove::<Spartan>(&r1cs, prover_key, proof, instance),
+            ProofSystemKind::SpartanSnark => {
+                prove::<SpartanSnark>(&r1cs, prover_key, proof, instance)
+            }
+        }
+    }
+}
+
+fn verify<P: ProofSystem>(verifier_key: &PathBuf, proof: &PathBuf, instance: &PathBuf) -> bool {
+    let vk = P::read_vk(File::open(verifier_key).unwrap()).unwrap();
+    let pf = P::read_proof(File::open(proof).unwrap()).unwrap();
+    let inputs = parse_public_inputs(BufReader::new(File::open(instance).unwrap()));
+    let start = Instant::now();
+    let verified =
+        P::verify(&vk, &pf, &inputs).unwrap_or_else(|e| panic!("Cannot verify: {}", e));
+    println!("verifier ms: {}", start.elapsed().as_millis());
+    verified
+}
+
 fn main() {
     env_logger::Builder::from_default_env()
         .format_level(false)
@@ -168,10 +356,14 @@ fn main() {
     let options = Options::from_args();
     let path_buf = options.path.clone();
     println!("{:?}", options);
+    if let Backend::R1cs { action: ProofAction::Prove, .. } = &options.backend {
+        prove_from_setup(&options);
+        return;
+    }
     let mode = match options.backend {
         Backend::R1cs { .. } => Mode::Proof,
         Backend::Ilp { .. } => Mode::Opt,
-        Backend::Mpc { .. } => Mode::Mpc(options.parties),
+        Backend::Mpc { .. } | Backend::Bristol { .. } => Mode::Mpc(options.parties),
         Backend::Smt { .. } => Mode::Proof,
     };
     let language = determine_language(&options.frontend.language, &options.path);
@@ -201,119 +393,156 @@ fn main() {
             C::gen(inputs)
         }
     };
-    let cs = match mode {
+    let mut cs = match mode {
         Mode::Opt => opt(cs, vec![Opt::ConstantFold]),
         Mode::Mpc(_) => opt(
             cs,
             vec![Opt::Sha, Opt::ConstantFold, Opt::Mem, Opt::ConstantFold],
         ),
-        Mode::Proof | Mode::ProofOfHighValue(_) => opt(
-            cs,
-            vec![
+        Mode::Proof | Mode::ProofOfHighValue(_) => {
+            let mut passes = vec![
                 Opt::Flatten,
                 Opt::Sha,
                 Opt::ConstantFold,
                 Opt::Flatten,
                 //Opt::FlattenAssertions,
                 Opt::Inline,
-                Opt::Mem,
+                Opt::Lookup(options.lookup_threshold.unwrap_or(usize::MAX)),
+                match options.perm_mem_threshold {
+                    Some(size) => Opt::PermMem(size),
+                    None => Opt::Mem,
+                },
                 Opt::Flatten,
                 //Opt::FlattenAssertions,
-                Opt::ConstantFold,
-                Opt::Inline,
-            ],
-        ),
+            ];
+            // Only R1CS lowering handles the field terms (and casts) these passes introduce
+            if let Backend::R1cs { proof_system, .. } = &options.backend {
+                passes.push(Opt::Narrow);
+                passes.push(Opt::BvToField(Arc::new(proof_system.modulus())));
+            }
+            passes.push(Opt::ConstantFold);
+            passes.push(Opt::Inline);
+            opt(cs, passes)
+        }
     };
     println!("Done with IR optimization");
+    if !options.reveal_to.is_empty() {
+        for p in &options.reveal_to {
+            assert!(
+                *p >= 1 && *p <= options.parties,
+                "Cannot reveal to party {}: there are {} parties",
+                p,
+                options.parties
+            );
+        }
+        cs.metadata.output_parties = Some(options.reveal_to.iter().map(|p| p - 1).collect());
+    }
 
     match options.backend {
-        Backend::R1cs { action, proof, prover_key, verifier_key, .. } => {
+        Backend::R1cs { action, proof, prover_key, verifier_key, witness_generator, constraint_system, export, instance, proof_system } => {
 	    println!("Converting to r1cs");
+            let modulus = proof_system.modulus();
 
-            let r1cs = to_r1cs(cs, circ::target::r1cs::spartan::SPARTAN_MODULUS.clone());
+            let (r1cs, wit_gen) = match &options.profile {
+                Some(path) => {
+                    let (r1cs, wit_gen, profile) = to_r1cs_with_profile(cs, modulus);
+                    print!("{}", profile);
+                    profile.write_folded(File::create(path).unwrap()).unwrap();
+                    (r1cs, wit_gen)
+                }
+                None => to_r1cs_and_wit_gen(cs, modulus),
+            };
             println!("Pre-opt R1cs size: {}", r1cs.constraints().len());
-//            let r1cs = reduce_linearities(r1cs);
-	    //println!("Post-opt R1cs size: {}", r1cs.constraints().len());
-	    let num_r1cs = r1cs.constraints().len().clone();
+            let r1cs = reduce_linearities(r1cs, options.lc_size_thresh);
+            println!("Post-opt R1cs size: {}", r1cs.constraints().len());
 
             match action {
                 ProofAction::Count => {
                     println!("Final R1cs size: {}", r1cs.constraints().len());
                 }
-		ProofAction::Spartan => {
-		    println!("Converting R1CS to Spartan");
- 
-		    let (inst, vars, inps, num_cons, num_vars, num_inputs) = r1cs_to_spartan(r1cs);
-
-		    println!("Proving with Spartan");
-		    let prover = Instant::now();
-
-		    // produce public parameters
-		    let gens = NIZKGens::new(num_cons, num_vars, num_inputs);
-		    // produce proof
-		    let mut prover_transcript = Transcript::new(b"nizk_example");
-		    let pf = NIZK::prove(&inst, vars, &inps, &gens, &mut prover_transcript);		    
-	
-                     let prover_ms = prover.elapsed().as_millis();
-   		     let innerproof = &pf.r1cs_sat_proof;
-        	     let proof_len = bincode::serialize(innerproof).unwrap().len();
-      		     //let comm_len = bincode::serialize(&innerproof.comm_vars).unwrap().len();
-                     let comm_len = -1;
-
-   	            // write proof file
-		    //let mut pf_file = File::create(proof).unwrap();
-                    //pf.write(&mut pf_file).unwrap();
-
-                    println!("Verifying with Spartan");
-		    let verifier = Instant::now();                    
-
-		    // verify proof
-		    let mut verifier_transcript = Transcript::new(b"nizk_example");
-		    assert!(pf
-			.verify(&inst, &inps, &mut verifier_transcript, &gens)
-			.is_ok());
-
-		    let verifier_ms = verifier.elapsed().as_millis();
-		    println!("proof verification successful!");
-                    
-		    println!("{:#?}, r1cs: {}, prover ms: {}, verifier ms: {}, comm len: {}, proof len: {}", path_buf, num_r1cs, prover_ms, verifier_ms, comm_len, proof_len);
-		    //eprintln!("{:#?}, r1cs: {}, prover ms: {}, verifier ms: {}, comm len: {}, proof len: {}", path_buf, num_r1cs, prover_ms, verifier_ms, comm_len, proof_len);
-
-		}
-                ProofAction::Prove => {
-                    println!("Proving");
-                    let rng = &mut rand::thread_rng();
-                    let mut pk_file = File::open(prover_key).unwrap();
-                    let pk = Parameters::<Bls12>::read(&mut pk_file, false).unwrap();
-            	    let pf = create_random_proof(&r1cs, &pk, rng).unwrap();
-                    let mut pf_file = File::create(proof).unwrap();
-                    pf.write(&mut pf_file).unwrap();
-                }
                 ProofAction::Setup => {
-                    let rng = &mut rand::thread_rng();
-                    let p =
-                        generate_random_parameters::<bls12_381::Bls12, _, _>(&r1cs, rng).unwrap();
-                    let mut pk_file = File::create(prover_key).unwrap();
-                    p.write(&mut pk_file).unwrap();
-                    let mut vk_file = File::create(verifier_key).unwrap();
-                    p.vk.write(&mut vk_file).unwrap();
+                    match proof_system {
+                        ProofSystemKind::Groth16 => {
+                            setup::<Groth16>(&r1cs, &prover_key, &verifier_key)
+                        }
+                        ProofSystemKind::Spartan => {
+                            setup::<Spartan>(&r1cs, &prover_key, &verifier_key)
+                        }
+                        ProofSystemKind::SpartanSnark => {
+                            setup::<SpartanSnark>(&r1cs, &prover_key, &verifier_key)
+                        }
+                    }
+                    let mut wg_file = File::create(witness_generator).unwrap();
+                    wit_gen.write(&mut wg_file).unwrap();
+                    let mut cs_file = File::create(constraint_system).unwrap();
+                    r1cs.write(&mut cs_file).unwrap();
                 }
+                ProofAction::Prove => unreachable!("Proving uses the output of setup"),
                 ProofAction::Verify => {
                     println!("Verifying");
-                    let mut vk_file = File::open(verifier_key).unwrap();
-                    let vk = VerifyingKey::<Bls12>::read(&mut vk_file).unwrap();
-                    let pvk = prepare_verifying_key(&vk);
-                    let mut pf_file = File::open(proof).unwrap();
-                    let pf = Proof::read(&mut pf_file).unwrap();
-                    verify_proof(&pvk, &pf, &[]).unwrap();
+                    let verified = match proof_system {
+                        ProofSystemKind::Groth16 => {
+                            verify::<Groth16>(&verifier_key, &proof, &instance)
+                        }
+                        ProofSystemKind::Spartan => {
+                            verify::<Spartan>(&verifier_key, &proof, &instance)
+                        }
+                        ProofSystemKind::SpartanSnark => {
+                            verify::<SpartanSnark>(&verifier_key, &proof, &instance)
+                        }
+                    };
+                    assert!(verified, "Proof did not verify");
+                    println!("proof verification successful!");
+                }
+                ProofAction::Export => {
+                    let mut r1cs_file = File::create(export.with_extension("r1cs")).unwrap();
+                    write_circom_r1cs(&r1cs, &mut r1cs_file).unwrap();
+                    let mut json_file = File::create(export.with_extension("json")).unwrap();
+                    write_json(&r1cs, &mut json_file).unwrap();
+                    if r1cs.has_values() {
+                        let mut wtns_file = File::create(export.with_extension("wtns")).unwrap();
+                        write_circom_wtns(&r1cs, &mut wtns_file).unwrap();
+                    }
                 }
             }
         }
-        Backend::Mpc { .. } => {
+        Backend::Mpc {
+            aby_out,
+            mp_spdz: None,
+            cost_model,
+            cost_width,
+            latency,
+            assigner,
+            region_size,
+        } => {
             println!("Converting to aby");
             let lang = &String::from("zok");
-            to_aby(cs, &path_buf, &lang);
-            write_aby_exec(&path_buf, &lang);
+            let costs = CostModel::load(&cost_model, cost_width).with_latency(latency);
+            let assigner = assigner.assigner(region_size);
+            to_aby(cs, &path_buf, &lang, &aby_out, assigner, &costs);
+            write_aby_exec(&path_buf, &lang, &aby_out);
+        }
+        Backend::Mpc {
+            mp_spdz: Some(mp_spdz),
+            ..
+        } => {
+            println!("Converting to MP-SPDZ");
+            // MP-SPDZ ignores the sharings, so there is no use in assigning them by cost.
+            let circ = to_aby_circuit(&cs, &all_boolean_sharing(&cs));
+            std::fs::write(&mp_spdz, to_mpspdz(&circ)).unwrap();
+        }
+        Backend::Bristol { bristol_out } => {
+            println!("Converting to Bristol Fashion");
+            let circ = to_bristol(&cs);
+            println!("AND gates: {}", circ.and_count());
+            for (i, b) in circ.inputs.iter().enumerate() {
+                let party = b.party.map_or("public".to_owned(), |p| format!("party {}", p));
+                println!("Input value {} ({}):", i, party);
+                for (name, width) in &b.inputs {
+                    println!("  {}: {} bits", name, width);
+                }
+            }
+            circ.write(File::create(&bristol_out).unwrap()).unwrap();
         }
         Backend::Ilp { .. } => {
             println!("Converting to ilp");
@@ -351,7 +580,16 @@ fn main() {
                     }
                 }
             } else {
-                    todo!()
+                let vars = underconstrained_vars(&cs);
+                if vars.is_empty() {
+                    println!("Fully constrained");
+                } else {
+                    println!("Under-constrained variables:");
+                    for (var, pair) in &vars {
+                        println!("{}, e.g.:\n{}", var, pair);
+                    }
+                    std::process::exit(1)
+                }
             }
         }
     }
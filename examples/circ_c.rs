@@ -5,6 +5,8 @@ use bls12_381::Scalar;
 use circ::front::c::{Inputs, C};
 use circ::front::{FrontEnd, Mode};
 use circ::ir::opt::{opt, Opt};
+use circ::target::aby::assignment::ilp::CostModel;
+use circ::target::aby::assignment::Assigner;
 use circ::target::aby::output::write_aby_exec;
 use circ::target::aby::trans::to_aby;
 use circ::target::ilp::trans::to_ilp;
@@ -13,6 +15,7 @@ use circ::target::r1cs::trans::to_r1cs;
 use env_logger;
 use good_lp::default_solver;
 use std::path::PathBuf;
+use structopt::clap::arg_enum;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -33,6 +36,40 @@ struct Options {
     /// Whether to maximize the output
     #[structopt(short, long)]
     maximize: bool,
+
+    /// Directory to write the ABY project to
+    #[structopt(long, default_value = "aby", parse(from_os_str))]
+    aby_out: PathBuf,
+
+    /// Costs for assigning sharings: a built-in profile (e.g., `lan`) or an OPA cost file
+    #[structopt(long, default_value = "lan")]
+    cost_model: String,
+
+    /// The bit-width whose costs to use
+    #[structopt(long, default_value = "32")]
+    cost_width: usize,
+
+    /// The cost of a round of communication, in the units of the cost model. If positive, sharing
+    /// assignment minimizes the total time, rather than the cost of the operations.
+    #[structopt(long, default_value = "0")]
+    latency: f64,
+
+    /// How to assign sharings: `ilp` (optimal), `greedy`, or `partition` (optimal within regions)
+    #[structopt(long, default_value = "ilp")]
+    assigner: AssignerKind,
+
+    /// The maximum number of terms in a region, for the `partition` assigner
+    #[structopt(long, default_value = "500")]
+    region_size: usize,
+}
+
+arg_enum! {
+    #[derive(PartialEq, Debug)]
+    enum AssignerKind {
+        Ilp,
+        Greedy,
+        Partition,
+    }
 }
 
 fn main() {
@@ -73,8 +110,15 @@ fn main() {
         Mode::Mpc(_) => {
             println!("Converting to aby");
             let lang = &String::from("c");
-            to_aby(cs, &path_buf, &lang);
-            write_aby_exec(&path_buf, &lang);
+            let costs = CostModel::load(&options.cost_model, options.cost_width)
+                .with_latency(options.latency);
+            let assigner = match options.assigner {
+                AssignerKind::Ilp => Assigner::Ilp,
+                AssignerKind::Greedy => Assigner::Greedy,
+                AssignerKind::Partition => Assigner::Partition(options.region_size),
+            };
+            to_aby(cs, &path_buf, &lang, &options.aby_out, assigner, &costs);
+            write_aby_exec(&path_buf, &lang, &options.aby_out);
         }
         _ => unimplemented!(),
     }
@@ -1,17 +1,46 @@
 use circ::ir::term::*;
-use circ::target::aby::assignment::ilp;
+use circ::target::aby::assignment::ilp::{self, CostModel};
+use circ::target::aby::assignment::{assignment_cost, greedy, partition, SharingMap};
 use circ::term;
+use structopt::clap::arg_enum;
 use structopt::StructOpt;
 
+use std::time::Instant;
+
+arg_enum! {
+    #[derive(PartialEq, Debug)]
+    enum Assigner {
+        Ilp,
+        Greedy,
+        Partition,
+    }
+}
+
 #[derive(Debug, StructOpt)]
 #[structopt(
     name = "opa_bench",
     about = "Optimal Protocol Assignment via ILP benchmarker"
 )]
 struct Options {
-    /// Number of parties for an MPC. If missing, generates a proof circuit.
+    /// Number of multiplications in each chain
     #[structopt(name = "MULTS")]
     n_mults: u32,
+
+    /// Number of independent chains of multiplications
+    #[structopt(long, default_value = "1")]
+    chains: u32,
+
+    /// Assigners to compare
+    #[structopt(long, possible_values = &Assigner::variants(), case_insensitive = true, default_value = "ilp,greedy,partition", use_delimiter = true)]
+    assigners: Vec<Assigner>,
+
+    /// Maximum number of terms in a region, for the partition assigner
+    #[structopt(long, default_value = "500")]
+    region_size: usize,
+
+    /// The cost of a round of communication
+    #[structopt(long, default_value = "0")]
+    latency: f64,
 }
 
 fn main() {
@@ -20,16 +49,35 @@ fn main() {
         .format_timestamp(None)
         .init();
     let options = Options::from_args();
-    let v = leaf_term(Op::Var("a".to_owned(), Sort::BitVector(32)));
-    let mut t = v.clone();
-    for _i in 0..options.n_mults {
-        t = term![BV_MUL; t.clone(), t.clone()];
-    }
+    let outputs = (0..options.chains)
+        .map(|i| {
+            let v = leaf_term(Op::Var(format!("a{}", i), Sort::BitVector(32)));
+            let mut t = v.clone();
+            for _i in 0..options.n_mults {
+                t = term![BV_MUL; t.clone(), t.clone()];
+            }
+            term![Op::Eq; t, v]
+        })
+        .collect();
     let cs = Computation {
-        outputs: vec![term![Op::Eq; t, v]],
+        outputs,
         metadata: ComputationMetadata::default(),
         values: None,
     };
-    let _assignment = ilp::assign(&cs);
-    //dbg!(&assignment);
+    let costs = CostModel::default().with_latency(options.latency);
+    for a in &options.assigners {
+        let start = Instant::now();
+        let assignment: SharingMap = match a {
+            Assigner::Ilp => ilp::assign_with_costs(&cs, &costs),
+            Assigner::Greedy => greedy::assign_greedy(&cs, &costs),
+            Assigner::Partition => partition::assign_partitioned(&cs, &costs, options.region_size),
+        };
+        let time = start.elapsed();
+        println!(
+            "{:?}: cost {:.1}, time {:.3}s",
+            a,
+            assignment_cost(&cs, &assignment, &costs),
+            time.as_secs_f64()
+        );
+    }
 }
@@ -1,3 +1,4 @@
+import os
 from subprocess import Popen, PIPE
 import sys
 from typing import List
@@ -31,8 +32,9 @@ def flatten_args(args: dict) -> list:
     return flat_args
 
 def update_path(path: str, lang: str) -> str:
-    '''Append path with language type'''
-    return f'{path}_{lang}_test'
+    '''Find the executable for test `path` in the generated ABY project for language type'''
+    name = f'{os.path.basename(path)}_{lang}'
+    return f'./aby/{name}/build/{name}_test'
 
 def build_server_cmd(exec: str, args: dict) -> List[str]:
     return [exec, "-r", "0", "-i"] + flatten_args(args)
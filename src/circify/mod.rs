@@ -695,6 +695,11 @@ impl<E: Embeddable> Circify<E> {
         }
     }
 
+    /// The names of the functions being called, outermost first.
+    pub fn call_stack(&self) -> Vec<String> {
+        self.fn_stack.iter().map(|f| f.name.clone()).collect()
+    }
+
     /// Return (subject to the current path condition).
     pub fn return_(&mut self, val: Option<E::T>) -> Result<()> {
         let last = self.fn_stack.last().expect("No fn");
@@ -21,6 +21,9 @@ use log::debug;
 use std::fmt::Display;
 use std::path::PathBuf;
 
+/// Parse a file of input values, in the format of [Inputs::inputs].
+pub use parser::parse_inputs;
+
 /// The prover visibility
 const PROVER_VIS: Option<PartyId> = Some(proof::PROVER_ID);
 /// Public visibility
@@ -53,7 +56,8 @@ impl FrontEnd for C {
     fn gen(i: Inputs) -> Computation {
         let parser = parser::CParser::new();
         let p = parser.parse_file(&i.file).unwrap();
-        let mut g = CGen::new(i.inputs, i.mode, p.unit);
+        let lines = parser::LineMap::new(&i.file, &p.source);
+        let mut g = CGen::new(i.inputs, i.mode, p.unit, lines);
         g.gen();
         g.circ.consume().borrow().clone()
     }
@@ -63,6 +67,7 @@ struct CGen {
     circ: Circify<Ct>,
     mode: Mode,
     tu: TranslationUnit,
+    lines: parser::LineMap,
 }
 
 enum CLoc {
@@ -80,11 +85,17 @@ impl CLoc {
 }
 
 impl CGen {
-    fn new(inputs: Option<PathBuf>, mode: Mode, tu: TranslationUnit) -> Self {
+    fn new(
+        inputs: Option<PathBuf>,
+        mode: Mode,
+        tu: TranslationUnit,
+        lines: parser::LineMap,
+    ) -> Self {
         let this = Self {
             circ: Circify::new(Ct::new(inputs.map(|i| parser::parse_inputs(i)))),
             mode,
             tu,
+            lines,
         };
         this.circ
             .cir_ctx()
@@ -316,6 +327,14 @@ impl CGen {
     }
 */
     fn gen_expr(&mut self, expr: Expression) -> CTerm {
+        let start = match &expr {
+            Expression::Identifier(node) => Some(node.span.start),
+            Expression::Constant(node) => Some(node.span.start),
+            Expression::BinaryOperator(node) => Some(node.span.start),
+            Expression::UnaryOperator(node) => Some(node.span.start),
+            Expression::Cast(node) => Some(node.span.start),
+            _ => None,
+        };
         let res = match expr.clone() {
             Expression::Identifier(node) => Ok(self
                 .unwrap(self.circ.get_value(Loc::local(node.node.name.clone())))
@@ -413,7 +432,32 @@ impl CGen {
             }
             _ => unimplemented!("Expr {:#?} hasn't been implemented", expr),
         };
-        self.unwrap(res)
+        let res = self.unwrap(res);
+        if let Some(start) = start {
+            self.record_loc(&res, start);
+        }
+        res
+    }
+
+    /// Record that the terms of `t` come from byte `offset` of the (preprocessed) source.
+    fn record_loc(&self, t: &CTerm, offset: usize) {
+        let term = match &t.term {
+            CTermData::CBool(b) | CTermData::CInt(_, _, b) => b.clone(),
+            _ => return,
+        };
+        if let Some((file, line)) = self.lines.locate(offset) {
+            let loc = SourceLoc {
+                file: file.to_owned(),
+                line,
+                call_stack: self.circ.call_stack(),
+            };
+            self.circ
+                .cir_ctx()
+                .cs
+                .borrow_mut()
+                .metadata
+                .set_term_loc(term, loc);
+        }
     }
 
     fn gen_init(&mut self, derived_ty: Ty, init: Initializer) -> CTerm {
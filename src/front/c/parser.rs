@@ -6,7 +6,7 @@ use rug::Integer;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// Parse an inputs file where each line has format: `no-whitespace integer`.
 ///
@@ -46,3 +46,57 @@ impl CParser {
         Ok(parse(&self.config, path)?)
     }
 }
+
+/// Where the lines of preprocessed C come from, according to the preprocessor's line markers.
+pub struct LineMap {
+    /// For each line of the preprocessed source: its byte offset, its file, and its line there.
+    lines: Vec<(usize, usize, usize)>,
+    files: Vec<String>,
+}
+
+impl LineMap {
+    /// Map the lines of `source`, which was preprocessed from `path`.
+    pub fn new(path: &Path, source: &str) -> Self {
+        let mut lines = Vec::new();
+        let mut files = vec![path.display().to_string()];
+        let mut file = 0;
+        let mut line = 1;
+        let mut offset = 0;
+        for l in source.split('\n') {
+            if let Some((n, f)) = line_marker(l) {
+                file = match files.iter().position(|g| g == &f) {
+                    Some(i) => i,
+                    None => {
+                        files.push(f);
+                        files.len() - 1
+                    }
+                };
+                line = n;
+            } else {
+                lines.push((offset, file, line));
+                line += 1;
+            }
+            offset += l.len() + 1;
+        }
+        Self { lines, files }
+    }
+
+    /// The file and line that byte `offset` of the preprocessed source comes from.
+    pub fn locate(&self, offset: usize) -> Option<(&str, usize)> {
+        let i = match self.lines.binary_search_by_key(&offset, |l| l.0) {
+            Ok(i) => i,
+            Err(i) => i.checked_sub(1)?,
+        };
+        let (_, file, line) = self.lines[i];
+        Some((&self.files[file], line))
+    }
+}
+
+/// Parse a line marker, `# <line> "<file>" <flags>`.
+fn line_marker(l: &str) -> Option<(usize, String)> {
+    let mut parts = l.strip_prefix("# ")?.splitn(2, ' ');
+    let line = parts.next()?.parse().ok()?;
+    let file = parts.next()?.strip_prefix('"')?;
+    let end = file.find('"')?;
+    Some((line, file[..end].to_owned()))
+}
@@ -355,6 +355,12 @@ impl Embeddable for Datalog {
         user_name: Option<String>,
         visibility: Option<PartyId>,
     ) -> Self::T {
+        if let Some(u) = &user_name {
+            ctx.cs
+                .borrow_mut()
+                .metadata
+                .set_user_name(raw_name.clone(), u.clone());
+        }
         let get_int_val = || -> Integer { panic!("No values in Datalog") };
         match ty {
             Ty::Bool => T::new(
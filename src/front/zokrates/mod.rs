@@ -21,6 +21,8 @@ use term::*;
 pub use term::ZOKRATES_MODULUS;
 /// The modulus for the ZoKrates language.
 pub use term::ZOKRATES_MODULUS_ARC;
+/// Parse a file of input values, in the format of [Inputs::inputs].
+pub use parser::parse_inputs;
 
 /// The prover visibility
 pub const PROVER_VIS: Option<PartyId> = Some(proof::PROVER_ID);
@@ -420,7 +422,22 @@ impl<'ast> ZGen<'ast> {
                 })
             }
         };
-        self.unwrap(res, e.span())
+        let res = self.unwrap(res, e.span());
+        self.record_loc(&res, e.span());
+        res
+    }
+    /// Record that the terms of `t` come from `span`.
+    fn record_loc(&self, t: &T, span: &ast::Span) {
+        let loc = SourceLoc {
+            file: self.cur_path().display().to_string(),
+            line: span.start_pos().line_col().0,
+            call_stack: self.circ.call_stack(),
+        };
+        let cs = self.circ.cir_ctx().cs.clone();
+        let mut cs = cs.borrow_mut();
+        for term in t.terms() {
+            cs.metadata.set_term_loc(term, loc.clone());
+        }
     }
     fn array_lit_elem(&mut self, e: &ast::SpreadOrExpression<'ast>) -> Vec<T> {
         match e {
@@ -585,6 +585,12 @@ impl Embeddable for ZoKrates {
         user_name: Option<String>,
         visibility: Option<PartyId>,
     ) -> Self::T {
+        if let Some(u) = &user_name {
+            ctx.cs
+                .borrow_mut()
+                .metadata
+                .set_user_name(raw_name.clone(), u.clone());
+        }
         let get_int_val = || -> Integer {
             self.values
                 .as_ref()
@@ -177,6 +177,9 @@ pub fn fold_cache(node: &Term, cache: &mut TermMap<Term>) -> Term {
                     PfUnOp::Neg => -pf.clone(),
                 })))
             }),
+            Op::Lookup(table) => get(0)
+                .as_bv_opt()
+                .map(|i| leaf_term(Op::Const(table[i.uint().to_usize().unwrap()].clone()))),
             _ => None,
         };
         let c_get = |x: &Term| -> Term { cache.get(&x).expect("postorder cache").clone() };
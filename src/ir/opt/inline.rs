@@ -176,14 +176,30 @@ impl<'a> Inliner<'a> {
 /// First, maintains a set of variables being substituted.
 /// Second, maintain a
 pub fn inline(assertions: &mut Vec<Term>, public_inputs: &FxHashSet<String>) {
+    inline_with_sources(assertions, public_inputs, Vec::new());
+}
+
+/// Like [inline], but also applies the substitutions to the terms `others`, and returns the
+/// assertion that each remaining assertion was rewritten from.
+pub fn inline_with_sources(
+    assertions: &mut Vec<Term>,
+    public_inputs: &FxHashSet<String>,
+    others: Vec<&mut Term>,
+) -> Vec<Term> {
     let mut new_assertions = Vec::new();
+    let mut sources = Vec::new();
     let mut inliner = Inliner::new(public_inputs);
     for assertion in assertions.drain(..) {
         if let Some(rewritten_assertion) = inliner.ingest_term(&assertion) {
             new_assertions.push(rewritten_assertion);
+            sources.push(assertion);
         }
     }
     *assertions = new_assertions;
+    for t in others {
+        *t = inliner.apply(t);
+    }
+    sources
 }
 
 #[cfg(test)]
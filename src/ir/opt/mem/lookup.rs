@@ -0,0 +1,229 @@
+//! Table lookups
+//!
+//! Reads from constant arrays (e.g., S-boxes or round constants) at non-constant indices become
+//! [Op::Lookup]s. Back-ends can check a batch of lookups into the same table at once, rather than
+//! scanning the array for each read (as [super::lin] does).
+//!
+//! An array is constant if it is a constant value, or if it is made by storing constants at
+//! constant indices in a constant `const-array`. Its index sort must be a bit-vector sort.
+//!
+//! The table for a length-`n` array has `2^k` entries, where `k` is the number of bits needed to
+//! hold `n - 1`. It is padded with the array's default value. If [crate::ir::opt::range] cannot
+//! show that an index is less than `2^k`, then the lookup is guarded by a comparison, and the read
+//! yields the default value otherwise.
+
+use crate::ir::opt::range::RangeAnalysis;
+use crate::ir::term::*;
+
+use rug::Integer;
+
+/// Replaces reads from constant arrays with table lookups, caching results.
+struct LookupRewriter {
+    size_thresh: usize,
+    ranges: RangeAnalysis,
+    /// Maps (original) constant-array terms to their values.
+    consts: TermMap<Value>,
+    cache: TermMap<Term>,
+}
+
+impl LookupRewriter {
+    /// If `t` is a constant array, record its value.
+    fn record_const(&mut self, t: &Term) {
+        let value = match &t.op {
+            Op::Const(v @ Value::Array(..)) => Some(v.clone()),
+            Op::ConstArray(Sort::BitVector(_), n) => match &t.cs[0].op {
+                Op::Const(v) => Some(Value::Array(
+                    check(t),
+                    Box::new(v.clone()),
+                    Default::default(),
+                    *n,
+                )),
+                _ => None,
+            },
+            Op::Store => match (self.consts.get(&t.cs[0]), &t.cs[1].op, &t.cs[2].op) {
+                (Some(Value::Array(s, d, m, n)), Op::Const(k), Op::Const(v)) => {
+                    let mut m = m.clone();
+                    m.insert(k.clone(), v.clone());
+                    Some(Value::Array(s.clone(), d.clone(), m, *n))
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(v) = value {
+            self.consts.insert(t.clone(), v);
+        }
+    }
+
+    /// If `t` reads a large enough constant array at a non-constant index, get the lookup.
+    fn lookup(&mut self, t: &Term) -> Option<Term> {
+        if t.op != Op::Select {
+            return None;
+        }
+        if let Op::Const(_) = &t.cs[1].op {
+            return None;
+        }
+        let (default, map, size) = match self.consts.get(&t.cs[0]) {
+            Some(Value::Array(Sort::Array(k, v, _), d, m, n))
+                if n >= &self.size_thresh && n > &0 =>
+            {
+                match (&**k, &**v) {
+                    (Sort::BitVector(_), Sort::Array(..))
+                    | (Sort::BitVector(_), Sort::Tuple(_)) => return None,
+                    (Sort::BitVector(_), _) => ((**d).clone(), m.clone(), *n),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        let w = check(&t.cs[1]).as_bv();
+        let k = std::cmp::min(
+            w,
+            std::cmp::max(1, Integer::from(size - 1).significant_bits() as usize),
+        );
+        let table_size = Integer::from(1) << k as u32;
+        if map.keys().any(|i| i.as_bv().uint() >= &table_size) {
+            // This store is beyond the table.
+            return None;
+        }
+        let table: Vec<Value> = (0..(1usize << k))
+            .map(|i| {
+                let key = Value::BitVector(BitVector::new(Integer::from(i), w));
+                map.get(&key).unwrap_or(&default).clone()
+            })
+            .collect();
+        let idx = self.cache.get(&t.cs[1]).expect("postorder cache").clone();
+        let in_range = k == w || self.ranges.range(&t.cs[1]).uhi < table_size;
+        let narrow_idx = if k < w {
+            term![Op::BvExtract(k - 1, 0); idx.clone()]
+        } else {
+            idx.clone()
+        };
+        let lookup = term![Op::Lookup(std::sync::Arc::new(table)); narrow_idx];
+        Some(if in_range {
+            lookup
+        } else {
+            term![ITE;
+                term![BV_ULT; idx, bv_lit(table_size, w)],
+                lookup,
+                leaf_term(Op::Const(default))
+            ]
+        })
+    }
+
+    fn rewrite(&mut self, node: &Term) -> Term {
+        // (node, children pushed)
+        let mut stack = vec![(node.clone(), false)];
+        while let Some((t, children_pushed)) = stack.pop() {
+            if self.cache.contains_key(&t) {
+                continue;
+            }
+            if !children_pushed {
+                stack.push((t.clone(), true));
+                stack.extend(t.cs.iter().map(|c| (c.clone(), false)));
+                continue;
+            }
+            self.record_const(&t);
+            let new_t = self.lookup(&t).unwrap_or_else(|| {
+                term(
+                    t.op.clone(),
+                    t.cs.iter()
+                        .map(|c| self.cache.get(c).expect("postorder cache").clone())
+                        .collect(),
+                )
+            });
+            self.cache.insert(t, new_t);
+        }
+        self.cache.get(node).expect("postorder cache").clone()
+    }
+}
+
+/// Replace reads at non-constant indices from constant arrays of at least `size_thresh` elements
+/// with table lookups. See the module documentation.
+pub fn lookups(t: &Term, size_thresh: usize) -> Term {
+    LookupRewriter {
+        size_thresh,
+        ranges: RangeAnalysis::new(),
+        consts: TermMap::new(),
+        cache: TermMap::new(),
+    }
+    .rewrite(t)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fxhash::FxHashMap;
+    use quickcheck_macros::quickcheck;
+
+    fn v_bv(n: &str, w: usize) -> Term {
+        leaf_term(Op::Var(n.to_owned(), Sort::BitVector(w)))
+    }
+
+    /// The array `[3, 1, 4, 1, 5]`, of 8-bit values, with 32-bit indices and default 9.
+    fn digits() -> Term {
+        [3, 1, 4, 1, 5].iter().enumerate().fold(
+            term![Op::ConstArray(Sort::BitVector(32), 5); bv_lit(9, 8)],
+            |a, (i, d)| term![Op::Store; a, bv_lit(i, 32), bv_lit(*d, 8)],
+        )
+    }
+
+    fn count_lookups(t: &Term) -> usize {
+        PostOrderIter::new(t.clone())
+            .filter(|c| match &c.op {
+                Op::Lookup(_) => true,
+                _ => false,
+            })
+            .count()
+    }
+
+    #[quickcheck]
+    fn semantics(i: u32, j: u8) {
+        let t = term![BV_ADD;
+            term![Op::Select; digits(), v_bv("i", 32)],
+            term![Op::Select; digits(), term![Op::BvUext(24); v_bv("j", 8)]]
+        ];
+        let vs: FxHashMap<String, Value> = vec![
+            (
+                "i".to_owned(),
+                Value::BitVector(BitVector::new(Integer::from(i % 16), 32)),
+            ),
+            (
+                "j".to_owned(),
+                Value::BitVector(BitVector::new(Integer::from(j), 8)),
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let tt = lookups(&t, 0);
+        assert_eq!(count_lookups(&tt), 2);
+        assert_eq!(eval(&t, &vs), eval(&tt, &vs), "{} vs {}", t, tt);
+    }
+
+    #[test]
+    fn narrow_index() {
+        let t = term![Op::Select; digits(), term![Op::BvUext(29); v_bv("i", 3)]];
+        let table = [3, 1, 4, 1, 5, 9, 9, 9]
+            .iter()
+            .map(|d| Value::BitVector(BitVector::new(Integer::from(*d), 8)))
+            .collect();
+        assert_eq!(
+            lookups(&t, 0),
+            term![Op::Lookup(std::sync::Arc::new(table));
+                term![Op::BvExtract(2, 0); term![Op::BvUext(29); v_bv("i", 3)]]]
+        );
+    }
+
+    #[test]
+    fn below_threshold() {
+        let t = term![Op::Select; digits(), v_bv("i", 32)];
+        assert_eq!(lookups(&t, 6), t);
+    }
+
+    #[test]
+    fn non_constant_array() {
+        let a = term![Op::Store; digits(), v_bv("k", 32), bv_lit(0, 8)];
+        let t = term![Op::Select; a, v_bv("i", 32)];
+        assert_eq!(lookups(&t, 0), t);
+    }
+}
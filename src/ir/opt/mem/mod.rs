@@ -1,7 +1,9 @@
 //! Memory optimizations
 
 pub mod lin;
+pub mod lookup;
 pub mod obliv;
+pub mod perm;
 mod visit;
 
 use crate::ir::term::*;
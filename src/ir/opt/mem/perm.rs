@@ -0,0 +1,447 @@
+//! Permutation-based memory checking
+//!
+//! [super::lin] replaces every access to a length-`n` array with an `n`-way scan. For large arrays
+//! that are accessed many times, this module instead checks the consistency of a *trace* of all
+//! accesses, whose cost depends on the number of accesses rather than the size of the array.
+//!
+//! Each read becomes a fresh variable, chosen by the prover (and computed by witness generation
+//! from [ComputationMetadata::checked_memories]). Each access (read or write) becomes a
+//! trace entry recording its index, a timestamp, and the value read or written. A sorting network
+//! permutes the trace into (index, timestamp) order, and then we assert that each read agrees
+//! with the latest write to the same index (or with the array's default value, if there is none).
+//!
+//! Since the trace is sorted in-circuit, no permutation needs to be supplied by the prover. The
+//! sorting network has `O(m log^2 m)` comparators, for `m` accesses.
+//!
+//! ## Supported arrays
+//!
+//! The arrays handled here are *memories*: chains of versions, starting from a constant array
+//! with bit-vector indices, where each version is either
+//!
+//!    * `(store A k v)`, or
+//!    * `(ite c (store A k v) A)` (or `(ite c A (store A k v))`), a conditional write,
+//!
+//! of the previous version `A`. Every version must be used only to make the next version and in
+//! `select`s. Other arrays are left for [super::lin].
+//!
+//! Since reads become variables, the resulting computation only makes sense as a set of
+//! assertions (i.e., a proof statement).
+
+use crate::ir::proof::PROVER_ID;
+use crate::ir::term::extras::substitute_cache;
+use crate::ir::term::*;
+
+use log::debug;
+use rug::Integer;
+
+#[derive(Clone)]
+/// An access, in a memory trace.
+struct Entry {
+    key: Term,
+    time: Term,
+    val: Term,
+    /// Is this a read (whose value must be checked)?
+    read: Term,
+    /// Is this a write (which changes the memory)?
+    write: Term,
+}
+
+/// The comparators of Batcher's odd-even merge sort for `n` items, in order.
+fn batcher_pairs(n: usize) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    let mut p = 1;
+    while p < n {
+        let mut k = p;
+        while k >= 1 {
+            let mut j = k % p;
+            while j + k < n {
+                for i in 0..std::cmp::min(k, n - j - k) {
+                    if (i + j) / (2 * p) == (i + j + k) / (2 * p) {
+                        pairs.push((i + j, i + j + k));
+                    }
+                }
+                j += 2 * k;
+            }
+            k /= 2;
+        }
+        p *= 2;
+    }
+    pairs
+}
+
+/// Order two entries by (key, time).
+fn compare_swap(a: &Entry, b: &Entry) -> (Entry, Entry) {
+    let in_order = term![OR;
+        term![BV_ULT; a.key.clone(), b.key.clone()],
+        term![AND;
+            term![EQ; a.key.clone(), b.key.clone()],
+            term![BV_ULE; a.time.clone(), b.time.clone()]
+        ]
+    ];
+    let pick = |x: &Term, y: &Term| term![ITE; in_order.clone(), x.clone(), y.clone()];
+    let lo = Entry {
+        key: pick(&a.key, &b.key),
+        time: pick(&a.time, &b.time),
+        val: pick(&a.val, &b.val),
+        read: pick(&a.read, &b.read),
+        write: pick(&a.write, &b.write),
+    };
+    let hi = Entry {
+        key: pick(&b.key, &a.key),
+        time: pick(&b.time, &a.time),
+        val: pick(&b.val, &a.val),
+        read: pick(&b.read, &a.read),
+        write: pick(&b.write, &a.write),
+    };
+    (lo, hi)
+}
+
+/// Assertions that the reads in `trace` are consistent, for a memory whose default value is
+/// `default`.
+fn check_trace(mut trace: Vec<Entry>, default: &Term) -> Vec<Term> {
+    for (i, j) in batcher_pairs(trace.len()) {
+        let (lo, hi) = compare_swap(&trace[i], &trace[j]);
+        trace[i] = lo;
+        trace[j] = hi;
+    }
+    let mut assertions = Vec::new();
+    // (key, memory value at key) after the previous entry
+    let mut prev: Option<(Term, Term)> = None;
+    for e in trace {
+        let before = match prev {
+            Some((key, cur)) => {
+                term![ITE; term![EQ; key, e.key.clone()], cur, default.clone()]
+            }
+            None => default.clone(),
+        };
+        assertions.push(term![IMPLIES; e.read, term![EQ; e.val.clone(), before.clone()]]);
+        prev = Some((e.key, term![ITE; e.write, e.val, before]));
+    }
+    assertions
+}
+
+/// A memory: a chain of array versions.
+struct Memory {
+    /// The default value (of the constant array).
+    default: Term,
+    /// The writes that make each version after the root: (key, value, condition).
+    writes: Vec<(Term, Term, Option<Term>)>,
+    /// The reads of each version after the root.
+    reads: Vec<Vec<Term>>,
+}
+
+/// Maps array terms to the terms that use them, and the child index of that use.
+type Uses = TermMap<Vec<(Term, usize)>>;
+
+/// If `s` makes a new version of `v`, get the new version and the write.
+fn successor(uses: &Uses, v: &Term, s: &Term) -> Option<(Term, (Term, Term, Option<Term>))> {
+    let write = |cond: Option<Term>| (s.cs[1].clone(), s.cs[2].clone(), cond);
+    match uses.get(s).map(|u| u.as_slice()).unwrap_or(&[]) {
+        [(ite, i)] if ite.op == Op::Ite && *i != 0 && &ite.cs[3 - i] == v => {
+            let c = ite.cs[0].clone();
+            let cond = if *i == 1 { c } else { term![NOT; c] };
+            Some((ite.clone(), write(Some(cond))))
+        }
+        _ => Some((s.clone(), write(None))),
+    }
+}
+
+/// Follow the chain of versions starting with `first` (made by `first_write`) as far as it
+/// goes. Returns [None] if some version is used in an unsupported way.
+fn follow_chain(
+    uses: &Uses,
+    default: &Term,
+    first: Term,
+    first_write: (Term, Term, Option<Term>),
+) -> Option<Memory> {
+    let mut mem = Memory {
+        default: default.clone(),
+        writes: vec![first_write],
+        reads: Vec::new(),
+    };
+    let mut v = first;
+    loop {
+        let mut reads = Vec::new();
+        let mut next = None;
+        let mut stores = Vec::new();
+        let mut ites = Vec::new();
+        for (user, i) in uses.get(&v).map(|u| u.as_slice()).unwrap_or(&[]) {
+            match (&user.op, i) {
+                (Op::Select, 0) => reads.push(user.clone()),
+                (Op::Store, 0) => stores.push(user.clone()),
+                (Op::Ite, 1) | (Op::Ite, 2) => ites.push(user.clone()),
+                _ => return None,
+            }
+        }
+        mem.reads.push(reads);
+        match stores.as_slice() {
+            [] => {}
+            [s] => next = successor(uses, &v, s),
+            _ => return None,
+        }
+        // The only ite allowed is the one making a conditional write.
+        let n_expected_ites = match &next {
+            Some((n, (_, _, Some(_)))) => {
+                if ites.iter().any(|i| i != n) {
+                    return None;
+                }
+                1
+            }
+            _ => 0,
+        };
+        if ites.len() != n_expected_ites {
+            return None;
+        }
+        match next {
+            Some((n, w)) => {
+                mem.writes.push(w);
+                v = n;
+            }
+            None => return Some(mem),
+        }
+    }
+}
+
+/// Check memories made from constant arrays of size at least `size_thresh` using permutation-based
+/// memory checking. See the module documentation.
+///
+/// The reads of those memories are replaced with fresh variables, known to the prover, and
+/// assertions about those variables are added to the outputs. If `cs` has values, values for the
+/// new variables are added. Each memory is recorded in the metadata, as a [CheckedMemory].
+pub fn check_memory(cs: &mut Computation, size_thresh: usize) {
+    let mut uses: Uses = TermMap::new();
+    let mut roots = Vec::new();
+    let mut visited = TermSet::new();
+    for o in &cs.outputs {
+        for t in PostOrderIter::new(o.clone()) {
+            if !visited.insert(t.clone()) {
+                continue;
+            }
+            for (i, c) in t.cs.iter().enumerate() {
+                if let Sort::Array(..) = check(c) {
+                    uses.entry(c.clone())
+                        .or_insert_with(Vec::new)
+                        .push((t.clone(), i));
+                }
+            }
+            if let Op::ConstArray(Sort::BitVector(_), size) = &t.op {
+                let scalar = match check(&t.cs[0]) {
+                    Sort::Array(..) | Sort::Tuple(..) => false,
+                    _ => true,
+                };
+                if *size >= size_thresh && scalar {
+                    roots.push(t.clone());
+                }
+            }
+        }
+    }
+    // Maps each read to its replacement
+    let mut subs: TermMap<Term> = TermMap::new();
+    let mut memories = Vec::new();
+    for root in roots {
+        let default = root.cs[0].clone();
+        for (user, i) in uses.get(&root).cloned().unwrap_or_else(Vec::new) {
+            match (&user.op, i) {
+                (Op::Select, 0) => {
+                    subs.insert(user, default.clone());
+                }
+                (Op::Store, 0) => {
+                    if let Some((first, write)) = successor(&uses, &root, &user) {
+                        if let Some(mem) = follow_chain(&uses, &default, first, write) {
+                            memories.push(mem);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    if memories.is_empty() {
+        return;
+    }
+
+    let mut new_vars = Vec::new();
+    for mem in &memories {
+        for read in mem.reads.iter().flatten() {
+            let mut name = format!("__mem_read_{}", cs.metadata.inputs.len());
+            while cs.metadata.inputs.contains_key(&name) {
+                name.push('_');
+            }
+            cs.metadata.new_input(name.clone(), Some(PROVER_ID));
+            subs.insert(read.clone(), leaf_term(Op::Var(name.clone(), check(read))));
+            new_vars.push((name, read.clone()));
+        }
+    }
+    if let Some(values) = cs.values.as_mut() {
+        let reads = term(Op::Tuple, new_vars.iter().map(|(_, r)| r.clone()).collect());
+        let read_values = eval(&reads, values);
+        for ((name, _), v) in new_vars.iter().zip(read_values.as_tuple()) {
+            values.insert(name.clone(), v.clone());
+        }
+    }
+    debug!(
+        "Checking {} memories, with {} reads",
+        memories.len(),
+        new_vars.len()
+    );
+
+    for o in &mut cs.outputs {
+        *o = substitute_cache(o, &mut subs);
+    }
+    for mem in memories {
+        let time_width = Integer::from(2 * mem.writes.len() + 1).significant_bits() as usize;
+        let time = |t: usize| bv_lit(t, time_width);
+        let tru = leaf_term(Op::Const(Value::Bool(true)));
+        let fls = leaf_term(Op::Const(Value::Bool(false)));
+        let mut trace = Vec::new();
+        let default = substitute_cache(&mem.default, &mut subs);
+        let mut checked = CheckedMemory {
+            default: default.clone(),
+            writes: Vec::new(),
+            reads: Vec::new(),
+        };
+        // Version i (counting the root as 0) is written at time 2i, and read at time 2i+1.
+        for (i, (key, val, cond)) in mem.writes.iter().enumerate() {
+            let key = substitute_cache(key, &mut subs);
+            let val = substitute_cache(val, &mut subs);
+            let cond = cond.as_ref().map(|c| substitute_cache(c, &mut subs));
+            trace.push(Entry {
+                key: key.clone(),
+                time: time(2 * i + 2),
+                val: val.clone(),
+                read: fls.clone(),
+                write: cond.clone().unwrap_or_else(|| tru.clone()),
+            });
+            checked.writes.push((key, val, cond));
+        }
+        for (i, reads) in mem.reads.iter().enumerate() {
+            for r in reads {
+                let key = substitute_cache(&r.cs[1], &mut subs);
+                let var = subs.get(r).unwrap().clone();
+                trace.push(Entry {
+                    key: key.clone(),
+                    time: time(2 * i + 3),
+                    val: var.clone(),
+                    read: tru.clone(),
+                    write: fls.clone(),
+                });
+                if let Op::Var(name, _) = &var.op {
+                    checked.reads.push((name.clone(), i + 1, key));
+                }
+            }
+        }
+        cs.outputs.extend(check_trace(trace, &default));
+        cs.metadata.checked_memories.push(checked);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ir::proof::Constraints;
+    use fxhash::FxHashMap;
+    use quickcheck_macros::quickcheck;
+
+    fn bv3(u: u8) -> Value {
+        Value::BitVector(BitVector::new(Integer::from(u % 8), 3))
+    }
+
+    fn v_bv(n: &str, w: usize) -> Term {
+        leaf_term(Op::Var(n.to_owned(), Sort::BitVector(w)))
+    }
+
+    fn array_free(t: &Term) -> bool {
+        PostOrderIter::new(t.clone()).all(|c| match check(&c) {
+            Sort::Array(..) => false,
+            _ => true,
+        })
+    }
+
+    fn all_true(cs: &Computation, values: &FxHashMap<String, Value>) -> bool {
+        cs.outputs
+            .iter()
+            .all(|o| eval(o, values) == Value::Bool(true))
+    }
+
+    #[quickcheck]
+    fn batcher_sorts(mut xs: Vec<u8>) {
+        let mut expected = xs.clone();
+        expected.sort();
+        for (i, j) in batcher_pairs(xs.len()) {
+            if xs[i] > xs[j] {
+                xs.swap(i, j);
+            }
+        }
+        assert_eq!(xs, expected);
+    }
+
+    #[quickcheck]
+    fn semantics(i: u8, j: u8, x: u8, y: u8, c: bool) {
+        // values and indices are both 3 bits
+        let a0 = term![Op::ConstArray(Sort::BitVector(3), 8); bv_lit(5, 3)];
+        let a1 = term![Op::Store; a0.clone(), v_bv("i", 3), v_bv("x", 3)];
+        let a2 = term![ITE; v_bool("c"), term![Op::Store; a1.clone(), v_bv("j", 3), v_bv("y", 3)], a1.clone()];
+        let r0 = term![Op::Select; a0, v_bv("j", 3)];
+        let r1 = term![Op::Select; a1.clone(), v_bv("j", 3)];
+        let r2 = term![Op::Select; a2.clone(), v_bv("i", 3)];
+        let r3 = term![Op::Select; a2, term![Op::Select; a1, v_bv("i", 3)]];
+        let sum = term![BV_ADD; r0, r1, r2, r3];
+        let mut values: FxHashMap<String, Value> = vec![
+            ("i".to_owned(), bv3(i)),
+            ("j".to_owned(), bv3(j)),
+            ("x".to_owned(), bv3(x)),
+            ("y".to_owned(), bv3(y)),
+            ("c".to_owned(), Value::Bool(c)),
+        ]
+        .into_iter()
+        .collect();
+        values.insert("out".to_owned(), eval(&sum, &values));
+        let assertion = term![EQ; sum, v_bv("out", 3)];
+        let mut cs = Computation::from_constraint_system_parts(
+            vec![assertion],
+            vec!["out".to_owned()].into_iter().collect(),
+            Some(values),
+        );
+        check_memory(&mut cs, 8);
+        assert!(cs.outputs.iter().all(array_free));
+        let values = cs.values.clone().unwrap();
+        assert!(all_true(&cs, &values));
+        // Changing any read makes some assertion false
+        for name in values.keys().filter(|n| n.starts_with("__mem_read")) {
+            let mut bad_values = values.clone();
+            let old = bad_values.get(name).unwrap().as_bv().clone();
+            let new = old + BitVector::new(Integer::from(1), 3);
+            bad_values.insert(name.clone(), Value::BitVector(new));
+            assert!(!all_true(&cs, &bad_values), "{} can be changed", name);
+        }
+    }
+
+    fn v_bool(n: &str) -> Term {
+        leaf_term(Op::Var(n.to_owned(), Sort::Bool))
+    }
+
+    #[test]
+    fn below_threshold() {
+        let a0 = term![Op::ConstArray(Sort::BitVector(3), 8); bv_lit(5, 3)];
+        let a1 = term![Op::Store; a0, v_bv("i", 3), v_bv("x", 3)];
+        let t = term![EQ; term![Op::Select; a1, v_bv("j", 3)], v_bv("x", 3)];
+        let mut cs =
+            Computation::from_constraint_system_parts(vec![t.clone()], Default::default(), None);
+        check_memory(&mut cs, 9);
+        assert_eq!(cs.outputs, vec![t]);
+    }
+
+    #[test]
+    fn array_equality_unsupported() {
+        let a0 = term![Op::ConstArray(Sort::BitVector(3), 8); bv_lit(5, 3)];
+        let a1 = term![Op::Store; a0.clone(), v_bv("i", 3), v_bv("x", 3)];
+        let t = term![AND;
+            term![EQ; a1.clone(), a0],
+            term![EQ; term![Op::Select; a1, v_bv("j", 3)], v_bv("x", 3)]
+        ];
+        let mut cs =
+            Computation::from_constraint_system_parts(vec![t.clone()], Default::default(), None);
+        check_memory(&mut cs, 0);
+        assert_eq!(cs.outputs, vec![t]);
+    }
+}
@@ -3,11 +3,16 @@ pub mod cfold;
 pub mod flat;
 pub mod inline;
 pub mod mem;
+pub mod narrow;
+pub mod range;
 pub mod sha;
+pub mod to_field;
 pub mod tuple;
 
 use super::term::*;
 use log::debug;
+use rug::Integer;
+use std::sync::Arc;
 
 #[derive(Debug)]
 /// An optimization pass
@@ -20,36 +25,58 @@ pub enum Opt {
     Sha,
     /// Memory elimination
     Mem,
+    /// Memory elimination, using permutation-based memory checking for arrays of at least this
+    /// size
+    PermMem(usize),
+    /// Replace reads from constant arrays of at least this size with table lookups
+    Lookup(usize),
     /// Extract top-level ANDs as distinct outputs
     FlattenAssertions,
     /// Find outputs like `(= variable term)`, and substitute out `variable`
     Inline,
     /// Eliminate tuples
     Tuple,
+    /// Narrow bit-vector operations, using range analysis
+    Narrow,
+    /// Move non-wrapping bit-vector arithmetic into the field with this modulus
+    BvToField(Arc<Integer>),
 }
 
 /// Run optimizations on `cs`, in this order, returning the new constraint system.
+///
+/// Source locations of terms that an optimization rewrites move to their rewritten versions. The
+/// terms in the metadata (see [ComputationMetadata::terms_mut]) are rewritten with the outputs.
 pub fn opt<I: IntoIterator<Item = Opt>>(mut cs: Computation, optimizations: I) -> Computation {
     for i in optimizations {
         debug!("Applying: {:?}", i);
+        let track_locs = !cs.metadata.term_locs.is_empty();
+        let mut sources = if track_locs {
+            cs.outputs.clone()
+        } else {
+            Vec::new()
+        };
         match i {
             Opt::ConstantFold => {
                 let mut cache = TermMap::new();
-                for a in &mut cs.outputs {
-                    *a = cfold::fold_cache(a, &mut cache);
-                }
+                rewrite_all(&mut cs, |a| cfold::fold_cache(a, &mut cache));
             }
             Opt::Sha => {
-                for a in &mut cs.outputs {
-                    *a = sha::sha_rewrites(a);
-                }
+                rewrite_all(&mut cs, sha::sha_rewrites);
             }
             Opt::Mem => {
-                for a in &mut cs.outputs {
-                    *a = mem::array_elim(a);
-                }
+                rewrite_all(&mut cs, mem::array_elim);
+            }
+            Opt::PermMem(size_thresh) => {
+                rewrite_all(&mut cs, mem::obliv::elim_obliv);
+                mem::perm::check_memory(&mut cs, size_thresh);
+                rewrite_all(&mut cs, |a| mem::lin::linearize(a, usize::MAX));
+            }
+            Opt::Lookup(size_thresh) => {
+                rewrite_all(&mut cs, |a| mem::lookup::lookups(a, size_thresh));
             }
             Opt::FlattenAssertions => {
+                // The new outputs are children of the old ones, so keep their own locations.
+                sources.clear();
                 let mut new_outputs = Vec::new();
                 for a in std::mem::take(&mut cs.outputs) {
                     assert_eq!(check(&a), Sort::Bool, "Non-bool in {:?}", i);
@@ -63,17 +90,36 @@ pub fn opt<I: IntoIterator<Item = Opt>>(mut cs: Computation, optimizations: I) -
             }
             Opt::Flatten => {
                 let mut cache = flat::Cache::new();
-                for a in &mut cs.outputs {
-                    *a = flat::flatten_nary_ops_cached(a.clone(), &mut cache);
-                }
+                rewrite_all(&mut cs, |a| {
+                    flat::flatten_nary_ops_cached(a.clone(), &mut cache)
+                });
             }
             Opt::Inline => {
                 let public_inputs = cs.metadata.public_inputs().map(ToOwned::to_owned).collect();
-                inline::inline(&mut cs.outputs, &public_inputs);
+                let kept = inline::inline_with_sources(
+                    &mut cs.outputs,
+                    &public_inputs,
+                    cs.metadata.terms_mut(),
+                );
+                if track_locs {
+                    sources = kept;
+                }
             }
             Opt::Tuple => {
                 cs = tuple::eliminate_tuples(cs);
             }
+            Opt::Narrow => {
+                let mut narrower = narrow::Narrower::new();
+                rewrite_all(&mut cs, |a| narrower.narrow(a));
+            }
+            Opt::BvToField(ref m) => {
+                let mut rewriter = to_field::BvToField::new(m.clone(), TermSet::new());
+                rewrite_all(&mut cs, |a| rewriter.rewrite(a));
+            }
+        }
+        if track_locs {
+            carry_locs(&sources, &cs.outputs, &mut cs.metadata.term_locs);
+            prune_locs(&cs.outputs, &mut cs.metadata.term_locs);
         }
         debug!("After {:?}: {} outputs", i, cs.outputs.len());
         debug!("After {:?}: {} terms", i, cs.terms());
@@ -81,3 +127,48 @@ pub fn opt<I: IntoIterator<Item = Opt>>(mut cs: Computation, optimizations: I) -
     garbage_collect();
     cs
 }
+
+/// Rewrite the outputs of `cs` with `f`, and the terms in its metadata too.
+fn rewrite_all(cs: &mut Computation, mut f: impl FnMut(&Term) -> Term) {
+    for a in &mut cs.outputs {
+        *a = f(a);
+    }
+    for t in cs.metadata.terms_mut() {
+        *t = f(t);
+    }
+}
+
+/// Give the terms in `new` the locations of the terms in `old` that they were rewritten from.
+///
+/// Each new output is matched with the old output at the same position. Then, while a new term
+/// differs from its old one but has the same number of children, their children are matched
+/// too.
+fn carry_locs(old: &[Term], new: &[Term], locs: &mut TermMap<SourceLoc>) {
+    let mut visited = TermSet::new();
+    let mut stack: Vec<(Term, Term)> = old.iter().cloned().zip(new.iter().cloned()).collect();
+    while let Some((o, n)) = stack.pop() {
+        if o == n || !visited.insert(o.clone()) {
+            continue;
+        }
+        if !locs.contains_key(&n) {
+            if let Some(l) = locs.get(&o).cloned() {
+                locs.insert(n.clone(), l);
+            }
+        }
+        if o.cs.len() == n.cs.len() {
+            stack.extend(o.cs.iter().cloned().zip(n.cs.iter().cloned()));
+        }
+    }
+}
+
+/// Forget the locations of terms that are no longer part of `outputs`, so that they can be
+/// collected.
+fn prune_locs(outputs: &[Term], locs: &mut TermMap<SourceLoc>) {
+    let mut live = TermSet::new();
+    for o in outputs {
+        for t in PostOrderIter::new(o.clone()) {
+            live.insert(t);
+        }
+    }
+    locs.retain(|t, _| live.contains(t));
+}
@@ -0,0 +1,273 @@
+//! Bit-width narrowing
+//!
+//! Uses [super::range] to find bit-vector operations whose values provably fit in fewer bits than
+//! their sort has, and performs them at the smaller width.
+//!
+//! Rewrites:
+//!
+//! * A bit-vector term with only one possible value becomes a constant.
+//! * Additions, multiplications, subtractions, negations, bit-wise operations, and `ite`s whose
+//!   (unsigned) value fits in `k` bits are done at width `k`, and then zero-extended. This is
+//!   sound because the low `k` bits of these operations depend only on the low `k` bits of their
+//!   arguments.
+//! * Equalities and unsigned comparisons whose arguments fit in `k` bits are done at width `k`.
+//!   Signed comparisons whose arguments fit in `k` signed bits are likewise narrowed.
+//! * Nested zero-extensions are merged, and sign-extensions of non-negative values become
+//!   zero-extensions.
+
+use super::range::RangeAnalysis;
+use crate::ir::term::*;
+
+/// Zero-extend `t` by `n` bits, merging nested extensions.
+fn uext(t: Term, n: usize) -> Term {
+    if n == 0 {
+        t
+    } else if let Op::BvUext(m) = &t.op {
+        term![Op::BvUext(n + m); t.cs[0].clone()]
+    } else {
+        term![Op::BvUext(n); t]
+    }
+}
+
+/// Get the low `k` bits of `t`, avoiding extractions where possible.
+fn low_bits(t: Term, k: usize) -> Term {
+    let w = check(&t).as_bv();
+    assert!(k <= w);
+    if k == w {
+        return t;
+    }
+    match &t.op {
+        Op::Const(Value::BitVector(b)) => {
+            leaf_term(Op::Const(Value::BitVector(b.clone().extract(k - 1, 0))))
+        }
+        Op::BvUext(n) => {
+            let inner = t.cs[0].clone();
+            let inner_w = w - n;
+            if inner_w <= k {
+                uext(inner, k - inner_w)
+            } else {
+                low_bits(inner, k)
+            }
+        }
+        _ => term![Op::BvExtract(k - 1, 0); t],
+    }
+}
+
+/// Apply the predicate of `t` to the low `k` bits of `cs`, if `k` is less than their width,
+/// `w`.
+fn narrow_pred(t: &Term, cs: Vec<Term>, w: usize, k: usize) -> Term {
+    if k < w {
+        term(
+            t.op.clone(),
+            cs.into_iter().map(|c| low_bits(c, k)).collect(),
+        )
+    } else {
+        term(t.op.clone(), cs)
+    }
+}
+
+/// Narrows bit-vector terms, caching results.
+pub struct Narrower {
+    ranges: RangeAnalysis,
+    cache: TermMap<Term>,
+}
+
+impl Narrower {
+    /// Create an empty narrower.
+    pub fn new() -> Self {
+        Self {
+            ranges: RangeAnalysis::new(),
+            cache: TermMap::new(),
+        }
+    }
+
+    /// Narrow the bit-vector operations in `node`.
+    pub fn narrow(&mut self, node: &Term) -> Term {
+        // (node, children pushed)
+        let mut stack = vec![(node.clone(), false)];
+        while let Some((t, children_pushed)) = stack.pop() {
+            if self.cache.contains_key(&t) {
+                continue;
+            }
+            if !children_pushed {
+                stack.push((t.clone(), true));
+                stack.extend(t.cs.iter().map(|c| (c.clone(), false)));
+                continue;
+            }
+            let new_t = self.rewrite(&t);
+            self.cache.insert(t, new_t);
+        }
+        self.cache.get(node).expect("postorder cache").clone()
+    }
+
+    /// The fewest bits that holds both `a` and `b` (unsigned).
+    fn unsigned_bits(&mut self, a: &Term, b: &Term) -> usize {
+        std::cmp::max(
+            self.ranges.range(a).unsigned_bits(),
+            self.ranges.range(b).unsigned_bits(),
+        )
+    }
+
+    /// The fewest bits that holds both `a` and `b` (signed).
+    fn signed_bits(&mut self, a: &Term, b: &Term) -> usize {
+        std::cmp::max(
+            self.ranges.range(a).signed_bits(),
+            self.ranges.range(b).signed_bits(),
+        )
+    }
+
+    /// Rewrite `t`, whose children have already been rewritten.
+    fn rewrite(&mut self, t: &Term) -> Term {
+        let cs: Vec<Term> =
+            t.cs.iter()
+                .map(|c| self.cache.get(c).expect("postorder cache").clone())
+                .collect();
+        match check(t) {
+            Sort::BitVector(w) => {
+                let r = self.ranges.range(t);
+                let k = r.unsigned_bits();
+                if let Some(b) = r.as_const() {
+                    return leaf_term(Op::Const(Value::BitVector(b)));
+                }
+                match &t.op {
+                    Op::BvNaryOp(_) | Op::BvBinOp(BvBinOp::Sub) | Op::BvUnOp(_) | Op::Ite
+                        if k < w =>
+                    {
+                        let narrow_cs = cs
+                            .into_iter()
+                            .map(|c| match check(&c) {
+                                Sort::BitVector(_) => low_bits(c, k),
+                                _ => c,
+                            })
+                            .collect();
+                        uext(term(t.op.clone(), narrow_cs), w - k)
+                    }
+                    Op::BvUext(n) => {
+                        let inner_w = w - n;
+                        let k = std::cmp::min(k, inner_w);
+                        uext(low_bits(cs[0].clone(), k), w - k)
+                    }
+                    Op::BvSext(n) if self.ranges.range(&t.cs[0]).slo >= 0 => {
+                        uext(cs[0].clone(), *n)
+                    }
+                    _ => term(t.op.clone(), cs),
+                }
+            }
+            Sort::Bool => match &t.op {
+                Op::Eq => match check(&t.cs[0]) {
+                    Sort::BitVector(w) => {
+                        let k = std::cmp::min(
+                            self.unsigned_bits(&t.cs[0], &t.cs[1]),
+                            self.signed_bits(&t.cs[0], &t.cs[1]),
+                        );
+                        narrow_pred(t, cs, w, k)
+                    }
+                    _ => term(t.op.clone(), cs),
+                },
+                Op::BvBinPred(p) => {
+                    let w = check(&t.cs[0]).as_bv();
+                    let k = match p {
+                        BvBinPred::Ult | BvBinPred::Ugt | BvBinPred::Ule | BvBinPred::Uge => {
+                            self.unsigned_bits(&t.cs[0], &t.cs[1])
+                        }
+                        BvBinPred::Slt | BvBinPred::Sgt | BvBinPred::Sle | BvBinPred::Sge => {
+                            self.signed_bits(&t.cs[0], &t.cs[1])
+                        }
+                    };
+                    narrow_pred(t, cs, w, k)
+                }
+                _ => term(t.op.clone(), cs),
+            },
+            _ => term(t.op.clone(), cs),
+        }
+    }
+}
+
+/// Narrow the bit-vector operations in `t`.
+pub fn narrow(t: &Term) -> Term {
+    Narrower::new().narrow(t)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ir::term::dist::test::*;
+    use fxhash::FxHashMap;
+    use quickcheck_macros::quickcheck;
+    use rug::Integer;
+
+    fn v_bv(n: &str, w: usize) -> Term {
+        leaf_term(Op::Var(n.to_owned(), Sort::BitVector(w)))
+    }
+
+    fn max_op_width(t: &Term) -> usize {
+        PostOrderIter::new(t.clone())
+            .filter(|c| match &c.op {
+                Op::BvNaryOp(_) | Op::BvBinPred(_) => true,
+                _ => false,
+            })
+            .map(|c| check(&c.cs[0]).as_bv())
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[quickcheck]
+    fn semantics_random(ArbitraryTermEnv(t, vs): ArbitraryTermEnv) {
+        let tt = narrow(&t);
+        let orig = eval(&t, &vs);
+        let new = eval(&tt, &vs);
+        assert!(orig == new, "{} ({}) vs {} ({})", t, orig, tt, new);
+    }
+
+    #[quickcheck]
+    fn semantics_small_operands(x: u8, y: u8, z: u8) {
+        let xx = term![Op::BvUext(24); v_bv("x", 8)];
+        let yy = term![Op::BvUext(24); v_bv("y", 8)];
+        let zz = term![Op::BvSext(24); v_bv("z", 8)];
+        let t = term![AND;
+            term![BV_ULT; term![BV_ADD; xx.clone(), yy.clone()], term![BV_MUL; yy.clone(), bv_lit(3, 32)]],
+            term![BV_SLE; term![BV_SUB; zz.clone(), xx.clone()], term![BV_XOR; yy.clone(), xx.clone()]],
+            term![EQ; term![BV_UREM; xx, bv_lit(7, 32)], term![BV_AND; zz, yy]]
+        ];
+        let vs: FxHashMap<String, Value> = vec![("x", x), ("y", y), ("z", z)]
+            .into_iter()
+            .map(|(n, v)| {
+                (
+                    n.to_owned(),
+                    Value::BitVector(BitVector::new(Integer::from(v), 8)),
+                )
+            })
+            .collect();
+        let tt = narrow(&t);
+        assert_eq!(eval(&t, &vs), eval(&tt, &vs), "{} vs {}", t, tt);
+        assert!(max_op_width(&tt) < 32, "{}", tt);
+    }
+
+    #[test]
+    fn add() {
+        let t =
+            term![BV_ADD; term![Op::BvUext(24); v_bv("a", 8)], term![Op::BvUext(24); v_bv("b", 8)]];
+        assert_eq!(
+            narrow(&t),
+            term![Op::BvUext(23); term![BV_ADD; term![Op::BvUext(1); v_bv("a", 8)], term![Op::BvUext(1); v_bv("b", 8)]]]
+        );
+    }
+
+    #[test]
+    fn ult() {
+        let t = term![BV_ULT; term![Op::BvUext(24); v_bv("a", 8)], bv_lit(100, 32)];
+        assert_eq!(narrow(&t), term![BV_ULT; v_bv("a", 8), bv_lit(100, 8)]);
+    }
+
+    #[test]
+    fn sext_nonneg() {
+        let t = term![Op::BvSext(16); term![Op::BvUext(8); v_bv("a", 8)]];
+        assert_eq!(narrow(&t), term![Op::BvUext(24); v_bv("a", 8)]);
+    }
+
+    #[test]
+    fn constant_range() {
+        let t = term![BV_AND; v_bv("a", 8), bv_lit(0b0011, 8), bv_lit(0b1100, 8)];
+        assert_eq!(narrow(&t), bv_lit(0, 8));
+    }
+}
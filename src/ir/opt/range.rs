@@ -0,0 +1,623 @@
+//! Range analysis for bit-vector terms
+//!
+//! An abstract interpretation that bounds the value of each bit-vector term by an unsigned
+//! interval, a two's complement signed interval, and a set of known bits. Every transfer function
+//! computes exact integer bounds and falls back to the full range if the operation might wrap.
+
+use crate::ir::term::*;
+use rug::Integer;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// Facts about the value of a bit-vector term.
+///
+/// If `x` is the value of the term, then:
+///
+/// * `ulo <= x <= uhi`, reading `x` as an unsigned integer,
+/// * `slo <= x <= shi`, reading `x` as a two's complement integer, and
+/// * `x & known_mask == known_bits`.
+pub struct BvRange {
+    /// The bit-width
+    pub width: usize,
+    /// Unsigned lower bound
+    pub ulo: Integer,
+    /// Unsigned upper bound
+    pub uhi: Integer,
+    /// Signed lower bound
+    pub slo: Integer,
+    /// Signed upper bound
+    pub shi: Integer,
+    /// Mask of the bits whose value is known
+    pub known_mask: Integer,
+    /// Values of the known bits (zero outside of `known_mask`)
+    pub known_bits: Integer,
+}
+
+fn pow2(n: usize) -> Integer {
+    Integer::from(1) << n as u32
+}
+
+fn umax(w: usize) -> Integer {
+    pow2(w) - 1
+}
+
+fn smin(w: usize) -> Integer {
+    -pow2(w - 1)
+}
+
+fn smax(w: usize) -> Integer {
+    pow2(w - 1) - 1
+}
+
+/// The number of bits needed to represent `i` in two's complement.
+fn signed_bits(i: &Integer) -> usize {
+    if *i >= 0 {
+        i.significant_bits() as usize + 1
+    } else {
+        (-i.clone() - 1u32).significant_bits() as usize + 1
+    }
+}
+
+fn min(a: Integer, b: Integer) -> Integer {
+    if a < b {
+        a
+    } else {
+        b
+    }
+}
+
+fn max(a: Integer, b: Integer) -> Integer {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+impl BvRange {
+    /// No information about a `w`-bit value.
+    pub fn full(w: usize) -> Self {
+        BvRange {
+            width: w,
+            ulo: Integer::from(0),
+            uhi: umax(w),
+            slo: smin(w),
+            shi: smax(w),
+            known_mask: Integer::from(0),
+            known_bits: Integer::from(0),
+        }
+    }
+
+    /// Exactly the value `b`.
+    pub fn constant(b: &BitVector) -> Self {
+        BvRange {
+            width: b.width(),
+            ulo: b.uint().clone(),
+            uhi: b.uint().clone(),
+            slo: b.as_sint(),
+            shi: b.as_sint(),
+            known_mask: umax(b.width()),
+            known_bits: b.uint().clone(),
+        }
+    }
+
+    /// A `w`-bit value in `[lo, hi]` (unsigned). If these bounds are not representable in `w`
+    /// bits, then we know nothing.
+    pub fn from_unsigned(w: usize, lo: Integer, hi: Integer) -> Self {
+        if lo < 0 || hi > umax(w) {
+            Self::full(w)
+        } else {
+            BvRange {
+                ulo: lo,
+                uhi: hi,
+                ..Self::full(w)
+            }
+            .normalize()
+        }
+    }
+
+    /// A `w`-bit value in `[lo, hi]` (signed). If these bounds are not representable in `w`
+    /// bits, then we know nothing.
+    pub fn from_signed(w: usize, lo: Integer, hi: Integer) -> Self {
+        if lo < smin(w) || hi > smax(w) {
+            Self::full(w)
+        } else {
+            BvRange {
+                slo: lo,
+                shi: hi,
+                ..Self::full(w)
+            }
+            .normalize()
+        }
+    }
+
+    /// A `w`-bit value whose bits in `mask` are `bits`.
+    pub fn from_known(w: usize, mask: Integer, bits: Integer) -> Self {
+        let mask = mask & umax(w);
+        let bits = bits & &mask;
+        BvRange {
+            known_mask: mask,
+            known_bits: bits,
+            ..Self::full(w)
+        }
+        .normalize()
+    }
+
+    /// Combine the facts from two sound ranges for the same value.
+    pub fn meet(self, other: Self) -> Self {
+        assert_eq!(self.width, other.width);
+        BvRange {
+            width: self.width,
+            ulo: max(self.ulo, other.ulo),
+            uhi: min(self.uhi, other.uhi),
+            slo: max(self.slo, other.slo),
+            shi: min(self.shi, other.shi),
+            known_mask: self.known_mask | other.known_mask,
+            known_bits: self.known_bits | other.known_bits,
+        }
+        .normalize()
+    }
+
+    /// A range containing the values of both ranges.
+    pub fn join(self, other: Self) -> Self {
+        assert_eq!(self.width, other.width);
+        let agree = !(self.known_bits.clone() ^ &other.known_bits);
+        let known_mask = self.known_mask & other.known_mask & agree & umax(self.width);
+        BvRange {
+            width: self.width,
+            ulo: min(self.ulo, other.ulo),
+            uhi: max(self.uhi, other.uhi),
+            slo: min(self.slo, other.slo),
+            shi: max(self.shi, other.shi),
+            known_bits: self.known_bits & &known_mask,
+            known_mask,
+        }
+        .normalize()
+    }
+
+    /// Propagate facts between the intervals and the known bits.
+    fn normalize(mut self) -> Self {
+        let w = self.width;
+        // known bits bound the unsigned value
+        let klo = self.known_bits.clone();
+        let khi = self.known_bits.clone() | (umax(w) ^ self.known_mask.clone());
+        self.ulo = max(self.ulo, klo);
+        self.uhi = min(self.uhi, khi);
+        // unsigned to signed
+        let (slo, shi) = if self.uhi <= smax(w) {
+            (self.ulo.clone(), self.uhi.clone())
+        } else if self.ulo > smax(w) {
+            (self.ulo.clone() - pow2(w), self.uhi.clone() - pow2(w))
+        } else {
+            (smin(w), smax(w))
+        };
+        self.slo = max(self.slo, slo);
+        self.shi = min(self.shi, shi);
+        // signed to unsigned
+        let (ulo, uhi) = if self.slo >= 0 {
+            (self.slo.clone(), self.shi.clone())
+        } else if self.shi < 0 {
+            (self.slo.clone() + pow2(w), self.shi.clone() + pow2(w))
+        } else {
+            (Integer::from(0), umax(w))
+        };
+        self.ulo = max(self.ulo, ulo);
+        self.uhi = min(self.uhi, uhi);
+        debug_assert!(self.ulo <= self.uhi, "Empty range: {:?}", self);
+        // the common prefix of the unsigned bounds is known
+        let diff = Integer::from(&self.ulo ^ &self.uhi);
+        let prefix_mask = umax(w) ^ (pow2(diff.significant_bits() as usize) - 1);
+        self.known_bits |= self.ulo.clone() & &prefix_mask;
+        self.known_mask |= prefix_mask;
+        self
+    }
+
+    /// If this range contains just one value, get it.
+    pub fn as_const(&self) -> Option<BitVector> {
+        if self.ulo == self.uhi {
+            Some(BitVector::new(self.ulo.clone(), self.width))
+        } else {
+            None
+        }
+    }
+
+    /// The fewest bits that can represent every value in the range as an unsigned integer.
+    pub fn unsigned_bits(&self) -> usize {
+        std::cmp::max(1, self.uhi.significant_bits() as usize)
+    }
+
+    /// The fewest bits that can represent every value in the range as a two's complement
+    /// integer.
+    pub fn signed_bits(&self) -> usize {
+        std::cmp::max(signed_bits(&self.slo), signed_bits(&self.shi))
+    }
+
+    /// Does this range contain `b`?
+    pub fn contains(&self, b: &BitVector) -> bool {
+        b.width() == self.width
+            && &self.ulo <= b.uint()
+            && b.uint() <= &self.uhi
+            && self.slo <= b.as_sint()
+            && b.as_sint() <= self.shi
+            && Integer::from(b.uint() & &self.known_mask) == self.known_bits
+    }
+}
+
+/// The (signed) product of two signed intervals.
+fn mul_signed(a: (Integer, Integer), b: (&Integer, &Integer)) -> (Integer, Integer) {
+    let ps = [
+        a.0.clone() * b.0,
+        a.0.clone() * b.1,
+        a.1.clone() * b.0,
+        a.1 * b.1,
+    ];
+    (
+        ps.iter().min().unwrap().clone(),
+        ps.iter().max().unwrap().clone(),
+    )
+}
+
+/// A cache of [BvRange]s for bit-vector terms.
+pub struct RangeAnalysis {
+    ranges: TermMap<Option<BvRange>>,
+}
+
+impl RangeAnalysis {
+    /// Create an empty analysis.
+    pub fn new() -> Self {
+        Self {
+            ranges: TermMap::new(),
+        }
+    }
+
+    /// Get the range of bit-vector term `t`.
+    ///
+    /// Panics if `t` is not a bit-vector.
+    pub fn range(&mut self, t: &Term) -> &BvRange {
+        // (node, children pushed)
+        let mut stack = vec![(t.clone(), false)];
+        while let Some((t, children_pushed)) = stack.pop() {
+            if self.ranges.contains_key(&t) {
+                continue;
+            }
+            if !children_pushed {
+                stack.push((t.clone(), true));
+                stack.extend(t.cs.iter().map(|c| (c.clone(), false)));
+                continue;
+            }
+            let r = match check(&t) {
+                Sort::BitVector(w) => Some(self.transfer(&t, w)),
+                _ => None,
+            };
+            self.ranges.insert(t, r);
+        }
+        self.ranges
+            .get(t)
+            .unwrap()
+            .as_ref()
+            .unwrap_or_else(|| panic!("{} is not a bit-vector", t))
+    }
+
+    /// Compute the range of `t`, given the ranges of its children.
+    fn transfer(&self, t: &Term, w: usize) -> BvRange {
+        let get = |i: usize| {
+            self.ranges
+                .get(&t.cs[i])
+                .expect("postorder cache")
+                .as_ref()
+                .expect("bit-vector child")
+        };
+        let all = || (0..t.cs.len()).map(get);
+        match &t.op {
+            Op::Const(Value::BitVector(b)) => BvRange::constant(b),
+            Op::BvNaryOp(o) => {
+                match o {
+                    BvNaryOp::Add => {
+                        let u = BvRange::from_unsigned(
+                            w,
+                            all().map(|r| &r.ulo).sum(),
+                            all().map(|r| &r.uhi).sum(),
+                        );
+                        let s = BvRange::from_signed(
+                            w,
+                            all().map(|r| &r.slo).sum(),
+                            all().map(|r| &r.shi).sum(),
+                        );
+                        u.meet(s)
+                    }
+                    BvNaryOp::Mul => {
+                        let u = BvRange::from_unsigned(
+                            w,
+                            all().map(|r| &r.ulo).product(),
+                            all().map(|r| &r.uhi).product(),
+                        );
+                        let (slo, shi) = all()
+                            .skip(1)
+                            .fold((get(0).slo.clone(), get(0).shi.clone()), |acc, r| {
+                                mul_signed(acc, (&r.slo, &r.shi))
+                            });
+                        u.meet(BvRange::from_signed(w, slo, shi))
+                    }
+                    BvNaryOp::And => {
+                        let ones = all().fold(umax(w), |acc, r| acc & &r.known_bits);
+                        let zeros = all().fold(Integer::from(0), |acc, r| {
+                            acc | (r.known_mask.clone() ^ &r.known_bits)
+                        });
+                        let uhi = all().map(|r| r.uhi.clone()).min().unwrap();
+                        BvRange::from_known(w, ones.clone() | zeros, ones)
+                            .meet(BvRange::from_unsigned(w, Integer::from(0), uhi))
+                    }
+                    BvNaryOp::Or => {
+                        let ones = all().fold(Integer::from(0), |acc, r| acc | &r.known_bits);
+                        let zeros = all().fold(umax(w), |acc, r| {
+                            acc & (r.known_mask.clone() ^ &r.known_bits)
+                        });
+                        let ulo = all().map(|r| r.ulo.clone()).max().unwrap();
+                        let bits = all().map(|r| r.unsigned_bits()).max().unwrap();
+                        BvRange::from_known(w, ones.clone() | zeros, ones)
+                            .meet(BvRange::from_unsigned(w, ulo, umax(bits)))
+                    }
+                    BvNaryOp::Xor => {
+                        let mask = all().fold(umax(w), |acc, r| acc & &r.known_mask);
+                        let bits = all().fold(Integer::from(0), |acc, r| acc ^ &r.known_bits);
+                        let n_bits = all().map(|r| r.unsigned_bits()).max().unwrap();
+                        BvRange::from_known(w, mask.clone(), bits & mask)
+                            .meet(BvRange::from_unsigned(w, Integer::from(0), umax(n_bits)))
+                    }
+                }
+            }
+            Op::BvBinOp(o) => {
+                let a = get(0);
+                let b = get(1);
+                let const_shift = b.as_const().map(|s| s.uint().to_usize().unwrap_or(w));
+                match o {
+                    BvBinOp::Sub => {
+                        let lo = a.ulo.clone() - &b.uhi;
+                        let u = if lo >= 0 {
+                            BvRange::from_unsigned(w, lo, a.uhi.clone() - &b.ulo)
+                        } else {
+                            BvRange::full(w)
+                        };
+                        let s =
+                            BvRange::from_signed(w, a.slo.clone() - &b.shi, a.shi.clone() - &b.slo);
+                        u.meet(s)
+                    }
+                    BvBinOp::Udiv => {
+                        if b.ulo > 0 {
+                            BvRange::from_unsigned(
+                                w,
+                                a.ulo.clone() / &b.uhi,
+                                a.uhi.clone() / &b.ulo,
+                            )
+                        } else if b.uhi > 0 {
+                            // division by zero gives all ones
+                            BvRange::from_unsigned(w, a.ulo.clone() / &b.uhi, umax(w))
+                        } else {
+                            BvRange::constant(&BitVector::ones(w))
+                        }
+                    }
+                    BvBinOp::Urem => {
+                        if b.uhi == 0 || a.uhi < b.ulo {
+                            // division by zero gives the dividend
+                            a.clone()
+                        } else if b.ulo > 0 {
+                            BvRange::from_unsigned(
+                                w,
+                                Integer::from(0),
+                                min(a.uhi.clone(), b.uhi.clone() - 1),
+                            )
+                        } else {
+                            BvRange::from_unsigned(w, Integer::from(0), a.uhi.clone())
+                        }
+                    }
+                    BvBinOp::Shl => match const_shift {
+                        Some(s) if s >= w => BvRange::constant(&BitVector::zeros(w)),
+                        Some(s) => BvRange::from_unsigned(
+                            w,
+                            a.ulo.clone() << s as u32,
+                            a.uhi.clone() << s as u32,
+                        )
+                        .meet(BvRange::from_known(
+                            w,
+                            (a.known_mask.clone() << s as u32) | (pow2(s) - 1),
+                            a.known_bits.clone() << s as u32,
+                        )),
+                        None => BvRange::full(w),
+                    },
+                    BvBinOp::Lshr => match const_shift {
+                        Some(s) if s >= w => BvRange::constant(&BitVector::zeros(w)),
+                        Some(s) => BvRange::from_unsigned(
+                            w,
+                            a.ulo.clone() >> s as u32,
+                            a.uhi.clone() >> s as u32,
+                        )
+                        .meet(BvRange::from_known(
+                            w,
+                            (a.known_mask.clone() >> s as u32) | (umax(w) ^ umax(w - s)),
+                            a.known_bits.clone() >> s as u32,
+                        )),
+                        None => BvRange::from_unsigned(w, Integer::from(0), a.uhi.clone()),
+                    },
+                    BvBinOp::Ashr => match const_shift {
+                        Some(s) => {
+                            let s = std::cmp::min(s, w - 1) as u32;
+                            BvRange::from_signed(w, a.slo.clone() >> s, a.shi.clone() >> s)
+                        }
+                        None => BvRange::full(w),
+                    },
+                }
+            }
+            Op::BvUnOp(BvUnOp::Not) => {
+                let a = get(0);
+                BvRange::from_unsigned(w, umax(w) - &a.uhi, umax(w) - &a.ulo)
+                    .meet(BvRange::from_signed(
+                        w,
+                        -a.shi.clone() - 1u32,
+                        -a.slo.clone() - 1u32,
+                    ))
+                    .meet(BvRange::from_known(
+                        w,
+                        a.known_mask.clone(),
+                        !a.known_bits.clone(),
+                    ))
+            }
+            Op::BvUnOp(BvUnOp::Neg) => {
+                let a = get(0);
+                let u = if a.uhi == 0 {
+                    BvRange::constant(&BitVector::zeros(w))
+                } else if a.ulo > 0 {
+                    BvRange::from_unsigned(w, pow2(w) - &a.uhi, pow2(w) - &a.ulo)
+                } else {
+                    BvRange::full(w)
+                };
+                let s = if a.slo > smin(w) {
+                    BvRange::from_signed(w, -a.shi.clone(), -a.slo.clone())
+                } else {
+                    BvRange::full(w)
+                };
+                u.meet(s)
+            }
+            Op::BvUext(n) => {
+                let a = get(0);
+                let high = umax(w) ^ umax(w - n);
+                BvRange::from_unsigned(w, a.ulo.clone(), a.uhi.clone()).meet(BvRange::from_known(
+                    w,
+                    a.known_mask.clone() | high,
+                    a.known_bits.clone(),
+                ))
+            }
+            Op::BvSext(_) => {
+                let a = get(0);
+                BvRange::from_signed(w, a.slo.clone(), a.shi.clone()).meet(BvRange::from_known(
+                    w,
+                    a.known_mask.clone(),
+                    a.known_bits.clone(),
+                ))
+            }
+            Op::BvExtract(h, l) => {
+                let a = get(0);
+                let u = if a.uhi < pow2(h + 1) {
+                    BvRange::from_unsigned(
+                        w,
+                        a.ulo.clone() >> *l as u32,
+                        a.uhi.clone() >> *l as u32,
+                    )
+                } else {
+                    BvRange::full(w)
+                };
+                u.meet(BvRange::from_known(
+                    w,
+                    a.known_mask.clone() >> *l as u32,
+                    a.known_bits.clone() >> *l as u32,
+                ))
+            }
+            Op::BvConcat => {
+                let mut ulo = Integer::from(0);
+                let mut uhi = Integer::from(0);
+                let mut mask = Integer::from(0);
+                let mut bits = Integer::from(0);
+                for r in all() {
+                    let shift = r.width as u32;
+                    ulo = (ulo << shift) + &r.ulo;
+                    uhi = (uhi << shift) + &r.uhi;
+                    mask = (mask << shift) | &r.known_mask;
+                    bits = (bits << shift) | &r.known_bits;
+                }
+                BvRange::from_unsigned(w, ulo, uhi).meet(BvRange::from_known(w, mask, bits))
+            }
+            Op::Ite => get(1).clone().join(get(2).clone()),
+            _ => BvRange::full(w),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ir::term::dist::test::*;
+    use quickcheck_macros::quickcheck;
+
+    fn v_bv(n: &str, w: usize) -> Term {
+        leaf_term(Op::Var(n.to_owned(), Sort::BitVector(w)))
+    }
+
+    #[quickcheck]
+    fn sound_random(ArbitraryTermEnv(t, vs): ArbitraryTermEnv) {
+        let mut a = RangeAnalysis::new();
+        for s in PostOrderIter::new(t) {
+            if let Sort::BitVector(_) = check(&s) {
+                let v = eval(&s, &vs);
+                let r = a.range(&s);
+                assert!(r.contains(v.as_bv()), "{} = {} not in {:?}", s, v, r);
+            }
+        }
+    }
+
+    #[quickcheck]
+    fn sound_small_operands(x: u8, y: u8, z: u8) {
+        let xx = term![Op::BvUext(8); v_bv("x", 8)];
+        let yy = term![Op::BvUext(12); term![Op::BvExtract(3, 0); v_bv("y", 8)]];
+        let zz = term![Op::BvSext(8); v_bv("z", 8)];
+        let ts = vec![
+            term![BV_ADD; xx.clone(), yy.clone()],
+            term![BV_MUL; xx.clone(), yy.clone()],
+            term![BV_SUB; xx.clone(), yy.clone()],
+            term![BV_ADD; xx.clone(), zz.clone()],
+            term![BV_MUL; zz.clone(), yy.clone()],
+            term![BV_UDIV; xx.clone(), yy.clone()],
+            term![BV_UREM; xx.clone(), yy.clone()],
+            term![BV_OR; xx.clone(), yy.clone()],
+            term![BV_LSHR; zz.clone(), bv_lit(3, 16)],
+            term![BV_ASHR; zz.clone(), bv_lit(3, 16)],
+            term![BV_SHL; yy.clone(), bv_lit(5, 16)],
+            term![Op::BvUnOp(BvUnOp::Neg); yy.clone()],
+            term![Op::BvUnOp(BvUnOp::Not); zz],
+            term![BV_CONCAT; xx, yy],
+        ];
+        let vs = vec![("x", x), ("y", y), ("z", z)]
+            .into_iter()
+            .map(|(n, v)| {
+                (
+                    n.to_owned(),
+                    Value::BitVector(BitVector::new(Integer::from(v), 8)),
+                )
+            })
+            .collect();
+        let mut a = RangeAnalysis::new();
+        for t in ts {
+            for s in PostOrderIter::new(t) {
+                let v = eval(&s, &vs);
+                let r = a.range(&s);
+                assert!(r.contains(v.as_bv()), "{} = {} not in {:?}", s, v, r);
+            }
+        }
+    }
+
+    #[test]
+    fn uext_add() {
+        let t = term![BV_ADD; term![Op::BvUext(8); v_bv("x", 8)], bv_lit(10, 16)];
+        let r = RangeAnalysis::new().range(&t).clone();
+        assert_eq!(r.ulo, 10);
+        assert_eq!(r.uhi, 265);
+        assert_eq!(r.unsigned_bits(), 9);
+        assert_eq!(r.signed_bits(), 10);
+    }
+
+    #[test]
+    fn sext_sub() {
+        let t = term![BV_SUB; term![Op::BvSext(8); v_bv("x", 8)], bv_lit(1, 16)];
+        let r = RangeAnalysis::new().range(&t).clone();
+        assert_eq!(r.slo, -129);
+        assert_eq!(r.shi, 126);
+        assert_eq!(r.signed_bits(), 9);
+        assert_eq!(r.unsigned_bits(), 16);
+    }
+
+    #[test]
+    fn and_known_bits() {
+        let t = term![BV_AND; v_bv("x", 8), bv_lit(0b1010, 8)];
+        let r = RangeAnalysis::new().range(&t).clone();
+        assert_eq!(r.known_mask, 0b11110101);
+        assert_eq!(r.known_bits, 0);
+        assert_eq!(r.uhi, 0b1010);
+    }
+}
@@ -0,0 +1,247 @@
+//! Lowering of non-wrapping bit-vector arithmetic into a prime field
+//!
+//! A bit-vector addition, subtraction, or multiplication that does not wrap computes the same
+//! integer as the corresponding field operation, so long as that integer is smaller than the
+//! field's modulus. This pass rewrites such arithmetic into `Sort::Field` terms. Bit-vectors
+//! enter the field through `UbvToPf`, and results leave it through `PfToBv` wherever they are
+//! used as bit-vectors again. Equalities between such results are decided in the field.
+//!
+//! An operation is known not to wrap if [super::range] shows it, or if the caller promises it.
+//!
+//! For R1CS, this avoids bit-decomposing intermediate results.
+
+use super::range::RangeAnalysis;
+use crate::ir::term::*;
+use rug::Integer;
+use std::sync::Arc;
+
+/// Rewrites non-wrapping bit-vector arithmetic into field arithmetic, caching results.
+pub struct BvToField {
+    modulus: Arc<Integer>,
+    no_wrap: TermSet,
+    ranges: RangeAnalysis,
+    /// Maps bit-vector terms that were moved into the field to their field versions.
+    arith: TermMap<Term>,
+    /// Maps terms to their rewritten versions (of the same sort).
+    cache: TermMap<Term>,
+}
+
+impl BvToField {
+    /// Create a rewriter into the field of size `modulus`.
+    ///
+    /// The bit-vector operations in `no_wrap` are assumed not to wrap, even if range analysis
+    /// cannot show it.
+    pub fn new(modulus: Arc<Integer>, no_wrap: TermSet) -> Self {
+        Self {
+            modulus,
+            no_wrap,
+            ranges: RangeAnalysis::new(),
+            arith: TermMap::new(),
+            cache: TermMap::new(),
+        }
+    }
+
+    /// Rewrite `node`.
+    pub fn rewrite(&mut self, node: &Term) -> Term {
+        // (node, children pushed)
+        let mut stack = vec![(node.clone(), false)];
+        while let Some((t, children_pushed)) = stack.pop() {
+            if self.cache.contains_key(&t) {
+                continue;
+            }
+            if !children_pushed {
+                stack.push((t.clone(), true));
+                stack.extend(t.cs.iter().map(|c| (c.clone(), false)));
+                continue;
+            }
+            self.visit(t);
+        }
+        self.cache.get(node).expect("postorder cache").clone()
+    }
+
+    /// The field version of (original) bit-vector term `t`.
+    fn field(&self, t: &Term) -> Term {
+        if let Some(f) = self.arith.get(t) {
+            f.clone()
+        } else {
+            let new = self.cache.get(t).expect("postorder cache");
+            match &new.op {
+                Op::Const(Value::BitVector(b)) => leaf_term(Op::Const(Value::Field(
+                    FieldElem::new(b.uint().clone(), self.modulus.clone()),
+                ))),
+                _ => term![Op::UbvToPf(self.modulus.clone()); new.clone()],
+            }
+        }
+    }
+
+    /// Is every value of `t` smaller than the modulus?
+    fn fits(&mut self, t: &Term) -> bool {
+        self.ranges.range(t).uhi < *self.modulus
+    }
+
+    /// Can the arithmetic in `t` (of width `w`) be done in the field?
+    fn convertible(&mut self, t: &Term, w: usize) -> bool {
+        let pow2_w = Integer::from(1) << w as u32;
+        if self.no_wrap.contains(t) && pow2_w <= *self.modulus {
+            return true;
+        }
+        // the (integer) result must be less than this
+        let bound = std::cmp::min(pow2_w, (*self.modulus).clone());
+        let ranges: Vec<_> = t.cs.iter().map(|c| self.ranges.range(c).clone()).collect();
+        match &t.op {
+            Op::BvNaryOp(BvNaryOp::Add) => ranges.iter().map(|r| &r.uhi).sum::<Integer>() < bound,
+            Op::BvNaryOp(BvNaryOp::Mul) => {
+                ranges.iter().map(|r| &r.uhi).product::<Integer>() < bound
+            }
+            Op::BvBinOp(BvBinOp::Sub) => ranges[0].ulo >= ranges[1].uhi && ranges[0].uhi < bound,
+            _ => false,
+        }
+    }
+
+    /// Rewrite `t`, whose children have already been rewritten.
+    fn visit(&mut self, t: Term) {
+        let default = term(
+            t.op.clone(),
+            t.cs.iter()
+                .map(|c| self.cache.get(c).expect("postorder cache").clone())
+                .collect(),
+        );
+        let in_field = |s: &Self, c: &Term| s.arith.contains_key(c);
+        let arith = match check(&t) {
+            Sort::BitVector(w) => match &t.op {
+                Op::BvNaryOp(BvNaryOp::Add) if self.convertible(&t, w) => {
+                    Some(term(PF_ADD, t.cs.iter().map(|c| self.field(c)).collect()))
+                }
+                Op::BvNaryOp(BvNaryOp::Mul) if self.convertible(&t, w) => {
+                    Some(term(PF_MUL, t.cs.iter().map(|c| self.field(c)).collect()))
+                }
+                Op::BvBinOp(BvBinOp::Sub) if self.convertible(&t, w) => Some(term![PF_ADD;
+                    self.field(&t.cs[0]),
+                    term![PF_NEG; self.field(&t.cs[1])]
+                ]),
+                Op::BvUext(_) if in_field(self, &t.cs[0]) => Some(self.field(&t.cs[0])),
+                Op::Ite
+                    if (in_field(self, &t.cs[1]) || in_field(self, &t.cs[2]))
+                        && self.fits(&t.cs[1])
+                        && self.fits(&t.cs[2]) =>
+                {
+                    Some(term![ITE;
+                        self.cache.get(&t.cs[0]).expect("postorder cache").clone(),
+                        self.field(&t.cs[1]),
+                        self.field(&t.cs[2])
+                    ])
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        let new = match arith {
+            Some(f) => {
+                let w = check(&t).as_bv();
+                self.arith.insert(t.clone(), f.clone());
+                term![Op::PfToBv(w); f]
+            }
+            None => match &t.op {
+                Op::Eq
+                    if (in_field(self, &t.cs[0]) || in_field(self, &t.cs[1]))
+                        && self.fits(&t.cs[0])
+                        && self.fits(&t.cs[1]) =>
+                {
+                    term![EQ; self.field(&t.cs[0]), self.field(&t.cs[1])]
+                }
+                _ => default,
+            },
+        };
+        self.cache.insert(t, new);
+    }
+}
+
+/// Rewrite non-wrapping bit-vector arithmetic in `t` into the field of size `modulus`.
+pub fn bv_to_field(t: &Term, modulus: Arc<Integer>) -> Term {
+    BvToField::new(modulus, TermSet::new()).rewrite(t)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ir::term::dist::test::*;
+    use fxhash::FxHashMap;
+    use quickcheck_macros::quickcheck;
+
+    fn v_bv(n: &str, w: usize) -> Term {
+        leaf_term(Op::Var(n.to_owned(), Sort::BitVector(w)))
+    }
+
+    fn test_field() -> Arc<Integer> {
+        Arc::new(Integer::from(crate::ir::term::field::TEST_FIELD))
+    }
+
+    fn env(vals: &[(&str, u64, usize)]) -> FxHashMap<String, Value> {
+        vals.iter()
+            .map(|(n, v, w)| {
+                (
+                    n.to_string(),
+                    Value::BitVector(BitVector::new(Integer::from(*v), *w)),
+                )
+            })
+            .collect()
+    }
+
+    fn has_bv_arith(t: &Term) -> bool {
+        PostOrderIter::new(t.clone()).any(|c| match &c.op {
+            Op::BvNaryOp(BvNaryOp::Add) | Op::BvNaryOp(BvNaryOp::Mul) => true,
+            _ => false,
+        })
+    }
+
+    #[quickcheck]
+    fn semantics_random(ArbitraryTermEnv(t, vs): ArbitraryTermEnv) {
+        let tt = bv_to_field(&t, test_field());
+        let orig = eval(&t, &vs);
+        let new = eval(&tt, &vs);
+        assert!(orig == new, "{} ({}) vs {} ({})", t, orig, tt, new);
+    }
+
+    #[quickcheck]
+    fn semantics_small_operands(a: u8, b: u8, c: u16) {
+        let t = term![EQ;
+            term![BV_MUL;
+                term![BV_ADD; term![Op::BvUext(24); v_bv("a", 8)], term![Op::BvUext(24); v_bv("b", 8)]],
+                bv_lit(3, 32)
+            ],
+            term![Op::BvUext(16); v_bv("c", 16)]
+        ];
+        let vs = env(&[("a", a as u64, 8), ("b", b as u64, 8), ("c", c as u64, 16)]);
+        let tt = bv_to_field(&t, test_field());
+        assert_eq!(eval(&t, &vs), eval(&tt, &vs), "{} vs {}", t, tt);
+        assert!(!has_bv_arith(&tt), "{}", tt);
+    }
+
+    #[test]
+    fn wrapping_add_kept() {
+        let t = term![EQ; term![BV_ADD; v_bv("a", 8), v_bv("b", 8)], v_bv("c", 8)];
+        assert_eq!(bv_to_field(&t, test_field()), t);
+    }
+
+    #[test]
+    fn annotated_no_wrap() {
+        let sum = term![BV_ADD; v_bv("a", 16), v_bv("b", 16)];
+        let t = term![BV_ULT; sum.clone(), v_bv("c", 16)];
+        let mut no_wrap = TermSet::new();
+        no_wrap.insert(sum);
+        let tt = BvToField::new(test_field(), no_wrap).rewrite(&t);
+        let m = test_field();
+        assert_eq!(
+            tt,
+            term![BV_ULT;
+                term![Op::PfToBv(16); term![PF_ADD;
+                    term![Op::UbvToPf(m.clone()); v_bv("a", 16)],
+                    term![Op::UbvToPf(m); v_bv("b", 16)]
+                ]],
+                v_bv("c", 16)
+            ]
+        );
+        let vs = env(&[("a", 1000, 16), ("b", 2000, 16), ("c", 3001, 16)]);
+        assert_eq!(eval(&tt, &vs), Value::Bool(true));
+    }
+}
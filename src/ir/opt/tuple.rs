@@ -243,5 +243,19 @@ pub fn eliminate_tuples(mut cs: Computation) -> Computation {
         .map(|c| pass.get_tree(c).unwrap_leaf().clone())
         .collect();
     pass.cs.outputs = new_ouputs;
+    let others: Vec<Term> = pass
+        .cs
+        .metadata
+        .terms_mut()
+        .into_iter()
+        .map(|t| t.clone())
+        .collect();
+    let others: Vec<Term> = others
+        .iter()
+        .map(|t| pass.embed(t).unwrap_leaf().clone())
+        .collect();
+    for (t, new) in pass.cs.metadata.terms_mut().into_iter().zip(others) {
+        *t = new;
+    }
     pass.cs
 }
@@ -137,6 +137,10 @@ pub enum Op {
     ///
     /// Makes an array equal to `array`, but with `value` at `index`.
     Store,
+    /// Unary operator, with a bit-vector argument (the index).
+    ///
+    /// Gets the value at index in this constant table, which has an entry for every index.
+    Lookup(Arc<Vec<Value>>),
 
     /// Assemble n things into a tuple
     Tuple,
@@ -250,6 +254,7 @@ impl Op {
             Op::ConstArray(_, _) => Some(1),
             Op::Select => Some(2),
             Op::Store => Some(3),
+            Op::Lookup(_) => Some(1),
             Op::Tuple => None,
             Op::Field(_) => Some(1),
         }
@@ -292,6 +297,7 @@ impl Display for Op {
             Op::ConstArray(_, s) => write!(f, "const-array {}", s),
             Op::Select => write!(f, "select"),
             Op::Store => write!(f, "store"),
+            Op::Lookup(t) => write!(f, "lookup {}", t.len()),
             Op::Tuple => write!(f, "tuple"),
             Op::Field(i) => write!(f, "field{}", i),
         }
@@ -1094,7 +1100,7 @@ pub fn eval(t: &Term, h: &FxHashMap<String, Value>) -> Value {
             }),
             Op::PfToBv(w) => Value::BitVector({
                 let a = vs.get(&c.cs[0]).unwrap().as_pf().clone();
-                assert!(a.i() < &(Integer::from(1) << 1));
+                assert!(a.i() < &(Integer::from(1) << *w as u32));
                 BitVector::new(a.i().clone(), *w)
             }),
             Op::BvUext(w) => Value::BitVector({
@@ -1148,6 +1154,37 @@ pub fn eval(t: &Term, h: &FxHashMap<String, Value>) -> Value {
                 let a = vs.get(&c.cs[0]).unwrap().as_bv().clone();
                 field::FieldElem::new(a.uint().clone(), m.clone())
             }),
+            Op::ConstArray(k, n) => {
+                let v = vs.get(&c.cs[0]).unwrap().clone();
+                Value::Array(
+                    Sort::Array(Box::new(k.clone()), Box::new(v.sort()), *n),
+                    Box::new(v),
+                    BTreeMap::new(),
+                    *n,
+                )
+            }
+            Op::Store => {
+                let mut a = vs.get(&c.cs[0]).unwrap().clone();
+                let k = vs.get(&c.cs[1]).unwrap().clone();
+                let v = vs.get(&c.cs[2]).unwrap().clone();
+                if let Value::Array(_, _, m, _) = &mut a {
+                    m.insert(k, v);
+                } else {
+                    panic!("Not an array: {}", a)
+                }
+                a
+            }
+            Op::Select => match vs.get(&c.cs[0]).unwrap() {
+                Value::Array(_, d, m, _) => m
+                    .get(vs.get(&c.cs[1]).unwrap())
+                    .unwrap_or(&**d)
+                    .clone(),
+                a => panic!("Not an array: {}", a),
+            },
+            Op::Lookup(t) => {
+                let i = vs.get(&c.cs[0]).unwrap().as_bv().uint().to_usize().unwrap();
+                t[i].clone()
+            }
             Op::Tuple => Value::Tuple(c.cs.iter().map(|c| vs.get(c).unwrap().clone()).collect()),
             Op::Field(i) => {
                 let t = vs.get(&c.cs[0]).unwrap().as_tuple();
@@ -1247,6 +1284,30 @@ impl std::iter::Iterator for PostOrderIter {
 /// A party identifier
 pub type PartyId = u8;
 
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+/// A location in the source of a computation.
+pub struct SourceLoc {
+    /// The source file
+    pub file: String,
+    /// The (1-indexed) line
+    pub line: usize,
+    /// The functions being called, outermost first
+    pub call_stack: Vec<String>,
+}
+
+impl SourceLoc {
+    /// The innermost function.
+    pub fn function(&self) -> &str {
+        self.call_stack.last().map(|s| s.as_str()).unwrap_or("<top>")
+    }
+}
+
+impl Display for SourceLoc {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}:{} ({})", self.file, self.line, self.function())
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 /// An IR constraint system.
 pub struct ComputationMetadata {
@@ -1256,6 +1317,30 @@ pub struct ComputationMetadata {
     pub next_party_id: PartyId,
     /// All inputs, including who knows them. If no visibility is set, the input is public.
     pub inputs: FxHashMap<String, Option<PartyId>>,
+    /// The parties that learn the outputs. If not set, all parties do.
+    pub output_parties: Option<Vec<PartyId>>,
+    /// Where in the source some terms come from (for profiling).
+    ///
+    /// [crate::ir::opt::opt] moves these to the terms it rewrites them into, and drops those of
+    /// terms that are gone.
+    pub term_locs: TermMap<SourceLoc>,
+    /// For inputs that the user gives values for, the names that the user gives them by.
+    pub user_names: FxHashMap<String, String>,
+    /// Memories whose reads are prover inputs, so that witness generation can compute them.
+    ///
+    /// [crate::ir::opt::opt] rewrites their terms along with the outputs.
+    pub checked_memories: Vec<CheckedMemory>,
+}
+
+#[derive(Clone, Debug)]
+/// A memory checked by [crate::ir::opt::mem::perm], whose reads were replaced with prover inputs.
+pub struct CheckedMemory {
+    /// The value at every key, before any write
+    pub default: Term,
+    /// The writes, in order: key, value, and condition (none if unconditional)
+    pub writes: Vec<(Term, Term, Option<Term>)>,
+    /// The reads: the input that replaced each, the number of writes before it, and its key
+    pub reads: Vec<(String, usize, Term)>,
 }
 
 impl ComputationMetadata {
@@ -1276,6 +1361,10 @@ impl ComputationMetadata {
         );
         self.inputs.insert(input_name, party);
     }
+    /// Record that the user gives the value of input `input_name` as `user_name`.
+    pub fn set_user_name(&mut self, input_name: String, user_name: String) {
+        self.user_names.insert(input_name, user_name);
+    }
     /// Returns None if the value is public. Otherwise, the unique party that knows it.
     pub fn get_input_visibility(&self, input_name: &str) -> Option<PartyId> {
         self.inputs
@@ -1287,6 +1376,24 @@ impl ComputationMetadata {
     pub fn is_input_public(&self, input_name: &str) -> bool {
         self.get_input_visibility(input_name).is_none()
     }
+    /// Record that `t` comes from `loc`, unless it already has a location.
+    pub fn set_term_loc(&mut self, t: Term, loc: SourceLoc) {
+        self.term_locs.entry(t).or_insert(loc);
+    }
+    /// The terms of the [CheckedMemory]s, for passes that rewrite the outputs to rewrite too.
+    pub fn terms_mut(&mut self) -> Vec<&mut Term> {
+        let mut terms = Vec::new();
+        for mem in &mut self.checked_memories {
+            terms.push(&mut mem.default);
+            for (key, val, cond) in &mut mem.writes {
+                terms.push(key);
+                terms.push(val);
+                terms.extend(cond.as_mut());
+            }
+            terms.extend(mem.reads.iter_mut().map(|(_, _, key)| key));
+        }
+        terms
+    }
     /// Get all public inputs.
     pub fn public_inputs(&self) -> impl Iterator<Item = &str> {
         self.inputs.iter().filter_map(|(name, party)| {
@@ -92,6 +92,7 @@ pub fn check_raw(t: &Term) -> Result<Sort, TypeError> {
         )),
         Op::Select => array_or(&check_raw(&t.cs[0])?, "select").map(|(_, v)| v.clone()),
         Op::Store => Ok(check_raw(&t.cs[0])?),
+        Op::Lookup(t) => Ok(t[0].sort()),
         Op::Tuple => Ok(Sort::Tuple(
             t.cs.iter()
                 .map(|c| check_raw(c))
@@ -273,6 +274,20 @@ pub fn rec_check_raw(t: &Term) -> Result<Sort, TypeError> {
                     (Op::Store, &[Sort::Array(k, v, n), a, b]) => eq_or(k, a, "store")
                         .and_then(|_| eq_or(v, b, "store"))
                         .map(|_| Sort::Array(k.clone(), v.clone(), *n)),
+                    (Op::Lookup(t), &[a]) => bv_or(a, "lookup").and_then(|a| {
+                        if t.len().is_power_of_two()
+                            && t.len().trailing_zeros() as usize == a.as_bv()
+                        {
+                            let sorts: Vec<Sort> = t.iter().map(|v| v.sort()).collect();
+                            all_eq_or(sorts.iter(), "lookup").map(|s| s.clone())
+                        } else {
+                            Err(TypeErrorReason::Custom(format!(
+                                "lookup table of size {} for index {}",
+                                t.len(),
+                                a
+                            )))
+                        }
+                    }),
                     (Op::Tuple, a) => {
                         Ok(Sort::Tuple(a.into_iter().map(|a| (*a).clone()).collect()))
                     }
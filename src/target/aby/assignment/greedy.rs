@@ -0,0 +1,285 @@
+//! Greedy sharing assignment, improved by local search
+//!
+//! This scales to circuits that are too large for [super::ilp], but gives no guarantee of
+//! optimality. It has three phases:
+//!
+//! 1. Bottom-up, estimate the cost of computing each term in each sharing: its own cost, plus,
+//!    for each child, the cheapest way to compute the child and convert it. A child with many uses
+//!    contributes only its share of its own cost to each of them.
+//! 2. Top-down, choose the sharing for each term that minimizes its estimate plus the cost of
+//!    converting it for the (already chosen) terms that use it.
+//! 3. Repeatedly move single terms to the sharing that minimizes the cost of the whole
+//!    assignment, until no move helps.
+//!
+//! It ignores rounds of communication (see [CostModel::with_latency]): their cost depends on the
+//! critical path of the whole circuit, which no single move sees.
+
+use super::ilp::CostModel;
+use super::{terms_and_uses, ShareType, SharingMap, SHARE_TYPES};
+use crate::ir::term::*;
+
+/// The maximum number of passes of local search
+const MAX_PASSES: usize = 16;
+
+/// The position of `ty` in [SHARE_TYPES]
+fn slot(ty: ShareType) -> usize {
+    SHARE_TYPES.iter().position(|s| *s == ty).unwrap()
+}
+
+struct Greedy<'a> {
+    costs: &'a CostModel,
+    terms: Vec<Term>,
+    /// The children of each term, without duplicates, as indices
+    children: Vec<Vec<usize>>,
+    /// The uses of each term, as indices
+    uses: Vec<Vec<usize>>,
+    /// The sharings each term can be evaluated in, and their costs
+    options: Vec<Vec<(ShareType, f64)>>,
+    /// The current assignment
+    assignment: Vec<ShareType>,
+    /// For each term, how many of its uses are in each sharing (indexed by [slot])
+    use_counts: Vec<[usize; 3]>,
+}
+
+impl<'a> Greedy<'a> {
+    fn new(c: &Computation, costs: &'a CostModel) -> Self {
+        let (terms, uses) = terms_and_uses(c);
+        let index: TermMap<usize> = terms
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (t.clone(), i))
+            .collect();
+        let idx = |t: &Term| *index.get(t).unwrap();
+        let children = terms
+            .iter()
+            .map(|t| {
+                let mut cs: Vec<usize> = t.cs.iter().map(idx).collect();
+                cs.sort_unstable();
+                cs.dedup();
+                cs
+            })
+            .collect();
+        let uses: Vec<Vec<usize>> = terms
+            .iter()
+            .map(|t| uses.get(t).unwrap().iter().map(idx).collect())
+            .collect();
+        let use_counts = uses
+            .iter()
+            .map(|us| {
+                let mut counts = [0; 3];
+                counts[slot(ShareType::Boolean)] = us.len();
+                counts
+            })
+            .collect();
+        let options = terms.iter().map(|t| costs.op_costs(t)).collect();
+        Greedy {
+            costs,
+            assignment: vec![ShareType::Boolean; terms.len()],
+            terms,
+            children,
+            uses,
+            options,
+            use_counts,
+        }
+    }
+
+    /// Move term `i` to sharing `ty`
+    fn set(&mut self, i: usize, ty: ShareType) {
+        let old = self.assignment[i];
+        for c in &self.children[i] {
+            self.use_counts[*c][slot(old)] -= 1;
+            self.use_counts[*c][slot(ty)] += 1;
+        }
+        self.assignment[i] = ty;
+    }
+
+    /// Phases 1 and 2: an initial assignment
+    fn initial(&mut self) {
+        let n = self.terms.len();
+        let mut estimates: Vec<Vec<(ShareType, f64)>> = Vec::with_capacity(n);
+        for i in 0..n {
+            let est = self.options[i]
+                .iter()
+                .map(|(ty, cost)| {
+                    let children: f64 = self.children[i]
+                        .iter()
+                        .map(|c| {
+                            let n_uses = self.uses[*c].len().max(1) as f64;
+                            estimates[*c]
+                                .iter()
+                                .map(|(c_ty, c_est)| {
+                                    c_est / n_uses + self.costs.conversion_cost(*c_ty, *ty)
+                                })
+                                .fold(f64::INFINITY, f64::min)
+                        })
+                        .sum();
+                    (*ty, cost + children)
+                })
+                .collect();
+            estimates.push(est);
+        }
+        for i in (0..n).rev() {
+            let users = self.use_counts[i];
+            let (ty, _) = estimates[i]
+                .iter()
+                .map(|(ty, est)| (*ty, est + self.conversions_to(*ty, &users)))
+                .fold(None, |best: Option<(ShareType, f64)>, (ty, c)| match best {
+                    Some((_, b)) if b <= c => best,
+                    _ => Some((ty, c)),
+                })
+                .unwrap();
+            self.set(i, ty);
+        }
+    }
+
+    /// The cost of converting a value in sharing `from` once to each sharing that has uses in
+    /// `counts` (indexed by [slot])
+    fn conversions_to(&self, from: ShareType, counts: &[usize; 3]) -> f64 {
+        SHARE_TYPES
+            .iter()
+            .zip(counts)
+            .filter(|(ty, n)| **ty != from && **n > 0)
+            .map(|(ty, _)| self.costs.conversion_cost(from, *ty))
+            .sum()
+    }
+
+    /// The part of the total cost that depends on the sharing of term `i`, if it were `ty`
+    fn local_cost(&self, i: usize, ty: ShareType, cost: f64) -> f64 {
+        let current = self.assignment[i];
+        cost + self.conversions_to(ty, &self.use_counts[i])
+            + self.children[i]
+                .iter()
+                .map(|c| {
+                    // Term `i` is one of the uses of its child
+                    let mut counts = self.use_counts[*c];
+                    counts[slot(current)] -= 1;
+                    counts[slot(ty)] += 1;
+                    self.conversions_to(self.assignment[*c], &counts)
+                })
+                .sum::<f64>()
+    }
+
+    /// Phase 3: local search. Returns the number of passes made.
+    fn improve(&mut self) -> usize {
+        for pass in 0..MAX_PASSES {
+            let mut improved = false;
+            for i in 0..self.terms.len() {
+                let current = self.assignment[i];
+                let current_cost = self.options[i]
+                    .iter()
+                    .find(|(ty, _)| *ty == current)
+                    .map(|(_, c)| self.local_cost(i, current, *c))
+                    .unwrap();
+                let best = self.options[i]
+                    .iter()
+                    .map(|(ty, c)| (*ty, self.local_cost(i, *ty, *c)))
+                    .fold((current, current_cost), |best, (ty, c)| {
+                        if c < best.1 {
+                            (ty, c)
+                        } else {
+                            best
+                        }
+                    });
+                if best.0 != current && best.1 < current_cost - 1e-9 {
+                    self.set(i, best.0);
+                    improved = true;
+                }
+            }
+            if !improved {
+                return pass + 1;
+            }
+        }
+        MAX_PASSES
+    }
+}
+
+/// Assign sharings greedily, and then improve the assignment by local search
+pub fn assign_greedy(c: &Computation, costs: &CostModel) -> SharingMap {
+    let mut g = Greedy::new(c, costs);
+    g.initial();
+    g.improve();
+    g.terms.into_iter().zip(g.assignment).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::target::aby::assignment::{all_boolean_sharing, assignment_cost, ilp};
+
+    fn bv(name: &str) -> Term {
+        leaf_term(Op::Var(name.to_owned(), Sort::BitVector(32)))
+    }
+
+    #[test]
+    fn mults_go_arithmetic() {
+        let costs = CostModel::default();
+        let mut t = bv("a");
+        for _ in 0..6 {
+            t = term![BV_MUL; t, bv("a")];
+        }
+        let cs = Computation {
+            outputs: vec![term![Op::Eq; t, bv("b")]],
+            metadata: ComputationMetadata::default(),
+            values: None,
+        };
+        let greedy = assign_greedy(&cs, &costs);
+        assert_eq!(
+            &ShareType::Arithmetic,
+            greedy.get(&cs.outputs[0].cs[0]).unwrap()
+        );
+        let greedy_cost = assignment_cost(&cs, &greedy, &costs);
+        let exact_cost = assignment_cost(&cs, &ilp::assign_with_costs(&cs, &costs), &costs);
+        let boolean_cost = assignment_cost(&cs, &all_boolean_sharing(&cs), &costs);
+        assert!(greedy_cost <= boolean_cost);
+        assert!(exact_cost <= greedy_cost + 1e-6);
+    }
+
+    #[test]
+    fn shared_terms() {
+        let costs = CostModel::default();
+        // One product, used by many comparisons: computing it in Yao is cheaper than computing it
+        // in arithmetic sharing and converting it.
+        let prod = term![BV_MUL; bv("a"), bv("b")];
+        let outputs = (0..8)
+            .map(|i| term![BV_ULT; prod.clone(), bv(&format!("c{}", i))])
+            .collect();
+        let cs = Computation {
+            outputs,
+            metadata: ComputationMetadata::default(),
+            values: None,
+        };
+        let greedy = assign_greedy(&cs, &costs);
+        assert_eq!(greedy.len(), 2 + 1 + 8 + 8);
+        assert_eq!(&ShareType::Yao, greedy.get(&prod).unwrap());
+        let exact = ilp::assign_with_costs(&cs, &costs);
+        let greedy_cost = assignment_cost(&cs, &greedy, &costs);
+        let exact_cost = assignment_cost(&cs, &exact, &costs);
+        assert!(exact_cost <= greedy_cost + 1e-6);
+        // After local search, each term is in its best sharing, given the others
+        let mut g = Greedy::new(&cs, &costs);
+        g.initial();
+        g.improve();
+        assert_eq!(g.improve(), 1);
+        // The counts of uses in each sharing follow the moves
+        for (i, us) in g.uses.iter().enumerate() {
+            let mut counts = [0; 3];
+            for u in us {
+                counts[slot(g.assignment[*u])] += 1;
+            }
+            assert_eq!(g.use_counts[i], counts);
+        }
+    }
+
+    #[test]
+    fn repeated_children() {
+        let sq = term![BV_MUL; bv("a"), bv("a")];
+        let cs = Computation {
+            outputs: vec![term![BV_ADD; sq.clone(), sq.clone()]],
+            metadata: ComputationMetadata::default(),
+            values: None,
+        };
+        let g = Greedy::new(&cs, &CostModel::default());
+        assert_eq!(g.uses, vec![vec![1], vec![2], vec![]]);
+        assert_eq!(g.children, vec![vec![], vec![0], vec![1]]);
+    }
+}
@@ -28,16 +28,54 @@
 //! of all variables (term and conversion) scaled by their cost. In trying to minimize that, `C`
 //! will be set to the smallest value possible (0) if either of the variables on the right of (2)
 //! are 0.  If they are both 1 (for ANY `s`), then it must be 1.
+//!
+//! If the cost model has a latency (see [CostModel::with_latency]), we also model rounds of
+//! communication. Let `D[t]` be the round in which term `t` is done, and `R` the number of rounds
+//! in the whole computation. Let `r(t, a)` be the rounds that `t` takes in protocol `a`,
+//! `r(t) = \sum_a r(t, a) T[t, a]` those it takes in its protocol, and `r(a, b)` those that a
+//! conversion from `a` to `b` takes. Then, with `Cs(t)` the children of `t`,
+//!
+//! `forall t. D[t] >= r(t)     (3)`
+//!
+//! `forall t a b. forall s in Cs(t). D[t] >= D[s] + r(t) + r(a, b) (T[s, a] + T[t, b] - 1)   (4)`
+//!
+//! `forall t. R >= D[t]     (5)`
+//!
+//! and the objective also includes `R`, scaled by the latency. As for (2), minimizing it makes the
+//! `D[t]` as small as (3) and (4) allow, so `R` is the number of rounds on the critical path.
 
-use fxhash::{FxHashMap, FxHashSet};
+use fxhash::FxHashMap;
 use serde_json::Value;
 
-use super::{ShareType, SharingMap, SHARE_TYPES};
+use super::{terms_and_uses, ShareType, SharingMap, SHARE_TYPES};
 use crate::ir::term::*;
 
 use crate::target::ilp::{variable, Expression, Ilp, Variable};
 
-use std::{env::var, fs::File, path::Path};
+use std::{fs, path::Path};
+
+/// The built-in cost profiles: their names, and their costs, in the format of OPA's cost files.
+///
+/// * `lan`: OPA's sample costs.
+/// * `wan`: the `lan` costs, plus 100us for each round that an operation takes (see
+///   [default_rounds]). That is a 100ms round trip, as in the WAN recipe of the `aby_calibrate`
+///   example, amortized over the 1000 copies of an operation that it runs at once.
+///
+/// Other profiles can be measured with the `aby_calibrate` example, and loaded with
+/// [CostModel::load].
+pub const COST_PROFILES: &[(&str, &str)] = &[
+    (
+        "lan",
+        include_str!("../../../../third_party/opa/sample_costs.json"),
+    ),
+    (
+        "wan",
+        include_str!("../../../../third_party/opa/wan_costs.json"),
+    ),
+];
+
+/// The bit-width whose costs are used by default
+pub const DEFAULT_COST_WIDTH: usize = 32;
 
 /// A cost model for ABY operations and share conversions
 #[derive(Debug)]
@@ -47,11 +85,33 @@ pub struct CostModel {
 
     /// Operator costs: maps (op, type) to cost
     ops: FxHashMap<Op, FxHashMap<ShareType, f64>>,
+
+    /// Conversion rounds, where the cost file gives them: maps (from, to) pairs to rounds
+    conversion_rounds: FxHashMap<(ShareType, ShareType), f64>,
+
+    /// Operator rounds, where the cost file gives them: maps (op, type) to rounds
+    op_rounds: FxHashMap<Op, FxHashMap<ShareType, f64>>,
+
+    /// The cost of one round of communication
+    latency: f64,
 }
 
 impl CostModel {
-    /// Create a cost model from an OPA json file, like [this](https://github.com/ishaq/OPA/blob/d613c15ff715fa62c03e37b673548f94c16bfe0d/solver/sample-costs.json)
+    /// Create a cost model from an OPA json file, like [this](https://github.com/ishaq/OPA/blob/d613c15ff715fa62c03e37b673548f94c16bfe0d/solver/sample-costs.json),
+    /// using its costs for 32-bit values.
     pub fn from_opa_cost_file(p: &impl AsRef<Path>) -> CostModel {
+        let json = fs::read_to_string(p).expect("Missing file");
+        CostModel::from_opa_json(&json, DEFAULT_COST_WIDTH)
+    }
+
+    /// Create a cost model from the text of an OPA json file.
+    ///
+    /// The file gives costs for one or more bit-widths. We use those for `width`, or, if there are
+    /// none, those for the next larger width, or, if there are none, those for the largest width.
+    ///
+    /// Next to its `"1"` (cost) entry, an operation or conversion may have a `"rounds"` entry,
+    /// which overrides the estimate of [default_rounds].
+    pub fn from_opa_json(json: &str, width: usize) -> CostModel {
         use ShareType::*;
         let get_cost_opt =
             |op_name: &str, obj: &serde_json::map::Map<String, Value>| -> Option<f64> {
@@ -66,126 +126,401 @@ impl CostModel {
         let get_cost = |op_name: &str, obj: &serde_json::map::Map<String, Value>| -> f64 {
             get_cost_opt(op_name, obj).unwrap()
         };
+        let get_rounds =
+            |op_name: &str, obj: &serde_json::map::Map<String, Value>| -> Option<f64> {
+                let r = obj.get(op_name)?.get("rounds")?;
+                Some(r.as_f64().expect("not a number"))
+            };
         let mut conversions = FxHashMap::default();
         let mut ops = FxHashMap::default();
-        let f = File::open(p).expect("Missing file");
-        let json: Value = serde_json::from_reader(f).expect("Bad JSON");
+        let mut conversion_rounds = FxHashMap::default();
+        let mut op_rounds = FxHashMap::default();
+        let json: Value = serde_json::from_str(json).expect("Bad JSON");
         let obj = json.as_object().unwrap();
-        for (_width, json) in obj {
-            //let w = u32::from_str(width).expect("bad width");
-            let obj = json.as_object().unwrap();
-
-            // conversions
-            conversions.insert((Arithmetic, Boolean), get_cost("a2b", obj));
-            conversions.insert((Boolean, Arithmetic), get_cost("b2a", obj));
-            conversions.insert((Yao, Boolean), get_cost("y2b", obj));
-            conversions.insert((Boolean, Yao), get_cost("b2y", obj));
-            conversions.insert((Yao, Arithmetic), get_cost("y2a", obj));
-            conversions.insert((Arithmetic, Yao), get_cost("a2y", obj));
-
-            let ops_from_name = |name: &str| {
-                match name {
-                    // assume comparisions are unsigned
-                    "ge" => vec![BV_UGE],
-                    "le" => vec![BV_ULE],
-                    "gt" => vec![BV_UGT],
-                    "lt" => vec![BV_ULT],
-                    // assume n-ary ops apply to BVs
-                    "add" => vec![BV_ADD],
-                    "mul" => vec![BV_MUL],
-                    "and" => vec![BV_AND],
-                    "or" => vec![BV_OR],
-                    "xor" => vec![BV_XOR],
-                    // assume eq applies to BVs
-                    "eq" => vec![Op::Eq],
-                    "shl" => vec![BV_SHL],
-                    // assume shr is logical, not arithmetic
-                    "shr" => vec![BV_LSHR],
-                    "sub" => vec![BV_SUB],
-                    "mux" => vec![ITE],
-                    "ne" => vec![Op::Not, Op::Eq],
-                    "bvudiv" => vec![BV_UDIV],
-                    "bvurem" => vec![BV_UREM],
-                    // added to pass test case
-                    "&&" => vec![AND],
-                    "||" => vec![OR],
-                    _ => panic!("Unknown operator name: {}", name),
-                }
-            };
-            for (op_name, json) in obj {
-                // HACK: assumes the presence of 2 partitions names into conversion and otherwise.
-                if !op_name.contains("2") {
-                    for op in ops_from_name(op_name) {
-                        let obj = json.as_object().unwrap();
-                        for (share_type, share_name) in
-                            &[(Arithmetic, "a"), (Boolean, "b"), (Yao, "y")]
+        let mut widths: Vec<usize> = obj
+            .keys()
+            .map(|w| w.parse().unwrap_or_else(|_| panic!("Bad width: {}", w)))
+            .collect();
+        widths.sort_unstable();
+        let chosen = *widths
+            .iter()
+            .find(|w| **w >= width)
+            .or_else(|| widths.last())
+            .expect("No costs in the cost file");
+        let obj = obj[&chosen.to_string()].as_object().unwrap();
+
+        // conversions
+        conversions.insert((Arithmetic, Boolean), get_cost("a2b", obj));
+        conversions.insert((Boolean, Arithmetic), get_cost("b2a", obj));
+        conversions.insert((Yao, Boolean), get_cost("y2b", obj));
+        conversions.insert((Boolean, Yao), get_cost("b2y", obj));
+        conversions.insert((Yao, Arithmetic), get_cost("y2a", obj));
+        conversions.insert((Arithmetic, Yao), get_cost("a2y", obj));
+        for (from, to) in conversions.keys() {
+            let name = format!("{}2{}", from.char(), to.char());
+            if let Some(r) = get_rounds(&name, obj) {
+                conversion_rounds.insert((*from, *to), r);
+            }
+        }
+
+        let ops_from_name = |name: &str| {
+            match name {
+                // signed comparisons flip the sign bits of unsigned ones, which is free
+                "ge" => vec![BV_UGE, BV_SGE],
+                "le" => vec![BV_ULE, BV_SLE],
+                "gt" => vec![BV_UGT, BV_SGT],
+                "lt" => vec![BV_ULT, BV_SLT],
+                // assume n-ary ops apply to BVs
+                "add" => vec![BV_ADD],
+                "mul" => vec![BV_MUL],
+                "and" => vec![BV_AND],
+                "or" => vec![BV_OR],
+                "xor" => vec![BV_XOR],
+                // assume eq applies to BVs
+                "eq" => vec![Op::Eq],
+                "shl" => vec![BV_SHL],
+                "shr" => vec![BV_LSHR, BV_ASHR],
+                "sub" => vec![BV_SUB],
+                "mux" => vec![ITE],
+                "ne" => vec![Op::Not, Op::Eq],
+                "bvudiv" => vec![BV_UDIV],
+                "bvurem" => vec![BV_UREM],
+                // added to pass test case
+                "&&" => vec![AND],
+                "||" => vec![OR],
+                _ => panic!("Unknown operator name: {}", name),
+            }
+        };
+        for (op_name, json) in obj {
+            // HACK: assumes the presence of 2 partitions names into conversion and otherwise.
+            if !op_name.contains("2") {
+                for op in ops_from_name(op_name) {
+                    let obj = json.as_object().unwrap();
+                    for (share_type, share_name) in
+                        &[(Arithmetic, "a"), (Boolean, "b"), (Yao, "y")]
+                    {
+                        // ABY's arithmetic circuits only add, subtract, and multiply (and
+                        // multiplexers are built from those)
+                        if *share_type == Arithmetic && ![BV_ADD, BV_SUB, BV_MUL, ITE].contains(&op)
                         {
-                            if let Some(cost) = get_cost_opt(share_name, obj) {
-                                ops.entry(op.clone())
-                                    .or_insert_with(|| FxHashMap::default())
-                                    .insert(*share_type, cost);
-                            }
+                            continue;
+                        }
+                        if let Some(cost) = get_cost_opt(share_name, obj) {
+                            ops.entry(op.clone())
+                                .or_insert_with(|| FxHashMap::default())
+                                .insert(*share_type, cost);
+                        }
+                        if let Some(r) = get_rounds(share_name, obj) {
+                            op_rounds
+                                .entry(op.clone())
+                                .or_insert_with(FxHashMap::default)
+                                .insert(*share_type, r);
                         }
                     }
                 }
             }
         }
-        CostModel { conversions, ops }
+        // In arithmetic sharing, `ite(c, t, f)` is `f + c * (t - f)`
+        let arith = |op: &Op, ops: &FxHashMap<Op, FxHashMap<ShareType, f64>>| {
+            ops.get(op)
+                .and_then(|costs| costs.get(&Arithmetic))
+                .copied()
+        };
+        if arith(&ITE, &ops).is_none() {
+            if let (Some(mul), Some(add), Some(sub)) = (
+                arith(&BV_MUL, &ops),
+                arith(&BV_ADD, &ops),
+                arith(&BV_SUB, &ops),
+            ) {
+                ops.entry(ITE)
+                    .or_insert_with(FxHashMap::default)
+                    .insert(Arithmetic, mul + add + sub);
+            }
+        }
+        CostModel {
+            conversions,
+            ops,
+            conversion_rounds,
+            op_rounds,
+            latency: 0.0,
+        }
+    }
+
+    /// This model, with each round of communication costing `latency`, in the units of the other
+    /// costs.
+    ///
+    /// With a latency of zero (the default), sharing assignment ignores rounds. A high latency
+    /// (e.g., for a WAN) favors Yao sharing, which needs a constant number of rounds, over Boolean
+    /// sharing, which needs a round for each layer of AND gates.
+    pub fn with_latency(mut self, latency: f64) -> CostModel {
+        assert!(latency >= 0.0, "Negative latency: {}", latency);
+        self.latency = latency;
+        self
+    }
+
+    /// The cost of one round of communication
+    pub fn latency(&self) -> f64 {
+        self.latency
+    }
+
+    /// The built-in profile `name` (see [COST_PROFILES]), for `width`-bit values
+    pub fn from_profile(name: &str, width: usize) -> Option<CostModel> {
+        COST_PROFILES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, json)| CostModel::from_opa_json(json, width))
+    }
+
+    /// Load `spec`, for `width`-bit values: either the name of a built-in profile, or the path
+    /// of an OPA json file.
+    pub fn load(spec: &str, width: usize) -> CostModel {
+        CostModel::from_profile(spec, width).unwrap_or_else(|| {
+            let json = fs::read_to_string(spec).unwrap_or_else(|e| {
+                panic!(
+                    "{} is neither a cost profile ({}) nor a readable cost file: {}",
+                    spec,
+                    COST_PROFILES
+                        .iter()
+                        .map(|(n, _)| *n)
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    e
+                )
+            });
+            CostModel::from_opa_json(&json, width)
+        })
+    }
+
+    /// The cost of `t` in each sharing that supports it.
+    ///
+    /// Panics if no sharing does.
+    pub fn op_costs(&self, t: &Term) -> Vec<(ShareType, f64)> {
+        let mut costs = match &t.op {
+            // These only move wires around
+            Op::Var(..) | Op::Const(_) | Op::Tuple | Op::Field(_) | Op::ConstArray(..) => {
+                SHARE_TYPES.iter().map(|ty| (*ty, 0.0)).collect()
+            }
+            // These only move wires, but only Boolean and Yao shares have wires for each bit
+            Op::BvExtract(..) | Op::BvConcat | Op::BvUext(_) | Op::BvSext(_) => {
+                vec![(ShareType::Boolean, 0.0), (ShareType::Yao, 0.0)]
+            }
+            Op::BvBinOp(BvBinOp::Shl) | Op::BvBinOp(BvBinOp::Lshr) | Op::BvBinOp(BvBinOp::Ashr)
+                if matches!(t.cs[1].op, Op::Const(_)) =>
+            {
+                vec![(ShareType::Boolean, 0.0), (ShareType::Yao, 0.0)]
+            }
+            Op::Select | Op::Store => self.array_access(t),
+            op => self
+                .ops
+                .get(op)
+                .map(|costs| costs.iter().map(|(ty, c)| (*ty, *c)).collect())
+                .unwrap_or_else(Vec::new),
+        };
+        if costs.is_empty() {
+            panic!("No cost for op {}", &t.op)
+        }
+        costs.sort_by_key(|(ty, _)| SHARE_TYPES.iter().position(|s| s == ty));
+        costs
+    }
+
+    /// The cost of `t` in sharing `ty`, if it can be evaluated in it
+    pub fn op_cost(&self, t: &Term, ty: ShareType) -> Option<f64> {
+        self.op_costs(t)
+            .into_iter()
+            .find(|(s, _)| *s == ty)
+            .map(|(_, c)| c)
+    }
+
+    /// The cost of converting a value from sharing `from` to sharing `to`
+    pub fn conversion_cost(&self, from: ShareType, to: ShareType) -> f64 {
+        if from == to {
+            0.0
+        } else {
+            *self.conversions.get(&(from, to)).unwrap()
+        }
+    }
+
+    /// The rounds of communication that `t` takes in sharing `ty`
+    pub fn op_rounds(&self, t: &Term, ty: ShareType) -> f64 {
+        let width =
+            t.cs.iter()
+                .chain(std::iter::once(t))
+                .map(|t| match check(t) {
+                    Sort::BitVector(w) => w,
+                    _ => 1,
+                })
+                .max()
+                .unwrap();
+        let rounds = |op: &Op, width: usize| {
+            self.op_rounds
+                .get(op)
+                .and_then(|r| r.get(&ty))
+                .copied()
+                .unwrap_or_else(|| default_rounds(op, ty, width))
+        };
+        match &t.op {
+            Op::Select | Op::Store => {
+                if let Op::Const(_) = &t.cs[1].op {
+                    return 0.0;
+                }
+                let (k, n) = match check(&t.cs[0]) {
+                    Sort::Array(k, _, n) => (k, n),
+                    s => panic!("Expected an array, but got {}", s),
+                };
+                let k_width = match *k {
+                    Sort::BitVector(w) => w,
+                    _ => 1,
+                };
+                // See [CostModel::array_access]: the comparisons are in parallel, followed by a
+                // tree of multiplexers (select) or masks and multiplexers (store)
+                let after = if t.op == Op::Select {
+                    (n as f64).log2().ceil() * rounds(&ITE, width)
+                } else {
+                    rounds(&AND, 1) + rounds(&ITE, width)
+                };
+                rounds(&BV_UGT, k_width) + after
+            }
+            // Shifts by constants only move wires
+            Op::BvBinOp(BvBinOp::Shl) | Op::BvBinOp(BvBinOp::Lshr) | Op::BvBinOp(BvBinOp::Ashr)
+                if matches!(t.cs[1].op, Op::Const(_)) =>
+            {
+                0.0
+            }
+            // n-ary operations are evaluated as a chain of binary ones
+            op => rounds(op, width) * t.cs.len().saturating_sub(1).max(1) as f64,
+        }
+    }
+
+    /// The rounds of communication that converting a value from sharing `from` to sharing `to`
+    /// takes.
+    ///
+    /// Unless the cost file says otherwise, every conversion takes one round (for oblivious
+    /// transfers), except from Yao to Boolean sharing, which is local.
+    pub fn conversion_rounds(&self, from: ShareType, to: ShareType) -> f64 {
+        if from == to {
+            0.0
+        } else if let Some(r) = self.conversion_rounds.get(&(from, to)) {
+            *r
+        } else if (from, to) == (ShareType::Yao, ShareType::Boolean) {
+            0.0
+        } else {
+            1.0
+        }
+    }
+
+    /// The cost of `t`, a select or store, in each sharing that supports it.
+    ///
+    /// With a constant index, both are free. Otherwise, both take about one comparison and one
+    /// multiplexer per element.
+    fn array_access(&self, t: &Term) -> Vec<(ShareType, f64)> {
+        if let Op::Const(_) = &t.cs[1].op {
+            return SHARE_TYPES.iter().map(|ty| (*ty, 0.0)).collect();
+        }
+        let n = match check(&t.cs[0]) {
+            Sort::Array(_, _, n) => n,
+            s => panic!("Expected an array, but got {}", s),
+        };
+        let (gt, mux) = match (self.ops.get(&BV_UGT), self.ops.get(&ITE)) {
+            (Some(gt), Some(mux)) => (gt, mux),
+            _ => return Vec::new(),
+        };
+        SHARE_TYPES
+            .iter()
+            .filter_map(|ty| Some((*ty, n as f64 * (gt.get(ty)? + mux.get(ty)?))))
+            .collect()
     }
 }
 
-/// Uses an ILP to assign...
+/// An estimate of the rounds of communication that `op` takes on `w`-bit values in sharing `ty`.
+///
+/// Garbled circuits are evaluated without interaction, so Yao sharing takes none, and arithmetic
+/// sharing only interacts to multiply (including in `ite`). Boolean (GMW) sharing takes a round
+/// for each layer of AND gates; ABY builds depth-optimized circuits for it, most of which have
+/// depth logarithmic in `w`.
+pub fn default_rounds(op: &Op, ty: ShareType, w: usize) -> f64 {
+    let log = (w as f64).log2().ceil();
+    match ty {
+        ShareType::Yao => 0.0,
+        ShareType::Arithmetic => match op {
+            Op::BvNaryOp(BvNaryOp::Mul) | Op::Ite => 1.0,
+            _ => 0.0,
+        },
+        ShareType::Boolean => match op {
+            Op::BvNaryOp(BvNaryOp::Add) | Op::BvBinOp(BvBinOp::Sub) => log + 1.0,
+            Op::BvNaryOp(BvNaryOp::Mul) => 2.0 * log + 1.0,
+            Op::BvNaryOp(BvNaryOp::And)
+            | Op::BvNaryOp(BvNaryOp::Or)
+            | Op::BoolNaryOp(BoolNaryOp::And)
+            | Op::BoolNaryOp(BoolNaryOp::Or)
+            | Op::Ite => 1.0,
+            Op::BvBinPred(_) => log + 1.0,
+            Op::Eq => log,
+            Op::BvBinOp(BvBinOp::Shl) | Op::BvBinOp(BvBinOp::Lshr) | Op::BvBinOp(BvBinOp::Ashr) => {
+                log
+            }
+            // Restoring division: a subtraction and a multiplexer per bit
+            Op::BvBinOp(BvBinOp::Udiv) | Op::BvBinOp(BvBinOp::Urem) => w as f64 * (log + 2.0),
+            _ => 0.0,
+        },
+    }
+}
+
+impl Default for CostModel {
+    /// The `lan` profile, for 32-bit values
+    fn default() -> Self {
+        CostModel::from_profile("lan", DEFAULT_COST_WIDTH).unwrap()
+    }
+}
+
+/// Uses an ILP to assign, with the default costs
 pub fn assign(c: &Computation) -> SharingMap {
-    let p = format!(
-        "{}/third_party/opa/sample_costs.json",
-        var("CARGO_MANIFEST_DIR").expect("Could not find env var CARGO_MANIFEST_DIR")
-    );
-    let costs = CostModel::from_opa_cost_file(&p);
-    build_ilp(c, &costs)
+    build_ilp(c, &CostModel::default())
+}
+
+/// Uses an ILP to assign, with the costs `costs`
+pub fn assign_with_costs(c: &Computation, costs: &CostModel) -> SharingMap {
+    build_ilp(c, costs)
 }
 
 fn build_ilp(c: &Computation, costs: &CostModel) -> SharingMap {
-    let mut terms: TermSet = TermSet::new();
-    let mut def_uses: FxHashSet<(Term, Term)> = FxHashSet::default();
-    for o in &c.outputs {
-        for t in PostOrderIter::new(o.clone()) {
-            terms.insert(t.clone());
-            for c in &t.cs {
-                def_uses.insert((c.clone(), t.clone()));
-            }
-        }
-    }
-    let terms: FxHashMap<Term, usize> = terms.into_iter().enumerate().map(|(i, t)| (t, i)).collect();
+    let (terms, uses) = terms_and_uses(c);
+    solve_region(&terms, &uses, &TermMap::new(), &TermMap::new(), costs)
+}
+
+/// Use an ILP to assign sharings to the terms in `region`, given the uses of every term.
+///
+/// Terms outside the region with a sharing in `fixed` are taken into account: converting between
+/// them and terms in the region costs the same as within the region, unless the same value is
+/// already converted to the same sharing for another fixed term. Other terms outside the region
+/// are ignored.
+///
+/// If the costs have a latency, `fixed_rounds` gives the round in which each fixed term is done
+/// (see [super::add_rounds]); other terms outside the region are taken to be done at round 0.
+pub(super) fn solve_region(
+    region: &[Term],
+    uses: &TermMap<Vec<Term>>,
+    fixed: &SharingMap,
+    fixed_rounds: &TermMap<f64>,
+    costs: &CostModel,
+) -> SharingMap {
+    let index: TermMap<usize> = region
+        .iter()
+        .enumerate()
+        .map(|(i, t)| (t.clone(), i))
+        .collect();
     let mut term_vars: FxHashMap<(Term, ShareType), (Variable, f64, String)> = FxHashMap::default();
-    let mut conv_vars: FxHashMap<(Term, ShareType, ShareType), (Variable, f64)> = FxHashMap::default();
+    let mut conv_vars: FxHashMap<(Term, ShareType, ShareType), (Variable, f64)> =
+        FxHashMap::default();
     let mut ilp = Ilp::new();
 
     // build variables for all term assignments
-    for (t, i) in terms.iter() {
+    for (i, t) in region.iter().enumerate() {
         let mut vars = vec![];
-        if let Op::Var(_, _) = &t.op {
-            for ty in &SHARE_TYPES {
-                let name = format!("t_{}_{}", i, ty.char());
-                let v = ilp.new_variable(variable().binary(), name.clone());
-                term_vars.insert((t.clone(), *ty), (v, 0.0, name));
-                vars.push(v);
-            }
-        } else if let Op::Const(_) = &t.op {
-            for ty in &SHARE_TYPES {
-                let name = format!("t_{}_{}", i, ty.char());
-                let v = ilp.new_variable(variable().binary(), name.clone());
-                term_vars.insert((t.clone(), *ty), (v, 0.0, name));
-                vars.push(v);
-            }
-        } else if let Some(costs) = costs.ops.get(&t.op) {
-            for (ty, cost) in costs {
-                let name = format!("t_{}_{}", i, ty.char());
-                let v = ilp.new_variable(variable().binary(), name.clone());
-                term_vars.insert((t.clone(), *ty), (v, *cost, name));
-                vars.push(v);
-            }
-        } else {
-            panic!("No cost for op {}", &t.op)
+        for (ty, cost) in costs.op_costs(t) {
+            let name = format!("t_{}_{}", i, ty.char());
+            let v = ilp.new_variable(variable().binary(), name.clone());
+            term_vars.insert((t.clone(), ty), (v, cost, name));
+            vars.push(v);
         }
         // Sum of assignments is at least 1.
         ilp.new_constraint(
@@ -195,64 +530,128 @@ fn build_ilp(c: &Computation, costs: &CostModel) -> SharingMap {
         );
     }
 
-    // build variables for all conversions assignments
-    for (def, use_) in &def_uses {
-        let def_i = terms.get(def).unwrap();
-        for from_ty in &SHARE_TYPES {
-            for to_ty in &SHARE_TYPES {
-                // if def can be from_ty, and use can be to_ty
-                if term_vars.contains_key(&(def.clone(), *from_ty))
-                    && term_vars.contains_key(&(use_.clone(), *to_ty))
-                    && from_ty != to_ty
-                {
-                    let v = ilp.new_variable(
-                        variable().binary(),
-                        format!("c_{}_{}2{}", def_i, from_ty.char(), to_ty.char()),
-                    );
-                    conv_vars.insert(
-                        (def.clone(), *from_ty, *to_ty),
-                        (v, *costs.conversions.get(&(*from_ty, *to_ty)).unwrap()),
-                    );
-                }
+    // The definitions whose conversions matter: the region, and the fixed terms it uses
+    let mut defs: Vec<Term> = region.to_vec();
+    let mut fixed_defs = TermSet::new();
+    for t in region {
+        for c in &t.cs {
+            if !index.contains_key(c) && fixed.contains_key(c) && fixed_defs.insert(c.clone()) {
+                defs.push(c.clone());
             }
         }
     }
 
-    let def_uses: FxHashMap<Term, Vec<Term>> = {
-        let mut t = FxHashMap::default();
-        for (d, u) in def_uses {
-            t.entry(d).or_insert_with(|| Vec::new()).push(u);
+    // Whether term `t` may be in sharing `ty`: `Some(Some(v))` for a variable `v` in the region,
+    // `Some(None)` if `t` is fixed to `ty`, and `None` otherwise.
+    let sharing = |t: &Term, ty: ShareType| -> Option<Option<Variable>> {
+        if index.contains_key(t) {
+            term_vars.get(&(t.clone(), ty)).map(|v| Some(v.0))
+        } else if fixed.get(t) == Some(&ty) {
+            Some(None)
+        } else {
+            None
         }
-        t
     };
 
-    for (def, uses) in def_uses {
-        for use_ in uses {
+    // build variables and constraints for all conversions
+    for (def_i, def) in defs.iter().enumerate() {
+        let def_uses = uses.get(def).map(|u| u.as_slice()).unwrap_or(&[]);
+        for use_ in def_uses {
+            if !index.contains_key(def) && !index.contains_key(use_) {
+                continue;
+            }
             for from_ty in &SHARE_TYPES {
                 for to_ty in &SHARE_TYPES {
-                    conv_vars.get(&(def.clone(), *from_ty, *to_ty)).map(|c| {
-                        term_vars.get(&(def.clone(), *from_ty)).map(|t_from| {
-                            // c[term i from pi to pi'] >= t[term j with pi'] + t[term i with pi] - 1
-                            term_vars
-                                .get(&(use_.clone(), *to_ty))
-                                .map(|t_to| ilp.new_constraint(c.0 >> t_from.0 + t_to.0 - 1.0))
+                    if from_ty == to_ty {
+                        continue;
+                    }
+                    // if def can be from_ty, and use can be to_ty
+                    let (t_from, t_to) = match (sharing(def, *from_ty), sharing(use_, *to_ty)) {
+                        (Some(f), Some(t)) => (f, t),
+                        _ => continue,
+                    };
+                    let paid = t_from.is_none()
+                        && def_uses
+                            .iter()
+                            .any(|u| !index.contains_key(u) && fixed.get(u) == Some(to_ty));
+                    if paid {
+                        continue;
+                    }
+                    let c = conv_vars
+                        .entry((def.clone(), *from_ty, *to_ty))
+                        .or_insert_with(|| {
+                            let v = ilp.new_variable(
+                                variable().binary(),
+                                format!("c_{}_{}2{}", def_i, from_ty.char(), to_ty.char()),
+                            );
+                            (v, costs.conversion_cost(*from_ty, *to_ty))
                         })
-                    });
+                        .0;
+                    // c[term i from pi to pi'] >= t[term j with pi'] + t[term i with pi] - 1
+                    let one =
+                        |v: Option<Variable>| v.map_or(Expression::from(1.0), Expression::from);
+                    ilp.new_constraint(c >> one(t_from) + one(t_to) - 1.0);
                 }
             }
         }
     }
 
+    // build variables and constraints for rounds
+    let mut round_cost = Expression::from(0.0);
+    if costs.latency() > 0.0 {
+        let all_rounds = ilp.new_variable(variable().min(0.0), "rounds".to_owned());
+        let fixed_max = fixed_rounds.iter().map(|(_, r)| *r).fold(0.0, f64::max);
+        ilp.new_constraint(all_rounds >> fixed_max);
+        let done: Vec<Variable> = (0..region.len())
+            .map(|i| ilp.new_variable(variable().min(0.0), format!("d_{}", i)))
+            .collect();
+        let scaled = |v: Option<Variable>, k: f64| v.map_or(Expression::from(k), |v| v * k);
+        for (i, t) in region.iter().enumerate() {
+            let own = costs
+                .op_costs(t)
+                .into_iter()
+                .map(|(ty, _)| {
+                    let v = term_vars.get(&(t.clone(), ty)).unwrap().0;
+                    v * costs.op_rounds(t, ty)
+                })
+                .fold(Expression::from(0.0), |acc, e| acc + e);
+            ilp.new_constraint(all_rounds >> done[i]);
+            ilp.new_constraint(done[i] >> own.clone());
+            let mut children: Vec<&Term> = t.cs.iter().collect();
+            children.dedup();
+            for c in children {
+                let c_done = match index.get(c) {
+                    Some(j) => Expression::from(done[*j]),
+                    None => Expression::from(fixed_rounds.get(c).copied().unwrap_or(0.0)),
+                };
+                ilp.new_constraint(done[i] >> c_done.clone() + own.clone());
+                for from_ty in &SHARE_TYPES {
+                    for to_ty in &SHARE_TYPES {
+                        let rounds = costs.conversion_rounds(*from_ty, *to_ty);
+                        if rounds == 0.0 {
+                            continue;
+                        }
+                        if let (Some(f), Some(u)) = (sharing(c, *from_ty), sharing(t, *to_ty)) {
+                            let conv = scaled(f, rounds) + scaled(u, rounds) - rounds;
+                            ilp.new_constraint(done[i] >> c_done.clone() + own.clone() + conv);
+                        }
+                    }
+                }
+            }
+        }
+        round_cost = all_rounds * costs.latency();
+    }
+
     ilp.maximize(
         -conv_vars
             .values()
             .map(|(a, b)| (a, b))
             .chain(term_vars.values().map(|(a, b, _)| (a, b)))
-            .fold(0.0.into(), |acc: Expression, (v, cost)| {
+            .fold(round_cost, |acc: Expression, (v, cost)| {
                 acc + v.clone() * *cost
             }),
     );
-    
+
     let (_opt, solution) = ilp.default_solve().unwrap();
 
     let mut assignment = TermMap::new();
@@ -267,6 +666,8 @@ fn build_ilp(c: &Computation, costs: &CostModel) -> SharingMap {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::target::aby::assignment::assignment_rounds;
+    use std::env::var;
 
     #[test]
     fn parse_cost_model() {
@@ -298,6 +699,126 @@ mod tests {
         );
     }
 
+    #[test]
+    fn derived_costs() {
+        use ShareType::*;
+        let c = CostModel::default();
+        assert_eq!(c.ops[&BV_SGE], c.ops[&BV_UGE]);
+        assert_eq!(c.ops[&BV_ASHR], c.ops[&BV_LSHR]);
+        assert!(!c.ops[&BV_UDIV].contains_key(&Arithmetic));
+        assert_eq!(
+            c.ops[&ITE][&Arithmetic],
+            c.ops[&BV_MUL][&Arithmetic] + c.ops[&BV_ADD][&Arithmetic] + c.ops[&BV_SUB][&Arithmetic]
+        );
+        let a = leaf_term(Op::Var("a".to_owned(), Sort::BitVector(32)));
+        let b = leaf_term(Op::Var("b".to_owned(), Sort::BitVector(32)));
+        let free = vec![(Boolean, 0.0), (Yao, 0.0)];
+        assert_eq!(c.op_costs(&term![Op::BvExtract(7, 0); a.clone()]), free);
+        assert_eq!(c.op_costs(&term![BV_SHL; a.clone(), bv_lit(3, 32)]), free);
+        assert_eq!(
+            c.op_rounds(&term![BV_ASHR; a.clone(), bv_lit(3, 32)], Boolean),
+            0.0
+        );
+        assert_eq!(c.op_costs(&term![BV_SHL; a, b]).len(), 2);
+    }
+
+    /// An OPA cost file with one entry per width: all conversions cost 1, and addition costs
+    /// the width
+    fn cost_file(widths: &[usize]) -> String {
+        let entries: serde_json::Map<String, Value> = widths
+            .iter()
+            .map(|w| {
+                let mut entry = serde_json::json!({ "add": { "a": { "1": *w as f64 } } });
+                for c in &["a2b", "b2a", "y2b", "b2y", "y2a", "a2y"] {
+                    entry[*c] = serde_json::json!({ "1": 1.0 });
+                }
+                (w.to_string(), entry)
+            })
+            .collect();
+        Value::Object(entries).to_string()
+    }
+
+    #[test]
+    fn cost_widths() {
+        let json = cost_file(&[8, 32]);
+        let add_cost = |width: usize| {
+            let c = CostModel::from_opa_json(&json, width);
+            c.ops[&BV_ADD][&ShareType::Arithmetic]
+        };
+        assert_eq!(add_cost(8), 8.0);
+        assert_eq!(add_cost(4), 8.0);
+        assert_eq!(add_cost(16), 32.0);
+        assert_eq!(add_cost(32), 32.0);
+        assert_eq!(add_cost(64), 32.0);
+    }
+
+    #[test]
+    fn load_profiles_and_files() {
+        let lan = CostModel::load("lan", 32);
+        assert_eq!(lan.ops[&BV_MUL][&ShareType::Yao], 1127.0);
+        assert_eq!(
+            lan.conversions[&(ShareType::Arithmetic, ShareType::Boolean)],
+            2596.4
+        );
+        let wan = CostModel::load("wan", 32);
+        // A 32-bit GMW adder takes 6 rounds; a garbled circuit takes none.
+        assert_eq!(wan.ops[&BV_ADD][&ShareType::Boolean], 760.0);
+        assert_eq!(wan.ops[&BV_ADD][&ShareType::Yao], 48.0);
+        assert_eq!(
+            wan.conversions[&(ShareType::Arithmetic, ShareType::Boolean)],
+            2696.4
+        );
+        for (op, costs) in &lan.ops {
+            for (ty, cost) in costs {
+                assert!(wan.ops[op][ty] >= *cost, "{} in {:?}", op, ty);
+            }
+        }
+        let path = std::env::temp_dir().join(format!("circ_costs_{}.json", std::process::id()));
+        fs::write(&path, cost_file(&[16])).unwrap();
+        let file = CostModel::load(path.to_str().unwrap(), 32);
+        assert_eq!(file.ops[&BV_ADD][&ShareType::Arithmetic], 16.0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn latency_favors_yao() {
+        let json = |and_rounds: &str| {
+            format!(
+                r#"{{"32": {{
+                    "and": {{ "b": {{ "1": 10 {} }}, "y": {{ "1": 20 }} }},
+                    "a2b": {{ "1": 1000 }}, "b2a": {{ "1": 1000 }}, "y2b": {{ "1": 1000 }},
+                    "b2y": {{ "1": 1000 }}, "y2a": {{ "1": 1000 }}, "a2y": {{ "1": 1000 }}
+                }}}}"#,
+                and_rounds
+            )
+        };
+        let v = |i: usize| leaf_term(Op::Var(format!("a{}", i), Sort::BitVector(32)));
+        let mut t = v(0);
+        for i in 1..=8 {
+            t = term![BV_AND; t, v(i)];
+        }
+        let cs = Computation {
+            outputs: vec![t.clone()],
+            metadata: ComputationMetadata::default(),
+            values: None,
+        };
+        let assign = |costs: &CostModel| {
+            let a = assign_with_costs(&cs, costs);
+            let rounds = assignment_rounds(&cs, &a, costs);
+            (*a.get(&t).unwrap(), rounds)
+        };
+        // A chain of 8 ANDs: cheaper in Boolean sharing, but that takes 8 rounds
+        let costs = CostModel::from_opa_json(&json(""), 32);
+        assert_eq!(assign(&costs), (ShareType::Boolean, 8.0));
+        let costs = costs.with_latency(1.0);
+        assert_eq!(assign(&costs), (ShareType::Boolean, 8.0));
+        let costs = costs.with_latency(100.0);
+        assert_eq!(assign(&costs), (ShareType::Yao, 0.0));
+        // ... unless the cost file says otherwise
+        let costs = CostModel::from_opa_json(&json(r#", "rounds": 0"#), 32).with_latency(100.0);
+        assert_eq!(assign(&costs), (ShareType::Boolean, 0.0));
+    }
+
     #[test]
     fn mul1_bv_opt() {
         let p = format!(
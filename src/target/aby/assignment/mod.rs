@@ -1,11 +1,17 @@
 //! Machinery for assigning operations to sharing schemes
 
-use crate::ir::term::{BvNaryOp, Computation, Op, PostOrderIter, TermMap};
+use crate::ir::term::{BvNaryOp, Computation, Op, PostOrderIter, Term, TermMap};
 
+use serde::{Deserialize, Serialize};
+
+pub mod greedy;
 pub mod ilp;
+pub mod partition;
+
+use ilp::CostModel;
 
 /// The sharing scheme used for an operation
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum ShareType {
     /// Arithmetic sharing (additive mod `Z_(2^l)`)
     Arithmetic,
@@ -31,6 +37,28 @@ impl ShareType {
 /// A map from terms (operations or inputs) to sharing schemes they use
 pub type SharingMap = TermMap<ShareType>;
 
+/// An algorithm that assigns sharings by cost
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Assigner {
+    /// Optimal, by solving an ILP for the whole circuit (see [ilp])
+    Ilp,
+    /// Greedy, improved by local search (see [greedy])
+    Greedy,
+    /// Optimal within regions of at most this many terms (see [partition])
+    Partition(usize),
+}
+
+impl Assigner {
+    /// Assign sharings to the terms of `c`, with the costs `costs`
+    pub fn assign(&self, c: &Computation, costs: &CostModel) -> SharingMap {
+        match self {
+            Assigner::Ilp => ilp::assign_with_costs(c, costs),
+            Assigner::Greedy => greedy::assign_greedy(c, costs),
+            Assigner::Partition(max_size) => partition::assign_partitioned(c, costs, *max_size),
+        }
+    }
+}
+
 /// Assigns boolean sharing to all terms
 pub fn all_boolean_sharing(c: &Computation) -> SharingMap {
     c.outputs
@@ -57,3 +85,95 @@ pub fn some_arith_sharing(c: &Computation) -> SharingMap {
         })
         .collect()
 }
+
+/// The terms of `c`, children first, and the terms that use each of them
+pub(crate) fn terms_and_uses(c: &Computation) -> (Vec<Term>, TermMap<Vec<Term>>) {
+    let mut terms = Vec::new();
+    let mut uses: TermMap<Vec<Term>> = TermMap::new();
+    for o in &c.outputs {
+        for t in PostOrderIter::new(o.clone()) {
+            if uses.contains_key(&t) {
+                continue;
+            }
+            uses.insert(t.clone(), Vec::new());
+            for c in &t.cs {
+                // A repeated child already has `t` as its last use
+                let c_uses = uses.get_mut(c).unwrap();
+                if c_uses.last() != Some(&t) {
+                    c_uses.push(t.clone());
+                }
+            }
+            terms.push(t);
+        }
+    }
+    (terms, uses)
+}
+
+/// Extend `rounds` with the round of communication in which each of `terms` is done, if they are
+/// evaluated with the sharings in `s_map`.
+///
+/// Each child of a term must come before it in `terms`, or already be in `rounds`; other children
+/// are taken to be done at round 0.
+pub(crate) fn add_rounds(
+    terms: &[Term],
+    s_map: &SharingMap,
+    costs: &CostModel,
+    rounds: &mut TermMap<f64>,
+) {
+    for t in terms {
+        let s = *s_map
+            .get(t)
+            .unwrap_or_else(|| panic!("No sharing type for {}", t));
+        let start =
+            t.cs.iter()
+                .filter_map(|c| {
+                    let c_done = rounds.get(c)?;
+                    Some(c_done + costs.conversion_rounds(*s_map.get(c).unwrap(), s))
+                })
+                .fold(0.0, f64::max);
+        rounds.insert(t.clone(), start + costs.op_rounds(t, s));
+    }
+}
+
+/// The rounds of communication on the critical path of `c`, with the sharings in `s_map`
+pub fn assignment_rounds(c: &Computation, s_map: &SharingMap, costs: &CostModel) -> f64 {
+    let (terms, _) = terms_and_uses(c);
+    let mut rounds = TermMap::new();
+    add_rounds(&terms, s_map, costs, &mut rounds);
+    terms
+        .iter()
+        .map(|t| *rounds.get(t).unwrap())
+        .fold(0.0, f64::max)
+}
+
+/// The cost of evaluating `c` with the sharings in `s_map`, according to `costs`.
+///
+/// This is the objective that [ilp::assign] minimizes: the cost of each term in its sharing, plus
+/// that of converting each term once to each other sharing that its uses need, plus the latency
+/// of each round of communication (see [assignment_rounds]).
+pub fn assignment_cost(c: &Computation, s_map: &SharingMap, costs: &CostModel) -> f64 {
+    let (terms, uses) = terms_and_uses(c);
+    let share = |t: &Term| {
+        *s_map
+            .get(t)
+            .unwrap_or_else(|| panic!("No sharing type for {}", t))
+    };
+    let mut total = 0.0;
+    for t in &terms {
+        let s = share(t);
+        total += costs
+            .op_cost(t, s)
+            .unwrap_or_else(|| panic!("{} cannot be evaluated in {:?}", t.op, s));
+        let mut targets: Vec<ShareType> = uses.get(t).unwrap().iter().map(share).collect();
+        targets.sort_by_key(|ty| SHARE_TYPES.iter().position(|s| s == ty));
+        targets.dedup();
+        total += targets
+            .into_iter()
+            .map(|to| costs.conversion_cost(s, to))
+            .sum::<f64>();
+    }
+    if costs.latency() > 0.0 {
+        total += costs.latency() * assignment_rounds(c, s_map, costs);
+    }
+    total
+}
@@ -0,0 +1,133 @@
+//! Sharing assignment by solving the ILP of [super::ilp] on one region of the circuit at a time
+//!
+//! The terms are split into connected regions of bounded size, grown from the inputs towards the
+//! outputs. Each region's ILP is solved with the sharings of earlier regions fixed, so
+//! conversions across region boundaries are paid for by the later region. The result is optimal
+//! within each region, but not overall; its cost approaches the exact assignment as regions grow.
+//!
+//! If the costs have a latency, each region's ILP also knows in which round the terms of earlier
+//! regions are done, but not how its own choices delay later regions.
+
+use super::ilp::{solve_region, CostModel};
+use super::{add_rounds, terms_and_uses, SharingMap};
+use crate::ir::term::*;
+
+use std::collections::VecDeque;
+
+/// The default maximum number of terms in a region
+pub const DEFAULT_REGION_SIZE: usize = 500;
+
+/// Split the terms of `c` into connected regions of at most `max_size` terms.
+///
+/// Each region is grown, breadth-first, from the earliest term (in post-order) that is not yet in
+/// a region, through both the children and the uses of its terms.
+pub fn regions(c: &Computation, max_size: usize) -> Vec<Vec<Term>> {
+    assert!(max_size > 0, "Regions must have at least one term");
+    let (terms, uses) = terms_and_uses(c);
+    let mut placed = TermSet::new();
+    let mut regions = Vec::new();
+    for seed in &terms {
+        if placed.contains(seed) {
+            continue;
+        }
+        let mut region = Vec::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(seed.clone());
+        placed.insert(seed.clone());
+        while let Some(t) = queue.pop_front() {
+            for n in t.cs.iter().chain(uses.get(&t).unwrap()) {
+                if region.len() + queue.len() + 1 < max_size && placed.insert(n.clone()) {
+                    queue.push_back(n.clone());
+                }
+            }
+            region.push(t);
+        }
+        regions.push(region);
+    }
+    regions
+}
+
+/// Assign sharings by solving the ILP for regions of at most `max_size` terms, in turn
+pub fn assign_partitioned(c: &Computation, costs: &CostModel, max_size: usize) -> SharingMap {
+    let (terms, uses) = terms_and_uses(c);
+    let order: TermMap<usize> = terms
+        .iter()
+        .enumerate()
+        .map(|(i, t)| (t.clone(), i))
+        .collect();
+    let mut assignment = SharingMap::new();
+    let mut rounds = TermMap::new();
+    for mut region in regions(c, max_size) {
+        for (t, s) in solve_region(&region, &uses, &assignment, &rounds, costs) {
+            assignment.insert(t, s);
+        }
+        if costs.latency() > 0.0 {
+            region.sort_by_key(|t| *order.get(t).unwrap());
+            add_rounds(&region, &assignment, costs, &mut rounds);
+        }
+    }
+    assignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::target::aby::assignment::{assignment_cost, ilp};
+
+    fn bv(name: &str) -> Term {
+        leaf_term(Op::Var(name.to_owned(), Sort::BitVector(32)))
+    }
+
+    /// Sums of products of pairs of inputs, compared to each other
+    fn cs() -> Computation {
+        let sums: Vec<Term> = (0..4)
+            .map(|i| {
+                let prods = (0..4)
+                    .map(|j| term![BV_MUL; bv(&format!("a{}", i)), bv(&format!("b{}", j))])
+                    .collect();
+                term(BV_ADD, prods)
+            })
+            .collect();
+        Computation {
+            outputs: sums
+                .windows(2)
+                .map(|w| term![BV_ULT; w[0].clone(), w[1].clone()])
+                .collect(),
+            metadata: ComputationMetadata::default(),
+            values: None,
+        }
+    }
+
+    #[test]
+    fn regions_cover_terms() {
+        let cs = cs();
+        let (terms, _) = terms_and_uses(&cs);
+        for size in &[1, 3, 10, 1000] {
+            let regions = regions(&cs, *size);
+            assert!(regions.iter().all(|r| !r.is_empty() && r.len() <= *size));
+            let all: Vec<Term> = regions.into_iter().flatten().collect();
+            assert_eq!(all.len(), terms.len());
+            let mut distinct = TermSet::new();
+            for t in all {
+                distinct.insert(t);
+            }
+            assert_eq!(distinct.len(), terms.len());
+        }
+        assert_eq!(regions(&cs, 1000).len(), 1);
+    }
+
+    #[test]
+    fn partitioned_cost() {
+        let cs = cs();
+        let costs = CostModel::default();
+        let exact = assignment_cost(&cs, &ilp::assign_with_costs(&cs, &costs), &costs);
+        let whole = assign_partitioned(&cs, &costs, 1000);
+        assert!((assignment_cost(&cs, &whole, &costs) - exact).abs() < 1e-6);
+        for size in &[1, 5, 20] {
+            let parts = assign_partitioned(&cs, &costs, *size);
+            let (terms, _) = terms_and_uses(&cs);
+            assert!(terms.iter().all(|t| parts.contains_key(t)));
+            assert!(assignment_cost(&cs, &parts, &costs) >= exact - 1e-6);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! A gate-level representation of ABY circuits
+//!
+//! [super::trans] lowers a computation to an [AbyCircuit]: a list of gates, each evaluated in one
+//! sharing, where every gate reads the outputs of earlier gates. Back-ends (e.g., the C++ emitter
+//! in [super::emit]) and analyses work from this list, rather than from the IR.
+
+use crate::ir::term::PartyId;
+use crate::target::aby::assignment::ShareType;
+
+use fxhash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use std::fmt::{self, Display, Formatter};
+
+/// A wire: the output of the gate at this index
+pub type Wire = usize;
+
+/// The operation of a gate
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GateOp {
+    /// The input `name`, given by `party`, or by both parties if `None`
+    In {
+        /// The name of the input in the IR
+        name: String,
+        /// The party that gives it
+        party: Option<PartyId>,
+    },
+    /// A constant
+    Const(u64),
+    /// Addition, modulo `2^width`
+    Add(Wire, Wire),
+    /// Subtraction, modulo `2^width`
+    Sub(Wire, Wire),
+    /// Multiplication, modulo `2^width`
+    Mul(Wire, Wire),
+    /// Bit-wise exclusive or
+    Xor(Wire, Wire),
+    /// Bit-wise and
+    And(Wire, Wire),
+    /// Bit-wise or
+    Or(Wire, Wire),
+    /// Unsigned greater-than; one bit wide
+    Gt(Wire, Wire),
+    /// Bit-wise negation
+    Inv(Wire),
+    /// `Mux(s, t, f)` is `t` if `s` is set, and `f` otherwise
+    Mux(Wire, Wire, Wire),
+    /// The value of a wire, converted to this gate's sharing
+    Conv(Wire),
+    /// `Slice(a, lo)` is bits `lo` through `lo + width - 1` of `a` (Boolean and Yao only)
+    Slice(Wire, usize),
+    /// `Concat(hi, lo)` is the bits of `hi` above those of `lo` (Boolean and Yao only)
+    Concat(Wire, Wire),
+}
+
+impl GateOp {
+    /// The wires this gate reads
+    pub fn args(&self) -> Vec<Wire> {
+        match self {
+            GateOp::In { .. } | GateOp::Const(_) => vec![],
+            GateOp::Inv(a) | GateOp::Conv(a) | GateOp::Slice(a, _) => vec![*a],
+            GateOp::Mux(s, t, f) => vec![*s, *t, *f],
+            GateOp::Add(a, b)
+            | GateOp::Sub(a, b)
+            | GateOp::Mul(a, b)
+            | GateOp::Xor(a, b)
+            | GateOp::And(a, b)
+            | GateOp::Or(a, b)
+            | GateOp::Gt(a, b)
+            | GateOp::Concat(a, b) => vec![*a, *b],
+        }
+    }
+
+    /// This operation, reading wire `f(w)` wherever it reads wire `w`
+    pub fn map_wires(&self, f: impl Fn(Wire) -> Wire) -> GateOp {
+        match self {
+            GateOp::In { .. } | GateOp::Const(_) => self.clone(),
+            GateOp::Add(a, b) => GateOp::Add(f(*a), f(*b)),
+            GateOp::Sub(a, b) => GateOp::Sub(f(*a), f(*b)),
+            GateOp::Mul(a, b) => GateOp::Mul(f(*a), f(*b)),
+            GateOp::Xor(a, b) => GateOp::Xor(f(*a), f(*b)),
+            GateOp::And(a, b) => GateOp::And(f(*a), f(*b)),
+            GateOp::Or(a, b) => GateOp::Or(f(*a), f(*b)),
+            GateOp::Gt(a, b) => GateOp::Gt(f(*a), f(*b)),
+            GateOp::Inv(a) => GateOp::Inv(f(*a)),
+            GateOp::Mux(s, t, e) => GateOp::Mux(f(*s), f(*t), f(*e)),
+            GateOp::Conv(a) => GateOp::Conv(f(*a)),
+            GateOp::Slice(a, lo) => GateOp::Slice(f(*a), *lo),
+            GateOp::Concat(a, b) => GateOp::Concat(f(*a), f(*b)),
+        }
+    }
+
+    /// A short name for the kind of gate
+    pub fn name(&self) -> &'static str {
+        match self {
+            GateOp::In { .. } => "in",
+            GateOp::Const(_) => "const",
+            GateOp::Add(..) => "add",
+            GateOp::Sub(..) => "sub",
+            GateOp::Mul(..) => "mul",
+            GateOp::Xor(..) => "xor",
+            GateOp::And(..) => "and",
+            GateOp::Or(..) => "or",
+            GateOp::Gt(..) => "gt",
+            GateOp::Inv(..) => "inv",
+            GateOp::Mux(..) => "mux",
+            GateOp::Conv(..) => "conv",
+            GateOp::Slice(..) => "slice",
+            GateOp::Concat(..) => "concat",
+        }
+    }
+}
+
+/// A gate
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Gate {
+    /// What it computes
+    pub op: GateOp,
+    /// The sharing it is evaluated in
+    pub share: ShareType,
+    /// The number of bits in its output
+    pub width: usize,
+}
+
+/// An ABY circuit
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AbyCircuit {
+    /// The gates, in evaluation order
+    pub gates: Vec<Gate>,
+    /// The wires that are revealed, in order
+    pub outputs: Vec<Wire>,
+    /// The parties the outputs are revealed to, or `None` for all parties
+    pub output_parties: Option<Vec<PartyId>>,
+}
+
+impl AbyCircuit {
+    /// Add a gate, returning its output wire.
+    ///
+    /// Panics if it reads a wire that does not exist yet.
+    pub fn add(&mut self, op: GateOp, share: ShareType, width: usize) -> Wire {
+        let w = self.gates.len();
+        for a in op.args() {
+            assert!(
+                a < w,
+                "Gate {:?} reads wire {}, which is not yet defined",
+                op,
+                a
+            );
+        }
+        self.gates.push(Gate { op, share, width });
+        w
+    }
+
+    /// The gate that drives wire `w`
+    pub fn gate(&self, w: Wire) -> &Gate {
+        &self.gates[w]
+    }
+
+    /// The input gates: their wires, names, and parties
+    pub fn inputs(&self) -> impl Iterator<Item = (Wire, &str, Option<PartyId>)> {
+        self.gates
+            .iter()
+            .enumerate()
+            .filter_map(|(w, g)| match &g.op {
+                GateOp::In { name, party } => Some((w, name.as_str(), *party)),
+                _ => None,
+            })
+    }
+
+    /// The number of gates of each kind, in each sharing
+    pub fn gate_counts(&self) -> FxHashMap<(&'static str, ShareType), usize> {
+        let mut counts = FxHashMap::default();
+        for g in &self.gates {
+            *counts.entry((g.op.name(), g.share)).or_insert(0) += 1;
+        }
+        counts
+    }
+}
+
+impl Display for AbyCircuit {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (w, g) in self.gates.iter().enumerate() {
+            write!(f, "w{} = {:?} {}", w, g.share, g.op.name())?;
+            match &g.op {
+                GateOp::In { name, party } => write!(f, " {} {:?}", name, party)?,
+                GateOp::Const(c) => write!(f, " {}", c)?,
+                GateOp::Slice(a, lo) => write!(f, " w{} {}", a, lo)?,
+                op => {
+                    for a in op.args() {
+                        write!(f, " w{}", a)?;
+                    }
+                }
+            }
+            writeln!(f, " : {}", g.width)?;
+        }
+        for w in &self.outputs {
+            match &self.output_parties {
+                Some(ps) => writeln!(f, "out w{} to {:?}", w, ps)?,
+                None => writeln!(f, "out w{}", w)?,
+            }
+        }
+        Ok(())
+    }
+}
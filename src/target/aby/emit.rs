@@ -0,0 +1,249 @@
+//! Emitting an [AbyCircuit] as C++ that builds it with ABY
+//!
+//! Inv and Or gates need to typecast the circuit object to a boolean circuit
+//! [Link to comment in EzPC Compiler](https://github.com/mpc-msri/EzPC/blob/da94a982709123c8186d27c9c93e27f243d85f0e/EzPC/EzPC/codegen.ml)
+//!
+//! Slices and concatenations of wires use the `slice` and `concat` helpers of the C++ template.
+//!
+//! Arithmetic shares have ABY's bit length (`bitlen`), whatever the width of their values; Boolean
+//! and Yao shares have exactly as many wires as their values have bits. So arithmetic shares are
+//! converted to Yao sharing and sliced to their values' width before they are revealed.
+
+use crate::target::aby::assignment::ShareType;
+use crate::target::aby::circuit::{AbyCircuit, GateOp, Wire};
+use crate::target::aby::trans::parse_var_name;
+
+use std::fmt::Write;
+
+const SERVER: u8 = 0;
+const CLIENT: u8 = 1;
+
+/// The C++ code for an ABY circuit
+pub struct AbyCpp {
+    /// Reads the inputs, and feeds them into the circuit
+    pub setup: String,
+    /// Builds the gates, and queues the outputs
+    pub circuit: String,
+}
+
+fn circ_name(s: ShareType) -> &'static str {
+    match s {
+        ShareType::Arithmetic => "acirc",
+        ShareType::Boolean => "bcirc",
+        ShareType::Yao => "ycirc",
+    }
+}
+
+/// The C++ variable that holds input `name`
+fn input_var(name: &str) -> String {
+    name.replace(".", "_")
+}
+
+/// The C++ variable that holds the share on wire `w`
+fn share_var(circ: &AbyCircuit, w: Wire) -> String {
+    match &circ.gate(w).op {
+        GateOp::In { name, .. } => format!("s_{}", input_var(name)),
+        _ => format!("s_{}", w),
+    }
+}
+
+/// Convert `x`, a share in sharing `from`, to sharing `to`
+fn conv(from: ShareType, to: ShareType, x: String) -> String {
+    match (from, to) {
+        (ShareType::Arithmetic, ShareType::Arithmetic)
+        | (ShareType::Boolean, ShareType::Boolean)
+        | (ShareType::Yao, ShareType::Yao) => x,
+        (ShareType::Arithmetic, ShareType::Boolean) => {
+            format!("bcirc->PutY2BGate(ycirc->PutA2YGate({}))", x)
+        }
+        (ShareType::Arithmetic, ShareType::Yao) => format!("ycirc->PutA2YGate({})", x),
+        (ShareType::Boolean, ShareType::Arithmetic) => format!("acirc->PutB2AGate({})", x),
+        (ShareType::Boolean, ShareType::Yao) => format!("ycirc->PutB2YGate({})", x),
+        (ShareType::Yao, ShareType::Arithmetic) => {
+            format!("acirc->PutB2AGate(bcirc->PutY2BGate({}))", x)
+        }
+        (ShareType::Yao, ShareType::Boolean) => format!("bcirc->PutY2BGate({})", x),
+    }
+}
+
+/// The C++ expression for the (non-input) gate driving `w`
+fn gate_expr(circ: &AbyCircuit, w: Wire) -> String {
+    let g = circ.gate(w);
+    let c = circ_name(g.share);
+    let v = |a: &Wire| share_var(circ, *a);
+    match &g.op {
+        GateOp::In { .. } => unreachable!("Inputs are set up separately"),
+        GateOp::Const(n) => format!("{}->PutCONSGate((uint64_t){}, (uint32_t){})", c, n, g.width),
+        GateOp::Add(a, b) => format!("{}->PutADDGate({}, {})", c, v(a), v(b)),
+        GateOp::Sub(a, b) => format!("{}->PutSUBGate({}, {})", c, v(a), v(b)),
+        GateOp::Mul(a, b) => format!("{}->PutMULGate({}, {})", c, v(a), v(b)),
+        GateOp::Xor(a, b) => format!("{}->PutXORGate({}, {})", c, v(a), v(b)),
+        GateOp::And(a, b) => format!("{}->PutANDGate({}, {})", c, v(a), v(b)),
+        GateOp::Or(a, b) => format!("((BooleanCircuit *) {})->PutORGate({}, {})", c, v(a), v(b)),
+        GateOp::Gt(a, b) => format!("{}->PutGTGate({}, {})", c, v(a), v(b)),
+        GateOp::Inv(a) => format!("((BooleanCircuit *) {})->PutINVGate({})", c, v(a)),
+        GateOp::Mux(s, t, f) => format!("{}->PutMUXGate({}, {}, {})", c, v(t), v(f), v(s)),
+        GateOp::Conv(a) => {
+            let from = circ.gate(*a).share;
+            let x = conv(from, g.share, v(a));
+            if from == ShareType::Arithmetic {
+                // Drop the bits of the arithmetic share above the value's width
+                format!("slice({}, {}, 0, {})", c, x, g.width)
+            } else {
+                x
+            }
+        }
+        GateOp::Slice(a, lo) => format!("slice({}, {}, {}, {})", c, v(a), lo, g.width),
+        GateOp::Concat(hi, lo) => format!(
+            "concat({}, {}, {}, {}, {})",
+            c,
+            v(hi),
+            circ.gate(*hi).width,
+            v(lo),
+            circ.gate(*lo).width
+        ),
+    }
+}
+
+/// The bit length to give input `w` to ABY
+fn input_width(circ: &AbyCircuit, w: Wire) -> String {
+    let g = circ.gate(w);
+    match g.share {
+        ShareType::Arithmetic => "bitlen".to_owned(),
+        _ => format!("(uint32_t){}", g.width),
+    }
+}
+
+/// Emit the setup code: parse each input from the command-line parameters (as a 64-bit unsigned
+/// integer), and give it to ABY.
+///
+/// Each party gives its own inputs, and dummies for the other party's. Public inputs are
+/// constants.
+fn emit_setup(circ: &AbyCircuit) -> String {
+    let mut out = String::new();
+    let inputs: Vec<_> = circ.inputs().collect();
+    for (w, name, party) in &inputs {
+        writeln!(
+            out,
+            "uint64_t {} = std::strtoull(params[\"{}\"].c_str(), nullptr, 10);",
+            input_var(name),
+            parse_var_name(name.to_string())
+        )
+        .unwrap();
+        writeln!(out, "share* {};", share_var(circ, *w)).unwrap();
+        match party {
+            None | Some(SERVER) | Some(CLIENT) => {}
+            Some(p) => panic!(
+                "ABY supports two parties, but input {} is from party {}; \
+                 use `--mp-spdz` to emit an MP-SPDZ program instead",
+                name,
+                p + 1
+            ),
+        }
+    }
+    for (w, name, _) in inputs.iter().filter(|(_, _, p)| p.is_none()) {
+        writeln!(
+            out,
+            "{} = {}->PutCONSGate((uint64_t){}, {});",
+            share_var(circ, *w),
+            circ_name(circ.gate(*w).share),
+            input_var(name),
+            input_width(circ, *w)
+        )
+        .unwrap();
+    }
+    for (role, role_name) in &[(SERVER, "SERVER"), (CLIENT, "CLIENT")] {
+        writeln!(out, "if (role == {}) {{", role_name).unwrap();
+        for (w, name, _) in inputs.iter().filter(|(_, _, p)| *p == Some(*role)) {
+            writeln!(
+                out,
+                "\t{} = {}->PutINGate({}, {}, {});",
+                share_var(circ, *w),
+                circ_name(circ.gate(*w).share),
+                input_var(name),
+                input_width(circ, *w),
+                role_name
+            )
+            .unwrap();
+        }
+        for (w, _, _) in inputs
+            .iter()
+            .filter(|(_, _, p)| p.is_some() && *p != Some(*role))
+        {
+            writeln!(
+                out,
+                "\t{} = {}->PutDummyINGate({});",
+                share_var(circ, *w),
+                circ_name(circ.gate(*w).share),
+                input_width(circ, *w)
+            )
+            .unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+    }
+    out
+}
+
+/// The ABY role that the outputs are revealed to
+fn output_role(circ: &AbyCircuit) -> &'static str {
+    let mut ps = match &circ.output_parties {
+        None => return "ALL",
+        Some(ps) => ps.clone(),
+    };
+    ps.sort_unstable();
+    ps.dedup();
+    match ps.as_slice() {
+        [SERVER] => "SERVER",
+        [CLIENT] => "CLIENT",
+        [SERVER, CLIENT] => "ALL",
+        ps => panic!("ABY cannot reveal outputs to parties {:?}", ps),
+    }
+}
+
+/// Emit the gates, and the outputs.
+fn emit_gates(circ: &AbyCircuit) -> String {
+    let mut out = String::new();
+    for (w, g) in circ.gates.iter().enumerate() {
+        if let GateOp::In { .. } = g.op {
+            continue;
+        }
+        writeln!(
+            out,
+            "share* {} = {};",
+            share_var(circ, w),
+            gate_expr(circ, w)
+        )
+        .unwrap();
+    }
+    let to = output_role(circ);
+    for w in &circ.outputs {
+        let g = circ.gate(*w);
+        let (c, x) = if g.share == ShareType::Arithmetic {
+            // Drop the bits of the arithmetic share above the value's width
+            let y = conv(ShareType::Arithmetic, ShareType::Yao, share_var(circ, *w));
+            (
+                ShareType::Yao,
+                format!("slice(ycirc, {}, 0, {})", y, g.width),
+            )
+        } else {
+            (g.share, share_var(circ, *w))
+        };
+        writeln!(
+            out,
+            "add_to_output_queue(out_q, {}->PutOUTGate({}, {}), role, std::cout);",
+            circ_name(c),
+            x,
+            to
+        )
+        .unwrap();
+    }
+    out
+}
+
+/// Emit C++ that builds `circ` with ABY.
+pub fn to_cpp(circ: &AbyCircuit) -> AbyCpp {
+    AbyCpp {
+        setup: emit_setup(circ),
+        circuit: emit_gates(circ),
+    }
+}
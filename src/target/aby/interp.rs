@@ -0,0 +1,1011 @@
+//! Plaintext evaluation of ABY circuits, and of the C++ that [super::emit] produces for them
+//!
+//! [eval_circuit] evaluates an [AbyCircuit] gate by gate, following the semantics of ABY's gates,
+//! and of the `slice` and `concat` helpers of the C++ template. Like ABY, it rejects gates given
+//! shares of the wrong sharing, and gates that a sharing does not support (e.g., comparisons in
+//! arithmetic sharing).
+//!
+//! [interpret] runs the emitted C++ (both parties' code, in one process) with the same semantics,
+//! to check that it builds the circuit it was emitted from.
+//!
+//! This lets us test MPC compilation against [eval], without building ABY.
+
+use crate::ir::term::*;
+use crate::target::aby::assignment::ilp::{assign_with_costs, CostModel};
+use crate::target::aby::assignment::{ShareType, SharingMap};
+use crate::target::aby::circuit::{AbyCircuit, GateOp};
+use crate::target::aby::emit::to_cpp;
+use crate::target::aby::trans::{array_keys, parse_var_name, to_aby_circuit, write_aby_circuit};
+
+use fxhash::FxHashMap;
+use thiserror::Error;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+/// An error in interpreting ABY code
+pub enum InterpError {
+    #[error("Cannot parse '{0}'")]
+    /// The code is not of the form we emit
+    Parse(String),
+    #[error("No value for '{0}'")]
+    /// A parameter or variable is missing
+    Unbound(String),
+    #[error("Unknown gate or function '{0}'")]
+    /// A call to something we do not model
+    UnknownGate(String),
+    #[error("{1:?} sharing does not support '{0}'")]
+    /// A gate that the circuit does not support
+    Unsupported(String, ShareType),
+    #[error("'{0}' in {1:?} sharing given a share in {2:?} sharing")]
+    /// A gate given shares of the wrong sharing
+    WrongSharing(String, ShareType, ShareType),
+    #[error("'{0}' expects {1}")]
+    /// An argument of the wrong kind
+    BadArgument(String, String),
+    #[error("Expected {0} outputs, but got {1}")]
+    /// The interpreted code has the wrong number of outputs
+    OutputCount(usize, usize),
+    #[error("Output {0} should be {1}, but is {2}")]
+    /// The evaluated result differs from [eval]
+    Mismatch(usize, u64, u64),
+    #[error("The circuit gives output {0} as {1}, but its C++ gives {2}")]
+    /// The emitted C++ computes something other than its circuit
+    Emitted(usize, u64, u64),
+}
+
+/// A plaintext share
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Share {
+    /// The sharing it is in
+    pub ty: ShareType,
+    /// The number of wires
+    pub width: u32,
+    /// The value of those wires
+    pub value: u64,
+}
+
+/// The result of evaluating an expression
+#[derive(Debug, Clone)]
+enum Val {
+    Int(u64),
+    Circ(ShareType),
+    Share(Share),
+    /// A placeholder for another party's input
+    Dummy,
+    /// A symbol we need not interpret (e.g., `role`, `ALL`)
+    Sym,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Num(u64),
+    Ident(String),
+    Call(Option<Box<Expr>>, String, Vec<Expr>),
+}
+
+/// Types that we skip casts to
+const CAST_TYPES: [&str; 6] = [
+    "uint64_t",
+    "uint32_t",
+    "int32_t",
+    "Circuit",
+    "BooleanCircuit",
+    "ArithmeticCircuit",
+];
+
+fn tokenize(line: &str) -> Result<Vec<String>, InterpError> {
+    let mut tokens = Vec::new();
+    let cs: Vec<char> = line.chars().collect();
+    let mut i = 0;
+    while i < cs.len() {
+        let c = cs[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_alphanumeric() || c == '_' {
+            let start = i;
+            while i < cs.len()
+                && (cs[i].is_alphanumeric()
+                    || cs[i] == '_'
+                    || (cs[i] == ':' && cs.get(i + 1) == Some(&':'))
+                    || (cs[i] == ':' && i > 0 && cs[i - 1] == ':'))
+            {
+                i += 1;
+            }
+            tokens.push(cs[start..i].iter().collect());
+        } else if c == '-' && cs.get(i + 1) == Some(&'>') {
+            tokens.push("->".to_owned());
+            i += 2;
+        } else if "()*,;=".contains(c) {
+            tokens.push(c.to_string());
+            i += 1;
+        } else {
+            return Err(InterpError::Parse(line.to_owned()));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    line: &'a str,
+    tokens: Vec<String>,
+    i: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self, k: usize) -> Option<&str> {
+        self.tokens.get(self.i + k).map(|s| s.as_str())
+    }
+    fn next(&mut self) -> Result<String, InterpError> {
+        let t = self.tokens.get(self.i).cloned().ok_or_else(|| self.err())?;
+        self.i += 1;
+        Ok(t)
+    }
+    fn expect(&mut self, t: &str) -> Result<(), InterpError> {
+        if self.next()? == t {
+            Ok(())
+        } else {
+            Err(self.err())
+        }
+    }
+    fn err(&self) -> InterpError {
+        InterpError::Parse(self.line.to_owned())
+    }
+    fn done(&self) -> bool {
+        self.i == self.tokens.len()
+    }
+
+    fn expr(&mut self) -> Result<Expr, InterpError> {
+        let mut e = self.primary()?;
+        while self.peek(0) == Some("->") {
+            self.next()?;
+            let name = self.next()?;
+            let args = self.args()?;
+            e = Expr::Call(Some(Box::new(e)), name, args);
+        }
+        Ok(e)
+    }
+
+    fn primary(&mut self) -> Result<Expr, InterpError> {
+        let t = self.next()?;
+        if t == "(" {
+            let is_cast = self.peek(0).map_or(false, |t| CAST_TYPES.contains(&t))
+                && (self.peek(1) == Some(")")
+                    || (self.peek(1) == Some("*") && self.peek(2) == Some(")")));
+            if is_cast {
+                while self.next()? != ")" {}
+                self.primary()
+            } else {
+                let e = self.expr()?;
+                self.expect(")")?;
+                Ok(e)
+            }
+        } else if let Some(n) = parse_num(&t) {
+            Ok(Expr::Num(n))
+        } else if t
+            .chars()
+            .next()
+            .map_or(false, |c| c.is_alphabetic() || c == '_')
+        {
+            if self.peek(0) == Some("(") {
+                let args = self.args()?;
+                Ok(Expr::Call(None, t, args))
+            } else {
+                Ok(Expr::Ident(t))
+            }
+        } else {
+            Err(self.err())
+        }
+    }
+
+    fn args(&mut self) -> Result<Vec<Expr>, InterpError> {
+        self.expect("(")?;
+        let mut args = Vec::new();
+        if self.peek(0) == Some(")") {
+            self.next()?;
+            return Ok(args);
+        }
+        loop {
+            args.push(self.expr()?);
+            match self.next()?.as_str() {
+                ")" => return Ok(args),
+                "," => {}
+                _ => return Err(self.err()),
+            }
+        }
+    }
+}
+
+/// Parse a C++ integer literal (decimal, `0b` binary, or `0x` hexadecimal).
+fn parse_num(t: &str) -> Option<u64> {
+    if let Some(b) = t.strip_prefix("0b") {
+        u64::from_str_radix(b, 2).ok()
+    } else if let Some(x) = t.strip_prefix("0x") {
+        u64::from_str_radix(x, 16).ok()
+    } else {
+        t.parse().ok()
+    }
+}
+
+fn mask(width: u32) -> u64 {
+    if width >= 64 {
+        u64::MAX
+    } else {
+        (1u64 << width) - 1
+    }
+}
+
+/// Interprets ABY code.
+struct Interpreter<'a> {
+    params: &'a FxHashMap<String, u64>,
+    bitlen: u32,
+    vars: FxHashMap<String, Val>,
+    outputs: Vec<u64>,
+}
+
+impl<'a> Interpreter<'a> {
+    fn stmt(&mut self, line: &str) -> Result<(), InterpError> {
+        let line = line.trim();
+        // Both parties' blocks run: dummy inputs are skipped, so each input gets its real value.
+        if line.is_empty() || line == "}" || line.starts_with("if (role ==") {
+            return Ok(());
+        }
+        if let Some(rest) = line.strip_prefix("uint64_t ") {
+            // uint64_t x = std::strtoull(params["p"].c_str(), nullptr, 10);
+            let err = || InterpError::Parse(line.to_owned());
+            let name = rest.split(' ').next().ok_or_else(err)?;
+            let param = rest
+                .split("params[\"")
+                .nth(1)
+                .and_then(|r| r.split('"').next())
+                .ok_or_else(err)?;
+            let v = *self
+                .params
+                .get(param)
+                .ok_or_else(|| InterpError::Unbound(param.to_owned()))?;
+            self.vars.insert(name.to_owned(), Val::Int(v));
+            return Ok(());
+        }
+        let mut p = Parser {
+            line,
+            tokens: tokenize(line)?,
+            i: 0,
+        };
+        if p.peek(0) == Some("share") && p.peek(1) == Some("*") {
+            p.i += 2;
+        }
+        let name = if p.peek(1) == Some("=") || p.peek(1) == Some(";") {
+            let name = p.next()?;
+            if p.next()? == ";" {
+                // A declaration
+                return if p.done() { Ok(()) } else { Err(p.err()) };
+            }
+            Some(name)
+        } else {
+            None
+        };
+        let e = p.expr()?;
+        p.expect(";")?;
+        if !p.done() {
+            return Err(p.err());
+        }
+        let v = self.eval(&e)?;
+        match (name, v) {
+            (Some(_), Val::Dummy) => {}
+            (Some(name), v) => {
+                self.vars.insert(name, v);
+            }
+            (None, _) => {}
+        }
+        Ok(())
+    }
+
+    fn eval(&mut self, e: &Expr) -> Result<Val, InterpError> {
+        Ok(match e {
+            Expr::Num(n) => Val::Int(*n),
+            Expr::Ident(i) => match i.as_str() {
+                "acirc" => Val::Circ(ShareType::Arithmetic),
+                "bcirc" => Val::Circ(ShareType::Boolean),
+                "ycirc" => Val::Circ(ShareType::Yao),
+                "bitlen" => Val::Int(self.bitlen as u64),
+                "role" | "out_q" | "ALL" | "SERVER" | "CLIENT" | "std::cout" => Val::Sym,
+                _ => self
+                    .vars
+                    .get(i)
+                    .cloned()
+                    .ok_or_else(|| InterpError::Unbound(i.clone()))?,
+            },
+            Expr::Call(recv, name, args) => {
+                let args = args
+                    .iter()
+                    .map(|a| self.eval(a))
+                    .collect::<Result<Vec<_>, _>>()?;
+                match recv {
+                    Some(r) => match self.eval(r)? {
+                        Val::Circ(c) => gate(c, name, args, self.bitlen)?,
+                        _ => {
+                            return Err(InterpError::BadArgument(name.clone(), "a circuit".into()))
+                        }
+                    },
+                    None => self.call(name, args)?,
+                }
+            }
+        })
+    }
+
+    fn call(&mut self, name: &str, args: Vec<Val>) -> Result<Val, InterpError> {
+        if name == "add_to_output_queue" {
+            let s = share_arg(name, &args, 1)?;
+            self.outputs.push(s.value);
+            return Ok(Val::Sym);
+        }
+        let c = match args.get(0) {
+            Some(Val::Circ(c)) => *c,
+            _ => return Err(InterpError::UnknownGate(name.to_owned())),
+        };
+        let x = share_arg(name, &args, 1)?;
+        let s = match name {
+            "slice" => {
+                let lo = int_arg(name, &args, 2)? as u32;
+                slice(c, &x, lo, int_arg(name, &args, 3)? as u32)?
+            }
+            "concat" => {
+                let hi_w = int_arg(name, &args, 2)? as u32;
+                let y = share_arg(name, &args, 3)?;
+                concat(c, &x, hi_w, &y, int_arg(name, &args, 4)? as u32)?
+            }
+            _ => return Err(InterpError::UnknownGate(name.to_owned())),
+        };
+        Ok(Val::Share(s))
+    }
+}
+
+fn share_arg(gate: &str, args: &[Val], i: usize) -> Result<Share, InterpError> {
+    match args.get(i) {
+        Some(Val::Share(s)) => Ok(s.clone()),
+        _ => Err(InterpError::BadArgument(gate.to_owned(), "a share".into())),
+    }
+}
+
+fn int_arg(gate: &str, args: &[Val], i: usize) -> Result<u64, InterpError> {
+    match args.get(i) {
+        Some(Val::Int(n)) => Ok(*n),
+        _ => Err(InterpError::BadArgument(
+            gate.to_owned(),
+            "an integer".into(),
+        )),
+    }
+}
+
+fn check_sharing(gate: &str, c: ShareType, s: &Share) -> Result<(), InterpError> {
+    if s.ty == c {
+        Ok(())
+    } else {
+        Err(InterpError::WrongSharing(gate.to_owned(), c, s.ty))
+    }
+}
+
+/// A share in sharing `c` of `value`, on `width` wires.
+///
+/// Like ABY, arithmetic shares are always `bitlen` bits wide.
+fn share(c: ShareType, width: u32, value: u64, bitlen: u32) -> Share {
+    let width = if c == ShareType::Arithmetic {
+        bitlen
+    } else {
+        width
+    };
+    Share {
+        ty: c,
+        width,
+        value: value & mask(width),
+    }
+}
+
+/// Apply a gate of kind `kind` (see [GateOp::name]), in sharing `c`, to `args`, getting a share
+/// on `width` wires.
+///
+/// Conversions take a share in another sharing; other gates take shares in `c`. Arithmetic
+/// sharing only supports addition, subtraction and multiplication.
+fn apply(
+    kind: &str,
+    c: ShareType,
+    args: &[Share],
+    width: u32,
+    bitlen: u32,
+) -> Result<Share, InterpError> {
+    if kind != "conv" {
+        for a in args {
+            check_sharing(kind, c, a)?;
+        }
+    }
+    let arithmetic = matches!(kind, "add" | "sub" | "mul" | "conv");
+    if c == ShareType::Arithmetic && !arithmetic {
+        return Err(InterpError::Unsupported(kind.to_owned(), c));
+    }
+    let v = |i: usize| -> Result<u64, InterpError> {
+        args.get(i)
+            .map(|s| s.value)
+            .ok_or_else(|| InterpError::BadArgument(kind.to_owned(), "more shares".into()))
+    };
+    let value = match kind {
+        "add" => v(0)?.wrapping_add(v(1)?),
+        "sub" => v(0)?.wrapping_sub(v(1)?),
+        "mul" => v(0)?.wrapping_mul(v(1)?),
+        "xor" => v(0)? ^ v(1)?,
+        "and" => v(0)? & v(1)?,
+        "or" => v(0)? | v(1)?,
+        "gt" => (v(0)? > v(1)?) as u64,
+        "inv" => !v(0)?,
+        "mux" => {
+            if v(0)? & 1 == 1 {
+                v(1)?
+            } else {
+                v(2)?
+            }
+        }
+        "conv" => v(0)?,
+        _ => return Err(InterpError::UnknownGate(kind.to_owned())),
+    };
+    Ok(share(c, width, value, bitlen))
+}
+
+/// Bits `lo` through `lo + width - 1` of `x`, as the template's `slice` computes them.
+fn slice(c: ShareType, x: &Share, lo: u32, width: u32) -> Result<Share, InterpError> {
+    check_sharing("slice", c, x)?;
+    if c == ShareType::Arithmetic {
+        return Err(InterpError::Unsupported("slice".into(), c));
+    }
+    if lo + width > x.width {
+        return Err(InterpError::BadArgument(
+            "slice".into(),
+            format!("at most {} wires", x.width),
+        ));
+    }
+    Ok(Share {
+        ty: c,
+        width,
+        value: x.value.checked_shr(lo).unwrap_or(0) & mask(width),
+    })
+}
+
+/// The low `hi_w` bits of `hi` above the low `lo_w` bits of `lo`, as the template's `concat`
+/// computes them.
+fn concat(
+    c: ShareType,
+    hi: &Share,
+    hi_w: u32,
+    lo: &Share,
+    lo_w: u32,
+) -> Result<Share, InterpError> {
+    check_sharing("concat", c, hi)?;
+    check_sharing("concat", c, lo)?;
+    if c == ShareType::Arithmetic {
+        return Err(InterpError::Unsupported("concat".into(), c));
+    }
+    if hi_w > hi.width || lo_w > lo.width {
+        return Err(InterpError::BadArgument(
+            "concat".into(),
+            "widths of at most those of the shares".into(),
+        ));
+    }
+    let width = hi_w + lo_w;
+    let value = (hi.value & mask(hi_w)).checked_shl(lo_w).unwrap_or(0) | (lo.value & mask(lo_w));
+    Ok(Share {
+        ty: c,
+        width,
+        value: value & mask(width),
+    })
+}
+
+/// Evaluate the C++ gate `name`, of the circuit for sharing `c`.
+fn gate(c: ShareType, name: &str, args: Vec<Val>, bitlen: u32) -> Result<Val, InterpError> {
+    use ShareType::*;
+    let shares = |n: usize| -> Result<Vec<Share>, InterpError> {
+        (0..n).map(|i| share_arg(name, &args, i)).collect()
+    };
+    let binary = |kind: &str| -> Result<Share, InterpError> {
+        let s = shares(2)?;
+        apply(kind, c, &s, s[0].width.max(s[1].width), bitlen)
+    };
+    let convert = |from: ShareType, to: ShareType| -> Result<Share, InterpError> {
+        if c != to {
+            return Err(InterpError::Unsupported(name.to_owned(), c));
+        }
+        let s = share_arg(name, &args, 0)?;
+        check_sharing(name, from, &s)?;
+        apply("conv", c, &[s.clone()], s.width, bitlen)
+    };
+    Ok(Val::Share(match name {
+        "PutCONSGate" | "PutINGate" => {
+            let w = int_arg(name, &args, 1)? as u32;
+            share(c, w, int_arg(name, &args, 0)?, bitlen)
+        }
+        "PutDummyINGate" => return Ok(Val::Dummy),
+        "PutOUTGate" => {
+            let s = share_arg(name, &args, 0)?;
+            check_sharing(name, c, &s)?;
+            s
+        }
+        "PutADDGate" => binary("add")?,
+        "PutSUBGate" => binary("sub")?,
+        "PutMULGate" => binary("mul")?,
+        "PutXORGate" => binary("xor")?,
+        "PutANDGate" => binary("and")?,
+        "PutORGate" => binary("or")?,
+        "PutGTGate" => apply("gt", c, &shares(2)?, 1, bitlen)?,
+        "PutINVGate" => {
+            let s = shares(1)?;
+            apply("inv", c, &s, s[0].width, bitlen)?
+        }
+        "PutMUXGate" => {
+            // ABY's multiplexer takes the selector last.
+            let s = shares(3)?;
+            let w = s[0].width.max(s[1].width);
+            let args = [s[2].clone(), s[0].clone(), s[1].clone()];
+            apply("mux", c, &args, w, bitlen)?
+        }
+        "PutA2YGate" => convert(Arithmetic, Yao)?,
+        "PutB2YGate" => convert(Boolean, Yao)?,
+        "PutY2BGate" => convert(Yao, Boolean)?,
+        "PutB2AGate" => convert(Boolean, Arithmetic)?,
+        _ => return Err(InterpError::UnknownGate(name.to_owned())),
+    }))
+}
+
+/// Evaluate `circ` on plaintext values, with the command-line `params` that the parties give,
+/// and ABY's bit length set to `bitlen`.
+///
+/// Returns the outputs, in order, each reduced to its gate's width.
+pub fn eval_circuit(
+    circ: &AbyCircuit,
+    params: &FxHashMap<String, u64>,
+    bitlen: u32,
+) -> Result<Vec<u64>, InterpError> {
+    let mut shares: Vec<Share> = Vec::new();
+    for g in &circ.gates {
+        let width = g.width as u32;
+        let args: Vec<Share> = g.op.args().iter().map(|a| shares[*a].clone()).collect();
+        let s = match &g.op {
+            GateOp::In { name, .. } => {
+                let param = parse_var_name(name.clone());
+                let v = *params
+                    .get(&param)
+                    .ok_or_else(|| InterpError::Unbound(param.clone()))?;
+                share(g.share, width, v, bitlen)
+            }
+            GateOp::Const(n) => share(g.share, width, *n, bitlen),
+            GateOp::Slice(_, lo) => slice(g.share, &args[0], *lo as u32, width)?,
+            GateOp::Concat(..) => {
+                concat(g.share, &args[0], args[0].width, &args[1], args[1].width)?
+            }
+            op => apply(op.name(), g.share, &args, width, bitlen)?,
+        };
+        shares.push(s);
+    }
+    Ok(circ
+        .outputs
+        .iter()
+        .map(|o| shares[*o].value & mask(circ.gate(*o).width as u32))
+        .collect())
+}
+
+/// Interpret ABY `code`, with the command-line `params` that the parties give, and ABY's bit
+/// length set to `bitlen`.
+///
+/// Returns the outputs, in order.
+pub fn interpret(
+    code: &str,
+    params: &FxHashMap<String, u64>,
+    bitlen: u32,
+) -> Result<Vec<u64>, InterpError> {
+    let mut interp = Interpreter {
+        params,
+        bitlen,
+        vars: FxHashMap::default(),
+        outputs: Vec::new(),
+    };
+    for line in code.lines() {
+        interp.stmt(line)?;
+    }
+    Ok(interp.outputs)
+}
+
+/// The values ABY would output for `v`: one per boolean or bit-vector in it.
+fn aby_values(v: &Value) -> Vec<u64> {
+    match v {
+        Value::Bool(b) => vec![*b as u64],
+        Value::BitVector(b) => vec![b.uint().to_u64().expect("bit-vector too wide for ABY")],
+        Value::Tuple(vs) => vs.iter().flat_map(aby_values).collect(),
+        Value::Array(Sort::Array(k, _, _), d, m, n) => array_keys(k, *n)
+            .iter()
+            .flat_map(|i| aby_values(m.get(i).unwrap_or(d)))
+            .collect(),
+        v => panic!("Cannot output {} from ABY", v),
+    }
+}
+
+/// Lower `cs` to ABY (assigning sharings as [super::trans::to_aby] does), evaluate the result on
+/// the input `values`, and check that its outputs are those that [eval] gives.
+///
+/// Also checks that the C++ written to `work_dir` computes the same outputs.
+pub fn check_against_eval(
+    cs: &Computation,
+    values: &FxHashMap<String, Value>,
+    work_dir: &Path,
+) -> Result<(), InterpError> {
+    let (name, lang) = (PathBuf::from("check"), "interp".to_owned());
+    let circ = to_aby_circuit(cs, &assign_with_costs(cs, &CostModel::default()));
+    write_aby_circuit(&circ, &name, &lang, work_dir);
+    let mut code = String::new();
+    for part in &["setup", "circuit"] {
+        let path = work_dir.join(format!("check_interp_{}_tmp.txt", part));
+        code += &fs::read_to_string(&path).expect("Unable to read the file");
+        fs::remove_file(&path).expect("Unable to remove the file");
+    }
+    compare_with_eval(cs, &circ, &code, values)
+}
+
+/// Lower `cs` to ABY, with the sharings in `s_map`, evaluate the result on the input `values`,
+/// and check that its outputs are those that [eval] gives, as are those of its C++.
+pub fn check_assignment_against_eval(
+    cs: &Computation,
+    s_map: &SharingMap,
+    values: &FxHashMap<String, Value>,
+) -> Result<(), InterpError> {
+    let circ = to_aby_circuit(cs, s_map);
+    let cpp = to_cpp(&circ);
+    compare_with_eval(
+        cs,
+        &circ,
+        &format!("{}\n{}", cpp.setup, cpp.circuit),
+        values,
+    )
+}
+
+/// Evaluate `circ`, the ABY lowering of `cs`, on the input `values`, and check that its outputs
+/// are those that [eval] gives. Then check that `code`, its C++, gives the same outputs.
+fn compare_with_eval(
+    cs: &Computation,
+    circ: &AbyCircuit,
+    code: &str,
+    values: &FxHashMap<String, Value>,
+) -> Result<(), InterpError> {
+    let params = values
+        .iter()
+        .map(|(n, v)| (parse_var_name(n.clone()), aby_values(v)[0]))
+        .collect();
+    let outputs = eval_circuit(circ, &params, 32)?;
+    let expected: Vec<u64> = cs
+        .outputs
+        .iter()
+        .flat_map(|o| aby_values(&eval(o, values)))
+        .collect();
+    if outputs.len() != expected.len() {
+        return Err(InterpError::OutputCount(expected.len(), outputs.len()));
+    }
+    for (i, (expected, actual)) in expected.into_iter().zip(&outputs).enumerate() {
+        if expected != *actual {
+            return Err(InterpError::Mismatch(i, expected, *actual));
+        }
+    }
+    let emitted = interpret(code, &params, 32)?;
+    if emitted.len() != outputs.len() {
+        return Err(InterpError::OutputCount(outputs.len(), emitted.len()));
+    }
+    for (i, (circuit, cpp)) in outputs.into_iter().zip(emitted).enumerate() {
+        if circuit != cpp {
+            return Err(InterpError::Emitted(i, circuit, cpp));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::target::aby::circuit::Wire;
+
+    fn params(ps: &[(&str, u64)]) -> FxHashMap<String, u64> {
+        ps.iter().map(|(n, v)| (n.to_string(), *v)).collect()
+    }
+
+    fn input(c: &mut AbyCircuit, name: &str, party: u8, share: ShareType, width: usize) -> Wire {
+        let op = GateOp::In {
+            name: name.to_owned(),
+            party: Some(party),
+        };
+        c.add(op, share, width)
+    }
+
+    /// Evaluate `circ`, and check that its C++ computes the same outputs.
+    fn eval_and_emit(
+        circ: &AbyCircuit,
+        ps: &FxHashMap<String, u64>,
+        bitlen: u32,
+    ) -> Result<Vec<u64>, InterpError> {
+        let outputs = eval_circuit(circ, ps, bitlen)?;
+        let cpp = to_cpp(circ);
+        let code = format!("{}\n{}", cpp.setup, cpp.circuit);
+        assert_eq!(interpret(&code, ps, bitlen).unwrap(), outputs);
+        Ok(outputs)
+    }
+
+    #[test]
+    fn gates() {
+        use ShareType::*;
+        let mut c = AbyCircuit::default();
+        let a = input(&mut c, "a_v0", 0, Arithmetic, 32);
+        let b = input(&mut c, "b_v0", 1, Arithmetic, 32);
+        let prod = c.add(GateOp::Mul(a, b), Arithmetic, 32);
+        let prod_y = c.add(GateOp::Conv(prod), Yao, 32);
+        let a_y = c.add(GateOp::Conv(a), Yao, 32);
+        let gt = c.add(GateOp::Gt(prod_y, a_y), Yao, 1);
+        let gt_b = c.add(GateOp::Conv(gt), Boolean, 1);
+        let not = c.add(GateOp::Inv(gt_b), Boolean, 1);
+        let seven = c.add(GateOp::Const(7), Boolean, 32);
+        let prod_b = c.add(GateOp::Conv(prod_y), Boolean, 32);
+        let mux = c.add(GateOp::Mux(not, seven, prod_b), Boolean, 32);
+        c.outputs = vec![prod, not, mux];
+        let out = eval_and_emit(&c, &params(&[("a", 6), ("b", 3)]), 32).unwrap();
+        assert_eq!(out, vec![18, 0, 18]);
+        let out = eval_and_emit(&c, &params(&[("a", 6), ("b", 0)]), 32).unwrap();
+        assert_eq!(out, vec![0, 1, 7]);
+    }
+
+    #[test]
+    fn arithmetic_shares_have_bitlen_bits() {
+        use ShareType::*;
+        for bitlen in &[32, 16] {
+            // Arithmetic gates are as wide as ABY's bit length, whatever their arguments' widths.
+            let w = *bitlen as usize;
+            let mut c = AbyCircuit::default();
+            let x = c.add(GateOp::Const(200), Arithmetic, 8);
+            let sum = c.add(GateOp::Add(x, x), Arithmetic, w);
+            let prod = c.add(GateOp::Mul(sum, sum), Arithmetic, w);
+            let y = c.add(GateOp::Const(200), Boolean, 8);
+            let bool_sum = c.add(GateOp::Add(y, y), Boolean, 8);
+            let y_a = c.add(GateOp::Conv(y), Arithmetic, 8);
+            let mixed = c.add(GateOp::Mul(y_a, sum), Arithmetic, w);
+            c.outputs = vec![sum, prod, bool_sum, mixed];
+            let m = mask(*bitlen);
+            assert_eq!(
+                eval_and_emit(&c, &params(&[]), *bitlen).unwrap(),
+                vec![400, 160000 & m, 144, 80000 & m]
+            );
+        }
+    }
+
+    #[test]
+    fn wide_inputs() {
+        let mut c = AbyCircuit::default();
+        let a = input(&mut c, "a_v0", 0, ShareType::Boolean, 64);
+        let b = input(&mut c, "b_v0", 1, ShareType::Boolean, 64);
+        let sum = c.add(GateOp::Add(a, b), ShareType::Boolean, 64);
+        c.outputs = vec![sum];
+        let ps = params(&[("a", (1 << 40) + 1), ("b", u64::MAX)]);
+        assert_eq!(eval_and_emit(&c, &ps, 32).unwrap(), vec![1 << 40]);
+    }
+
+    #[test]
+    #[should_panic(expected = "use `--mp-spdz`")]
+    fn third_party_needs_mp_spdz() {
+        let mut c = AbyCircuit::default();
+        let a = input(&mut c, "a_v0", 2, ShareType::Boolean, 8);
+        c.outputs = vec![a];
+        to_cpp(&c);
+    }
+
+    #[test]
+    fn rejects_bad_sharing() {
+        use ShareType::*;
+        let ps = params(&[]);
+        let mut wrong = AbyCircuit::default();
+        let one = wrong.add(GateOp::Const(1), Arithmetic, 1);
+        let other = wrong.add(GateOp::Const(1), Boolean, 1);
+        wrong.add(GateOp::Add(one, other), Boolean, 1);
+        assert_eq!(
+            eval_circuit(&wrong, &ps, 32),
+            Err(InterpError::WrongSharing("add".into(), Boolean, Arithmetic))
+        );
+        let mut unsupported = AbyCircuit::default();
+        let one = unsupported.add(GateOp::Const(1), Arithmetic, 1);
+        unsupported.add(GateOp::Gt(one, one), Arithmetic, 1);
+        assert_eq!(
+            eval_circuit(&unsupported, &ps, 32),
+            Err(InterpError::Unsupported("gt".into(), Arithmetic))
+        );
+        // ABY's conversions each take one sharing
+        let bad_conv =
+            "share* s_0 = acirc->PutB2AGate(ycirc->PutCONSGate((uint64_t)1, (uint32_t)1));";
+        assert_eq!(
+            interpret(bad_conv, &ps, 32),
+            Err(InterpError::WrongSharing("PutB2AGate".into(), Boolean, Yao))
+        );
+    }
+
+    /// A computation with inputs `a_v0` (from the server) and `b_v0` (from the client), and
+    /// outputs `outputs`.
+    fn two_party(outputs: Vec<Term>) -> Computation {
+        let mut md = ComputationMetadata::default();
+        let server = md.add_party("server".to_owned());
+        let client = md.add_party("client".to_owned());
+        md.new_input("a_v0".to_owned(), Some(server));
+        md.new_input("b_v0".to_owned(), Some(client));
+        Computation {
+            outputs,
+            metadata: md,
+            values: None,
+        }
+    }
+
+    /// Values for the inputs of [two_party]
+    fn inputs(a: u32, b: u32) -> FxHashMap<String, Value> {
+        inputs_of_width(a, b, 32)
+    }
+
+    /// Values for the inputs of [two_party], as `width`-bit bit-vectors, of the low bits of `a`
+    /// and `b`
+    fn inputs_of_width(a: u32, b: u32, width: usize) -> FxHashMap<String, Value> {
+        vec![("a_v0", a), ("b_v0", b)]
+            .into_iter()
+            .map(|(n, v)| {
+                let v = rug::Integer::from(v).keep_bits(width as u32);
+                (n.to_owned(), Value::BitVector(BitVector::new(v, width)))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn compiled_matches_eval() {
+        let a = leaf_term(Op::Var("a_v0".to_owned(), Sort::BitVector(32)));
+        let b = leaf_term(Op::Var("b_v0".to_owned(), Sort::BitVector(32)));
+        let outputs = vec![
+            term![BV_ADD; a.clone(), b.clone()],
+            term![BV_MUL; term![BV_MUL; a.clone(), b.clone()], a.clone()],
+            term![Op::BvBinOp(BvBinOp::Sub); a.clone(), b.clone()],
+            term![BV_XOR; a.clone(), term![BV_AND; a.clone(), b.clone()]],
+            term![Op::BvBinPred(BvBinPred::Ugt); a.clone(), b.clone()],
+            term![Op::BvBinPred(BvBinPred::Ule); a.clone(), b.clone()],
+            term![Op::Eq; a.clone(), b.clone()],
+            term![Op::Ite; term![Op::BvBinPred(BvBinPred::Ult); a.clone(), b.clone()], a.clone(), b.clone()],
+            term![Op::Not; term![Op::BvBinPred(BvBinPred::Uge); a.clone(), b.clone()]],
+        ];
+        let cs = two_party(outputs);
+        let work_dir = std::env::temp_dir().join(format!("circ_aby_interp_{}", std::process::id()));
+        for (a, b) in &[
+            (0u32, 0u32),
+            (3, 5),
+            (5, 3),
+            (u32::MAX, 2),
+            (1 << 31, 1 << 31),
+        ] {
+            check_against_eval(&cs, &inputs(*a, *b), &work_dir).unwrap();
+        }
+        fs::remove_dir_all(&work_dir).unwrap();
+    }
+
+    #[test]
+    fn compiled_arrays_match_eval() {
+        let a = leaf_term(Op::Var("a_v0".to_owned(), Sort::BitVector(32)));
+        let b = leaf_term(Op::Var("b_v0".to_owned(), Sort::BitVector(32)));
+        let i = term![BV_AND; b.clone(), bv_lit(3, 32)];
+        let arr = term![Op::Store;
+            term![Op::ConstArray(Sort::BitVector(32), 4); a.clone()],
+            i.clone(),
+            b.clone()
+        ];
+        let pair = term![Op::Tuple; a.clone(), term![Op::Select; arr.clone(), bv_lit(1, 32)]];
+        let outputs = vec![
+            term![Op::Select; arr.clone(), i.clone()],
+            term![Op::Select; arr.clone(), term![BV_AND; a.clone(), bv_lit(3, 32)]],
+            pair.clone(),
+            term![Op::Field(1); pair],
+            arr,
+        ];
+        let cs = two_party(outputs);
+        let work_dir =
+            std::env::temp_dir().join(format!("circ_aby_interp_arrays_{}", std::process::id()));
+        for (a, b) in &[(0u32, 0u32), (7, 1), (9, 2), (2, 3), (1, 6)] {
+            check_against_eval(&cs, &inputs(*a, *b), &work_dir).unwrap();
+        }
+        fs::remove_dir_all(&work_dir).unwrap();
+    }
+
+    #[test]
+    fn each_sharing_matches_eval() {
+        for w in &[32, 16, 8] {
+            each_sharing_matches_eval_at(*w);
+        }
+    }
+
+    /// Check each operation on `w`-bit values, in several assignments of sharings.
+    fn each_sharing_matches_eval_at(w: usize) {
+        let a = leaf_term(Op::Var("a_v0".to_owned(), Sort::BitVector(w)));
+        let b = leaf_term(Op::Var("b_v0".to_owned(), Sort::BitVector(w)));
+        let lit = |n: usize| bv_lit(n, w);
+        let bin = |o: BvBinOp, x: &Term, y: &Term| term![Op::BvBinOp(o); x.clone(), y.clone()];
+        let pred = |p: BvBinPred| term![Op::BvBinPred(p); a.clone(), b.clone()];
+        let low = term![Op::BvExtract(w / 2 - 1, 0); a.clone()];
+        let outputs = vec![
+            bin(BvBinOp::Udiv, &a, &b),
+            bin(BvBinOp::Urem, &a, &b),
+            bin(BvBinOp::Shl, &a, &b),
+            bin(BvBinOp::Lshr, &a, &b),
+            bin(BvBinOp::Ashr, &a, &term![BV_AND; b.clone(), lit(w - 1)]),
+            bin(BvBinOp::Shl, &a, &lit(5)),
+            bin(BvBinOp::Shl, &a, &lit(40)),
+            bin(BvBinOp::Lshr, &a, &lit(w - 1)),
+            bin(BvBinOp::Ashr, &a, &lit(3)),
+            bin(BvBinOp::Ashr, &a, &lit(w)),
+            pred(BvBinPred::Slt),
+            pred(BvBinPred::Sgt),
+            pred(BvBinPred::Sle),
+            pred(BvBinPred::Sge),
+            term![Op::BvExtract(w - 1, w / 2); a.clone()],
+            term![Op::BvExtract(3, 3); b.clone()],
+            term![Op::BvConcat; low.clone(), b.clone()],
+            term![Op::BvUext(8); low.clone()],
+            term![Op::BvSext(16); low],
+            term![Op::Ite; pred(BvBinPred::Slt), a.clone(), b.clone()],
+            term![Op::Ite;
+                pred(BvBinPred::Ult),
+                term![Op::Eq; a.clone(), b.clone()],
+                pred(BvBinPred::Sgt)
+            ],
+            term![Op::Ite;
+                term![Op::Eq; a.clone(), b.clone()],
+                term![Op::Tuple; term![BV_ADD; a.clone(), b.clone()], pred(BvBinPred::Ule)],
+                term![Op::Tuple; term![BV_MUL; a.clone(), b.clone()], pred(BvBinPred::Sle)]
+            ],
+            term![BV_ADD; a.clone(), b.clone()],
+            term![BV_MUL; term![BV_MUL; a.clone(), b.clone()], a.clone()],
+            term![Op::BvBinOp(BvBinOp::Sub); b.clone(), a.clone()],
+        ];
+        let cs = two_party(outputs);
+        let assign = |f: &dyn Fn(&Term) -> ShareType| -> SharingMap {
+            cs.outputs
+                .iter()
+                .flat_map(|o| PostOrderIter::new(o.clone()))
+                .map(|t| {
+                    let s = f(&t);
+                    (t, s)
+                })
+                .collect()
+        };
+        use ShareType::*;
+        let is_var = |t: &Term| matches!(t.op, Op::Var(..));
+        let arith_ops = |t: &Term| {
+            is_var(t)
+                || matches!(
+                    t.op,
+                    Op::Ite
+                        | Op::BvBinOp(BvBinOp::Sub)
+                        | Op::BvNaryOp(BvNaryOp::Add)
+                        | Op::BvNaryOp(BvNaryOp::Mul)
+                )
+        };
+        let s_maps = vec![
+            assign(&|_| Boolean),
+            assign(&|_| Yao),
+            assign(&|t| if is_var(t) { Arithmetic } else { Yao }),
+            assign(&|t| if arith_ops(t) { Arithmetic } else { Boolean }),
+        ];
+        let top = 1u32 << (w - 1);
+        for (a, b) in &[
+            (0u32, 0u32),
+            (3, 5),
+            (5, 3),
+            (u32::MAX, 2),
+            (top, top),
+            (top + 5, 7),
+            (12345678, 0),
+            (7, 40),
+            (200, 100),
+        ] {
+            for s_map in &s_maps {
+                check_assignment_against_eval(&cs, s_map, &inputs_of_width(*a, *b, w)).unwrap();
+            }
+        }
+    }
+}
@@ -1,5 +1,9 @@
 //! ABY
 pub mod assignment;
+pub mod circuit;
+pub mod emit;
+pub mod interp;
+pub mod mpspdz;
 pub mod output;
 pub mod trans;
 pub mod utils;
@@ -0,0 +1,446 @@
+//! Emitting an [AbyCircuit] as an MP-SPDZ program
+//!
+//! ABY has exactly two parties. [MP-SPDZ](https://github.com/data61/MP-SPDZ) compiles the same
+//! high-level program for many protocols, with any number of parties, so this lets a computation
+//! take inputs from any party, and reveal its outputs to any subset of them.
+//!
+//! Every wire becomes an `sint` holding an unsigned value below `2^width`. The sharing of each
+//! gate is ignored: MP-SPDZ picks its own representation, depending on the protocol.
+//!
+//! MP-SPDZ treats secret values as signed, so arithmetic and comparisons are given a bit length
+//! that fits their unsigned operands, and their (possibly negative) intermediate results. The
+//! program sets its bit length to the largest of these; compile it with a field (`-F`) that
+//! large.
+//!
+//! Party `i` reads its inputs, in the order listed at the top of the program, from
+//! `Player-Data/Input-P<i>-0`. Public inputs are read with `public_input()`, from
+//! `Programs/Public-Input/<program>`.
+
+use crate::target::aby::circuit::{AbyCircuit, GateOp, Wire};
+
+use std::fmt::Write;
+
+/// Helpers that the emitted program calls
+const PRELUDE: &str = "\
+def bitwise(f, a, b, w):
+    x = a.bit_decompose(w)
+    y = b.bit_decompose(w)
+    return sint.bit_compose([f(i, j) for i, j in zip(x, y)])
+";
+
+/// The variable that holds wire `w`
+fn var(w: Wire) -> String {
+    format!("w{}", w)
+}
+
+/// A bit-wise operation on `w`-bit values, with `bit` on single bits
+fn bit_op(w: usize, a: &str, b: &str, bit: &str) -> String {
+    if w == 1 {
+        bit.replace("a", a).replace("b", b)
+    } else {
+        format!("bitwise(lambda a, b: {}, {}, {}, {})", bit, a, b, w)
+    }
+}
+
+/// The number of (signed) bits needed for the values involved in computing wire `w`
+fn bit_length(circ: &AbyCircuit, w: Wire) -> usize {
+    let g = circ.gate(w);
+    let m =
+        g.op.args()
+            .into_iter()
+            .map(|a| circ.gate(a).width)
+            .fold(g.width, usize::max);
+    match &g.op {
+        // The sum of two m-bit values has m + 1 bits, plus a sign bit
+        GateOp::Add(..) => m + 2,
+        GateOp::Sub(..) | GateOp::Gt(..) => m + 1,
+        GateOp::Mul(..) => 2 * m + 1,
+        _ => m,
+    }
+}
+
+/// The Python expression for the gate driving `w`
+fn gate_expr(circ: &AbyCircuit, w: Wire) -> String {
+    let g = circ.gate(w);
+    let n = g.width;
+    let k = bit_length(circ, w);
+    let v = |a: &Wire| var(*a);
+    match &g.op {
+        GateOp::In { party, .. } => match party {
+            Some(p) => format!("sint.get_input_from({})", p),
+            None => "sint(public_input())".to_string(),
+        },
+        GateOp::Const(c) => format!("sint({})", c),
+        GateOp::Add(a, b) => format!("({} + {}).mod2m({}, {})", v(a), v(b), n, k),
+        GateOp::Sub(a, b) => format!("({} - {}).mod2m({}, {})", v(a), v(b), n, k),
+        GateOp::Mul(a, b) => format!("({} * {}).mod2m({}, {})", v(a), v(b), n, k),
+        GateOp::Xor(a, b) => bit_op(n, &v(a), &v(b), "(a + b - 2 * a * b)"),
+        GateOp::And(a, b) => bit_op(n, &v(a), &v(b), "(a * b)"),
+        GateOp::Or(a, b) => bit_op(n, &v(a), &v(b), "(a + b - a * b)"),
+        GateOp::Gt(a, b) => format!("{}.greater_than({}, {})", v(a), v(b), k),
+        GateOp::Inv(a) => format!("({} - 1 - {})", 1u128 << n, v(a)),
+        GateOp::Mux(s, t, f) => format!("{}.if_else({}, {})", v(s), v(t), v(f)),
+        GateOp::Conv(a) => v(a),
+        GateOp::Slice(a, lo) => format!(
+            "sint.bit_compose({}.bit_decompose({})[{}:{}])",
+            v(a),
+            circ.gate(*a).width,
+            lo,
+            lo + n
+        ),
+        GateOp::Concat(hi, lo) => format!(
+            "({} + {} * {})",
+            v(lo),
+            v(hi),
+            1u128 << circ.gate(*lo).width
+        ),
+    }
+}
+
+/// Emit an MP-SPDZ (`.mpc`) program that computes `circ`.
+///
+/// Panics if the circuit has wires wider than 64 bits.
+pub fn to_mpspdz(circ: &AbyCircuit) -> String {
+    let mut out = String::new();
+    let mut parties: Vec<_> = circ.inputs().filter_map(|(_, _, p)| p).collect();
+    parties.sort_unstable();
+    parties.dedup();
+    for p in parties {
+        let names: Vec<_> = circ
+            .inputs()
+            .filter(|(_, _, q)| *q == Some(p))
+            .map(|(_, name, _)| name)
+            .collect();
+        writeln!(out, "# Inputs of party {}: {}", p, names.join(", ")).unwrap();
+    }
+    let public: Vec<_> = circ
+        .inputs()
+        .filter(|(_, _, p)| p.is_none())
+        .map(|(_, name, _)| name)
+        .collect();
+    if !public.is_empty() {
+        writeln!(out, "# Public inputs: {}", public.join(", ")).unwrap();
+    }
+    for (w, g) in circ.gates.iter().enumerate() {
+        assert!(
+            g.width <= 64,
+            "MP-SPDZ values are at most 64 bits, but wire {} has {}",
+            w,
+            g.width
+        );
+    }
+    let k = (0..circ.gates.len())
+        .map(|w| bit_length(circ, w))
+        .max()
+        .unwrap_or(1);
+    writeln!(out).unwrap();
+    writeln!(out, "program.set_bit_length({})", k).unwrap();
+    writeln!(out).unwrap();
+    out.push_str(PRELUDE);
+    writeln!(out).unwrap();
+    for w in 0..circ.gates.len() {
+        writeln!(out, "{} = {}", var(w), gate_expr(circ, w)).unwrap();
+    }
+    for w in &circ.outputs {
+        match &circ.output_parties {
+            None => writeln!(out, "print_ln('%s', {}.reveal())", var(*w)).unwrap(),
+            Some(ps) => {
+                for p in ps {
+                    writeln!(
+                        out,
+                        "print_ln_to({0}, '%s', {1}.reveal_to({0}))",
+                        p,
+                        var(*w)
+                    )
+                    .unwrap();
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ir::term::*;
+    use crate::target::aby::assignment::{all_boolean_sharing, ShareType};
+    use crate::target::aby::trans::to_aby_circuit;
+
+    use fxhash::FxHashMap;
+
+    use std::io::Write as _;
+    use std::process::{Command, Stdio};
+
+    /// A plaintext stand-in for the parts of MP-SPDZ that emitted programs use.
+    ///
+    /// It checks that values fit the bit lengths they are used at, and that those fit the
+    /// program's bit length.
+    const MOCK: &str = r#"
+class Program:
+    bit_length = 64
+    def set_bit_length(self, k):
+        self.bit_length = k
+program = Program()
+
+def fits(v, k):
+    assert k <= program.bit_length, "%d bits exceeds the bit length" % k
+    assert -2 ** (k - 1) <= v < 2 ** (k - 1), "%d does not fit in %d bits" % (v, k)
+
+def val(x):
+    return x.v if isinstance(x, sint) else x
+
+class sint:
+    def __init__(self, v):
+        self.v = v
+    @staticmethod
+    def get_input_from(p):
+        return sint(INPUTS[p].pop(0))
+    @staticmethod
+    def bit_compose(bits):
+        return sint(sum(val(b) << i for i, b in enumerate(bits)))
+    def __add__(self, o):
+        return sint(self.v + val(o))
+    __radd__ = __add__
+    def __sub__(self, o):
+        return sint(self.v - val(o))
+    def __rsub__(self, o):
+        return sint(val(o) - self.v)
+    def __mul__(self, o):
+        return sint(self.v * val(o))
+    __rmul__ = __mul__
+    def __gt__(self, o):
+        raise Exception("comparison without a bit length")
+    def mod2m(self, m, k):
+        fits(self.v, k)
+        return sint(self.v % 2 ** m)
+    def greater_than(self, o, k):
+        fits(self.v, k)
+        fits(val(o), k)
+        return sint(int(self.v > val(o)))
+    def bit_decompose(self, n):
+        assert 0 <= self.v < 2 ** n
+        return [sint((self.v >> i) & 1) for i in range(n)]
+    def if_else(self, a, b):
+        assert self.v in (0, 1)
+        return a if self.v else b
+    def reveal(self):
+        return self.v
+    def reveal_to(self, p):
+        return self.v
+
+def public_input():
+    return PUBLIC.pop(0)
+
+def print_ln(f, v):
+    print(v)
+
+def print_ln_to(p, f, v):
+    print(v)
+"#;
+
+    /// Whether `python3` can be run; the tests that need it are skipped if not.
+    fn has_python3() -> bool {
+        let found = Command::new("python3")
+            .arg("--version")
+            .output()
+            .map_or(false, |o| o.status.success());
+        if !found {
+            eprintln!("Skipping: python3 is not installed");
+        }
+        found
+    }
+
+    /// Run `program` with the mock, giving each input the value `value(name)`.
+    fn run(circ: &AbyCircuit, program: &str, value: &dyn Fn(&str) -> u64) -> Vec<u64> {
+        let mut inputs: Vec<Vec<u64>> = Vec::new();
+        let mut public = Vec::new();
+        for (_, name, party) in circ.inputs() {
+            match party {
+                Some(p) => {
+                    let p = p as usize;
+                    if inputs.len() <= p {
+                        inputs.resize(p + 1, Vec::new());
+                    }
+                    inputs[p].push(value(name));
+                }
+                None => public.push(value(name)),
+            }
+        }
+        let script = format!(
+            "INPUTS = {:?}\nPUBLIC = {:?}\n{}\n{}",
+            inputs, public, MOCK, program
+        );
+        let mut child = Command::new("python3")
+            .arg("-")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("Unable to run python3");
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(script.as_bytes())
+            .unwrap();
+        let out = child.wait_with_output().unwrap();
+        assert!(
+            out.status.success(),
+            "{}\n{}",
+            program,
+            String::from_utf8_lossy(&out.stderr)
+        );
+        String::from_utf8(out.stdout)
+            .unwrap()
+            .lines()
+            .map(|l| l.parse().unwrap())
+            .collect()
+    }
+
+    /// A computation with `width`-bit inputs `a`, `b` and `c`, from parties 0, 1 and 2.
+    fn three_party(
+        width: usize,
+        outputs: impl Fn(&Term, &Term, &Term) -> Vec<Term>,
+    ) -> Computation {
+        let mut md = ComputationMetadata::default();
+        let vars: Vec<Term> = ["a", "b", "c"]
+            .iter()
+            .map(|n| {
+                let p = md.add_party(format!("party_{}", n));
+                md.new_input(n.to_string(), Some(p));
+                leaf_term(Op::Var(n.to_string(), Sort::BitVector(width)))
+            })
+            .collect();
+        Computation {
+            outputs: outputs(&vars[0], &vars[1], &vars[2]),
+            metadata: md,
+            values: None,
+        }
+    }
+
+    /// Check that the MP-SPDZ program for `cs` computes what [eval] does, on `values`.
+    fn check(cs: &Computation, width: usize, values: &[[u64; 3]]) {
+        if !has_python3() {
+            return;
+        }
+        let circ = to_aby_circuit(cs, &all_boolean_sharing(cs));
+        let program = to_mpspdz(&circ);
+        for vs in values {
+            let ints: FxHashMap<&str, u64> = ["a", "b", "c"]
+                .iter()
+                .cloned()
+                .zip(vs.iter().cloned())
+                .collect();
+            let env: FxHashMap<String, Value> = ints
+                .iter()
+                .map(|(n, v)| {
+                    let bv = BitVector::new(rug::Integer::from(*v), width);
+                    (n.to_string(), Value::BitVector(bv))
+                })
+                .collect();
+            let expected: Vec<u64> = cs
+                .outputs
+                .iter()
+                .map(|o| match eval(o, &env) {
+                    Value::Bool(b) => b as u64,
+                    Value::BitVector(b) => b.uint().to_u64().unwrap(),
+                    v => panic!("Unexpected output {}", v),
+                })
+                .collect();
+            let actual = run(&circ, &program, &|name| ints[name]);
+            assert_eq!(actual, expected, "inputs {:?}", vs);
+        }
+    }
+
+    fn ops(a: &Term, b: &Term, c: &Term) -> Vec<Term> {
+        vec![
+            term![BV_ADD; a.clone(), b.clone()],
+            term![BV_MUL; term![BV_MUL; a.clone(), b.clone()], c.clone()],
+            term![Op::BvBinOp(BvBinOp::Sub); a.clone(), c.clone()],
+            term![Op::BvBinOp(BvBinOp::Udiv); a.clone(), b.clone()],
+            term![Op::BvBinOp(BvBinOp::Urem); a.clone(), b.clone()],
+            term![Op::BvBinPred(BvBinPred::Ugt); a.clone(), c.clone()],
+            term![Op::BvBinPred(BvBinPred::Slt); a.clone(), c.clone()],
+            term![BV_XOR; a.clone(), term![BV_OR; b.clone(), c.clone()]],
+            term![Op::Ite; term![Op::Eq; a.clone(), b.clone()], c.clone(), a.clone()],
+        ]
+    }
+
+    #[test]
+    fn three_parties_match_eval() {
+        let values = [
+            [0, 0, 0],
+            [200, 100, 3],
+            [255, 255, 255],
+            [128, 1, 127],
+            [7, 0, 200],
+        ];
+        check(&three_party(8, ops), 8, &values);
+        let values = [[u32::MAX as u64, 2, 1 << 31], [12345678, 1000, 3]];
+        check(&three_party(32, ops), 32, &values);
+    }
+
+    #[test]
+    fn bit_length_fits_products() {
+        let cs = three_party(32, |a, b, _| vec![term![BV_MUL; a.clone(), b.clone()]]);
+        let p = to_mpspdz(&to_aby_circuit(&cs, &all_boolean_sharing(&cs)));
+        assert!(p.contains("program.set_bit_length(65)\n"));
+    }
+
+    #[test]
+    fn reveal_to_subset() {
+        let mut c = AbyCircuit::default();
+        let s = ShareType::Boolean;
+        let a = c.add(
+            GateOp::In {
+                name: "a".into(),
+                party: Some(0),
+            },
+            s,
+            8,
+        );
+        c.outputs.push(a);
+        c.output_parties = Some(vec![0, 2]);
+        let p = to_mpspdz(&c);
+        assert!(p.contains("print_ln_to(0, '%s', w0.reveal_to(0))\n"));
+        assert!(p.contains("print_ln_to(2, '%s', w0.reveal_to(2))\n"));
+        assert!(!p.contains("reveal_to(1)"));
+        assert!(!p.contains(".reveal()"));
+        if has_python3() {
+            assert_eq!(run(&c, &p, &|_| 42), vec![42, 42]);
+        }
+    }
+
+    /// The program in `examples/mp_spdz`, which `scripts/test_mp_spdz.zsh` compiles and runs with
+    /// the real MP-SPDZ
+    #[test]
+    fn golden() {
+        let mut c = AbyCircuit::default();
+        let s = ShareType::Boolean;
+        let ins: Vec<Wire> = ["a", "b", "c"]
+            .iter()
+            .enumerate()
+            .map(|(p, n)| {
+                let op = GateOp::In {
+                    name: n.to_string(),
+                    party: Some(p as u8),
+                };
+                c.add(op, s, 8)
+            })
+            .collect();
+        let sum = c.add(GateOp::Add(ins[0], ins[1]), s, 8);
+        let prod = c.add(GateOp::Mul(sum, ins[2]), s, 8);
+        let gt = c.add(GateOp::Gt(prod, ins[0]), s, 1);
+        let xor = c.add(GateOp::Xor(prod, ins[0]), s, 8);
+        c.outputs = vec![prod, gt, xor];
+        let golden = include_str!("../../../examples/mp_spdz/three_party.mpc");
+        assert_eq!(to_mpspdz(&c), golden);
+        if has_python3() {
+            assert_eq!(
+                run(&c, golden, &|n| [7, 100, 3]["abc".find(n).unwrap()]),
+                vec![65, 1, 70]
+            );
+        }
+    }
+}
@@ -1,11 +1,19 @@
 //! Utility functions to write compiler output to ABY
+//!
+//! Each computation becomes a self-contained CMake project, `<out_dir>/<name>`, which builds
+//! against an installed ABY (found with `find_package(ABY)`), or against ABY's own targets if it
+//! is added as a subdirectory of ABY's build.
 
 use std::fs;
-use std::fs::{File, OpenOptions};
-use std::io::{prelude::*, BufRead, BufReader};
+use std::fs::File;
+use std::io::prelude::*;
 use std::path::Path;
 use std::path::PathBuf;
 
+const TEST_TEMPLATE: &str = include_str!("../../../third_party/ABY_templates/test_template.txt");
+const H_TEMPLATE: &str = include_str!("../../../third_party/ABY_templates/h_template.txt");
+const CPP_TEMPLATE: &str = include_str!("../../../third_party/ABY_templates/cpp_template.txt");
+
 /// Given PathBuf `path_buf`, return the filename of the path
 fn get_filename(path_buf: &PathBuf) -> String {
     Path::new(&path_buf.iter().last().unwrap().to_os_string())
@@ -16,122 +24,125 @@ fn get_filename(path_buf: &PathBuf) -> String {
         .unwrap()
 }
 
-/// In ABY examples, remove the existing directory and create a directory
-/// in order to write the new test case
-fn create_dir_in_aby(filename: &String) {
-    let path = format!("third_party/ABY/src/examples/{}", *filename);
-    let _ = fs::remove_dir_all(path.clone());
-    fs::create_dir_all(format!("{}/common", path.clone())).expect("Failed to create directory");
-}
-
-/// Update the CMake file in ABY
-fn update_cmake_file(filename: &String) {
-    let cmake_filename = "third_party/ABY/src/examples/CMakeLists.txt";
-    let file = File::open(cmake_filename.clone()).expect("Failed to open cmake file");
-    let reader = BufReader::new(file);
-    let mut flag = false;
-
-    for line in reader.lines() {
-        let line = line.unwrap();
-        if line.contains(&*filename) {
-            flag = true
-        }
-    }
-
-    if !flag {
-        let mut file = OpenOptions::new()
-            .write(true)
-            .append(true)
-            .open(cmake_filename)
-            .unwrap();
-
-        writeln!(file, "{}", format!("add_subdirectory({})", *filename))
-            .expect("Failed to write to cmake file");
-    }
+/// Remove any existing project directory for `filename` in `out_dir`, and create a fresh one
+fn create_project_dir(out_dir: &Path, filename: &String) -> PathBuf {
+    let path = out_dir.join(filename);
+    let _ = fs::remove_dir_all(&path);
+    fs::create_dir_all(path.join("common")).expect("Failed to create directory");
+    path
 }
 
-/// Create a CMake file for the corresponding filename (testcase)
-/// in the ABY examples directory
-fn write_test_cmake_file(filename: &String) {
-    let path = format!("third_party/ABY/src/examples/{}/CMakeLists.txt", *filename);
-
+/// Create a CMake file for the project
+fn write_test_cmake_file(dir: &Path, filename: &String) {
     fs::write(
-        path.clone(),
+        dir.join("CMakeLists.txt"),
         format!(
             concat!(
+                "cmake_minimum_required(VERSION 3.12)\n",
+                "project({} LANGUAGES CXX)\n",
+                "\n",
+                "if(NOT TARGET ABY::aby)\n",
+                "    find_package(ABY REQUIRED)\n",
+                "endif()\n",
+                "\n",
                 "add_executable({}_test {}_test.cpp common/{}.cpp)\n",
-                "target_link_libraries({}_test ABY::aby ENCRYPTO_utils::encrypto_utils)"
+                "target_compile_features({}_test PRIVATE cxx_std_17)\n",
+                "target_link_libraries({}_test ABY::aby ENCRYPTO_utils::encrypto_utils)\n"
             ),
-            *filename, *filename, *filename, *filename
+            *filename, *filename, *filename, *filename, *filename, *filename
         ),
     )
     .expect("Failed to write to cmake file");
 }
 
-/// Write the testcase in the ABY examples directory
-fn write_test_file(filename: &String) {
-    let template = fs::read_to_string("third_party/ABY_templates/test_template.txt")
-        .expect("Unable to read file");
-    let path = format!(
-        "third_party/ABY/src/examples/{}/{}_test.cpp",
-        *filename, *filename
-    );
-
-    fs::write(path.clone(), template.replace("{fn}", &*filename))
-        .expect("Failed to write to test file");
+/// Write the test driver for the project
+fn write_test_file(dir: &Path, filename: &String) {
+    fs::write(
+        dir.join(format!("{}_test.cpp", *filename)),
+        TEST_TEMPLATE.replace("{fn}", &*filename),
+    )
+    .expect("Failed to write to test file");
 }
 
-/// Using the h_template.txt, write the .h file for the new test case
-fn write_h_file(filename: &String) {
-    let template = fs::read_to_string("third_party/ABY_templates/h_template.txt")
-        .expect("Unable to read file");
-    let path = format!(
-        "third_party/ABY/src/examples/{}/common/{}.h",
-        *filename, *filename
-    );
-
-    fs::write(path.clone(), template.replace("{fn}", &*filename))
-        .expect("Failed to write to h file");
+/// Using the h_template.txt, write the .h file for the project
+fn write_h_file(dir: &Path, filename: &String) {
+    fs::write(
+        dir.join(format!("common/{}.h", *filename)),
+        H_TEMPLATE.replace("{fn}", &*filename),
+    )
+    .expect("Failed to write to h file");
 }
 
-/// Using the cpp_template.txt, write the .cpp file for the new test case
-fn write_circ_file(filename: &String) {
-    let setup_file_path = format!("third_party/ABY/src/examples/{}_setup_tmp.txt", *filename);
-    let mut setup_file = File::open(setup_file_path).expect("Unable to open the file");
-    let mut setup = String::new();
-    setup_file.read_to_string(&mut setup).expect("Unable to read the file");
-
-    let circuit_file_path = format!("third_party/ABY/src/examples/{}_circuit_tmp.txt", *filename);
-    let mut circuit_file = File::open(circuit_file_path).expect("Unable to open the file");
-    let mut circuit = String::new();
-    circuit_file.read_to_string(&mut circuit).expect("Unable to read the file");
+/// Read, and then remove, a temporary file written during translation
+fn take_tmp_file(path: &Path) -> String {
+    let mut file = File::open(path).expect("Unable to open the file");
+    let mut content = String::new();
+    file.read_to_string(&mut content)
+        .expect("Unable to read the file");
+    fs::remove_file(path).expect("Unable to remove the file");
+    content
+}
 
+/// Using the cpp_template.txt, write the .cpp file for the project
+fn write_circ_file(out_dir: &Path, dir: &Path, filename: &String) {
+    let setup = take_tmp_file(&out_dir.join(format!("{}_setup_tmp.txt", *filename)));
+    let circuit = take_tmp_file(&out_dir.join(format!("{}_circuit_tmp.txt", *filename)));
     let content = format!("{}\n{}", setup, circuit);
 
-    let template = fs::read_to_string("third_party/ABY_templates/cpp_template.txt")
-        .expect("Unable to read file");
-    let path = format!(
-        "third_party/ABY/src/examples/{}/common/{}.cpp",
-        *filename, *filename
-    );
-
     fs::write(
-        path.clone(),
-        template
+        dir.join(format!("common/{}.cpp", *filename)),
+        CPP_TEMPLATE
             .replace("{fn}", &*filename)
-            .replace("{circ}", &content)
+            .replace("{circ}", &content),
     )
     .expect("Failed to write to cpp file");
 }
 
-/// Write circuit output from translation later to ABY
-pub fn write_aby_exec(path_buf: &PathBuf, lang: &String) {
+/// Write circuit output from translation later to an ABY project in `out_dir`
+pub fn write_aby_exec(path_buf: &PathBuf, lang: &String, out_dir: &Path) {
     let filename = get_filename(path_buf);
     let name = format!("{}_{}", filename, lang);
-    create_dir_in_aby(&name);
-    update_cmake_file(&name);
-    write_test_cmake_file(&name);
-    write_test_file(&name);
-    write_h_file(&name);
-    write_circ_file(&name);
+    let dir = create_project_dir(out_dir, &name);
+    write_test_cmake_file(&dir, &name);
+    write_test_file(&dir, &name);
+    write_h_file(&dir, &name);
+    write_circ_file(out_dir, &dir, &name);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::target::aby::utils::{get_path, write_line_to_file};
+
+    #[test]
+    fn self_contained_project() {
+        let out_dir = std::env::temp_dir().join(format!("circ_aby_{}", std::process::id()));
+        let path_buf = PathBuf::from("examples/2pc_add.zok");
+        let lang = String::from("zok");
+        for (part, line) in &[("setup", "// setup\n"), ("circuit", "// circuit\n")] {
+            let tmp = get_path(&out_dir, &path_buf, &lang, &part.to_string());
+            write_line_to_file(&tmp, &line.to_string());
+        }
+        write_aby_exec(&path_buf, &lang, &out_dir);
+
+        let dir = out_dir.join("2pc_add_zok");
+        let cpp = fs::read_to_string(dir.join("common/2pc_add_zok.cpp")).unwrap();
+        assert!(cpp.contains("// setup\n\n// circuit\n"));
+        for f in &[
+            "CMakeLists.txt",
+            "2pc_add_zok_test.cpp",
+            "common/2pc_add_zok.h",
+        ] {
+            let content = fs::read_to_string(dir.join(f)).unwrap();
+            assert!(
+                !content.contains("#include \"../"),
+                "{} escapes the project",
+                f
+            );
+        }
+        // Only the project remains.
+        let entries: Vec<_> = fs::read_dir(&out_dir).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+        fs::remove_dir_all(&out_dir).unwrap();
+    }
 }
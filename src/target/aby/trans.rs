@@ -1,650 +1,713 @@
-//! Lowering IR to ABY DSL
-//! [EzPC Compiler](https://github.com/mpc-msri/EzPC/blob/da94a982709123c8186d27c9c93e27f243d85f0e/EzPC/EzPC/ABY_example/common/ezpc.h)
+//! Lowering IR to ABY
+//!
+//! A computation is lowered to an [AbyCircuit], with each term evaluated in the sharing chosen for
+//! it, and conversions between sharings where needed. [to_aby] then emits that circuit as C++.
+//!
+//! Tuples and arrays are lowered to one wire per boolean or bit-vector they contain. Accesses at
+//! secret indices scan the whole array, so their cost grows linearly with its size.
+//!
+//! Operations that ABY has no gate for are built from its gates, and from slices and
+//! concatenations of wires (which only Boolean and Yao sharing support): division is restoring
+//! long division, shifts by secret amounts are barrel shifters, and signed comparisons flip the
+//! sign bits of unsigned ones. In arithmetic sharing, `ite(c, t, f)` is `f + c * (t - f)`.
 
-//! Inv gates need to typecast circuit object to boolean circuit
-//! [Link to comment in EzPC Compiler](https://github.com/mpc-msri/EzPC/blob/da94a982709123c8186d27c9c93e27f243d85f0e/EzPC/EzPC/codegen.ml)
-
-use std::fmt;
 use crate::ir::term::*;
-use crate::target::aby::assignment::ilp::assign;
-use crate::target::aby::assignment::{ShareType, SharingMap};
+use crate::target::aby::assignment::ilp::CostModel;
+use crate::target::aby::assignment::{Assigner, ShareType, SharingMap};
+use crate::target::aby::circuit::{AbyCircuit, GateOp, Wire};
+use crate::target::aby::emit::to_cpp;
 use crate::target::aby::utils::*;
 
-use std::path::PathBuf;
+use fxhash::FxHashMap;
+
+use std::path::{Path, PathBuf};
+
+struct ToABY<'a> {
+    md: &'a ComputationMetadata,
+    s_map: &'a SharingMap,
+    circ: AbyCircuit,
+    /// The wires of each term: one per boolean or bit-vector leaf of its sort (see [leaf_sorts])
+    cache: TermMap<Vec<Wire>>,
+    /// Conversions of wires to other sharings, so each is converted once
+    convs: FxHashMap<(Wire, ShareType), Wire>,
+    /// For an index wire `i` and a bound `k`, the wire for `i >= k`
+    bounds: FxHashMap<(Wire, usize), Wire>,
+}
 
-const NO_ROLE: u8 = u8::MAX;
-const SERVER: u8 = 0;
-const CLIENT: u8 = 1;
-const BOOLEAN_BITLEN: i32 = 1;
+/// The boolean and bit-vector sorts that make up a value of sort `s`, in wire order.
+///
+/// Tuples are their fields in order, and arrays are their values in order of key.
+fn leaf_sorts(s: &Sort) -> Vec<Sort> {
+    match s {
+        Sort::Bool | Sort::BitVector(_) => vec![s.clone()],
+        Sort::Tuple(fs) => fs.iter().flat_map(leaf_sorts).collect(),
+        Sort::Array(_, v, n) => {
+            let v = leaf_sorts(v);
+            (0..*n).flat_map(|_| v.iter().cloned()).collect()
+        }
+        _ => panic!("Unsupported sort in ABY: {}", s),
+    }
+}
 
-#[derive(Clone)]
-enum EmbeddedTerm {
-    Bool(String),
-    Bv(String),
+/// The number of wires for a value of sort `s`
+fn n_leaves(s: &Sort) -> usize {
+    leaf_sorts(s).len()
 }
 
-impl fmt::Display for EmbeddedTerm {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self)
+fn leaf_width(s: &Sort) -> usize {
+    match s {
+        Sort::Bool => 1,
+        Sort::BitVector(w) => *w,
+        s => panic!("Unsupported sort in ABY: {:?}", s),
     }
 }
 
-struct ToABY {
-    md: ComputationMetadata,
-    inputs: TermMap<Option<PartyId>>,
-    cache: TermMap<EmbeddedTerm>,
-    s_map: SharingMap,
-    share_cnt: i32,
-    setup_fname: String,
-    circuit_fname: String
+/// The keys of an array with key sort `k` and size `n`, in order
+pub(crate) fn array_keys(k: &Sort, n: usize) -> Vec<Value> {
+    k.elems_iter()
+        .take(n)
+        .map(|t| match &t.op {
+            Op::Const(v) => v.clone(),
+            _ => unreachable!(),
+        })
+        .collect()
 }
 
-impl ToABY {
-    fn new(metadata: ComputationMetadata, s_map: SharingMap, path_buf: &PathBuf, lang: &String) -> Self {
+impl<'a> ToABY<'a> {
+    fn new(md: &'a ComputationMetadata, s_map: &'a SharingMap) -> Self {
         Self {
-            md: metadata,
-            inputs: TermMap::new(),
+            md,
+            s_map,
+            circ: AbyCircuit::default(),
             cache: TermMap::new(),
-            s_map: s_map,
-            share_cnt: 0,
-            setup_fname: get_path(path_buf, lang, &String::from("setup")),
-            circuit_fname: get_path(path_buf, lang, &String::from("circuit")),
+            convs: FxHashMap::default(),
+            bounds: FxHashMap::default(),
         }
     }
 
-    fn get_var_name(t: Term, b: bool) -> String {
-        match &t.op {
-            Op::Var(name, _) =>  if b { name.to_string().clone().replace(".", "_") } else { name.to_string() }, 
-            _ => panic!("Term {} is not of type Var", t),
+    fn share(&self, t: &Term) -> ShareType {
+        *self
+            .s_map
+            .get(t)
+            .unwrap_or_else(|| panic!("No sharing type for {:?}", t))
+    }
+
+    fn width(t: &Term) -> usize {
+        leaf_width(&check(t))
+    }
+
+    fn wires(&self, t: &Term) -> &Vec<Wire> {
+        self.cache
+            .get(t)
+            .unwrap_or_else(|| panic!("Missing wires for {:?}", t))
+    }
+
+    /// Wire `w`, in sharing `to`
+    fn convert(&mut self, w: Wire, to: ShareType) -> Wire {
+        let from = self.circ.gate(w).share;
+        if from == to {
+            return w;
         }
+        let width = self.circ.gate(w).width;
+        let circ = &mut self.circ;
+        *self
+            .convs
+            .entry((w, to))
+            .or_insert_with(|| circ.add(GateOp::Conv(w), to, width))
     }
 
-    fn get_share_name(&mut self) -> String {
-        format!("s_{}", self.share_cnt)
+    /// The wires for child `c` of `t`, in the sharing of `t`
+    fn args(&mut self, t: &Term, c: &Term) -> Vec<Wire> {
+        let to = self.share(t);
+        let ws = self.wires(c).clone();
+        ws.into_iter().map(|w| self.convert(w, to)).collect()
     }
 
-    fn inc_share(&mut self) {
-        self.share_cnt += 1;
+    /// The wire for scalar child `c` of `t`, in the sharing of `t`
+    fn arg(&mut self, t: &Term, c: &Term) -> Wire {
+        let ws = self.args(t, c);
+        assert_eq!(ws.len(), 1, "Expected a scalar, but got {}", c);
+        ws[0]
     }
 
-    /// Parse variable name from IR representation of a variable
-    fn parse_var_name(&self, full_name: String) -> String {
-        let parsed: Vec<String> = full_name.split("_").map(str::to_string).collect();
-        if parsed.len() < 2 {
-            panic!("Invalid variable name: {}", full_name);
+    /// Add a gate for part of `t`, in the sharing of `t`
+    fn gate(&mut self, t: &Term, op: GateOp, width: usize) -> Wire {
+        let share = self.share(t);
+        self.circ.add(op, share, width)
+    }
+
+    /// Add a gate for part of `t`, which needs Boolean or Yao sharing
+    fn bit_gate(&mut self, t: &Term, op: GateOp, width: usize) -> Wire {
+        assert_ne!(
+            self.share(t),
+            ShareType::Arithmetic,
+            "{} cannot be evaluated in arithmetic sharing",
+            t.op
+        );
+        self.gate(t, op, width)
+    }
+
+    /// Bits `lo` through `lo + width - 1` of `a`
+    fn slice(&mut self, t: &Term, a: Wire, lo: usize, width: usize) -> Wire {
+        if lo == 0 && width == self.circ.gate(a).width {
+            a
+        } else {
+            self.bit_gate(t, GateOp::Slice(a, lo), width)
+        }
+    }
+
+    /// The bits of `hi` above those of `lo`
+    fn concat(&mut self, t: &Term, hi: Wire, lo: Wire) -> Wire {
+        let width = self.circ.gate(hi).width + self.circ.gate(lo).width;
+        self.bit_gate(t, GateOp::Concat(hi, lo), width)
+    }
+
+    /// `k` copies of the top bit of `a`
+    fn sign_fill(&mut self, t: &Term, a: Wire, k: usize) -> Wire {
+        let width = self.circ.gate(a).width;
+        let mut fill = self.slice(t, a, width - 1, 1);
+        while self.circ.gate(fill).width < k {
+            fill = self.concat(t, fill, fill);
+        }
+        self.slice(t, fill, 0, k)
+    }
+
+    /// `a`, shifted by the constant `k`, as `op` (a shift) does
+    fn shift_const(&mut self, t: &Term, op: &BvBinOp, a: Wire, k: usize) -> Wire {
+        let width = self.circ.gate(a).width;
+        if k == 0 {
+            return a;
+        }
+        let k = k.min(width);
+        let fill = match op {
+            BvBinOp::Ashr => self.sign_fill(t, a, k),
+            _ => self.gate(t, GateOp::Const(0), k),
+        };
+        if k == width {
+            return fill;
         }
-        let mut name = parsed[parsed.len() - 2].to_string();
-        if full_name.contains(".") {
-            let index: Vec<String> = full_name.split(".").map(str::to_string).collect();
-            if index.len() < 1 {
-                panic!("Invalid variable name: {}", full_name);
+        match op {
+            BvBinOp::Shl => {
+                let lo = self.slice(t, a, 0, width - k);
+                self.concat(t, lo, fill)
             }
-            name += &("_".to_owned() + &index[index.len() - 1].to_string());
-        } 
-        name
+            _ => {
+                let hi = self.slice(t, a, k, width - k);
+                self.concat(t, fill, hi)
+            }
+        }
     }
 
-    fn get_sharetype_circ(&self, t: Term) -> String {
-        if !self.s_map.contains_key(&t) {
-            panic!("No sharing type for {:?}", t);
+    /// `a`, shifted by `b`, as `op` (a shift) does.
+    ///
+    /// Each bit of `b` selects whether to shift by its power of two; amounts of at least the
+    /// width of `a` shift out every bit.
+    fn shift(&mut self, t: &Term, op: &BvBinOp, a: Wire, b: Wire) -> Wire {
+        let width = self.circ.gate(a).width;
+        let mut acc = a;
+        let mut i = 0;
+        while (1 << i) < width {
+            let bit = self.slice(t, b, i, 1);
+            let shifted = self.shift_const(t, op, acc, 1 << i);
+            acc = self.gate(t, GateOp::Mux(bit, shifted, acc), width);
+            i += 1;
         }
-        let s = self.s_map.get(&t).unwrap();
-        match *s {
-            ShareType::Arithmetic => "acirc".to_string(),
-            ShareType::Boolean => "bcirc".to_string(),
-            ShareType::Yao => "ycirc".to_string(),
+        let max = self.gate(t, GateOp::Const(width as u64 - 1), width);
+        let too_far = self.gate(t, GateOp::Gt(b, max), 1);
+        let out = self.shift_const(t, op, a, width);
+        self.gate(t, GateOp::Mux(too_far, out, acc), width)
+    }
+
+    /// The quotient and remainder of unsigned `a` and `b`, by restoring division.
+    ///
+    /// Like the IR, dividing by zero gives all ones, with remainder `a`.
+    fn divide(&mut self, t: &Term, a: Wire, b: Wire) -> (Wire, Wire) {
+        let width = self.circ.gate(a).width;
+        let zero = self.gate(t, GateOp::Const(0), 1);
+        let b = self.concat(t, zero, b);
+        let mut r = self.gate(t, GateOp::Const(0), width);
+        let mut q = None;
+        for i in (0..width).rev() {
+            let a_i = self.slice(t, a, i, 1);
+            let r_i = self.concat(t, r, a_i);
+            let lt = self.gate(t, GateOp::Gt(b, r_i), 1);
+            let ge = self.gate(t, GateOp::Inv(lt), 1);
+            let diff = self.gate(t, GateOp::Sub(r_i, b), width + 1);
+            let next = self.gate(t, GateOp::Mux(ge, diff, r_i), width + 1);
+            r = self.slice(t, next, 0, width);
+            q = Some(match q {
+                None => ge,
+                Some(q) => self.concat(t, q, ge),
+            });
         }
+        (q.unwrap(), r)
     }
 
-    fn add_conv_gate(&self, p_t: Term, c_t: Term, c_circ: String) -> String {
-        let p_share = self.s_map.get(&p_t).unwrap();
-        let c_share = self.s_map.get(&c_t).unwrap();
+    /// `ite(s, a, b)`, for leaves `a` and `b`
+    fn mux(&mut self, t: &Term, s: Wire, a: Wire, b: Wire) -> Wire {
+        let width = self.circ.gate(a).width;
+        if self.share(t) == ShareType::Arithmetic {
+            let diff = self.gate(t, GateOp::Sub(a, b), width);
+            let prod = self.gate(t, GateOp::Mul(s, diff), width);
+            self.gate(t, GateOp::Add(b, prod), width)
+        } else {
+            self.gate(t, GateOp::Mux(s, a, b), width)
+        }
+    }
 
-        match (c_share, p_share) {
-            (ShareType::Arithmetic, ShareType::Arithmetic) => c_circ,
-            (ShareType::Boolean, ShareType::Boolean) => c_circ,
-            (ShareType::Yao, ShareType::Yao) => c_circ,
-            (ShareType::Arithmetic, ShareType::Boolean) => {
-                format!("bcirc->PutY2BGate(ycirc->PutA2YGate({}))", c_circ)
-            }
-            (ShareType::Arithmetic, ShareType::Yao) => format!("ycirc->PutA2YGate({})", c_circ),
-            (ShareType::Boolean, ShareType::Arithmetic) => format!("acirc->PutB2AGate({})", c_circ),
-            (ShareType::Boolean, ShareType::Yao) => format!("ycirc->PutB2YGate({})", c_circ),
-            (ShareType::Yao, ShareType::Arithmetic) => {
-                format!("acirc->PutB2AGate(bcirc->PutY2BGate({}))", c_circ)
+    /// Whether leaves `a` and `b`, of sort `s`, are equal
+    fn eq_leaf(&mut self, t: &Term, s: &Sort, a: Wire, b: Wire) -> Wire {
+        let neq = match s {
+            Sort::Bool => self.gate(t, GateOp::Xor(a, b), 1),
+            Sort::BitVector(_) => {
+                let gt = self.gate(t, GateOp::Gt(a, b), 1);
+                let lt = self.gate(t, GateOp::Gt(b, a), 1);
+                self.gate(t, GateOp::Xor(gt, lt), 1)
             }
-            (ShareType::Yao, ShareType::Boolean) => format!("bcirc->PutY2BGate({})", c_circ),
+            e => panic!("Unimplemented sort for Eq: {:?}", e),
+        };
+        let one = self.gate(t, GateOp::Const(1), 1);
+        self.gate(t, GateOp::Xor(neq, one), 1)
+    }
+
+    fn embed_eq(&mut self, t: &Term) -> Wire {
+        let a = self.args(t, &t.cs[0]);
+        let b = self.args(t, &t.cs[1]);
+        let sorts = leaf_sorts(&check(&t.cs[0]));
+        let eqs: Vec<Wire> = sorts
+            .iter()
+            .zip(a.into_iter().zip(b))
+            .map(|(s, (a, b))| self.eq_leaf(t, s, a, b))
+            .collect();
+        let mut acc = eqs[0];
+        for e in &eqs[1..] {
+            acc = self.gate(t, GateOp::And(acc, *e), 1);
         }
+        acc
     }
 
-    fn add_cons_gate(&self, t: Term) -> String {
-        let name = ToABY::get_var_name(t.clone(), true);
-        let s_circ = self.get_sharetype_circ(t.clone());
-        format!(
-            "s_{} = {}->PutCONSGate((uint64_t){}, bitlen);\n",
-            name, s_circ, name
-        )
-        .to_string()
-    }
-
-    fn add_in_gate(&self, t: Term, role: String) -> String {
-        let name = ToABY::get_var_name(t.clone(), true);
-        let s_circ = self.get_sharetype_circ(t.clone());
-        format!(
-            "\ts_{} = {}->PutINGate({}, bitlen, {});\n",
-            name, s_circ, name, role
-        )
-        .to_string()
-    }
-
-    fn add_dummy_gate(&self, t: Term) -> String {
-        let name = ToABY::get_var_name(t.clone(), true);
-        let s_circ = self.get_sharetype_circ(t.clone());
-        format!("\ts_{} = {}->PutDummyINGate(bitlen);\n", name, s_circ).to_string()
-    }
-
-    /// Initialize private and public inputs from each party
-    /// Party inputs are stored in *self.inputs*
-    fn init_inputs(&mut self) {
-        let mut server_inputs = TermSet::new();
-        let mut client_inputs = TermSet::new();
-        let mut public_inputs = TermSet::new();
-
-
-        // Parse input parameters from command line as uint32_t variables
-        // Initialize shares for each party
-        for (t, party) in self.inputs.iter() {
-            let name = ToABY::get_var_name(t.clone(), false);
-            let name_ = ToABY::get_var_name(t.clone(), true);
-
-            write_line_to_file(&self.setup_fname, &format!(
-                "uint32_t {} = std::atoi(params[\"{}\"].c_str());\n",
-                name_,
-                self.parse_var_name(name.to_string())
-            ));
-
-            write_line_to_file(&self.setup_fname, &format!("share* s_{};\n", name_).to_string());
-
-            let role = party.unwrap_or_else(|| NO_ROLE);
-            if role == SERVER {
-                server_inputs.insert(t.clone());
-            } else if role == CLIENT {
-                client_inputs.insert(t.clone());
-            } else if role != SERVER && role != CLIENT && self.md.is_input_public(&name_) {
-                public_inputs.insert(t.clone());
-            } else {
-                panic!("Unknown role or visibility for variable: {}", name);
-            }
+    /// Fold the children of `t` with `op`
+    fn embed_nary(&mut self, t: &Term, op: fn(Wire, Wire) -> GateOp) -> Wire {
+        let width = ToABY::width(t);
+        let mut acc = self.arg(t, &t.cs[0]);
+        for c in &t.cs[1..] {
+            let b = self.arg(t, c);
+            acc = self.gate(t, op(acc, b), width);
         }
+        acc
+    }
 
-        // Initialize public inputs as CONS gateshares
-        for t in public_inputs.iter() {
-            write_line_to_file(&self.setup_fname, &self.add_cons_gate(t.clone()));
+    /// The wires for constant `v`, as part of `t`
+    fn embed_const(&mut self, t: &Term, v: &Value) -> Vec<Wire> {
+        match v {
+            Value::Bool(b) => vec![self.gate(t, GateOp::Const(*b as u64), 1)],
+            Value::BitVector(b) => {
+                let c = b
+                    .uint()
+                    .to_u64()
+                    .unwrap_or_else(|| panic!("Constant too wide for ABY: {}", b));
+                vec![self.gate(t, GateOp::Const(c), b.width())]
+            }
+            Value::Tuple(vs) => vs.iter().flat_map(|v| self.embed_const(t, v)).collect(),
+            Value::Array(Sort::Array(k, _, _), d, m, n) => array_keys(k, *n)
+                .iter()
+                .flat_map(|i| self.embed_const(t, m.get(i).unwrap_or(d)))
+                .collect(),
+            _ => panic!("Unsupported constant in ABY: {}", v),
         }
+    }
 
-        // Initialize Server inputs
-        write_line_to_file(&self.setup_fname, &String::from("if (role == SERVER) {\n"));
-        // TODO: add in gates based on type / number of inputs 
-        for t in server_inputs.iter() {
-            write_line_to_file(&self.setup_fname, &self.add_in_gate(t.clone(), "SERVER".to_string()));
+    /// The constant index of `t`, an array access, if its index is constant
+    fn const_index(t: &Term) -> Option<usize> {
+        match &t.cs[1].op {
+            Op::Const(Value::BitVector(b)) => b.uint().to_usize(),
+            Op::Const(Value::Bool(b)) => Some(*b as usize),
+            _ => None,
         }
-        for t in client_inputs.iter() {
-            write_line_to_file(&self.setup_fname, &self.add_dummy_gate(t.clone()));
+    }
+
+    /// The wire for `i >= k`, where `i` is the index of `t`, an array access
+    fn at_least(&mut self, t: &Term, i: Wire, k: usize) -> Wire {
+        if let Some(w) = self.bounds.get(&(i, k)) {
+            return *w;
         }
-        write_line_to_file(&self.setup_fname, &String::from("}\n"));
+        let width = self.circ.gate(i).width;
+        let below = self.gate(t, GateOp::Const(k as u64 - 1), width);
+        let w = self.gate(t, GateOp::Gt(i, below), 1);
+        self.bounds.insert((i, k), w);
+        w
+    }
 
-        // Initialize Client inputs
-        write_line_to_file(&self.setup_fname, &String::from("if (role == CLIENT) {\n"));
-        for t in client_inputs.iter() {
-            write_line_to_file(&self.setup_fname, &self.add_in_gate(t.clone(), "CLIENT".to_string()));
+    /// Select element `i` of `elems`, whose indices are `lo..`, with a tree of multiplexers
+    fn select_tree(&mut self, t: &Term, i: Wire, elems: &[&[Wire]], lo: usize) -> Vec<Wire> {
+        if elems.len() == 1 {
+            return elems[0].to_vec();
         }
-        for t in server_inputs.iter() {
-            write_line_to_file(&self.setup_fname, &self.add_dummy_gate(t.clone()));
+        let mid = elems.len() / 2;
+        let low = self.select_tree(t, i, &elems[..mid], lo);
+        let high = self.select_tree(t, i, &elems[mid..], lo + mid);
+        let s = self.at_least(t, i, lo + mid);
+        low.into_iter()
+            .zip(high)
+            .map(|(l, h)| {
+                let width = self.circ.gate(l).width;
+                self.gate(t, GateOp::Mux(s, h, l), width)
+            })
+            .collect()
+    }
+
+    /// Embed `t`, a select or store.
+    ///
+    /// Constant indices pick out wires directly. A secret index `i` is compared against the
+    /// boundaries between elements: a select is a tree of multiplexers, and a store updates each
+    /// element if `i` falls within its boundaries. Secret indices are assumed to be in bounds.
+    fn embed_array_op(&mut self, t: &Term) -> Vec<Wire> {
+        let (v, n) = match check(&t.cs[0]) {
+            Sort::Array(_, v, n) => (v, n),
+            s => panic!("Expected an array, but got {}", s),
+        };
+        let m = n_leaves(&v);
+        let a = self.args(t, &t.cs[0]);
+        let elems: Vec<&[Wire]> = a.chunks(m).collect();
+        let idx = ToABY::const_index(t);
+        if let Some(i) = idx {
+            assert!(i < n, "Index {} out of bounds for array of size {}", i, n);
         }
-        write_line_to_file(&self.setup_fname, &String::from("}\n"));
-    }
-
-    /// Return constant gate evaluating to 0
-    // TODO: const should not be hardcoded to acirc
-    #[allow(dead_code)]
-    fn zero() -> String {
-        format!("acirc->PutCONSGate((uint64_t)0, (uint32_t)1)")
-    }
-
-    /// Return constant gate evaluating to 1
-    // TODO: const should not be hardcoded to acirc
-    fn one(s_type: &String) -> String {
-        format!("{}->PutCONSGate((uint64_t)1, (uint32_t)1)", s_type)
-    }
-
-    fn embed_eq(&mut self, t: Term, a: Term, b: Term) {
-        let s_circ = self.get_sharetype_circ(t.clone());
-        match check(&a) {
-            Sort::Bool => {
-                let a_circ = self.get_bool(&a).clone();
-                let b_circ = self.get_bool(&b).clone();
-
-                let a_conv = self.add_conv_gate(t.clone(), a, a_circ);
-                let b_conv = self.add_conv_gate(t.clone(), b, b_circ);
-
-                let share = self.get_share_name();
-                self.inc_share();
-                let s = format!(
-                    "share* {} = {}->PutXORGate({}->PutXORGate({}, {}), {});\n",
-                    share,
-                    s_circ,
-                    s_circ,
-                    a_conv,
-                    b_conv,
-                    ToABY::one(&s_circ)
-                );
-                write_line_to_file(&self.circuit_fname, &s);
-
-                self.cache.insert(
-                    t.clone(),
-                    EmbeddedTerm::Bool(share),
-                );
+        match (&t.op, idx) {
+            (Op::Select, Some(i)) => elems[i].to_vec(),
+            (Op::Select, None) => {
+                let i = self.arg(t, &t.cs[1]);
+                self.select_tree(t, i, &elems, 0)
             }
-            Sort::BitVector(_) => {
-                let a_circ = self.get_bv(&a).clone();
-                let b_circ = self.get_bv(&b).clone();
-
-                let a_conv = self.add_conv_gate(t.clone(), a, a_circ);
-                let b_conv = self.add_conv_gate(t.clone(), b, b_circ);
-
-                let share = self.get_share_name();
-                self.inc_share();
-                let s = format!(
-                    "share* {} = {}->PutXORGate({}->PutXORGate({}->PutGTGate({}, {}), {}->PutGTGate({}, {})), {});\n",
-                    share, s_circ, s_circ, s_circ, a_conv, b_conv, s_circ, b_conv, a_conv, ToABY::one(&s_circ)
-                );
-                write_line_to_file(&self.circuit_fname, &s);
-                self.cache.insert(t.clone(), EmbeddedTerm::Bool(share));
+            (Op::Store, Some(i)) => {
+                let v = self.args(t, &t.cs[2]);
+                let mut out = a.clone();
+                out[i * m..(i + 1) * m].copy_from_slice(&v);
+                out
             }
-            e => panic!("Unimplemented sort for Eq: {:?}", e),
-        }
-    }
-
-    /// Given term `t`, type-check `t` is of type Bool and return the variable name for
-    /// `t`
-    fn get_bool(&self, t: &Term) -> String {
-        match self
-            .cache
-            .get(t)
-            .unwrap_or_else(|| panic!("Missing wire for {:?}", t))
-        {
-            EmbeddedTerm::Bool(b) => b.clone(),
-            _ => panic!("Non-bool for {:?}", t),
+            (Op::Store, None) => {
+                let i = self.arg(t, &t.cs[1]);
+                let v = self.args(t, &t.cs[2]);
+                let mut out = Vec::new();
+                for (j, e) in elems.iter().enumerate() {
+                    let hit = match (j > 0, j + 1 < n) {
+                        (false, false) => None,
+                        (true, false) => Some(self.at_least(t, i, j)),
+                        (false, true) => {
+                            let above = self.at_least(t, i, j + 1);
+                            Some(self.gate(t, GateOp::Inv(above), 1))
+                        }
+                        (true, true) => {
+                            let lo = self.at_least(t, i, j);
+                            let above = self.at_least(t, i, j + 1);
+                            let hi = self.gate(t, GateOp::Inv(above), 1);
+                            Some(self.gate(t, GateOp::And(lo, hi), 1))
+                        }
+                    };
+                    for (old, new) in e.iter().zip(&v) {
+                        out.push(match hit {
+                            None => *new,
+                            Some(h) => {
+                                let width = self.circ.gate(*old).width;
+                                self.gate(t, GateOp::Mux(h, *new, *old), width)
+                            }
+                        });
+                    }
+                }
+                out
+            }
+            _ => unreachable!(),
         }
     }
 
-    fn embed_bool(&mut self, t: Term) -> String {
-        let mut s_circ = self.get_sharetype_circ(t.clone());
+    fn embed_term(&mut self, t: &Term) -> Vec<Wire> {
         match &t.op {
-            Op::Var(name, Sort::Bool) => {
-                if !self.inputs.contains_key(&t) {
-                    self.inputs
-                        .insert(t.clone(), *self.md.inputs.get(name).unwrap());
-                }
-                if !self.cache.contains_key(&t) {
-                    self.cache
-                        .insert(t.clone(), EmbeddedTerm::Bool(format!("s_{}", name.replace(".", "_"))));
-                }
+            Op::Const(v) => return self.embed_const(t, v),
+            Op::Eq => return vec![self.embed_eq(t)],
+            Op::Ite => {
+                let s = self.arg(t, &t.cs[0]);
+                let a = self.args(t, &t.cs[1]);
+                let b = self.args(t, &t.cs[2]);
+                return a
+                    .into_iter()
+                    .zip(b)
+                    .map(|(a, b)| self.mux(t, s, a, b))
+                    .collect();
             }
-            Op::Const(Value::Bool(b)) => {
-                let share = self.get_share_name();
-                self.inc_share();
-                let s = format!(
-                    "share* {} = {}->PutCONSGate((uint64_t){}, (uint32_t){});\n",
-                    share,
-                    s_circ,
-                    *b as isize, 
-                    BOOLEAN_BITLEN
-                );
-                write_line_to_file(&self.circuit_fname, &s);
-
-                self.cache.insert(
-                    t.clone(),
-                    EmbeddedTerm::Bool(share),
-                );
+            Op::Tuple => {
+                return t.cs.iter().flat_map(|c| self.args(t, c)).collect();
             }
-            Op::Eq => {
-                self.embed_eq(t.clone(), t.cs[0].clone(), t.cs[1].clone());
+            Op::Field(i) => {
+                let fields = check(&t.cs[0]).as_tuple().clone();
+                let start: usize = fields[..*i].iter().map(n_leaves).sum();
+                let len = n_leaves(&fields[*i]);
+                return self.args(t, &t.cs[0])[start..start + len].to_vec();
             }
-            Op::Ite => {
-                let sel_circ = self.get_bool(&t.cs[0]).clone();
-                let a_circ = self.get_bool(&t.cs[1]).clone();
-                let b_circ = self.get_bool(&t.cs[2]).clone();
-
-                let sel_conv = self.add_conv_gate(t.clone(), t.cs[0].clone(), sel_circ);
-                let a_conv = self.add_conv_gate(t.clone(), t.cs[1].clone(), a_circ);
-                let b_conv = self.add_conv_gate(t.clone(), t.cs[2].clone(), b_circ);
-
-                let share = self.get_share_name();
-                self.inc_share();
-                let s = format!(
-                    "share* {} = {}->PutMUXGate({}, {}, {});\n",
-                    share, s_circ, a_conv, b_conv, sel_conv
-                );
-                write_line_to_file(&self.circuit_fname, &s);
-                self.cache.insert(
-                    t.clone(),
-                    EmbeddedTerm::Bool(share),
-                );
+            Op::ConstArray(_, n) => {
+                let v = self.args(t, &t.cs[0]);
+                return (0..*n).flat_map(|_| v.iter().cloned()).collect();
+            }
+            Op::Select | Op::Store => return self.embed_array_op(t),
+            _ => {}
+        }
+        let width = ToABY::width(t);
+        let w = match &t.op {
+            Op::Var(name, _) => {
+                let party = *self
+                    .md
+                    .inputs
+                    .get(name)
+                    .unwrap_or_else(|| panic!("Unknown input: {}", name));
+                let op = GateOp::In {
+                    name: name.clone(),
+                    party,
+                };
+                self.gate(t, op, width)
             }
             Op::Not => {
-                let a_circ = self.get_bool(&t.cs[0]);
-                let a_conv = self.add_conv_gate(t.clone(), t.cs[0].clone(), a_circ);
-
-                let share = self.get_share_name();
-                self.inc_share();
-                let s = format!(
-                    "share* {} = ((BooleanCircuit *) {})->PutINVGate({});\n",
-                    share, s_circ, a_conv
-                );
-                write_line_to_file(&self.circuit_fname, &s);
-                self.cache.insert(
-                    t.clone(),
-                    EmbeddedTerm::Bool(share),
-                );
+                let a = self.arg(t, &t.cs[0]);
+                self.gate(t, GateOp::Inv(a), width)
             }
-            Op::BoolNaryOp(o) => {
-                if t.cs.len() == 1 {
-                    // HACK: Conditionals might not contain two variables
-                    // If t.cs len is 1, just output that term
-                    // This is to bypass adding an AND gate with a single conditional term
-                    // Refer to pub fn condition() in src/circify/mod.rs
-                    let a = self.get_bool(&t.cs[0]).clone();
-                    self.cache.insert(
-                        t.clone(),
-                        EmbeddedTerm::Bool(format!("{}", a)),
-                    );
-                } else {
-                    let a_circ = self.get_bool(&t.cs[0]).clone();
-                    let b_circ = self.get_bool(&t.cs[1]).clone();
-
-                    let a_conv = self.add_conv_gate(t.clone(), t.cs[0].clone(), a_circ);
-                    let b_conv = self.add_conv_gate(t.clone(), t.cs[1].clone(), b_circ);
-
-                    if *o == BoolNaryOp::Or {
-                        s_circ = format!("((BooleanCircuit *) {})", s_circ);
+            Op::BoolNaryOp(o) => self.embed_nary(
+                t,
+                match o {
+                    BoolNaryOp::Or => GateOp::Or,
+                    BoolNaryOp::And => GateOp::And,
+                    BoolNaryOp::Xor => GateOp::Xor,
+                },
+            ),
+            Op::BvNaryOp(o) => self.embed_nary(
+                t,
+                match o {
+                    BvNaryOp::Xor => GateOp::Xor,
+                    BvNaryOp::Or => GateOp::Or,
+                    BvNaryOp::And => GateOp::And,
+                    BvNaryOp::Add => GateOp::Add,
+                    BvNaryOp::Mul => GateOp::Mul,
+                },
+            ),
+            Op::BvBinOp(o) => {
+                let a = self.arg(t, &t.cs[0]);
+                match o {
+                    BvBinOp::Sub => {
+                        let b = self.arg(t, &t.cs[1]);
+                        self.gate(t, GateOp::Sub(a, b), width)
                     }
-
-                    let share = self.get_share_name();
-                    self.inc_share();
-                    let s = format!(
-                        "share* {} = {}->{}({}, {});\n",
-                        share,
-                        s_circ,
-                        match o {
-                            BoolNaryOp::Or => "PutORGate",
-                            BoolNaryOp::And => "PutANDGate",
-                            BoolNaryOp::Xor => "PutXORGate",
-                        },
-                        a_conv,
-                        b_conv
-                    );
-                    write_line_to_file(&self.circuit_fname, &s);
-                    self.cache.insert(
-                        t.clone(),
-                        EmbeddedTerm::Bool(share),
-                    );
+                    BvBinOp::Udiv | BvBinOp::Urem => {
+                        let b = self.arg(t, &t.cs[1]);
+                        let (q, r) = self.divide(t, a, b);
+                        if *o == BvBinOp::Udiv {
+                            q
+                        } else {
+                            r
+                        }
+                    }
+                    BvBinOp::Shl | BvBinOp::Lshr | BvBinOp::Ashr => match ToABY::const_index(t) {
+                        Some(k) => self.shift_const(t, o, a, k),
+                        None => {
+                            let b = self.arg(t, &t.cs[1]);
+                            self.shift(t, o, a, b)
+                        }
+                    },
                 }
             }
             Op::BvBinPred(op) => {
-                let a_circ = self.get_bv(&t.cs[0]);
-                let b_circ = self.get_bv(&t.cs[1]);
-
-                let a_conv = self.add_conv_gate(t.clone(), t.cs[0].clone(), a_circ);
-                let b_conv = self.add_conv_gate(t.clone(), t.cs[1].clone(), b_circ);
-
-                let share = self.get_share_name();
-                self.inc_share();
-                let s = match op {
-                    BvBinPred::Ugt => {
-                        format!(
-                            "share* {} = {}->PutGTGate({}, {});\n",
-                            share, s_circ, a_conv, b_conv
-                        )
-                    }
-                    BvBinPred::Ult => {
-                        format!(
-                            "share* {} = {}->PutGTGate({}, {});\n",
-                            share, s_circ, b_conv, a_conv
-                        )
-                    }
-                    BvBinPred::Uge => {
-                        format!(
-                            "share* {} = ((BooleanCircuit *){})->PutINVGate({}->PutGTGate({}, {}));\n",
-                            share, s_circ, s_circ, b_conv, a_conv
-                        )
+                let mut a = self.arg(t, &t.cs[0]);
+                let mut b = self.arg(t, &t.cs[1]);
+                use BvBinPred::*;
+                if let Slt | Sgt | Sle | Sge = op {
+                    // Flipping the sign bits maps signed order to unsigned order
+                    let w = ToABY::width(&t.cs[0]);
+                    let sign = self.gate(t, GateOp::Const(1 << (w - 1)), w);
+                    a = self.gate(t, GateOp::Xor(a, sign), w);
+                    b = self.gate(t, GateOp::Xor(b, sign), w);
+                }
+                match op {
+                    Ugt | Sgt => self.gate(t, GateOp::Gt(a, b), 1),
+                    Ult | Slt => self.gate(t, GateOp::Gt(b, a), 1),
+                    Uge | Sge => {
+                        let lt = self.gate(t, GateOp::Gt(b, a), 1);
+                        self.gate(t, GateOp::Inv(lt), 1)
                     }
-                    BvBinPred::Ule => {
-                        format!(
-                            "share* {} = ((BooleanCircuit *){})->PutINVGate({}->PutGTGate({}, {}));\n",
-                            share, s_circ, s_circ, a_conv, b_conv
-                        )
+                    Ule | Sle => {
+                        let gt = self.gate(t, GateOp::Gt(a, b), 1);
+                        self.gate(t, GateOp::Inv(gt), 1)
                     }
-                    _ => panic!("Non-field in bool BvBinPred: {}", op),
-                };
-                write_line_to_file(&self.circuit_fname, &s);
-                self.cache.insert(
-                    t.clone(),
-                    EmbeddedTerm::Bool(share),
-                );
-            }
-            _ => panic!("Non-field in embed_bool: {}", t),
-        }
-        self.get_bool(&t)
-    }
-
-    /// Given term `t`, type-check `t` is of type Bv and return the variable name for
-    /// `t`
-    fn get_bv(&self, t: &Term) -> String {
-        match self
-            .cache
-            .get(t)
-            .unwrap_or_else(|| panic!("Missing wire for {:?}", t))
-        {
-            EmbeddedTerm::Bv(b) => b.clone(),
-            _ => panic!("Non-bv for {:?}", t),
-        }
-    }
-
-    fn embed_bv(&mut self, t: Term) -> String {
-        let mut s_circ = self.get_sharetype_circ(t.clone());
-        match &t.op {
-            Op::Var(name, Sort::BitVector(_)) => {
-                if !self.inputs.contains_key(&t) {
-                    self.inputs
-                        .insert(t.clone(), *self.md.inputs.get(name).unwrap());
-                }
-                if !self.cache.contains_key(&t) {
-                    self.cache
-                        .insert(t.clone(), EmbeddedTerm::Bv(format!("s_{}", name.replace(".", "_"))));
                 }
             }
-            Op::Const(Value::BitVector(b)) => {
-                let share = self.get_share_name();
-                self.inc_share();
-                let s = format!(
-                    "share* {} = {}->PutCONSGate((uint64_t){}, (uint32_t){});\n",
-                    share,
-                    s_circ,
-                    format!("{}", b).replace("#", "0"),
-                    b.width()
-                );
-                write_line_to_file(&self.circuit_fname, &s);
-
-                self.cache.insert(
-                    t.clone(),
-                    EmbeddedTerm::Bv(share),
-                );
+            Op::BvExtract(hi, lo) => {
+                let a = self.arg(t, &t.cs[0]);
+                self.slice(t, a, *lo, hi - lo + 1)
             }
-            Op::Ite => {
-                let sel_circ = self.get_bool(&t.cs[0]).clone();
-                let a_circ = self.get_bv(&t.cs[1]).clone();
-                let b_circ = self.get_bv(&t.cs[2]).clone();
-
-                let sel_conv = self.add_conv_gate(t.clone(), t.cs[0].clone(), sel_circ);
-                let a_conv = self.add_conv_gate(t.clone(), t.cs[1].clone(), a_circ);
-                let b_conv = self.add_conv_gate(t.clone(), t.cs[2].clone(), b_circ);
-
-                let share = self.get_share_name();
-                self.inc_share();
-                let s = format!(
-                    "share* {} = {}->PutMUXGate({}, {}, {});\n",
-                    share, s_circ, a_conv, b_conv, sel_conv
-                );
-                write_line_to_file(&self.circuit_fname, &s);
-
-                self.cache.insert(
-                    t.clone(),
-                    EmbeddedTerm::Bv(share),
-                );
-            }
-            Op::BvNaryOp(o) => {
-                let a_circ = self.get_bv(&t.cs[0]);
-                let b_circ = self.get_bv(&t.cs[1]);
-
-                let a_conv = self.add_conv_gate(t.clone(), t.cs[0].clone(), a_circ);
-                let b_conv = self.add_conv_gate(t.clone(), t.cs[1].clone(), b_circ);
-
-                if *o == BvNaryOp::Or {
-                    s_circ = format!("((BooleanCircuit *){})", s_circ);
+            Op::BvConcat => {
+                let mut acc = self.arg(t, &t.cs[0]);
+                for c in &t.cs[1..] {
+                    let lo = self.arg(t, c);
+                    acc = self.concat(t, acc, lo);
                 }
-
-                let share = self.get_share_name();
-                self.inc_share();
-                let s = format!(
-                    "share* {} = {}->{}({}, {});\n",
-                    share,
-                    s_circ,
-                    match o {
-                        BvNaryOp::Xor => "PutXORGate",
-                        BvNaryOp::Or => "PutORGate",
-                        BvNaryOp::And => "PutANDGate",
-                        BvNaryOp::Add => "PutADDGate",
-                        BvNaryOp::Mul => "PutMULGate",
-                    },
-                    a_conv,
-                    b_conv
-                );
-                write_line_to_file(&self.circuit_fname, &s);
-                
-                self.cache.insert(
-                    t.clone(),
-                    EmbeddedTerm::Bv(share),
-                );
+                acc
             }
-            Op::BvBinOp(o) => {
-                let a_circ = self.get_bv(&t.cs[0]);
-                let b_circ = self.get_bv(&t.cs[1]);
-
-                let a_conv = self.add_conv_gate(t.clone(), t.cs[0].clone(), a_circ);
-                let b_conv = self.add_conv_gate(t.clone(), t.cs[1].clone(), b_circ);
-
-                let share = self.get_share_name();
-                self.inc_share();
-                let s = match o {
-                    BvBinOp::Sub => {
-                        format!(
-                            "share* {} = {}->PutSUBGate({}, {});\n",
-                            share,
-                            s_circ,
-                            a_conv,
-                            b_conv
-                        )
-                    }
-                    _ => {
-                        format!(
-                            "share* {} = {}({}, {}, {});\n",
-                            share,
-                            match o {
-                                BvBinOp::Udiv => "signeddivbl",
-                                BvBinOp::Urem => "signedmodbl",
-                                BvBinOp::Shl => "left_shift",
-                                BvBinOp::Lshr => "logical_right_shift",
-                                BvBinOp::Ashr => "arithmetic_right_shift",
-                                _ => unreachable!(),
-                            },
-                            s_circ,
-                            a_conv,
-                            b_conv,
-                        )
-                    }
-                };
-                write_line_to_file(&self.circuit_fname, &s);
-                self.cache.insert(
-                    t.clone(),
-                    EmbeddedTerm::Bv(share),
-                );
+            Op::BvUext(k) => {
+                let a = self.arg(t, &t.cs[0]);
+                let zeros = self.gate(t, GateOp::Const(0), *k);
+                self.concat(t, zeros, a)
             }
-            // TODO
-            Op::BvExtract(_start, _end) => {}
-            _ => panic!("Non-field in embed_bv: {:?}", t),
-        }
-
-        self.get_bv(&t)
+            Op::BvSext(k) => {
+                let a = self.arg(t, &t.cs[0]);
+                let fill = self.sign_fill(t, a, *k);
+                self.concat(t, fill, a)
+            }
+            _ => panic!("Unsupported term in ABY: {}", t),
+        };
+        vec![w]
     }
 
-    /// Given a Circuit `circ`, wrap `circ` in an OUT gate to extract the value of
-    /// the circuit to a share      
+    /// Given a term `t`, lower `t` to gates, and output it.
     ///
-    /// Return a String of the resulting Circuit
-    fn format_output_circuit(&self, t: Term) -> String {
-        match self.cache.get(&t) {
-            Some(EmbeddedTerm::Bool(s)) | Some(EmbeddedTerm::Bv(s))  => {
-                format!(
-                    "add_to_output_queue(out_q, {}->PutOUTGate({}, ALL), role, std::cout);\n",
-                    self.get_sharetype_circ(t),
-                    s
-                )
+    /// Tuples and arrays are output as one value per leaf (see [leaf_sorts]).
+    fn lower(&mut self, t: Term) {
+        for c in PostOrderIter::new(t.clone()) {
+            if !self.cache.contains_key(&c) {
+                let ws = self.embed_term(&c);
+                debug_assert_eq!(ws.len(), n_leaves(&check(&c)));
+                self.cache.insert(c, ws);
             }
-            None => panic!("Term not found in cache: {:#?}", t),
         }
+        let ws = self.wires(&t).clone();
+        self.circ.outputs.extend(ws);
     }
+}
 
-    fn embed(&mut self, t: Term) -> String {
-        for c in PostOrderIter::new(t.clone()) {
-            match check(&c) {
-                Sort::Bool => {
-                    self.embed_bool(c);
-                }
-                Sort::BitVector(_) => {
-                    self.embed_bv(c);
-                }
-                e => panic!("Unsupported sort in embed: {:?}", e),
-            }
+/// Parse the name of the command-line parameter for an input from its IR variable name
+pub(crate) fn parse_var_name(full_name: String) -> String {
+    let parsed: Vec<String> = full_name.split("_").map(str::to_string).collect();
+    if parsed.len() < 2 {
+        panic!("Invalid variable name: {}", full_name);
+    }
+    let mut name = parsed[parsed.len() - 2].to_string();
+    if full_name.contains(".") {
+        let index: Vec<String> = full_name.split(".").map(str::to_string).collect();
+        if index.len() < 1 {
+            panic!("Invalid variable name: {}", full_name);
         }
-        self.format_output_circuit(t)
+        name += &("_".to_owned() + &index[index.len() - 1].to_string());
     }
+    name
+}
 
-    /// Given a term `t`, lower `t` to ABY Circuits
-    fn lower(&mut self, t: Term) {
-        let s = self.embed(t.clone());
-        write_line_to_file(&self.circuit_fname, &s);
+/// Lower `ir` to an ABY circuit, evaluating each term in the sharing `s_map` gives it.
+pub fn to_aby_circuit(ir: &Computation, s_map: &SharingMap) -> AbyCircuit {
+    let mut converter = ToABY::new(&ir.metadata, s_map);
+    for t in &ir.outputs {
+        converter.lower(t.clone());
     }
+    converter.circ.output_parties = ir.metadata.output_parties.clone();
+    converter.circ
 }
 
-/// Convert this (IR) `ir` to ABY.
-pub fn to_aby(ir: Computation, path_buf: &PathBuf, lang: &String) {
-    let Computation {
-        outputs: terms,
-        metadata: md,
-        values: _,
-    } = ir.clone();
-    let s_map: SharingMap = assign(&ir);
-    // let s_map: SharingMap = some_arith_sharing(&ir);
-    let mut converter = ToABY::new(md, s_map, path_buf, lang);
-
-    for t in terms {
-        // println!("terms: {}", t);
-        converter.lower(t.clone());
+/// Convert this (IR) `ir` to ABY, assigning sharings with `assigner` and the cost model `costs`.
+///
+/// Writes the circuit to temporary files in `out_dir`, which [super::output::write_aby_exec]
+/// then assembles into a project.
+pub fn to_aby(
+    ir: Computation,
+    path_buf: &PathBuf,
+    lang: &String,
+    out_dir: &Path,
+    assigner: Assigner,
+    costs: &CostModel,
+) {
+    let s_map: SharingMap = assigner.assign(&ir, costs);
+    let circ = to_aby_circuit(&ir, &s_map);
+    write_aby_circuit(&circ, path_buf, lang, out_dir);
+}
+
+/// Emit `circ` as C++, to the temporary files in `out_dir` that [super::output::write_aby_exec]
+/// assembles into a project.
+pub fn write_aby_circuit(circ: &AbyCircuit, path_buf: &PathBuf, lang: &String, out_dir: &Path) {
+    let cpp = to_cpp(circ);
+    for (part, code) in &[("setup", cpp.setup), ("circuit", cpp.circuit)] {
+        let path = get_path(out_dir, path_buf, lang, &part.to_string());
+        write_line_to_file(&path, code);
     }
+}
 
-    // Iterating and lowering the terms populates self.inputs, which
-    // are the input parameters for the ABY circuit.
-    // Call init_inputs here after self.inputs is populated.
-    converter.init_inputs();
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::target::aby::assignment::all_boolean_sharing;
+
+    #[test]
+    fn lowering() {
+        let mut md = ComputationMetadata::default();
+        let server = md.add_party("server".to_owned());
+        let client = md.add_party("client".to_owned());
+        md.new_input("a_v0".to_owned(), Some(server));
+        md.new_input("b_v0".to_owned(), Some(client));
+        let a = leaf_term(Op::Var("a_v0".to_owned(), Sort::BitVector(8)));
+        let b = leaf_term(Op::Var("b_v0".to_owned(), Sort::BitVector(8)));
+        let sum = term![BV_ADD; a.clone(), b.clone(), a.clone()];
+        let cs = Computation {
+            outputs: vec![sum.clone(), term![Op::BvBinPred(BvBinPred::Ule); sum, b]],
+            metadata: md,
+            values: None,
+        };
+        let mut s_map = all_boolean_sharing(&cs);
+        for (t, s) in s_map.iter_mut() {
+            if t.op == BV_ADD || matches!(t.op, Op::Var(..)) {
+                *s = ShareType::Arithmetic;
+            }
+        }
+        let circ = to_aby_circuit(&cs, &s_map);
+        let counts = circ.gate_counts();
+        assert_eq!(counts[&("in", ShareType::Arithmetic)], 2);
+        // Three summands take two gates.
+        assert_eq!(counts[&("add", ShareType::Arithmetic)], 2);
+        // The sum and `b` are each converted once, for the comparison.
+        assert_eq!(counts[&("conv", ShareType::Boolean)], 2);
+        assert_eq!(counts[&("gt", ShareType::Boolean)], 1);
+        assert_eq!(counts[&("inv", ShareType::Boolean)], 1);
+        assert_eq!(circ.gates.len(), 8);
+        assert_eq!(circ.outputs.len(), 2);
+        assert_eq!(circ.gate(circ.outputs[0]).share, ShareType::Arithmetic);
+        assert_eq!(circ.gate(circ.outputs[1]).width, 1);
+        let inputs: Vec<_> = circ.inputs().map(|(_, n, p)| (n, p)).collect();
+        assert_eq!(inputs, vec![("a_v0", Some(server)), ("b_v0", Some(client))]);
+    }
+
+    #[test]
+    fn arrays_and_tuples() {
+        let mut md = ComputationMetadata::default();
+        let server = md.add_party("server".to_owned());
+        let client = md.add_party("client".to_owned());
+        md.new_input("a_v0".to_owned(), Some(server));
+        md.new_input("b_v0".to_owned(), Some(client));
+        md.new_input("i_v0".to_owned(), Some(client));
+        let a = leaf_term(Op::Var("a_v0".to_owned(), Sort::BitVector(8)));
+        let b = leaf_term(Op::Var("b_v0".to_owned(), Sort::BitVector(8)));
+        let i = leaf_term(Op::Var("i_v0".to_owned(), Sort::BitVector(8)));
+        let arr = term![Op::Store; term![Op::ConstArray(Sort::BitVector(8), 4); a], i.clone(), b];
+        let cs = Computation {
+            outputs: vec![term![Op::Tuple;
+                term![Op::Select; arr.clone(), i],
+                term![Op::Select; arr, bv_lit(0, 8)]
+            ]],
+            metadata: md,
+            values: None,
+        };
+        let circ = to_aby_circuit(&cs, &all_boolean_sharing(&cs));
+        let counts = circ.gate_counts();
+        // The store and the select share the comparisons `i >= 1`, `i >= 2`, and `i >= 3`.
+        assert_eq!(counts[&("gt", ShareType::Boolean)], 3);
+        // One per element for the store, and a tree of three for the select.
+        assert_eq!(counts[&("mux", ShareType::Boolean)], 7);
+        assert_eq!(circ.outputs.len(), 2);
+        match circ.gate(circ.outputs[1]).op {
+            GateOp::Mux(_, new, old) => {
+                assert_eq!(circ.gate(new).op.name(), "in");
+                assert_eq!(circ.gate(old).op.name(), "in");
+            }
+            ref op => panic!("Expected a mux, but got {:?}", op),
+        }
+    }
 }
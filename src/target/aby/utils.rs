@@ -5,8 +5,9 @@ use std::io::{prelude::*};
 use std::path::Path;
 use std::path::PathBuf;
 
-/// Given PathBuf `path_buf` and String denominator `lang`, return the filename of the path
-pub fn get_path(path_buf: &PathBuf, lang: &String, t: &String) -> String {
+/// Given PathBuf `path_buf` and String denominator `lang`, return the path of the temporary file
+/// for part `t` of the circuit, in `out_dir`
+pub fn get_path(out_dir: &Path, path_buf: &PathBuf, lang: &String, t: &String) -> String {
     let filename = Path::new(&path_buf.iter().last().unwrap().to_os_string())
         .file_stem()
         .unwrap()
@@ -16,12 +17,12 @@ pub fn get_path(path_buf: &PathBuf, lang: &String, t: &String) -> String {
 
     let name = format!("{}_{}", filename, lang);
 
-    // TODO: clean
-    let path = format!(
-        "third_party/ABY/src/examples/{}_{}_tmp.txt",
-        name,
-        t
-    );
+    fs::create_dir_all(out_dir).expect("Failed to create output directory");
+    let path = out_dir
+        .join(format!("{}_{}_tmp.txt", name, t))
+        .to_str()
+        .expect("Non-unicode output path")
+        .to_owned();
 
     if Path::new(&path).exists() {
         fs::remove_file(&path).expect("Failed to remove old circuit_tmp file");
@@ -0,0 +1,139 @@
+//! Bristol Fashion boolean circuits
+//!
+//! A [BristolCircuit] is a netlist of XOR, AND, and INV gates, in the
+//! [Bristol Fashion](https://nigelsmart.github.io/MPC-Circuits/) format that boolean MPC tools
+//! (e.g., MP-SPDZ and emp-toolkit) read.
+//!
+//! The input wires come first, as one value per party (see [InputBlock]). The output wires come
+//! last, as one value per output of the computation. Within each value, wires are ordered from
+//! least to most significant bit.
+
+pub mod trans;
+
+use crate::ir::term::PartyId;
+
+use std::io::{self, Write};
+
+/// A wire
+pub type Wire = usize;
+
+/// A gate, with its input wire(s) first, and its output wire last
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Gate {
+    /// Exclusive or
+    Xor(Wire, Wire, Wire),
+    /// And
+    And(Wire, Wire, Wire),
+    /// Negation
+    Inv(Wire, Wire),
+    /// A copy of a wire
+    Eqw(Wire, Wire),
+    /// A constant
+    Eq(bool, Wire),
+}
+
+/// The inputs of one party, which together make up one input value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputBlock {
+    /// The party that gives them, or `None` for public inputs
+    pub party: Option<PartyId>,
+    /// Their names and widths, in wire order
+    pub inputs: Vec<(String, usize)>,
+}
+
+impl InputBlock {
+    /// The number of wires in the block
+    pub fn width(&self) -> usize {
+        self.inputs.iter().map(|(_, w)| w).sum()
+    }
+}
+
+/// A boolean circuit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BristolCircuit {
+    /// The number of wires
+    pub n_wires: usize,
+    /// The input values: one per party with inputs, in order of party id, and then one for
+    /// public inputs, if there are any
+    pub inputs: Vec<InputBlock>,
+    /// The width of each output value
+    pub outputs: Vec<usize>,
+    /// The gates, in evaluation order
+    pub gates: Vec<Gate>,
+}
+
+impl BristolCircuit {
+    /// The number of AND gates: the main cost of evaluating the circuit in MPC
+    pub fn and_count(&self) -> usize {
+        self.gates
+            .iter()
+            .filter(|g| matches!(g, Gate::And(..)))
+            .count()
+    }
+
+    /// Write the circuit in Bristol Fashion
+    pub fn write<W: Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "{} {}", self.gates.len(), self.n_wires)?;
+        write!(w, "{}", self.inputs.len())?;
+        for b in &self.inputs {
+            write!(w, " {}", b.width())?;
+        }
+        writeln!(w)?;
+        write!(w, "{}", self.outputs.len())?;
+        for o in &self.outputs {
+            write!(w, " {}", o)?;
+        }
+        writeln!(w)?;
+        writeln!(w)?;
+        for g in &self.gates {
+            match g {
+                Gate::Xor(a, b, c) => writeln!(w, "2 1 {} {} {} XOR", a, b, c)?,
+                Gate::And(a, b, c) => writeln!(w, "2 1 {} {} {} AND", a, b, c)?,
+                Gate::Inv(a, c) => writeln!(w, "1 1 {} {} INV", a, c)?,
+                Gate::Eqw(a, c) => writeln!(w, "1 1 {} {} EQW", a, c)?,
+                Gate::Eq(v, c) => writeln!(w, "1 1 {} {} EQ", *v as u8, c)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluate the circuit on the bits of each input value, giving the bits of each output value
+    pub fn eval(&self, inputs: &[Vec<bool>]) -> Vec<Vec<bool>> {
+        assert_eq!(
+            inputs.len(),
+            self.inputs.len(),
+            "Wrong number of input values"
+        );
+        let mut wires: Vec<Option<bool>> = vec![None; self.n_wires];
+        let mut next = 0;
+        for (bits, block) in inputs.iter().zip(&self.inputs) {
+            assert_eq!(bits.len(), block.width(), "Wrong width of input value");
+            for b in bits {
+                wires[next] = Some(*b);
+                next += 1;
+            }
+        }
+        let get = |wires: &Vec<Option<bool>>, w: Wire| {
+            wires[w].unwrap_or_else(|| panic!("Wire {} is read before it is set", w))
+        };
+        for g in &self.gates {
+            let (out, v) = match *g {
+                Gate::Xor(a, b, c) => (c, get(&wires, a) ^ get(&wires, b)),
+                Gate::And(a, b, c) => (c, get(&wires, a) & get(&wires, b)),
+                Gate::Inv(a, c) => (c, !get(&wires, a)),
+                Gate::Eqw(a, c) => (c, get(&wires, a)),
+                Gate::Eq(v, c) => (c, v),
+            };
+            wires[out] = Some(v);
+        }
+        let mut next = self.n_wires - self.outputs.iter().sum::<usize>();
+        self.outputs
+            .iter()
+            .map(|width| {
+                let bits = (next..next + width).map(|w| get(&wires, w)).collect();
+                next += width;
+                bits
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,832 @@
+//! Lowering IR to a Bristol Fashion boolean circuit
+//!
+//! Bit-vectors are blasted into bits, least significant first, and each operator is built from
+//! gadgets that use few AND gates, since XOR and INV gates are free in most boolean MPC protocols:
+//!
+//!    * `w`-bit addition and subtraction use a ripple-carry adder with one AND per carry (`w - 1`)
+//!    * comparisons use just the carry chain of a subtraction (`w`)
+//!    * equality is an AND-tree over the bits' agreement (`w - 1`)
+//!    * if-then-else is one AND per bit (`w`)
+//!    * multiplication skips partial products above the result width
+//!    * shifts by constants are free, and other shifts are barrel shifters
+//!
+//! Gates are hash-consed, constants are folded away, and gates that no output needs are dropped.
+
+use super::{BristolCircuit, Gate, InputBlock, Wire};
+use crate::ir::term::*;
+
+use fxhash::FxHashMap;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Bit {
+    Const(bool),
+    Wire(Wire),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Node {
+    Xor(Wire, Wire),
+    And(Wire, Wire),
+    Inv(Wire),
+}
+
+impl Node {
+    fn args(&self) -> Vec<Wire> {
+        match *self {
+            Node::Xor(a, b) | Node::And(a, b) => vec![a, b],
+            Node::Inv(a) => vec![a],
+        }
+    }
+}
+
+/// Builds a netlist, gate by gate. The wire of the `i`th gate is `n_inputs + i`.
+struct Builder {
+    n_inputs: usize,
+    nodes: Vec<Node>,
+    cache: FxHashMap<Node, Wire>,
+    /// Pairs of wires that are known to be negations of each other, in both directions
+    negations: FxHashMap<Wire, Wire>,
+}
+
+impl Builder {
+    fn new(n_inputs: usize) -> Self {
+        Self {
+            n_inputs,
+            nodes: Vec::new(),
+            cache: FxHashMap::default(),
+            negations: FxHashMap::default(),
+        }
+    }
+
+    fn node(&mut self, n: Node) -> Wire {
+        let n = match n {
+            Node::Xor(a, b) if a > b => Node::Xor(b, a),
+            Node::And(a, b) if a > b => Node::And(b, a),
+            n => n,
+        };
+        if let Some(w) = self.cache.get(&n) {
+            return *w;
+        }
+        let w = self.n_inputs + self.nodes.len();
+        self.nodes.push(n);
+        self.cache.insert(n, w);
+        w
+    }
+
+    fn negates(&self, a: Wire, b: Wire) -> bool {
+        self.negations.get(&a) == Some(&b)
+    }
+
+    fn not(&mut self, a: Bit) -> Bit {
+        match a {
+            Bit::Const(b) => Bit::Const(!b),
+            Bit::Wire(w) => {
+                if let Some(n) = self.negations.get(&w) {
+                    return Bit::Wire(*n);
+                }
+                let n = self.node(Node::Inv(w));
+                self.negations.insert(w, n);
+                self.negations.insert(n, w);
+                Bit::Wire(n)
+            }
+        }
+    }
+
+    fn xor(&mut self, a: Bit, b: Bit) -> Bit {
+        match (a, b) {
+            (Bit::Const(false), x) | (x, Bit::Const(false)) => x,
+            (Bit::Const(true), x) | (x, Bit::Const(true)) => self.not(x),
+            (Bit::Wire(a), Bit::Wire(b)) if a == b => Bit::Const(false),
+            (Bit::Wire(a), Bit::Wire(b)) if self.negates(a, b) => Bit::Const(true),
+            (Bit::Wire(a), Bit::Wire(b)) => Bit::Wire(self.node(Node::Xor(a, b))),
+        }
+    }
+
+    fn and(&mut self, a: Bit, b: Bit) -> Bit {
+        match (a, b) {
+            (Bit::Const(false), _) | (_, Bit::Const(false)) => Bit::Const(false),
+            (Bit::Const(true), x) | (x, Bit::Const(true)) => x,
+            (Bit::Wire(a), Bit::Wire(b)) if a == b => Bit::Wire(a),
+            (Bit::Wire(a), Bit::Wire(b)) if self.negates(a, b) => Bit::Const(false),
+            (Bit::Wire(a), Bit::Wire(b)) => Bit::Wire(self.node(Node::And(a, b))),
+        }
+    }
+
+    fn or(&mut self, a: Bit, b: Bit) -> Bit {
+        let x = self.xor(a, b);
+        let y = self.and(a, b);
+        self.xor(x, y)
+    }
+
+    /// `t` if `s`, else `f`; one AND
+    fn mux(&mut self, s: Bit, t: Bit, f: Bit) -> Bit {
+        let d = self.xor(t, f);
+        let d = self.and(s, d);
+        self.xor(f, d)
+    }
+
+    /// The majority of three bits (e.g., the carry of a full adder); one AND
+    fn maj(&mut self, a: Bit, b: Bit, c: Bit) -> Bit {
+        let x = self.xor(a, b);
+        let y = self.xor(a, c);
+        let z = self.and(x, y);
+        self.xor(a, z)
+    }
+
+    /// The conjunction of `bits`, as a balanced tree
+    fn and_all(&mut self, bits: &[Bit]) -> Bit {
+        match bits.len() {
+            0 => Bit::Const(true),
+            1 => bits[0],
+            n => {
+                let a = self.and_all(&bits[..n / 2]);
+                let b = self.and_all(&bits[n / 2..]);
+                self.and(a, b)
+            }
+        }
+    }
+
+    fn or_all(&mut self, bits: &[Bit]) -> Bit {
+        let nots: Vec<Bit> = bits.iter().map(|b| self.not(*b)).collect();
+        let and = self.and_all(&nots);
+        self.not(and)
+    }
+
+    fn not_bv(&mut self, a: &[Bit]) -> Vec<Bit> {
+        a.iter().map(|b| self.not(*b)).collect()
+    }
+
+    fn mux_bv(&mut self, s: Bit, t: &[Bit], f: &[Bit]) -> Vec<Bit> {
+        t.iter().zip(f).map(|(t, f)| self.mux(s, *t, *f)).collect()
+    }
+
+    /// `a + b + c`, and its carry out (if `carry_out`)
+    fn add_carry(&mut self, a: &[Bit], b: &[Bit], mut c: Bit, carry_out: bool) -> (Vec<Bit>, Bit) {
+        assert_eq!(a.len(), b.len());
+        let mut sum = Vec::new();
+        for i in 0..a.len() {
+            let x = self.xor(a[i], b[i]);
+            sum.push(self.xor(x, c));
+            if carry_out || i + 1 < a.len() {
+                c = self.maj(a[i], b[i], c);
+            }
+        }
+        (sum, c)
+    }
+
+    fn add(&mut self, a: &[Bit], b: &[Bit]) -> Vec<Bit> {
+        self.add_carry(a, b, Bit::Const(false), false).0
+    }
+
+    fn sub(&mut self, a: &[Bit], b: &[Bit]) -> Vec<Bit> {
+        let not_b = self.not_bv(b);
+        self.add_carry(a, &not_b, Bit::Const(true), false).0
+    }
+
+    /// `a < b`, unsigned: the borrow of `a - b`
+    fn ult(&mut self, a: &[Bit], b: &[Bit]) -> Bit {
+        assert_eq!(a.len(), b.len());
+        let mut c = Bit::Const(true);
+        for (a, b) in a.iter().zip(b) {
+            let not_b = self.not(*b);
+            c = self.maj(*a, not_b, c);
+        }
+        self.not(c)
+    }
+
+    fn eq_bv(&mut self, a: &[Bit], b: &[Bit]) -> Bit {
+        let agree: Vec<Bit> = a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| {
+                let x = self.xor(*a, *b);
+                self.not(x)
+            })
+            .collect();
+        self.and_all(&agree)
+    }
+
+    fn mul(&mut self, a: &[Bit], b: &[Bit]) -> Vec<Bit> {
+        let w = a.len();
+        let mut acc: Vec<Bit> = vec![Bit::Const(false); w];
+        for i in 0..w {
+            let partial: Vec<Bit> = (0..w - i).map(|j| self.and(a[j], b[i])).collect();
+            if partial.iter().all(|p| *p == Bit::Const(false)) {
+                continue;
+            }
+            let high = self.add(&acc[i..], &partial);
+            acc.splice(i.., high);
+        }
+        acc
+    }
+
+    /// Unsigned quotient and remainder, by restoring division. Dividing by zero gives all ones,
+    /// and the dividend.
+    fn udivrem(&mut self, a: &[Bit], b: &[Bit]) -> (Vec<Bit>, Vec<Bit>) {
+        let w = a.len();
+        let mut not_b = self.not_bv(b);
+        not_b.push(Bit::Const(true));
+        let mut q = vec![Bit::Const(false); w];
+        let mut r = vec![Bit::Const(false); w];
+        for i in (0..w).rev() {
+            // The partial remainder, shifted, with the next bit of the dividend (w + 1 bits)
+            let mut shifted = vec![a[i]];
+            shifted.extend(r.iter().cloned());
+            let (diff, fits) = self.add_carry(&shifted, &not_b, Bit::Const(true), true);
+            q[i] = fits;
+            r = self.mux_bv(fits, &diff[..w], &shifted[..w]);
+        }
+        (q, r)
+    }
+
+    /// Shift `a` by a constant `n`
+    fn shift_const(&mut self, a: &[Bit], n: usize, op: &BvBinOp) -> Vec<Bit> {
+        let w = a.len();
+        let fill = match op {
+            BvBinOp::Ashr => a[w - 1],
+            _ => Bit::Const(false),
+        };
+        let n = n.min(w);
+        match op {
+            BvBinOp::Shl => {
+                let mut r = vec![Bit::Const(false); n];
+                r.extend_from_slice(&a[..w - n]);
+                r
+            }
+            _ => {
+                let mut r = a[n..].to_vec();
+                r.extend(std::iter::repeat(fill).take(n));
+                r
+            }
+        }
+    }
+
+    /// Shift `a` by `b`, with a barrel shifter
+    fn shift(&mut self, a: &[Bit], b: &[Bit], op: &BvBinOp) -> Vec<Bit> {
+        let w = a.len();
+        let mut r = a.to_vec();
+        let mut too_far = Bit::Const(false);
+        for (k, bit) in b.iter().enumerate() {
+            if k < 64 && (1usize << k) < w {
+                let shifted = self.shift_const(&r, 1 << k, op);
+                r = self.mux_bv(*bit, &shifted, &r);
+            } else {
+                too_far = self.or(too_far, *bit);
+            }
+        }
+        let all_out = self.shift_const(a, w, op);
+        self.mux_bv(too_far, &all_out, &r)
+    }
+
+    /// Build the netlist, with output wires last, dropping gates that no output needs
+    fn finish(self, inputs: Vec<InputBlock>, outputs: Vec<Vec<Bit>>) -> BristolCircuit {
+        let n_in = self.n_inputs;
+        let mut live = vec![false; self.nodes.len()];
+        let mut stack: Vec<Wire> = outputs
+            .iter()
+            .flatten()
+            .filter_map(|b| match b {
+                Bit::Wire(w) if *w >= n_in => Some(*w),
+                _ => None,
+            })
+            .collect();
+        while let Some(w) = stack.pop() {
+            if !live[w - n_in] {
+                live[w - n_in] = true;
+                stack.extend(
+                    self.nodes[w - n_in]
+                        .args()
+                        .into_iter()
+                        .filter(|a| *a >= n_in),
+                );
+            }
+        }
+
+        // Each gate computes the first output bit it drives directly; other output bits are copies.
+        let mut slots: FxHashMap<Wire, usize> = FxHashMap::default();
+        let mut copies: Vec<(Bit, usize)> = Vec::new();
+        for (slot, bit) in outputs.iter().flatten().enumerate() {
+            match bit {
+                Bit::Wire(w) if *w >= n_in && !slots.contains_key(w) => {
+                    slots.insert(*w, slot);
+                }
+                b => copies.push((*b, slot)),
+            }
+        }
+        let n_out: usize = outputs.iter().map(|o| o.len()).sum();
+        let n_live = live.iter().filter(|l| **l).count();
+        let first_out = n_in + n_live - slots.len();
+
+        let mut renamed = vec![usize::MAX; self.nodes.len()];
+        let mut next = n_in;
+        for (i, l) in live.iter().enumerate() {
+            if *l {
+                renamed[i] = match slots.get(&(n_in + i)) {
+                    Some(slot) => first_out + slot,
+                    None => {
+                        next += 1;
+                        next - 1
+                    }
+                };
+            }
+        }
+        let wire = |w: Wire| if w < n_in { w } else { renamed[w - n_in] };
+        let mut gates: Vec<Gate> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| live[*i])
+            .map(|(i, n)| match *n {
+                Node::Xor(a, b) => Gate::Xor(wire(a), wire(b), renamed[i]),
+                Node::And(a, b) => Gate::And(wire(a), wire(b), renamed[i]),
+                Node::Inv(a) => Gate::Inv(wire(a), renamed[i]),
+            })
+            .collect();
+        gates.extend(copies.into_iter().map(|(b, slot)| match b {
+            Bit::Const(v) => Gate::Eq(v, first_out + slot),
+            Bit::Wire(w) => Gate::Eqw(wire(w), first_out + slot),
+        }));
+        BristolCircuit {
+            n_wires: first_out + n_out,
+            inputs,
+            outputs: outputs.iter().map(|o| o.len()).collect(),
+            gates,
+        }
+    }
+}
+
+#[derive(Clone)]
+enum EmbeddedTerm {
+    Bool(Bit),
+    /// LSB in index 0
+    Bv(Vec<Bit>),
+}
+
+struct ToBristol {
+    b: Builder,
+    cache: TermMap<EmbeddedTerm>,
+}
+
+impl ToBristol {
+    fn get_bool(&self, t: &Term) -> Bit {
+        match self
+            .cache
+            .get(t)
+            .unwrap_or_else(|| panic!("Missing wires for {:?}", t))
+        {
+            EmbeddedTerm::Bool(b) => *b,
+            _ => panic!("Non-bool for {:?}", t),
+        }
+    }
+
+    fn get_bv(&self, t: &Term) -> Vec<Bit> {
+        match self
+            .cache
+            .get(t)
+            .unwrap_or_else(|| panic!("Missing wires for {:?}", t))
+        {
+            EmbeddedTerm::Bv(b) => b.clone(),
+            _ => panic!("Non-bv for {:?}", t),
+        }
+    }
+
+    fn embed_bool(&mut self, t: &Term) -> Bit {
+        match &t.op {
+            Op::Const(Value::Bool(b)) => Bit::Const(*b),
+            Op::Eq => match check(&t.cs[0]) {
+                Sort::Bool => {
+                    let a = self.get_bool(&t.cs[0]);
+                    let b = self.get_bool(&t.cs[1]);
+                    let x = self.b.xor(a, b);
+                    self.b.not(x)
+                }
+                Sort::BitVector(_) => {
+                    let a = self.get_bv(&t.cs[0]);
+                    let b = self.get_bv(&t.cs[1]);
+                    self.b.eq_bv(&a, &b)
+                }
+                s => panic!("Unsupported sort for Eq in Bristol: {}", s),
+            },
+            Op::Ite => {
+                let s = self.get_bool(&t.cs[0]);
+                let a = self.get_bool(&t.cs[1]);
+                let b = self.get_bool(&t.cs[2]);
+                self.b.mux(s, a, b)
+            }
+            Op::Not => {
+                let a = self.get_bool(&t.cs[0]);
+                self.b.not(a)
+            }
+            Op::Implies => {
+                let a = self.get_bool(&t.cs[0]);
+                let b = self.get_bool(&t.cs[1]);
+                let not_a = self.b.not(a);
+                self.b.or(not_a, b)
+            }
+            Op::BoolNaryOp(o) => {
+                let args: Vec<Bit> = t.cs.iter().map(|c| self.get_bool(c)).collect();
+                match o {
+                    BoolNaryOp::And => self.b.and_all(&args),
+                    BoolNaryOp::Or => self.b.or_all(&args),
+                    BoolNaryOp::Xor => args
+                        .into_iter()
+                        .fold(Bit::Const(false), |acc, a| self.b.xor(acc, a)),
+                }
+            }
+            Op::BoolMaj => {
+                let a = self.get_bool(&t.cs[0]);
+                let b = self.get_bool(&t.cs[1]);
+                let c = self.get_bool(&t.cs[2]);
+                self.b.maj(a, b, c)
+            }
+            Op::BvBit(i) => self.get_bv(&t.cs[0])[*i],
+            Op::BvBinPred(p) => {
+                let mut a = self.get_bv(&t.cs[0]);
+                let mut b = self.get_bv(&t.cs[1]);
+                if let BvBinPred::Slt | BvBinPred::Sgt | BvBinPred::Sle | BvBinPred::Sge = p {
+                    // Flipping the sign bits maps signed order to unsigned order.
+                    let w = a.len();
+                    a[w - 1] = self.b.not(a[w - 1]);
+                    b[w - 1] = self.b.not(b[w - 1]);
+                }
+                match p {
+                    BvBinPred::Ult | BvBinPred::Slt => self.b.ult(&a, &b),
+                    BvBinPred::Ugt | BvBinPred::Sgt => self.b.ult(&b, &a),
+                    BvBinPred::Ule | BvBinPred::Sle => {
+                        let gt = self.b.ult(&b, &a);
+                        self.b.not(gt)
+                    }
+                    BvBinPred::Uge | BvBinPred::Sge => {
+                        let lt = self.b.ult(&a, &b);
+                        self.b.not(lt)
+                    }
+                }
+            }
+            _ => panic!("Unsupported term in Bristol: {}", t),
+        }
+    }
+
+    fn embed_bv(&mut self, t: &Term) -> Vec<Bit> {
+        match &t.op {
+            Op::Const(Value::BitVector(bv)) => (0..bv.width())
+                .map(|i| Bit::Const(bv.uint().get_bit(i as u32)))
+                .collect(),
+            Op::Ite => {
+                let s = self.get_bool(&t.cs[0]);
+                let a = self.get_bv(&t.cs[1]);
+                let b = self.get_bv(&t.cs[2]);
+                self.b.mux_bv(s, &a, &b)
+            }
+            Op::BvNaryOp(o) => {
+                let mut acc = self.get_bv(&t.cs[0]);
+                for c in &t.cs[1..] {
+                    let b = self.get_bv(c);
+                    acc = match o {
+                        BvNaryOp::Add => self.b.add(&acc, &b),
+                        BvNaryOp::Mul => self.b.mul(&acc, &b),
+                        BvNaryOp::Xor => acc
+                            .iter()
+                            .zip(&b)
+                            .map(|(x, y)| self.b.xor(*x, *y))
+                            .collect(),
+                        BvNaryOp::And => acc
+                            .iter()
+                            .zip(&b)
+                            .map(|(x, y)| self.b.and(*x, *y))
+                            .collect(),
+                        BvNaryOp::Or => {
+                            acc.iter().zip(&b).map(|(x, y)| self.b.or(*x, *y)).collect()
+                        }
+                    };
+                }
+                acc
+            }
+            Op::BvBinOp(o) => {
+                let a = self.get_bv(&t.cs[0]);
+                let b = self.get_bv(&t.cs[1]);
+                match o {
+                    BvBinOp::Sub => self.b.sub(&a, &b),
+                    BvBinOp::Udiv => self.b.udivrem(&a, &b).0,
+                    BvBinOp::Urem => self.b.udivrem(&a, &b).1,
+                    BvBinOp::Shl | BvBinOp::Lshr | BvBinOp::Ashr => match as_const_usize(&b) {
+                        Some(n) => self.b.shift_const(&a, n, o),
+                        None => self.b.shift(&a, &b, o),
+                    },
+                }
+            }
+            Op::BvUnOp(BvUnOp::Not) => {
+                let a = self.get_bv(&t.cs[0]);
+                self.b.not_bv(&a)
+            }
+            Op::BvUnOp(BvUnOp::Neg) => {
+                let a = self.get_bv(&t.cs[0]);
+                let zero = vec![Bit::Const(false); a.len()];
+                self.b.sub(&zero, &a)
+            }
+            Op::BoolToBv => vec![self.get_bool(&t.cs[0])],
+            Op::BvExtract(high, low) => self.get_bv(&t.cs[0])[*low..=*high].to_vec(),
+            Op::BvConcat => t.cs.iter().rev().flat_map(|c| self.get_bv(c)).collect(),
+            Op::BvUext(n) => {
+                let mut a = self.get_bv(&t.cs[0]);
+                a.extend(std::iter::repeat(Bit::Const(false)).take(*n));
+                a
+            }
+            Op::BvSext(n) => {
+                let mut a = self.get_bv(&t.cs[0]);
+                let msb = *a.last().expect("Empty bit-vector");
+                a.extend(std::iter::repeat(msb).take(*n));
+                a
+            }
+            _ => panic!("Unsupported term in Bristol: {}", t),
+        }
+    }
+
+    fn embed(&mut self, t: Term) {
+        for c in PostOrderIter::new(t) {
+            if self.cache.contains_key(&c) {
+                continue;
+            }
+            let e = match check(&c) {
+                Sort::Bool => EmbeddedTerm::Bool(self.embed_bool(&c)),
+                Sort::BitVector(_) => EmbeddedTerm::Bv(self.embed_bv(&c)),
+                s => panic!("Unsupported sort in Bristol: {}", s),
+            };
+            self.cache.insert(c, e);
+        }
+    }
+}
+
+/// The value of `bits`, if they are all constant (and it fits in a `usize`)
+fn as_const_usize(bits: &[Bit]) -> Option<usize> {
+    let mut n: usize = 0;
+    for (i, b) in bits.iter().enumerate() {
+        match b {
+            Bit::Const(false) => {}
+            Bit::Const(true) if i < 64 => n |= 1 << i,
+            _ => return None,
+        }
+    }
+    Some(n)
+}
+
+/// The input blocks of `cs`: one per party with inputs, in order of party id, and then one for
+/// public inputs. Inputs within a block are ordered by name.
+fn input_blocks(cs: &Computation) -> Vec<(InputBlock, Vec<Term>)> {
+    let mut vars: Vec<(Option<PartyId>, String, Term)> = Vec::new();
+    let mut seen = TermSet::new();
+    for o in &cs.outputs {
+        for t in PostOrderIter::new(o.clone()) {
+            if let Op::Var(name, _) = &t.op {
+                if seen.insert(t.clone()) {
+                    let party = *cs
+                        .metadata
+                        .inputs
+                        .get(name)
+                        .unwrap_or_else(|| panic!("Unknown input: {}", name));
+                    vars.push((party, name.clone(), t));
+                }
+            }
+        }
+    }
+    // Public inputs (`None`) go last.
+    vars.sort_by(|(p0, n0, _), (p1, n1, _)| (p0.is_none(), p0, n0).cmp(&(p1.is_none(), p1, n1)));
+    let mut blocks: Vec<(InputBlock, Vec<Term>)> = Vec::new();
+    for (party, name, t) in vars {
+        let width = match check(&t) {
+            Sort::Bool => 1,
+            Sort::BitVector(w) => w,
+            s => panic!("Unsupported sort for input {} in Bristol: {}", name, s),
+        };
+        match blocks.last_mut() {
+            Some((b, ts)) if b.party == party => {
+                b.inputs.push((name, width));
+                ts.push(t);
+            }
+            _ => blocks.push((
+                InputBlock {
+                    party,
+                    inputs: vec![(name, width)],
+                },
+                vec![t],
+            )),
+        }
+    }
+    blocks
+}
+
+/// Lower `cs` (a computation for MPC) to a boolean circuit.
+pub fn to_bristol(cs: &Computation) -> BristolCircuit {
+    let blocks = input_blocks(cs);
+    let mut next: Wire = 0;
+    let mut cache = TermMap::new();
+    for (_, ts) in &blocks {
+        for t in ts {
+            let e = match check(t) {
+                Sort::Bool => {
+                    next += 1;
+                    EmbeddedTerm::Bool(Bit::Wire(next - 1))
+                }
+                Sort::BitVector(w) => {
+                    next += w;
+                    EmbeddedTerm::Bv((next - w..next).map(Bit::Wire).collect())
+                }
+                _ => unreachable!(),
+            };
+            cache.insert(t.clone(), e);
+        }
+    }
+    let mut converter = ToBristol {
+        b: Builder::new(next),
+        cache,
+    };
+    let mut outputs = Vec::new();
+    for o in &cs.outputs {
+        converter.embed(o.clone());
+        outputs.push(match converter.cache.get(o).unwrap() {
+            EmbeddedTerm::Bool(b) => vec![*b],
+            EmbeddedTerm::Bv(bs) => bs.clone(),
+        });
+    }
+    let inputs = blocks.into_iter().map(|(b, _)| b).collect();
+    converter.b.finish(inputs, outputs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ir::term::dist::{FixedSizeDist, UniformValue};
+
+    use fxhash::FxHashMap;
+    use quickcheck::{Arbitrary, Gen};
+    use quickcheck_macros::quickcheck;
+    use rand::distributions::Distribution;
+    use rand::SeedableRng;
+
+    fn bits(v: &Value) -> Vec<bool> {
+        match v {
+            Value::Bool(b) => vec![*b],
+            Value::BitVector(bv) => (0..bv.width())
+                .map(|i| bv.uint().get_bit(i as u32))
+                .collect(),
+            v => panic!("Unsupported value: {}", v),
+        }
+    }
+
+    /// Check that the circuit for `cs` computes what `cs` does, on `values`
+    fn check_eval(cs: &Computation, values: &FxHashMap<String, Value>) {
+        let circ = to_bristol(cs);
+        let inputs: Vec<Vec<bool>> = circ
+            .inputs
+            .iter()
+            .map(|b| {
+                b.inputs
+                    .iter()
+                    .flat_map(|(n, _)| bits(&values[n]))
+                    .collect()
+            })
+            .collect();
+        let actual = circ.eval(&inputs);
+        let expected: Vec<Vec<bool>> = cs.outputs.iter().map(|o| bits(&eval(o, values))).collect();
+        assert_eq!(actual, expected);
+    }
+
+    #[derive(Clone, Debug)]
+    struct BvTermEnv(Term, FxHashMap<String, Value>);
+
+    impl Arbitrary for BvTermEnv {
+        fn arbitrary(g: &mut Gen) -> Self {
+            let mut rng = rand::rngs::StdRng::seed_from_u64(u64::arbitrary(g));
+            let d = FixedSizeDist {
+                bv_width: Some(8),
+                pf_mod: None,
+                tuples: false,
+                size: g.size(),
+                sort: Sort::Bool,
+            };
+            let t = d.sample(&mut rng);
+            let values = PostOrderIter::new(t.clone())
+                .filter_map(|c| match &c.op {
+                    Op::Var(n, s) => Some((n.clone(), UniformValue(s).sample(&mut rng))),
+                    _ => None,
+                })
+                .collect();
+            BvTermEnv(t, values)
+        }
+    }
+
+    #[quickcheck]
+    fn random_terms(BvTermEnv(t, values): BvTermEnv) {
+        let mut cs = Computation::default();
+        // Split the inputs between two parties, and the public.
+        let mut names: Vec<&String> = values.keys().collect();
+        names.sort();
+        for (i, n) in names.into_iter().enumerate() {
+            let party = [Some(0), Some(1), None][i % 3];
+            cs.metadata.new_input(n.clone(), party);
+        }
+        cs.outputs.push(t);
+        check_eval(&cs, &values);
+    }
+
+    fn bv_var(name: &str, w: usize) -> Term {
+        leaf_term(Op::Var(name.to_owned(), Sort::BitVector(w)))
+    }
+
+    fn two_party(outputs: Vec<Term>) -> Computation {
+        let mut cs = Computation::default();
+        cs.metadata.new_input("a".to_owned(), Some(0));
+        cs.metadata.new_input("b".to_owned(), Some(1));
+        cs.outputs = outputs;
+        cs
+    }
+
+    #[test]
+    fn and_counts() {
+        let (a, b) = (bv_var("a", 32), bv_var("b", 32));
+        let count = |t: Term| to_bristol(&two_party(vec![t])).and_count();
+        assert_eq!(count(term![BV_ADD; a.clone(), b.clone()]), 31);
+        assert_eq!(count(term![BV_SUB; a.clone(), b.clone()]), 31);
+        assert_eq!(
+            count(term![Op::BvBinPred(BvBinPred::Ult); a.clone(), b.clone()]),
+            32
+        );
+        assert_eq!(
+            count(term![Op::BvBinPred(BvBinPred::Sge); a.clone(), b.clone()]),
+            32
+        );
+        assert_eq!(count(term![Op::Eq; a.clone(), b.clone()]), 31);
+        let lt = term![Op::BvBinPred(BvBinPred::Ult); a.clone(), b.clone()];
+        assert_eq!(count(term![Op::Ite; lt, a.clone(), b.clone()]), 64);
+        assert_eq!(count(term![BV_SHL; a.clone(), bv_lit(3, 32)]), 0);
+        // Multiplying by 5 adds a shifted copy: 30 bits, so 29 carries.
+        assert_eq!(count(term![BV_MUL; a.clone(), bv_lit(5, 32)]), 29);
+        assert_eq!(
+            count(term![BV_MUL; a, b]),
+            32 * 33 / 2 + (0..31).sum::<usize>()
+        );
+    }
+
+    #[test]
+    fn ops() {
+        let (a, b) = (bv_var("a", 8), bv_var("b", 8));
+        let cs = two_party(vec![
+            term![BV_UDIV; a.clone(), b.clone()],
+            term![BV_UREM; a.clone(), b.clone()],
+            term![BV_SHL; a.clone(), b.clone()],
+            term![BV_LSHR; a.clone(), b.clone()],
+            term![BV_ASHR; a.clone(), b.clone()],
+            term![BV_MUL; a.clone(), b.clone(), a.clone()],
+            term![Op::BvExtract(6, 2); term![BV_CONCAT; a.clone(), b.clone()]],
+            term![Op::BvSext(3); a.clone()],
+            term![Op::BvBinPred(BvBinPred::Sle); a.clone(), b.clone()],
+            a.clone(),
+            a,
+            bv_lit(9, 8),
+        ]);
+        // Shift amounts above the width are left out: [BitVector::ashr] does not support them.
+        for (a, b) in &[
+            (0u8, 0u8),
+            (200, 7),
+            (7, 8),
+            (128, 3),
+            (255, 5),
+            (37, 1),
+            (3, 6),
+        ] {
+            let values = vec![("a", *a), ("b", *b)]
+                .into_iter()
+                .map(|(n, v)| {
+                    (
+                        n.to_owned(),
+                        Value::BitVector(BitVector::new(rug::Integer::from(v), 8)),
+                    )
+                })
+                .collect();
+            check_eval(&cs, &values);
+        }
+    }
+
+    #[test]
+    fn format() {
+        let (a, b) = (bv_var("a", 4), bv_var("b", 4));
+        let circ = to_bristol(&two_party(vec![
+            term![BV_AND; a.clone(), b],
+            a,
+            leaf_term(Op::Const(Value::Bool(true))),
+        ]));
+        assert_eq!(circ.inputs.len(), 2);
+        assert_eq!(circ.inputs[0].party, Some(0));
+        assert_eq!(circ.outputs, vec![4, 4, 1]);
+        let mut out = Vec::new();
+        circ.write(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "9 17");
+        assert_eq!(lines[1], "2 4 4");
+        assert_eq!(lines[2], "3 4 4 1");
+        assert_eq!(lines[3], "");
+        assert_eq!(lines[4], "2 1 0 4 8 AND");
+        assert_eq!(lines[8], "1 1 0 12 EQW");
+        assert_eq!(lines[12], "1 1 1 16 EQ");
+    }
+}
@@ -1,9 +1,17 @@
 //! Exporting our R1CS to bellman
+use ::bellman::groth16::{
+    create_random_proof, generate_random_parameters, prepare_verifying_key, verify_proof,
+    Parameters, Proof, VerifyingKey,
+};
 use ::bellman::{Circuit, ConstraintSystem, LinearCombination, SynthesisError, Variable};
+use bls12_381::{Bls12, Scalar};
 use ff::{PrimeField, PrimeFieldBits};
 use gmp_mpfr_sys::gmp::limb_t;
 use log::debug;
 use std::collections::HashMap;
+use std::io::{BufRead, Read, Write};
+
+use super::proof::{bincode_to_io, input_names, ordered_inputs, ProofSystem};
 
 use super::*;
 
@@ -45,7 +53,9 @@ fn modulus_as_int<F: PrimeFieldBits>() -> Integer {
     acc
 }
 
-impl<'a, F: PrimeField + PrimeFieldBits, S: Display + Eq + Hash + Ord> Circuit<F> for &'a R1cs<S> {
+impl<'a, F: PrimeField + PrimeFieldBits, S: Clone + Display + Eq + Hash + Ord> Circuit<F>
+    for &'a R1cs<S>
+{
     fn synthesize<CS>(self, cs: &mut CS) -> std::result::Result<(), SynthesisError>
     where
         CS: ConstraintSystem<F>,
@@ -69,7 +79,28 @@ impl<'a, F: PrimeField + PrimeFieldBits, S: Display + Eq + Hash + Ord> Circuit<F
             });
         }
         let mut vars = HashMap::default();
+        let public = self.public_order();
+        for i in &public {
+            let s = self.idxs_signals.get(i).unwrap();
+            debug!("input: {}", s);
+            let v = cs.alloc_input(
+                || format!("{}", s),
+                || {
+                    Ok(int_to_ff(
+                        self.values
+                            .as_ref()
+                            .expect("missing values")
+                            .get(i)
+                            .unwrap(),
+                    ))
+                },
+            )?;
+            vars.insert(*i, v);
+        }
         for (i, s) in self.idxs_signals.iter() {
+            if self.public_idxs.contains(i) {
+                continue;
+            }
             if uses.get(i).unwrap() > &0 {
                 debug!("var: {}", s);
                 let v = cs.alloc(
@@ -105,12 +136,137 @@ impl<'a, F: PrimeField + PrimeFieldBits, S: Display + Eq + Hash + Ord> Circuit<F
     }
 }
 
+/// Write the values of the public signals of `r1cs` to `w`, one per line, as `name value`.
+pub fn write_public_inputs<S: Display + Eq + Hash + Clone, W: Write>(
+    r1cs: &R1cs<S>,
+    mut w: W,
+) -> std::io::Result<()> {
+    let values = r1cs.values.as_ref().expect("missing values");
+    for i in r1cs.public_order() {
+        let s = r1cs.idxs_signals.get(&i).unwrap();
+        writeln!(w, "{} {}", s, values.get(&i).unwrap())?;
+    }
+    Ok(())
+}
+
+/// Parse public inputs, written as by [write_public_inputs]. Blank lines are permitted.
+pub fn parse_public_inputs<R: BufRead>(r: R) -> HashMap<String, Integer> {
+    let mut m = HashMap::default();
+    for l in r.lines() {
+        let l = l.unwrap();
+        let l = l.trim();
+        if l.len() > 0 {
+            let mut s = l.split_whitespace();
+            let key = s.next().unwrap().to_owned();
+            let value = Integer::from(Integer::parse_radix(&s.next().unwrap(), 10).unwrap());
+            m.insert(key, value);
+        }
+    }
+    m
+}
+
+/// The public inputs to the circuit for `r1cs`, as field elements, from their values, by name.
+///
+/// This is the order in which bellman's verifier expects them.
+pub fn public_inputs_to_bellman<F: PrimeField, S: Display + Eq + Hash + Clone>(
+    r1cs: &R1cs<S>,
+    inputs: &HashMap<String, Integer>,
+) -> Vec<F> {
+    r1cs.public_signals()
+        .into_iter()
+        .map(|s| {
+            let v = inputs
+                .get(&s.to_string())
+                .unwrap_or_else(|| panic!("Missing public input {}", s));
+            int_to_ff(v)
+        })
+        .collect()
+}
+
+/// A Groth16 verifying key, with the names of the public inputs that it expects, in order.
+pub struct Groth16VerifyingKey {
+    vk: VerifyingKey<Bls12>,
+    inputs: Vec<String>,
+}
+
+/// Groth16, over BLS12-381.
+pub struct Groth16;
+
+impl ProofSystem for Groth16 {
+    type ProvingKey = Parameters<Bls12>;
+    type VerifyingKey = Groth16VerifyingKey;
+    type Proof = Proof<Bls12>;
+
+    fn modulus() -> Integer {
+        modulus_as_int::<Scalar>()
+    }
+
+    fn setup<S: Clone + Hash + Eq + Display + Ord>(
+        r1cs: &R1cs<S>,
+    ) -> (Self::ProvingKey, Self::VerifyingKey) {
+        let rng = &mut rand::thread_rng();
+        let pk = generate_random_parameters::<Bls12, _, _>(r1cs, rng).unwrap();
+        let vk = Groth16VerifyingKey {
+            vk: pk.vk.clone(),
+            inputs: input_names(r1cs),
+        };
+        (pk, vk)
+    }
+
+    fn prove<S: Clone + Hash + Eq + Display + Ord>(
+        pk: &Self::ProvingKey,
+        r1cs: &R1cs<S>,
+    ) -> Self::Proof {
+        let rng = &mut rand::thread_rng();
+        create_random_proof(r1cs, pk, rng).unwrap()
+    }
+
+    fn verify(
+        vk: &Self::VerifyingKey,
+        pf: &Self::Proof,
+        inputs: &HashMap<String, Integer>,
+    ) -> std::io::Result<bool> {
+        let inputs: Vec<Scalar> = ordered_inputs(&vk.inputs, inputs)?
+            .into_iter()
+            .map(int_to_ff)
+            .collect();
+        let pvk = prepare_verifying_key(&vk.vk);
+        Ok(verify_proof(&pvk, pf, &inputs).is_ok())
+    }
+
+    fn write_pk<W: Write>(pk: &Self::ProvingKey, w: W) -> std::io::Result<()> {
+        pk.write(w)
+    }
+    fn read_pk<R: Read>(r: R) -> std::io::Result<Self::ProvingKey> {
+        Parameters::read(r, false)
+    }
+    fn write_vk<W: Write>(vk: &Self::VerifyingKey, mut w: W) -> std::io::Result<()> {
+        bincode::serialize_into(&mut w, &vk.inputs).map_err(bincode_to_io)?;
+        vk.vk.write(w)
+    }
+    fn read_vk<R: Read>(mut r: R) -> std::io::Result<Self::VerifyingKey> {
+        let inputs = bincode::deserialize_from(&mut r).map_err(bincode_to_io)?;
+        let vk = VerifyingKey::read(r)?;
+        Ok(Groth16VerifyingKey { vk, inputs })
+    }
+    fn write_proof<W: Write>(pf: &Self::Proof, w: W) -> std::io::Result<()> {
+        pf.write(w)
+    }
+    fn read_proof<R: Read>(r: R) -> std::io::Result<Self::Proof> {
+        Proof::read(r)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use bls12_381::Scalar;
+    use crate::ir::proof::Constraints;
+    use crate::ir::term::*;
+    use crate::target::r1cs::trans::to_r1cs;
+    use fxhash::FxHashSet;
     use quickcheck::{Arbitrary, Gen};
     use quickcheck_macros::quickcheck;
+    use std::sync::Arc;
 
     #[derive(Clone, Debug)]
     struct BlsScalar(Integer);
@@ -165,4 +321,74 @@ mod test {
     fn one() {
         convert(Integer::from(1));
     }
+
+    #[test]
+    fn public_inputs() {
+        // w * w == x, with x public
+        let modulus = modulus_as_int::<Scalar>();
+        let field = Arc::new(modulus.clone());
+        let var = |n: &str| leaf_term(Op::Var(n.to_owned(), Sort::Field(field.clone())));
+        let t = term![Op::Eq; term![PF_MUL; var("w"), var("w")], var("x")];
+        let values = vec![("w", 3), ("x", 9)]
+            .into_iter()
+            .map(|(n, i)| {
+                let v = FieldElem::new(Integer::from(i), field.clone());
+                (n.to_owned(), Value::Field(v))
+            })
+            .collect();
+        let public: FxHashSet<String> = vec!["x".to_owned()].into_iter().collect();
+        let cs = Computation::from_constraint_system_parts(vec![t], public, Some(values));
+        let r1cs = to_r1cs(cs, modulus);
+
+        let rng = &mut rand::thread_rng();
+        let params = generate_random_parameters::<Bls12, _, _>(&r1cs, rng).unwrap();
+        let pf = create_random_proof(&r1cs, &params, rng).unwrap();
+        let pvk = prepare_verifying_key(&params.vk);
+
+        let mut bytes = Vec::new();
+        write_public_inputs(&r1cs, &mut bytes).unwrap();
+        let mut inputs = parse_public_inputs(&bytes[..]);
+        assert_eq!(inputs.get("x"), Some(&Integer::from(9)));
+        let x = public_inputs_to_bellman::<Scalar, _>(&r1cs, &inputs);
+        assert!(verify_proof(&pvk, &pf, &x).is_ok());
+
+        inputs.insert("x".to_owned(), Integer::from(10));
+        let x = public_inputs_to_bellman::<Scalar, _>(&r1cs, &inputs);
+        assert!(verify_proof(&pvk, &pf, &x).is_err());
+    }
+
+    #[test]
+    fn verifier_checks_input_names() {
+        // w * w == x, with x public
+        let modulus = Groth16::modulus();
+        let field = Arc::new(modulus.clone());
+        let var = |n: &str| leaf_term(Op::Var(n.to_owned(), Sort::Field(field.clone())));
+        let t = term![Op::Eq; term![PF_MUL; var("w"), var("w")], var("x")];
+        let values = vec![("w", 3), ("x", 9)]
+            .into_iter()
+            .map(|(n, i)| {
+                let v = FieldElem::new(Integer::from(i), field.clone());
+                (n.to_owned(), Value::Field(v))
+            })
+            .collect();
+        let public: FxHashSet<String> = vec!["x".to_owned()].into_iter().collect();
+        let cs = Computation::from_constraint_system_parts(vec![t], public, Some(values));
+        let r1cs = to_r1cs(cs, modulus);
+
+        let (pk, vk) = Groth16::setup(&r1cs);
+        let pf = Groth16::prove(&pk, &r1cs);
+        let mut bytes = Vec::new();
+        Groth16::write_vk(&vk, &mut bytes).unwrap();
+        let vk = Groth16::read_vk(&bytes[..]).unwrap();
+
+        let mut inputs: HashMap<String, Integer> = HashMap::new();
+        assert!(Groth16::verify(&vk, &pf, &inputs).is_err());
+        inputs.insert("x".to_owned(), Integer::from(9));
+        assert!(Groth16::verify(&vk, &pf, &inputs).unwrap());
+        inputs.insert("y".to_owned(), Integer::from(9));
+        assert!(Groth16::verify(&vk, &pf, &inputs).is_err());
+        inputs.remove("y");
+        inputs.insert("x".to_owned(), Integer::from(10));
+        assert!(!Groth16::verify(&vk, &pf, &inputs).unwrap());
+    }
 }
@@ -0,0 +1,383 @@
+//! Exporting our R1CS in interchange formats
+//!
+//! We write:
+//!
+//!    * circom's binary `.r1cs` format (version 1), with header, constraint, and wire-to-label
+//!      sections,
+//!    * circom's binary `.wtns` witness format (version 2), and
+//!    * the JSON that `snarkjs r1cs export json` produces.
+//!
+//! Wire 0 is the constant 1. Then come the public signals, in the order that back-ends take public
+//! inputs (see [R1cs::public_signals]), and then all others, in order of their CirC index. Signal
+//! `i` gets label `i + 1`, so if optimization has removed signals, there are more labels than
+//! wires. We don't distinguish private inputs from other
+//! private signals, so the private input count is always 0.
+
+use super::*;
+
+use rug::integer::Order;
+use serde_json::{json, Value as Json};
+
+use std::io::{Result, Write};
+
+/// The CirC signal indices, in wire order (wire 0, the constant, is omitted).
+fn wire_order<S: Clone + Hash + Eq + Display>(r1cs: &R1cs<S>) -> Vec<usize> {
+    let mut public = r1cs.public_order();
+    let mut private: Vec<usize> = r1cs
+        .idxs_signals
+        .keys()
+        .filter(|i| !r1cs.public_idxs.contains(i))
+        .cloned()
+        .collect();
+    private.sort_unstable();
+    public.extend(private);
+    public
+}
+
+/// Map from CirC signal indices to wires.
+fn wire_map<S: Clone + Hash + Eq + Display>(r1cs: &R1cs<S>) -> HashMap<usize, usize> {
+    wire_order(r1cs)
+        .into_iter()
+        .enumerate()
+        .map(|(wire, i)| (i, wire + 1))
+        .collect()
+}
+
+/// The number of bytes used for each field element: enough 64-bit words to hold the modulus.
+fn field_bytes<S: Hash + Eq>(r1cs: &R1cs<S>) -> usize {
+    ((r1cs.modulus.significant_bits() as usize + 63) / 64) * 8
+}
+
+/// The (wire, coefficient) pairs of `lc`, sorted by wire, without zero coefficients.
+fn lc_wires(lc: &Lc, wires: &HashMap<usize, usize>) -> Vec<(usize, Integer)> {
+    let mut terms: Vec<(usize, Integer)> = lc
+        .monomials
+        .iter()
+        .filter(|(_, c)| *c != &0)
+        .map(|(i, c)| (*wires.get(i).expect("Unknown signal"), c.clone()))
+        .collect();
+    if lc.constant != 0 {
+        terms.push((0, lc.constant.clone()));
+    }
+    terms.sort_by_key(|(w, _)| *w);
+    terms
+}
+
+fn write_u32<W: Write>(w: &mut W, x: usize) -> Result<()> {
+    assert!(x <= u32::MAX as usize, "{} does not fit in 32 bits", x);
+    w.write_all(&(x as u32).to_le_bytes())
+}
+
+fn write_u64<W: Write>(w: &mut W, x: usize) -> Result<()> {
+    w.write_all(&(x as u64).to_le_bytes())
+}
+
+/// Write `i` as `n8` little-endian bytes.
+fn write_int<W: Write>(w: &mut W, i: &Integer, n8: usize) -> Result<()> {
+    let mut bytes = i.to_digits::<u8>(Order::Lsf);
+    assert!(bytes.len() <= n8);
+    bytes.resize(n8, 0);
+    w.write_all(&bytes)
+}
+
+/// Write a section of type `ty`, with contents `bytes`.
+fn write_section<W: Write>(w: &mut W, ty: usize, bytes: &[u8]) -> Result<()> {
+    write_u32(w, ty)?;
+    write_u64(w, bytes.len())?;
+    w.write_all(bytes)
+}
+
+/// Write `r1cs` to `w` in circom's `.r1cs` format.
+pub fn write_circom_r1cs<S: Clone + Hash + Eq + Display, W: Write>(
+    r1cs: &R1cs<S>,
+    mut w: W,
+) -> Result<()> {
+    let n8 = field_bytes(r1cs);
+    let wires = wire_map(r1cs);
+
+    let mut header = Vec::new();
+    write_u32(&mut header, n8)?;
+    write_int(&mut header, &r1cs.modulus, n8)?;
+    write_u32(&mut header, r1cs.idxs_signals.len() + 1)?;
+    // public outputs
+    write_u32(&mut header, 0)?;
+    // public inputs
+    write_u32(&mut header, r1cs.public_idxs.len())?;
+    // private inputs
+    write_u32(&mut header, 0)?;
+    write_u64(&mut header, r1cs.next_idx + 1)?;
+    write_u32(&mut header, r1cs.constraints.len())?;
+
+    let mut constraints = Vec::new();
+    for (a, b, c) in &r1cs.constraints {
+        for lc in &[a, b, c] {
+            let terms = lc_wires(lc, &wires);
+            write_u32(&mut constraints, terms.len())?;
+            for (wire, coeff) in &terms {
+                write_u32(&mut constraints, *wire)?;
+                write_int(&mut constraints, coeff, n8)?;
+            }
+        }
+    }
+
+    let mut labels = Vec::new();
+    write_u64(&mut labels, 0)?;
+    for i in wire_order(r1cs) {
+        write_u64(&mut labels, i + 1)?;
+    }
+
+    w.write_all(b"r1cs")?;
+    write_u32(&mut w, 1)?;
+    write_u32(&mut w, 3)?;
+    write_section(&mut w, 1, &header)?;
+    write_section(&mut w, 2, &constraints)?;
+    write_section(&mut w, 3, &labels)
+}
+
+/// Write the values of `r1cs` to `w` in circom's `.wtns` format.
+///
+/// Panics if `r1cs` has no values.
+pub fn write_circom_wtns<S: Clone + Hash + Eq + Display, W: Write>(
+    r1cs: &R1cs<S>,
+    mut w: W,
+) -> Result<()> {
+    let n8 = field_bytes(r1cs);
+    let values = r1cs
+        .values
+        .as_ref()
+        .expect("Cannot export a witness without values");
+
+    let mut header = Vec::new();
+    write_u32(&mut header, n8)?;
+    write_int(&mut header, &r1cs.modulus, n8)?;
+    write_u32(&mut header, r1cs.idxs_signals.len() + 1)?;
+
+    let mut witness = Vec::new();
+    write_int(&mut witness, &Integer::from(1), n8)?;
+    for i in wire_order(r1cs) {
+        let v = values.get(&i).expect("Missing value in witness export");
+        write_int(&mut witness, v, n8)?;
+    }
+
+    w.write_all(b"wtns")?;
+    write_u32(&mut w, 2)?;
+    write_u32(&mut w, 2)?;
+    write_section(&mut w, 1, &header)?;
+    write_section(&mut w, 2, &witness)
+}
+
+/// Dump `r1cs` as JSON, in the format of `snarkjs r1cs export json`.
+///
+/// We also include the CirC name of each wire, under "signals".
+pub fn to_json<S: Clone + Hash + Eq + Display>(r1cs: &R1cs<S>) -> Json {
+    let wires = wire_map(r1cs);
+    let lc_json = |lc: &Lc| -> Json {
+        lc_wires(lc, &wires)
+            .into_iter()
+            .map(|(w, c)| (w.to_string(), Json::String(c.to_string())))
+            .collect::<serde_json::Map<_, _>>()
+            .into()
+    };
+    let constraints: Vec<Json> = r1cs
+        .constraints
+        .iter()
+        .map(|(a, b, c)| json!([lc_json(a), lc_json(b), lc_json(c)]))
+        .collect();
+    let order = wire_order(r1cs);
+    let map: Vec<usize> = std::iter::once(0)
+        .chain(order.iter().map(|i| i + 1))
+        .collect();
+    let signals: Vec<String> = std::iter::once("one".to_owned())
+        .chain(order.iter().map(|i| {
+            r1cs.idxs_signals
+                .get(i)
+                .map(|s| s.to_string())
+                .unwrap_or_default()
+        }))
+        .collect();
+    json!({
+        "n8": field_bytes(r1cs),
+        "prime": r1cs.modulus.to_string(),
+        "nVars": r1cs.idxs_signals.len() + 1,
+        "nOutputs": 0,
+        "nPubInputs": r1cs.public_idxs.len(),
+        "nPrvInputs": 0,
+        "nLabels": r1cs.next_idx + 1,
+        "nConstraints": r1cs.constraints.len(),
+        "constraints": constraints,
+        "map": map,
+        "signals": signals,
+    })
+}
+
+/// Write `r1cs` to `w` as JSON. See [to_json].
+pub fn write_json<S: Clone + Hash + Eq + Display, W: Write>(r1cs: &R1cs<S>, w: W) -> Result<()> {
+    serde_json::to_writer_pretty(w, &to_json(r1cs)).map_err(Into::into)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ir::proof::Constraints;
+    use crate::ir::term::dist::test::*;
+    use crate::ir::term::*;
+    use crate::target::r1cs::opt::reduce_linearities;
+    use crate::target::r1cs::trans::to_r1cs;
+    use fxhash::FxHashSet;
+    use quickcheck_macros::quickcheck;
+
+    /// Reads circom binary files.
+    struct Reader<'a>(&'a [u8]);
+
+    impl<'a> Reader<'a> {
+        fn bytes(&mut self, n: usize) -> &'a [u8] {
+            let (a, b) = self.0.split_at(n);
+            self.0 = b;
+            a
+        }
+        fn u32(&mut self) -> usize {
+            let mut b = [0u8; 4];
+            b.copy_from_slice(self.bytes(4));
+            u32::from_le_bytes(b) as usize
+        }
+        fn u64(&mut self) -> usize {
+            let mut b = [0u8; 8];
+            b.copy_from_slice(self.bytes(8));
+            u64::from_le_bytes(b) as usize
+        }
+        fn int(&mut self, n8: usize) -> Integer {
+            Integer::from_digits(self.bytes(n8), Order::Lsf)
+        }
+        /// Read the file header, then the sections, by type.
+        fn sections(&mut self, magic: &[u8]) -> HashMap<usize, Reader<'a>> {
+            assert_eq!(self.bytes(4), magic);
+            self.u32();
+            let n = self.u32();
+            (0..n)
+                .map(|_| {
+                    let ty = self.u32();
+                    let len = self.u64();
+                    (ty, Reader(self.bytes(len)))
+                })
+                .collect()
+        }
+    }
+
+    fn small_r1cs() -> R1cs<String> {
+        let mut r1cs = R1cs::new(Integer::from(crate::ir::term::field::TEST_FIELD), true);
+        r1cs.add_signal("a".to_owned(), Some(Integer::from(3)));
+        r1cs.add_signal("b".to_owned(), Some(Integer::from(4)));
+        r1cs.publicize(&"b".to_owned());
+        let a = r1cs.signal_lc(&"a".to_owned());
+        let b = r1cs.signal_lc(&"b".to_owned());
+        r1cs.constraint(a.clone(), a + 1, b * 3);
+        r1cs
+    }
+
+    #[test]
+    fn public_wires_first() {
+        let json = to_json(&small_r1cs());
+        assert_eq!(json["nVars"], 3);
+        assert_eq!(json["nPubInputs"], 1);
+        assert_eq!(json["map"], json!([0, 2, 1]));
+        assert_eq!(json["signals"], json!(["one", "b", "a"]));
+        assert_eq!(
+            json["constraints"],
+            json!([[{"2": "1"}, {"0": "1", "2": "1"}, {"1": "3"}]])
+        );
+    }
+
+    #[test]
+    fn public_wires_by_name() {
+        let mut r1cs = small_r1cs();
+        r1cs.add_signal("a2".to_owned(), Some(Integer::from(9)));
+        r1cs.publicize(&"a2".to_owned());
+        let json = to_json(&r1cs);
+        assert_eq!(json["map"], json!([0, 3, 2, 1]));
+        assert_eq!(json["signals"], json!(["one", "a2", "b", "a"]));
+    }
+
+    /// Compile the assertion that `t` evaluates as it does under `values`.
+    fn term_r1cs(t: Term, values: HashMap<String, Value>) -> R1cs<String> {
+        let v = eval(&t, &values);
+        let t = term![Op::Eq; t, leaf_term(Op::Const(v))];
+        let cs =
+            Computation::from_constraint_system_parts(vec![t], FxHashSet::default(), Some(values));
+        let cs = crate::ir::opt::tuple::eliminate_tuples(cs);
+        to_r1cs(cs, Integer::from(crate::ir::term::field::TEST_FIELD))
+    }
+
+    #[quickcheck]
+    fn witness_satisfies(ArbitraryTermEnv(t, values): ArbitraryTermEnv) {
+        check_export(&term_r1cs(t, values));
+    }
+
+    #[quickcheck]
+    fn witness_satisfies_after_reduction(ArbitraryTermEnv(t, values): ArbitraryTermEnv) {
+        check_export(&reduce_linearities(term_r1cs(t, values), None));
+    }
+
+    /// Export `r1cs` and its witness, and check that the witness satisfies the exported
+    /// constraints.
+    fn check_export(r1cs: &R1cs<String>) {
+        let mut r1cs_bytes = Vec::new();
+        write_circom_r1cs(r1cs, &mut r1cs_bytes).unwrap();
+        let mut wtns_bytes = Vec::new();
+        write_circom_wtns(r1cs, &mut wtns_bytes).unwrap();
+
+        let mut sections = Reader(&r1cs_bytes).sections(b"r1cs");
+        let header = sections.get_mut(&1).unwrap();
+        let n8 = header.u32();
+        assert_eq!(&header.int(n8), &*r1cs.modulus);
+        let n_wires = header.u32();
+        header.bytes(12);
+        let n_labels = header.u64();
+        let n_constraints = header.u32();
+        assert_eq!(n_wires, r1cs.idxs_signals.len() + 1);
+        assert_eq!(n_labels, r1cs.next_idx + 1);
+        assert_eq!(n_constraints, r1cs.constraints.len());
+
+        // Each wire's label is one more than the index of its signal
+        let labels = sections.get_mut(&3).unwrap();
+        assert_eq!(labels.u64(), 0);
+        let order = wire_order(r1cs);
+        for i in &order {
+            assert_eq!(labels.u64(), i + 1);
+        }
+        assert!(labels.0.is_empty());
+        let mut signals: Vec<usize> = r1cs.idxs_signals.keys().cloned().collect();
+        signals.sort_unstable();
+        let mut sorted_order = order;
+        sorted_order.sort_unstable();
+        assert_eq!(sorted_order, signals);
+
+        let mut wtns_sections = Reader(&wtns_bytes).sections(b"wtns");
+        let wtns_header = wtns_sections.get_mut(&1).unwrap();
+        assert_eq!(wtns_header.u32(), n8);
+        wtns_header.int(n8);
+        assert_eq!(wtns_header.u32(), n_wires);
+        let wtns = wtns_sections.get_mut(&2).unwrap();
+        let witness: Vec<Integer> = (0..n_wires).map(|_| wtns.int(n8)).collect();
+        assert_eq!(witness[0], 1);
+
+        let constraints = sections.get_mut(&2).unwrap();
+        for _ in 0..n_constraints {
+            let mut abc = (0..3).map(|_| {
+                let n = constraints.u32();
+                let mut acc = Integer::from(0);
+                for _ in 0..n {
+                    let w = constraints.u32();
+                    acc += constraints.int(n8) * &witness[w];
+                }
+                acc
+            });
+            let (a, b, c) = (
+                abc.next().unwrap(),
+                abc.next().unwrap(),
+                abc.next().unwrap(),
+            );
+            assert_eq!((a * b - c).rem_floor(&*r1cs.modulus), 0);
+        }
+        assert!(constraints.0.is_empty());
+    }
+}
@@ -1,5 +1,6 @@
 //! Rank 1 Constraint Systems
 
+use crate::ir::term::Value;
 use fxhash::{FxHashMap as HashMap, FxHashSet as HashSet};
 use log::debug;
 use rug::ops::{RemRounding, RemRoundingAssign};
@@ -10,9 +11,23 @@ use std::hash::Hash;
 use std::rc::Rc;
 
 pub mod bellman;
+pub mod export;
 pub mod opt;
+pub mod profile;
+pub mod proof;
 pub mod trans;
 pub mod spartan;
+pub mod wit;
+
+/// The field element for a scalar value.
+fn value_to_int(v: &Value) -> Integer {
+    match v {
+        Value::Bool(b) => Integer::from(*b),
+        Value::BitVector(b) => b.uint().clone(),
+        Value::Field(f) => f.i().clone(),
+        v => panic!("{} is not a scalar", v),
+    }
+}
 
 #[derive(Clone, Debug)]
 /// A Rank 1 Constraint System.
@@ -402,4 +417,40 @@ impl<S: Clone + Hash + Eq + Display> R1cs<S> {
     pub fn constraints(&self) -> &Vec<(Lc, Lc, Lc)> {
         &self.constraints
     }
+
+    /// The indices of all signals, in order. Some indices may be missing, if optimization removed
+    /// their signals.
+    fn signal_order(&self) -> Vec<usize> {
+        let mut idxs: Vec<usize> = self.idxs_signals.keys().cloned().collect();
+        idxs.sort_unstable();
+        idxs
+    }
+
+    /// The public signals, in the order that back-ends take public inputs: sorted by name.
+    ///
+    /// Public inputs are named after the computation's inputs (see [trans]), so a verifier can
+    /// order its public values the same way.
+    fn public_order(&self) -> Vec<usize> {
+        let mut public: Vec<usize> = self.public_idxs.iter().cloned().collect();
+        public.sort_by_cached_key(|i| self.idxs_signals.get(i).unwrap().to_string());
+        public
+    }
+
+    /// The names of the public signals, in the order that back-ends take public inputs.
+    pub fn public_signals(&self) -> Vec<&S> {
+        self.public_order()
+            .into_iter()
+            .map(|i| self.idxs_signals.get(&i).unwrap())
+            .collect()
+    }
+
+    /// Does this system track concrete values?
+    pub fn has_values(&self) -> bool {
+        self.values.is_some()
+    }
+
+    /// Set the values of all signals, replacing any current values.
+    pub fn set_values(&mut self, values: HashMap<usize, Integer>) {
+        self.values = Some(values);
+    }
 }
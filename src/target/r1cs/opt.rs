@@ -8,10 +8,11 @@ struct LinReducer<S: Eq + Hash> {
     r1cs: R1cs<S>,
     uses: HashMap<usize, HashSet<usize>>,
     queue: OnceQueue<usize>,
+    lc_size_thresh: usize,
 }
 
 impl<S: Eq + Hash + Display + Clone> LinReducer<S> {
-    fn new(mut r1cs: R1cs<S>) -> Self {
+    fn new(mut r1cs: R1cs<S>, lc_size_thresh: usize) -> Self {
         let sigs: HashSet<usize> = r1cs
             .constraints
             .iter()
@@ -38,7 +39,12 @@ impl<S: Eq + Hash + Display + Clone> LinReducer<S> {
         for c in &mut r1cs.constraints {
             normalize(c);
         }
-        Self { r1cs, uses, queue }
+        Self {
+            r1cs,
+            uses,
+            queue,
+            lc_size_thresh,
+        }
     }
 
     /// Substitute `val` for `var` in constraint with id `con_id`.
@@ -102,9 +108,14 @@ impl<S: Eq + Hash + Display + Clone> LinReducer<S> {
 
     fn run(mut self) -> R1cs<S> {
         while let Some(con_id) = self.queue.pop() {
-            if let Some((var, lc)) =
-                as_linear_sub(&self.r1cs.constraints[con_id], &self.r1cs.public_idxs)
-            {
+            if let Some((var, lc)) = as_linear_sub(
+                &self.r1cs.constraints[con_id],
+                &self.r1cs.public_idxs,
+                &self.uses,
+            ) {
+                if lc.monomials.len() > self.lc_size_thresh {
+                    continue;
+                }
                 debug!(
                     "Elim: {} -> {}",
                     self.r1cs.idxs_signals.get(&var).unwrap(),
@@ -124,26 +135,92 @@ impl<S: Eq + Hash + Display + Clone> LinReducer<S> {
             }
         }
         self.r1cs.constraints.retain(|c| !constantly_true(c));
+        self.dedup();
+        self.remove_unused();
         self.r1cs
     }
-}
 
-fn as_linear_sub((a, b, c): &(Lc, Lc, Lc), public: &HashSet<usize>) -> Option<(usize, Lc)> {
-    if a.is_zero() || b.is_zero() {
-        for i in c.monomials.keys() {
-            if !public.contains(i) {
-                let mut lc = c.clone();
-                let v = lc.monomials.remove(i).unwrap();
-                lc *= &(-v.invert(&*lc.modulus).unwrap());
-                return Some((*i, lc));
+    /// Remove duplicate constraints.
+    fn dedup(&mut self) {
+        let mut seen = HashSet::default();
+        self.r1cs
+            .constraints
+            .retain(|c| seen.insert(constraint_key(c)));
+    }
+
+    /// Remove (non-public) signals that no constraint uses.
+    fn remove_unused(&mut self) {
+        let mut used: HashSet<usize> = self.r1cs.public_idxs.clone();
+        for (a, b, c) in &self.r1cs.constraints {
+            used.extend(
+                a.monomials
+                    .keys()
+                    .chain(b.monomials.keys())
+                    .chain(c.monomials.keys()),
+            );
+        }
+        let unused: Vec<usize> = self
+            .r1cs
+            .idxs_signals
+            .keys()
+            .filter(|i| !used.contains(i))
+            .cloned()
+            .collect();
+        for i in unused {
+            let s = self.r1cs.idxs_signals.remove(&i).unwrap();
+            debug!("Unused: {}", s);
+            self.r1cs.signal_idxs.remove(&s);
+            if let Some(values) = self.r1cs.values.as_mut() {
+                values.remove(&i);
             }
         }
-        None
+    }
+}
+
+/// If this constraint is linear, get a (non-public) variable that it defines, and its definition.
+///
+/// We choose the variable with the fewest uses, so that substitution is cheap.
+fn as_linear_sub(
+    (a, b, c): &(Lc, Lc, Lc),
+    public: &HashSet<usize>,
+    uses: &HashMap<usize, HashSet<usize>>,
+) -> Option<(usize, Lc)> {
+    if a.is_zero() || b.is_zero() {
+        let i = *c
+            .monomials
+            .keys()
+            .filter(|i| !public.contains(i))
+            .min_by_key(|i| (uses.get(i).map(|u| u.len()).unwrap_or(0), **i))?;
+        let mut lc = c.clone();
+        let v = lc.monomials.remove(&i).unwrap();
+        lc *= &(-v.invert(&*lc.modulus).unwrap());
+        Some((i, lc))
     } else {
         None
     }
 }
 
+/// A canonical form for a combination: its constant, and its sorted, non-zero monomials.
+fn lc_key(lc: &Lc) -> (Integer, Vec<(usize, Integer)>) {
+    let mut monomials: Vec<(usize, Integer)> = lc
+        .monomials
+        .iter()
+        .filter(|(_, v)| *v != &0)
+        .map(|(i, v)| (*i, v.clone()))
+        .collect();
+    monomials.sort();
+    (lc.constant.clone(), monomials)
+}
+
+type LcKey = (Integer, Vec<(usize, Integer)>);
+
+/// A canonical form for a constraint. The A and B sides commute.
+fn constraint_key((a, b, c): &(Lc, Lc, Lc)) -> (LcKey, LcKey, LcKey) {
+    let (a, b) = (lc_key(a), lc_key(b));
+    let (a, b) = if a <= b { (a, b) } else { (b, a) };
+    (a, b, lc_key(c))
+}
+
 fn normalize((a, b, c): &mut (Lc, Lc, Lc)) {
     match (a.as_const(), b.as_const()) {
         (Some(ac), _) => {
@@ -166,8 +243,18 @@ fn constantly_true((a, b, c): &(Lc, Lc, Lc)) -> bool {
 }
 
 /// Attempt to shrink this system by reducing linearities.
-pub fn reduce_linearities<S: Eq + Hash + Clone + Display>(r1cs: R1cs<S>) -> R1cs<S> {
-    LinReducer::new(r1cs).run()
+///
+/// Linear constraints are substituted away (public signals are never eliminated), duplicate
+/// constraints are removed, and then so are signals that no constraint uses.
+///
+/// A linear constraint is only substituted if the combination it defines has at most
+/// `lc_size_thresh` monomials (if given). Larger substitutions remove more constraints, but make
+/// the remaining ones denser, which slows the prover.
+pub fn reduce_linearities<S: Eq + Hash + Clone + Display>(
+    r1cs: R1cs<S>,
+    lc_size_thresh: Option<usize>,
+) -> R1cs<S> {
+    LinReducer::new(r1cs, lc_size_thresh.unwrap_or(usize::MAX)).run()
 }
 
 #[cfg(test)]
@@ -234,7 +321,55 @@ mod test {
 
     #[quickcheck]
     fn random(SatR1cs(r1cs): SatR1cs) {
-        let r1cs2 = reduce_linearities(r1cs);
+        let r1cs2 = reduce_linearities(r1cs, None);
         r1cs2.check_all();
     }
+
+    #[quickcheck]
+    fn random_bounded(SatR1cs(r1cs): SatR1cs, thresh: u8) {
+        let thresh = thresh as usize % 4;
+        let r1cs2 = reduce_linearities(r1cs.clone(), Some(thresh));
+        r1cs2.check_all();
+        assert!(r1cs2.constraints.len() <= r1cs.constraints.len());
+    }
+
+    #[test]
+    fn respects_public() {
+        let mut r1cs = R1cs::new(Integer::from(101), true);
+        for (s, v) in &[("x", 2), ("y", 3), ("z", 6)] {
+            r1cs.add_signal(s.to_string(), Some(Integer::from(*v)));
+        }
+        r1cs.publicize(&"x".to_owned());
+        let x = r1cs.signal_lc(&"x".to_owned());
+        let y = r1cs.signal_lc(&"y".to_owned());
+        let z = r1cs.signal_lc(&"z".to_owned());
+        // y = x + 1, z = x * y, and a duplicate
+        r1cs.constraint(r1cs.zero(), r1cs.zero(), y.clone() - &x - 1);
+        r1cs.constraint(x.clone(), y.clone(), z.clone());
+        r1cs.constraint(y, x, z);
+        let r1cs = reduce_linearities(r1cs, None);
+        r1cs.check_all();
+        assert_eq!(r1cs.constraints.len(), 1);
+        assert!(r1cs.signal_idxs.contains_key("x"));
+        assert!(!r1cs.signal_idxs.contains_key("y"));
+        assert!(r1cs.signal_idxs.contains_key("z"));
+    }
+
+    #[test]
+    fn lc_size_thresh() {
+        let mut r1cs = R1cs::new(Integer::from(101), true);
+        for (s, v) in &[("a", 1), ("b", 2), ("c", 3), ("d", 6)] {
+            r1cs.add_signal(s.to_string(), Some(Integer::from(*v)));
+        }
+        let lc = |r1cs: &R1cs<String>, s: &str| r1cs.signal_lc(&s.to_owned());
+        // c = a + b, d = c * b
+        let def = lc(&r1cs, "c") - &lc(&r1cs, "a") - &lc(&r1cs, "b");
+        r1cs.constraint(r1cs.zero(), r1cs.zero(), def);
+        r1cs.constraint(lc(&r1cs, "c"), lc(&r1cs, "b"), lc(&r1cs, "d"));
+        assert_eq!(
+            reduce_linearities(r1cs.clone(), Some(1)).constraints.len(),
+            2
+        );
+        assert_eq!(reduce_linearities(r1cs, Some(2)).constraints.len(), 1);
+    }
 }
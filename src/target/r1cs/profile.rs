@@ -0,0 +1,247 @@
+//! Attributing R1CS constraints to the source that generated them
+//!
+//! Each constraint is tagged with the IR term it was generated for, and that term's source
+//! location. A term without a location of its own (e.g., one introduced by an IR optimization)
+//! inherits the location of the nearest term above it that has one.
+//!
+//! The profile describes the R1CS as produced by [super::trans], before any R1CS-level
+//! optimization.
+
+use crate::ir::term::*;
+
+use fxhash::FxHashMap;
+
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Write};
+
+/// Where a constraint came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConstraintTag {
+    /// The operator of the term the constraint was generated for, or "lookup" for the checks of
+    /// table lookups, which are shared by all lookups into a table.
+    pub op: String,
+    /// The source location of that term, if known.
+    pub loc: Option<SourceLoc>,
+}
+
+/// The origin of every constraint in an R1CS.
+#[derive(Clone, Debug, Default)]
+pub struct ConstraintProfile {
+    tags: Vec<ConstraintTag>,
+}
+
+impl ConstraintProfile {
+    /// Build a profile from the term that each constraint was generated for (`origins`), the
+    /// `assertions` of the computation, and the known term locations.
+    pub fn new(
+        origins: &[Option<Term>],
+        assertions: &[Term],
+        term_locs: &TermMap<SourceLoc>,
+    ) -> Self {
+        let locs = inherit_locs(assertions, term_locs);
+        let tags = origins
+            .iter()
+            .map(|o| match o {
+                Some(t) => ConstraintTag {
+                    op: format!("{}", t.op),
+                    loc: locs.get(t).cloned(),
+                },
+                None => ConstraintTag {
+                    op: "lookup".to_owned(),
+                    loc: None,
+                },
+            })
+            .collect();
+        Self { tags }
+    }
+
+    /// The tag of each constraint, in order.
+    pub fn tags(&self) -> &[ConstraintTag] {
+        &self.tags
+    }
+
+    /// Constraint counts per (innermost) function, largest first.
+    pub fn by_function(&self) -> Vec<(String, usize)> {
+        self.count_by(|t| {
+            t.loc
+                .as_ref()
+                .map(|l| l.function().to_owned())
+                .unwrap_or_else(|| UNKNOWN.to_owned())
+        })
+    }
+
+    /// Constraint counts per source line, largest first.
+    pub fn by_line(&self) -> Vec<(String, usize)> {
+        self.count_by(|t| {
+            t.loc
+                .as_ref()
+                .map(|l| format!("{}:{}", l.file, l.line))
+                .unwrap_or_else(|| UNKNOWN.to_owned())
+        })
+    }
+
+    /// Constraint counts per IR operator, largest first.
+    pub fn by_op(&self) -> Vec<(String, usize)> {
+        self.count_by(|t| t.op.clone())
+    }
+
+    fn count_by<F: Fn(&ConstraintTag) -> String>(&self, key: F) -> Vec<(String, usize)> {
+        let mut counts: FxHashMap<String, usize> = FxHashMap::default();
+        for t in &self.tags {
+            *counts.entry(key(t)).or_insert(0) += 1;
+        }
+        let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+        counts.sort_by(|(k0, n0), (k1, n1)| n1.cmp(n0).then_with(|| k0.cmp(k1)));
+        counts
+    }
+
+    /// Write the profile in the "folded stacks" format read by flamegraph tools.
+    ///
+    /// Each line is `f0;f1;...;file:line;op count`, where `f0;f1;...` is the call stack.
+    pub fn write_folded<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let mut counts: FxHashMap<String, usize> = FxHashMap::default();
+        for t in &self.tags {
+            let mut frames: Vec<String> = match &t.loc {
+                Some(l) => {
+                    let mut frames = l.call_stack.clone();
+                    frames.push(format!("{}:{}", l.file, l.line));
+                    frames
+                }
+                None => vec![UNKNOWN.to_owned()],
+            };
+            frames.push(t.op.clone());
+            *counts.entry(frames.join(";")).or_insert(0) += 1;
+        }
+        let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+        counts.sort();
+        for (stack, n) in counts {
+            writeln!(w, "{} {}", stack, n)?;
+        }
+        Ok(())
+    }
+}
+
+const UNKNOWN: &str = "<unknown>";
+
+impl Display for ConstraintProfile {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "Constraints: {}", self.tags.len())?;
+        for (title, counts) in &[
+            ("function", self.by_function()),
+            ("line", self.by_line()),
+            ("operator", self.by_op()),
+        ] {
+            writeln!(f, "By {}:", title)?;
+            for (k, n) in counts {
+                writeln!(f, "  {:>8}  {}", n, k)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Give each term below `assertions` its own location, or else the location of its parent.
+///
+/// Terms with many parents take the location of the first one visited.
+fn inherit_locs(assertions: &[Term], term_locs: &TermMap<SourceLoc>) -> TermMap<SourceLoc> {
+    let mut locs: TermMap<SourceLoc> = TermMap::new();
+    let mut visited: TermSet = TermSet::new();
+    let mut stack: Vec<(Term, Option<SourceLoc>)> =
+        assertions.iter().rev().map(|a| (a.clone(), None)).collect();
+    while let Some((t, parent_loc)) = stack.pop() {
+        if !visited.insert(t.clone()) {
+            continue;
+        }
+        let loc = term_locs.get(&t).cloned().or(parent_loc);
+        if let Some(l) = &loc {
+            locs.insert(t.clone(), l.clone());
+        }
+        for c in t.cs.iter().rev() {
+            stack.push((c.clone(), loc.clone()));
+        }
+    }
+    locs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ir::opt::{opt, Opt};
+    use crate::target::r1cs::trans::to_r1cs_with_profile;
+    use rug::Integer;
+
+    fn loc(line: usize, stack: &[&str]) -> SourceLoc {
+        SourceLoc {
+            file: "main.zok".to_owned(),
+            line,
+            call_stack: stack.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn attribution() {
+        let x = leaf_term(Op::Var("x".to_owned(), Sort::BitVector(4)));
+        let y = leaf_term(Op::Var("y".to_owned(), Sort::BitVector(4)));
+        let prod = term![BV_MUL; x, y];
+        let eq = term![EQ; prod.clone(), bv_lit(6, 4)];
+        let mut cs =
+            Computation::from_constraint_system_parts(vec![eq.clone()], Default::default(), None);
+        cs.metadata.set_term_loc(prod, loc(3, &["main", "mul"]));
+        cs.metadata.set_term_loc(eq, loc(7, &["main"]));
+        let (r1cs, _, profile) = to_r1cs_with_profile(cs, Integer::from(2305843009213693951u64));
+        let n = r1cs.constraints().len();
+        assert_eq!(profile.tags().len(), n);
+        let by_line = profile.by_line();
+        assert_eq!(by_line.iter().map(|(_, n)| n).sum::<usize>(), n);
+        // The variables' bit-splits inherit the multiplication's location.
+        let mul_line = by_line.iter().find(|(l, _)| l == "main.zok:3").unwrap().1;
+        assert!(mul_line >= 8);
+        assert!(by_line.iter().any(|(l, _)| l == "main.zok:7"));
+        assert!(by_line.iter().all(|(l, _)| l != UNKNOWN));
+        let by_function = profile.by_function();
+        assert_eq!(by_function[0], ("mul".to_owned(), mul_line));
+
+        let mut folded = Vec::new();
+        profile.write_folded(&mut folded).unwrap();
+        let folded = String::from_utf8(folded).unwrap();
+        let total: usize = folded
+            .lines()
+            .map(|l| l.rsplit(' ').next().unwrap().parse::<usize>().unwrap())
+            .sum();
+        assert_eq!(total, n);
+        assert!(folded.lines().all(|l| l.starts_with("main;")));
+    }
+
+    #[test]
+    fn attribution_after_optimization() {
+        let x = leaf_term(Op::Var("x".to_owned(), Sort::BitVector(4)));
+        let y = leaf_term(Op::Var("y".to_owned(), Sort::BitVector(4)));
+        let w = leaf_term(Op::Var("w".to_owned(), Sort::BitVector(4)));
+        let prod = term![BV_MUL; x, y.clone()];
+        let def = term![EQ; w.clone(), prod.clone()];
+        // Constant folding, flattening, and inlining `w` all rewrite `sum` and `eq`.
+        let sum = term![BV_ADD; w, term![BV_ADD; y, term![BV_MUL; bv_lit(2, 4), bv_lit(3, 4)]]];
+        let eq = term![EQ; sum.clone(), bv_lit(6, 4)];
+        let mut cs = Computation::from_constraint_system_parts(
+            vec![def.clone(), eq.clone()],
+            Default::default(),
+            None,
+        );
+        cs.metadata.set_term_loc(prod, loc(3, &["main", "mul"]));
+        cs.metadata.set_term_loc(def.clone(), loc(3, &["main"]));
+        cs.metadata.set_term_loc(sum, loc(5, &["main"]));
+        cs.metadata.set_term_loc(eq.clone(), loc(7, &["main"]));
+        let cs = opt(cs, vec![Opt::ConstantFold, Opt::Flatten, Opt::Inline]);
+        assert_eq!(cs.outputs.len(), 1);
+        assert_ne!(cs.outputs[0], eq);
+        // Locations of terms that optimization removed are dropped.
+        assert!(!cs.metadata.term_locs.contains_key(&def));
+        assert!(!cs.metadata.term_locs.contains_key(&eq));
+        let (_, _, profile) = to_r1cs_with_profile(cs, Integer::from(2305843009213693951u64));
+        let by_line = profile.by_line();
+        for line in &["main.zok:3", "main.zok:5", "main.zok:7"] {
+            assert!(by_line.iter().any(|(l, _)| l == line), "no {}", line);
+        }
+        assert!(by_line.iter().all(|(l, _)| l != UNKNOWN));
+    }
+}
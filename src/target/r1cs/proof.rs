@@ -0,0 +1,104 @@
+//! A common interface to proof systems for our R1CS
+//!
+//! Each proof system fixes the field that the R1CS must be over; [ProofSystem::to_r1cs] compiles
+//! to that field.
+//!
+//! Public inputs are given to the verifier by name, as written by
+//! [super::bellman::write_public_inputs], and ordered as in [R1cs::public_signals]. Verifying keys
+//! record these names, so that the verifier can check that it was given exactly these inputs.
+
+use super::trans::to_r1cs;
+use super::R1cs;
+use crate::ir::term::Computation;
+
+use rug::Integer;
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::io::{self, Read, Write};
+
+/// A proof system for R1CS.
+pub trait ProofSystem {
+    /// The key used to prove
+    type ProvingKey;
+    /// The key used to verify
+    type VerifyingKey;
+    /// A proof
+    type Proof;
+
+    /// The size of the field that this proof system works over.
+    fn modulus() -> Integer;
+
+    /// Generate keys for `r1cs`.
+    fn setup<S: Clone + Hash + Eq + Display + Ord>(
+        r1cs: &R1cs<S>,
+    ) -> (Self::ProvingKey, Self::VerifyingKey);
+
+    /// Prove that the values in `r1cs` satisfy it.
+    fn prove<S: Clone + Hash + Eq + Display + Ord>(
+        pk: &Self::ProvingKey,
+        r1cs: &R1cs<S>,
+    ) -> Self::Proof;
+
+    /// Check `pf`, given the values of the public inputs, by name.
+    ///
+    /// Errors if `inputs` are not exactly the public inputs that `vk` expects.
+    fn verify(
+        vk: &Self::VerifyingKey,
+        pf: &Self::Proof,
+        inputs: &HashMap<String, Integer>,
+    ) -> io::Result<bool>;
+
+    /// Serialize a proving key.
+    fn write_pk<W: Write>(pk: &Self::ProvingKey, w: W) -> io::Result<()>;
+    /// Deserialize a proving key.
+    fn read_pk<R: Read>(r: R) -> io::Result<Self::ProvingKey>;
+    /// Serialize a verifying key.
+    fn write_vk<W: Write>(vk: &Self::VerifyingKey, w: W) -> io::Result<()>;
+    /// Deserialize a verifying key.
+    fn read_vk<R: Read>(r: R) -> io::Result<Self::VerifyingKey>;
+    /// Serialize a proof.
+    fn write_proof<W: Write>(pf: &Self::Proof, w: W) -> io::Result<()>;
+    /// Deserialize a proof.
+    fn read_proof<R: Read>(r: R) -> io::Result<Self::Proof>;
+
+    /// Convert `cs` to R1CS, over this proof system's field.
+    fn to_r1cs(cs: Computation) -> R1cs<String> {
+        to_r1cs(cs, Self::modulus())
+    }
+}
+
+/// The names of the public inputs of `r1cs`, in order. Verifying keys record these.
+pub(super) fn input_names<S: Clone + Hash + Eq + Display>(r1cs: &R1cs<S>) -> Vec<String> {
+    r1cs.public_signals()
+        .into_iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+/// The values of the public inputs `names`, in order.
+///
+/// Errors if `inputs` is missing any of `names`, or has others.
+pub(super) fn ordered_inputs<'a>(
+    names: &[String],
+    inputs: &'a HashMap<String, Integer>,
+) -> io::Result<Vec<&'a Integer>> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    if let Some(extra) = inputs.keys().find(|n| !names.contains(n)) {
+        return Err(invalid(format!("Unexpected public input {}", extra)));
+    }
+    names
+        .iter()
+        .map(|n| {
+            inputs
+                .get(n)
+                .ok_or_else(|| invalid(format!("Missing public input {}", n)))
+        })
+        .collect()
+}
+
+/// Convert a bincode error to an IO error.
+pub(super) fn bincode_to_io(e: bincode::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+}
@@ -7,7 +7,11 @@ use core::clone::Clone;
 use core::ops::Shr;
 
 use log::debug;
+use merlin::Transcript;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::io::{Read, Write};
+use super::proof::{bincode_to_io, input_names, ordered_inputs, ProofSystem};
 use gmp_mpfr_sys::gmp::limb_t;
 use lazy_static::lazy_static;
 
@@ -27,76 +31,91 @@ pub struct Variable {
 }
 
 
-// circ R1cs -> spartan R1CSInstance
-pub fn r1cs_to_spartan<S: Eq + Hash + Clone + Display>(r1cs: R1cs<S>) -> (Instance, Assignment, Assignment, usize, usize, usize)
-{
-
-    // spartan format mapper: CirC -> Spartan
-    let mut wit = Vec::new();
-    let mut inp = Vec::new();
-    let mut trans: HashMap<usize, usize> = HashMap::new(); // Circ -> spartan ids
-    let mut itrans: HashMap<usize, usize> = HashMap::new(); // Circ -> spartan ids
-
-    // assume no public inputs for now
-    assert!(r1cs.public_idxs.len() == 0);
-
-    // TODO if not input?
-    match r1cs.values {
-	Some(_) => 
-	    for (k,v) in r1cs.values.as_ref().unwrap() { // CirC id, Integer
-
-		let mut name = r1cs.idxs_signals.get(k).unwrap().to_string();
-		let scalar = int_to_scalar(&v);
-
-
-		if name.contains("main_f0_lex0_w"){
-		    // witness
-		    //println!("As witness: {}", name);
-
-		    trans.insert(*k, wit.len());
-		    wit.push(scalar.to_bytes());
+/// Build Spartan's input assignment from the values of the public inputs, named as in the
+/// computation.
+pub fn public_inputs_to_spartan(inputs: &fxhash::FxHashMap<String, Value>) -> Assignment {
+    let mut names: Vec<&String> = inputs.keys().collect();
+    names.sort();
+    let inp: Vec<[u8; 32]> = names
+        .into_iter()
+        .map(|n| int_to_scalar(&value_to_int(&inputs[n])).to_bytes())
+        .collect();
+    InputsAssignment::new(&inp).unwrap()
+}
 
-		} else if name.contains("main_f0_lex0_"){
-		    // input
-		    //println!("As input: {}", name);
-	
-                    itrans.insert(*k, inp.len());
-                    inp.push(scalar.to_bytes());
-	
-		} else {
-		    // witness
-                    //println!("As witness: {}", name);
-		    
-		    trans.insert(*k, wit.len());
-                    wit.push(scalar.to_bytes());
+/// A Spartan R1CS instance, in a form we can save: its sizes and its (sparse) matrices.
+///
+/// Spartan's generators are determined by the sizes, so this is all that setup produces.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpartanInstance {
+    /// The number of constraints
+    pub num_cons: usize,
+    /// The number of witness variables
+    pub num_vars: usize,
+    /// The number of public inputs
+    pub num_inputs: usize,
+    /// The names of the public inputs, in order
+    pub input_names: Vec<String>,
+    a: Vec<(usize, usize, [u8; 32])>,
+    b: Vec<(usize, usize, [u8; 32])>,
+    c: Vec<(usize, usize, [u8; 32])>,
+}
 
-		}
-	
-	    }
-	None 	=> panic!("Tried to run Spartan without inputs/witness"),
+impl SpartanInstance {
+    /// Build Spartan's instance.
+    pub fn instance(&self) -> Instance {
+        Instance::new(self.num_cons, self.num_vars, self.num_inputs, &self.a, &self.b, &self.c)
+            .unwrap()
     }
+    /// Build Spartan's (NIZK) generators.
+    pub fn gens(&self) -> NIZKGens {
+        NIZKGens::new(self.num_cons, self.num_vars, self.num_inputs)
+    }
+    /// The most non-zero entries in any one of the matrices.
+    pub fn num_non_zero_entries(&self) -> usize {
+        std::cmp::max(self.a.len(), std::cmp::max(self.b.len(), self.c.len()))
+    }
+    /// Build the generators for Spartan's (preprocessing) SNARK.
+    pub fn snark_gens(&self) -> SNARKGens {
+        SNARKGens::new(
+            self.num_cons,
+            self.num_vars,
+            self.num_inputs,
+            self.num_non_zero_entries(),
+        )
+    }
+    /// Serialize this instance to `w`.
+    pub fn write<W: Write>(&self, w: W) -> bincode::Result<()> {
+        bincode::serialize_into(w, self)
+    }
+    /// Deserialize an instance from `r`.
+    pub fn read<R: Read>(r: R) -> bincode::Result<Self> {
+        bincode::deserialize_from(r)
+    }
+}
 
-    assert_eq!(wit.len() + inp.len(), r1cs.next_idx);
-
-    let num_vars = wit.len();
-    let const_id = wit.len();
-
-    let assn_witness = VarsAssignment::new(&wit).unwrap();
-
-    let num_inputs = inp.len();
-    let assn_inputs = InputsAssignment::new(&inp).unwrap();    
-
-    //drop inp and wit vecs
-    inp = Vec::new();
-    wit = Vec::new();
-
-    for (cid,sid) in itrans{
-//        println!("input translation cid, sid = {}, {}", cid, sid);
-	trans.insert(cid, sid + const_id + 1);
+/// Map CirC signals to Spartan variables.
+///
+/// Spartan's variables are the witnesses, then the constant 1, then the inputs. Our public signals
+/// become the inputs, in the order of [R1cs::public_signals]. Returns the map and the number of witnesses
+/// (which is also the id of the constant).
+fn spartan_ids<S: Eq + Hash + Clone + Display>(r1cs: &R1cs<S>) -> (HashMap<usize, usize>, usize) {
+    let mut trans: HashMap<usize, usize> = HashMap::new(); // Circ -> spartan ids
+    for k in r1cs.signal_order() {
+        if !r1cs.public_idxs.contains(&k) {
+            trans.insert(k, trans.len());
+        }
+    }
+    let const_id = trans.len();
+    for (j, k) in r1cs.public_order().into_iter().enumerate() {
+        trans.insert(k, const_id + 1 + j);
     }
+    (trans, const_id)
+}
 
-//    println!("Translation Mapping: {:#?}", trans);
-//    println!("const id {}", const_id);
+// circ R1cs -> spartan R1CSInstance
+pub fn spartan_instance<S: Eq + Hash + Clone + Display>(r1cs: &R1cs<S>) -> SpartanInstance {
+    let (trans, const_id) = spartan_ids(r1cs);
 
     // circuit
     let mut A: Vec<(usize, usize, [u8; 32])> = Vec::new();
@@ -126,16 +145,314 @@ pub fn r1cs_to_spartan<S: Eq + Hash + Clone + Display>(r1cs: R1cs<S>) -> (Instan
 
     }
 
-    
-    let num_cons = i;
-    let inst = Instance::new(num_cons, num_vars, num_inputs, &A, &B, &C).unwrap();
+    SpartanInstance {
+        num_cons: i,
+        num_vars: const_id,
+        num_inputs: r1cs.public_idxs.len(),
+        input_names: input_names(r1cs),
+        a: A,
+        b: B,
+        c: C,
+    }
+}
+
+/// The values of Spartan's witness variables and of its inputs, from the values in `r1cs`.
+pub fn spartan_assignments<S: Eq + Hash + Clone + Display>(
+    r1cs: &R1cs<S>,
+) -> (Vec<[u8; 32]>, Vec<[u8; 32]>) {
+    let values = r1cs.values.as_ref().expect("Tried to run Spartan without inputs/witness");
+    let (trans, const_id) = spartan_ids(r1cs);
+    let mut wit = vec![[0u8; 32]; const_id];
+    let mut inp = vec![[0u8; 32]; r1cs.public_idxs.len()];
+    for (k, sid) in trans {
+        let v = values.get(&k).expect("Missing value for Spartan");
+        let bytes = int_to_scalar(v).to_bytes();
+        if sid < const_id {
+            wit[sid] = bytes;
+        } else {
+            inp[sid - const_id - 1] = bytes;
+        }
+    }
+    (wit, inp)
+}
+
+// circ R1cs -> spartan R1CSInstance, and assignments
+pub fn r1cs_to_spartan<S: Eq + Hash + Clone + Display>(r1cs: R1cs<S>) -> (Instance, Assignment, Assignment, usize, usize, usize)
+{
+    let spartan = spartan_instance(&r1cs);
+    let (wit, inp) = spartan_assignments(&r1cs);
+    let assn_witness = VarsAssignment::new(&wit).unwrap();
+    let assn_inputs = InputsAssignment::new(&inp).unwrap();
+    let inst = spartan.instance();
 
     // check if the instance we created is satisfiable
     let res = inst.is_sat(&assn_witness, &assn_inputs);
     assert_eq!(res.unwrap(), true);
 
-    (inst, assn_witness, assn_inputs, num_cons, num_vars, num_inputs)
+    (inst, assn_witness, assn_inputs, spartan.num_cons, spartan.num_vars, spartan.num_inputs)
+
+}
+
+/// Setup: the instance (and generators) for `r1cs`.
+pub fn setup<S: Eq + Hash + Clone + Display>(r1cs: &R1cs<S>) -> SpartanInstance {
+    spartan_instance(r1cs)
+}
+
+/// Prove that the values in `r1cs` satisfy `inst`, which must have been set up for `r1cs`.
+///
+/// Returns the proof and the values of the public inputs.
+pub fn prove<S: Eq + Hash + Clone + Display>(
+    r1cs: &R1cs<S>,
+    inst: &SpartanInstance,
+) -> (NIZK, Vec<[u8; 32]>) {
+    assert!(
+        &spartan_instance(r1cs) == inst,
+        "The Spartan instance was not set up for this R1CS"
+    );
+    let (wit, inp) = spartan_assignments(r1cs);
+    let pf = NIZK::prove(
+        &inst.instance(),
+        VarsAssignment::new(&wit).unwrap(),
+        &InputsAssignment::new(&inp).unwrap(),
+        &inst.gens(),
+        &mut Transcript::new(TRANSCRIPT_LABEL),
+    );
+    (pf, inp)
+}
+
+/// Verify proof `pf` for instance `inst`, with public inputs `inp`.
+pub fn verify(inst: &SpartanInstance, pf: &NIZK, inp: &[[u8; 32]]) -> bool {
+    pf.verify(
+        &inst.instance(),
+        &InputsAssignment::new(inp).unwrap(),
+        &mut Transcript::new(TRANSCRIPT_LABEL),
+        &inst.gens(),
+    )
+    .is_ok()
+}
+
+/// Preprocess `inst` for Spartan's SNARK, committing to it. This takes time linear in the size of
+/// `inst`, but only happens once; the verifier then needs just the commitment.
+pub fn snark_encode(
+    inst: &SpartanInstance,
+    gens: &SNARKGens,
+) -> (ComputationCommitment, ComputationDecommitment) {
+    SNARK::encode(&inst.instance(), gens)
+}
+
+/// Prove, with Spartan's SNARK, that the values in `r1cs` satisfy `inst`, which must have been
+/// set up for `r1cs`, and committed to as `comm`/`decomm`.
+///
+/// Returns the proof and the values of the public inputs.
+pub fn snark_prove<S: Eq + Hash + Clone + Display>(
+    r1cs: &R1cs<S>,
+    inst: &SpartanInstance,
+    comm: &ComputationCommitment,
+    decomm: &ComputationDecommitment,
+    gens: &SNARKGens,
+) -> (SNARK, Vec<[u8; 32]>) {
+    assert!(
+        &spartan_instance(r1cs) == inst,
+        "The Spartan instance was not set up for this R1CS"
+    );
+    let (wit, inp) = spartan_assignments(r1cs);
+    let pf = SNARK::prove(
+        &inst.instance(),
+        comm,
+        decomm,
+        VarsAssignment::new(&wit).unwrap(),
+        &InputsAssignment::new(&inp).unwrap(),
+        gens,
+        &mut Transcript::new(TRANSCRIPT_LABEL),
+    );
+    (pf, inp)
+}
 
+/// Verify SNARK `pf` for the instance committed to as `comm`, with public inputs `inp`.
+pub fn snark_verify(
+    comm: &ComputationCommitment,
+    gens: &SNARKGens,
+    pf: &SNARK,
+    inp: &[[u8; 32]],
+) -> bool {
+    pf.verify(
+        comm,
+        &InputsAssignment::new(inp).unwrap(),
+        &mut Transcript::new(TRANSCRIPT_LABEL),
+        gens,
+    )
+    .is_ok()
+}
+
+const TRANSCRIPT_LABEL: &[u8] = b"circ_spartan";
+
+/// Spartan's NIZK, over the scalar field of curve25519.
+pub struct Spartan;
+
+impl ProofSystem for Spartan {
+    type ProvingKey = SpartanInstance;
+    type VerifyingKey = SpartanInstance;
+    type Proof = NIZK;
+
+    fn modulus() -> Integer {
+        SPARTAN_MODULUS.clone()
+    }
+
+    fn setup<S: Clone + Hash + Eq + Display + Ord>(
+        r1cs: &R1cs<S>,
+    ) -> (Self::ProvingKey, Self::VerifyingKey) {
+        let inst = setup(r1cs);
+        (inst.clone(), inst)
+    }
+
+    fn prove<S: Clone + Hash + Eq + Display + Ord>(
+        pk: &Self::ProvingKey,
+        r1cs: &R1cs<S>,
+    ) -> Self::Proof {
+        prove(r1cs, pk).0
+    }
+
+    fn verify(
+        vk: &Self::VerifyingKey,
+        pf: &Self::Proof,
+        inputs: &HashMap<String, Integer>,
+    ) -> std::io::Result<bool> {
+        Ok(verify(vk, pf, &scalar_inputs(&vk.input_names, inputs)?))
+    }
+
+    fn write_pk<W: Write>(pk: &Self::ProvingKey, w: W) -> std::io::Result<()> {
+        pk.write(w).map_err(bincode_to_io)
+    }
+    fn read_pk<R: Read>(r: R) -> std::io::Result<Self::ProvingKey> {
+        SpartanInstance::read(r).map_err(bincode_to_io)
+    }
+    fn write_vk<W: Write>(vk: &Self::VerifyingKey, w: W) -> std::io::Result<()> {
+        vk.write(w).map_err(bincode_to_io)
+    }
+    fn read_vk<R: Read>(r: R) -> std::io::Result<Self::VerifyingKey> {
+        SpartanInstance::read(r).map_err(bincode_to_io)
+    }
+    fn write_proof<W: Write>(pf: &Self::Proof, w: W) -> std::io::Result<()> {
+        bincode::serialize_into(w, pf).map_err(bincode_to_io)
+    }
+    fn read_proof<R: Read>(r: R) -> std::io::Result<Self::Proof> {
+        bincode::deserialize_from(r).map_err(bincode_to_io)
+    }
+}
+
+/// The proving key of Spartan's SNARK: an instance, with its commitment and decommitment.
+#[derive(Serialize, Deserialize)]
+pub struct SnarkProvingKey {
+    /// The instance
+    pub inst: SpartanInstance,
+    comm: ComputationCommitment,
+    decomm: ComputationDecommitment,
+}
+
+/// The verifying key of Spartan's SNARK: the commitment to an instance, and its sizes.
+#[derive(Serialize, Deserialize)]
+pub struct SnarkVerifyingKey {
+    num_cons: usize,
+    num_vars: usize,
+    num_inputs: usize,
+    num_non_zero_entries: usize,
+    /// The names of the public inputs, in order
+    pub input_names: Vec<String>,
+    comm: ComputationCommitment,
+}
+
+impl SnarkVerifyingKey {
+    /// Build the generators for the committed instance.
+    pub fn snark_gens(&self) -> SNARKGens {
+        SNARKGens::new(
+            self.num_cons,
+            self.num_vars,
+            self.num_inputs,
+            self.num_non_zero_entries,
+        )
+    }
+}
+
+/// Spartan's preprocessing SNARK, over the scalar field of curve25519.
+///
+/// Setup commits to the instance, in time linear in its size. The verifying key holds just the
+/// commitment, so verifying takes time sub-linear in the size of the instance.
+pub struct SpartanSnark;
+
+impl ProofSystem for SpartanSnark {
+    type ProvingKey = SnarkProvingKey;
+    type VerifyingKey = SnarkVerifyingKey;
+    type Proof = SNARK;
+
+    fn modulus() -> Integer {
+        SPARTAN_MODULUS.clone()
+    }
+
+    fn setup<S: Clone + Hash + Eq + Display + Ord>(
+        r1cs: &R1cs<S>,
+    ) -> (Self::ProvingKey, Self::VerifyingKey) {
+        let inst = setup(r1cs);
+        let gens = inst.snark_gens();
+        let (comm, decomm) = snark_encode(&inst, &gens);
+        // Spartan's commitments are not `Clone`, so copy it through its serialization.
+        let vk_comm = bincode::deserialize(&bincode::serialize(&comm).unwrap()).unwrap();
+        let vk = SnarkVerifyingKey {
+            num_cons: inst.num_cons,
+            num_vars: inst.num_vars,
+            num_inputs: inst.num_inputs,
+            num_non_zero_entries: inst.num_non_zero_entries(),
+            input_names: inst.input_names.clone(),
+            comm: vk_comm,
+        };
+        (SnarkProvingKey { inst, comm, decomm }, vk)
+    }
+
+    fn prove<S: Clone + Hash + Eq + Display + Ord>(
+        pk: &Self::ProvingKey,
+        r1cs: &R1cs<S>,
+    ) -> Self::Proof {
+        let gens = pk.inst.snark_gens();
+        snark_prove(r1cs, &pk.inst, &pk.comm, &pk.decomm, &gens).0
+    }
+
+    fn verify(
+        vk: &Self::VerifyingKey,
+        pf: &Self::Proof,
+        inputs: &HashMap<String, Integer>,
+    ) -> std::io::Result<bool> {
+        let inputs = scalar_inputs(&vk.input_names, inputs)?;
+        Ok(snark_verify(&vk.comm, &vk.snark_gens(), pf, &inputs))
+    }
+
+    fn write_pk<W: Write>(pk: &Self::ProvingKey, w: W) -> std::io::Result<()> {
+        bincode::serialize_into(w, pk).map_err(bincode_to_io)
+    }
+    fn read_pk<R: Read>(r: R) -> std::io::Result<Self::ProvingKey> {
+        bincode::deserialize_from(r).map_err(bincode_to_io)
+    }
+    fn write_vk<W: Write>(vk: &Self::VerifyingKey, w: W) -> std::io::Result<()> {
+        bincode::serialize_into(w, vk).map_err(bincode_to_io)
+    }
+    fn read_vk<R: Read>(r: R) -> std::io::Result<Self::VerifyingKey> {
+        bincode::deserialize_from(r).map_err(bincode_to_io)
+    }
+    fn write_proof<W: Write>(pf: &Self::Proof, w: W) -> std::io::Result<()> {
+        bincode::serialize_into(w, pf).map_err(bincode_to_io)
+    }
+    fn read_proof<R: Read>(r: R) -> std::io::Result<Self::Proof> {
+        bincode::deserialize_from(r).map_err(bincode_to_io)
+    }
+}
+
+/// Spartan's inputs, from the values of the public inputs `names`, by name.
+fn scalar_inputs(
+    names: &[String],
+    inputs: &HashMap<String, Integer>,
+) -> std::io::Result<Vec<[u8; 32]>> {
+    Ok(ordered_inputs(names, inputs)?
+        .into_iter()
+        .map(|i| int_to_scalar(i).to_bytes())
+        .collect())
 }
 
 fn int_to_scalar(i: &Integer) -> Scalar {
@@ -164,8 +481,6 @@ fn lc_to_v(lc: &Lc, const_id: usize, trans: &HashMap<usize,usize>) -> Vec<Variab
     let mut v: Vec<Variable> = Vec::new();
 
     for (k,m) in &lc.monomials {
-	if *k >= const_id { panic!("Error: variable number off") }
-
         let scalar = int_to_scalar(&m);
         //println!("int to scalar test: {:#?} -> {:#?}", m, scalar.to_bytes());
 	let var = Variable {
@@ -184,3 +499,126 @@ fn lc_to_v(lc: &Lc, const_id: usize, trans: &HashMap<usize,usize>) -> Vec<Variab
     }
     v
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ir::proof::Constraints;
+    use crate::ir::term::*;
+    use crate::target::r1cs::trans::to_r1cs;
+    use fxhash::{FxHashMap, FxHashSet};
+    use merlin::Transcript;
+    use std::sync::Arc;
+
+    fn field(i: u64) -> Value {
+        Value::Field(FieldElem::new(
+            Integer::from(i),
+            Arc::new(SPARTAN_MODULUS.clone()),
+        ))
+    }
+
+    /// `w * w == x`, with `x` and `y` public, and values for `w`, `x` and `y`.
+    fn square(w: u64, x: u64, y: u64) -> (R1cs<String>, FxHashMap<String, Value>) {
+        let sort = Sort::Field(Arc::new(SPARTAN_MODULUS.clone()));
+        let var = |n: &str| leaf_term(Op::Var(n.to_owned(), sort.clone()));
+        let t = term![AND;
+            term![Op::Eq; term![PF_MUL; var("w"), var("w")], var("x")],
+            term![Op::Eq; var("y"), var("y")]
+        ];
+        let values: FxHashMap<String, Value> =
+            vec![("w", field(w)), ("x", field(x)), ("y", field(y))]
+                .into_iter()
+                .map(|(n, v)| (n.to_owned(), v))
+                .collect();
+        let public: FxHashSet<String> = vec!["x".to_owned(), "y".to_owned()].into_iter().collect();
+        let cs = Computation::from_constraint_system_parts(vec![t], public, Some(values.clone()));
+        (to_r1cs(cs, SPARTAN_MODULUS.clone()), values)
+    }
+
+    #[test]
+    fn public_inputs() {
+        let (r1cs, values) = square(3, 9, 5);
+        assert_eq!(r1cs.public_signals(), vec!["x", "y"]);
+
+        let (inst, vars, inps, num_cons, num_vars, num_inputs) = r1cs_to_spartan(r1cs);
+        assert_eq!(num_inputs, 2);
+        let gens = NIZKGens::new(num_cons, num_vars, num_inputs);
+        let pf = NIZK::prove(&inst, vars, &inps, &gens, &mut Transcript::new(b"test"));
+
+        // The verifier rebuilds the inputs from the named public values.
+        let public_values: FxHashMap<String, Value> = values
+            .iter()
+            .filter(|(n, _)| n.as_str() != "w")
+            .map(|(n, v)| (n.clone(), v.clone()))
+            .collect();
+        let inps = public_inputs_to_spartan(&public_values);
+        assert!(pf
+            .verify(&inst, &inps, &mut Transcript::new(b"test"), &gens)
+            .is_ok());
+
+        let mut wrong_values = public_values;
+        wrong_values.insert("x".to_owned(), field(10));
+        let wrong_inps = public_inputs_to_spartan(&wrong_values);
+        assert!(pf
+            .verify(&inst, &wrong_inps, &mut Transcript::new(b"test"), &gens)
+            .is_err());
+    }
+
+    #[test]
+    fn persisted() {
+        let (r1cs, _) = square(4, 16, 1);
+        let mut bytes = Vec::new();
+        setup(&r1cs).write(&mut bytes).unwrap();
+        let inst = SpartanInstance::read(&bytes[..]).unwrap();
+
+        let (pf, inp) = prove(&r1cs, &inst);
+        let pf: NIZK = bincode::deserialize(&bincode::serialize(&pf).unwrap()).unwrap();
+        let inp: Vec<[u8; 32]> = bincode::deserialize(&bincode::serialize(&inp).unwrap()).unwrap();
+        assert!(verify(&inst, &pf, &inp));
+
+        let (other_r1cs, _) = square(5, 25, 1);
+        let (_, other_inp) = prove(&other_r1cs, &inst);
+        assert!(!verify(&inst, &pf, &other_inp));
+    }
+
+    #[test]
+    fn snark() {
+        let (r1cs, _) = square(4, 16, 1);
+        let inst = setup(&r1cs);
+        let gens = inst.snark_gens();
+        let (comm, decomm) = snark_encode(&inst, &gens);
+        let (pf, inp) = snark_prove(&r1cs, &inst, &comm, &decomm, &gens);
+        assert!(snark_verify(&comm, &gens, &pf, &inp));
+
+        let (other_r1cs, _) = square(5, 25, 1);
+        let (_, other_inp) = snark_prove(&other_r1cs, &inst, &comm, &decomm, &gens);
+        assert!(!snark_verify(&comm, &gens, &pf, &other_inp));
+    }
+
+    #[test]
+    fn persisted_snark() {
+        let (r1cs, values) = square(4, 16, 1);
+        let (pk, vk) = SpartanSnark::setup(&r1cs);
+        let mut pk_bytes = Vec::new();
+        SpartanSnark::write_pk(&pk, &mut pk_bytes).unwrap();
+        let mut vk_bytes = Vec::new();
+        SpartanSnark::write_vk(&vk, &mut vk_bytes).unwrap();
+
+        let pk = SpartanSnark::read_pk(&pk_bytes[..]).unwrap();
+        let pf = SpartanSnark::prove(&pk, &r1cs);
+        let mut pf_bytes = Vec::new();
+        SpartanSnark::write_proof(&pf, &mut pf_bytes).unwrap();
+
+        let vk = SpartanSnark::read_vk(&vk_bytes[..]).unwrap();
+        let pf = SpartanSnark::read_proof(&pf_bytes[..]).unwrap();
+        let mut inputs: HashMap<String, Integer> = vec!["x", "y"]
+            .into_iter()
+            .map(|n| (n.to_owned(), value_to_int(&values[n])))
+            .collect();
+        assert!(SpartanSnark::verify(&vk, &pf, &inputs).unwrap());
+        inputs.insert("x".to_owned(), Integer::from(25));
+        assert!(!SpartanSnark::verify(&vk, &pf, &inputs).unwrap());
+        inputs.remove("y");
+        assert!(SpartanSnark::verify(&vk, &pf, &inputs).is_err());
+    }
+}
@@ -5,6 +5,8 @@
 //! is a good intro to how this process works.
 use crate::ir::term::extras::Letified;
 use crate::ir::term::*;
+use crate::target::r1cs::profile::ConstraintProfile;
+use crate::target::r1cs::wit::{WitLc, WitOp, WitnessGenerator};
 use crate::target::r1cs::*;
 
 use fxhash::{FxHashMap, FxHashSet};
@@ -14,6 +16,7 @@ use rug::Integer;
 
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::sync::Arc;
 
 use std::fmt::Display;
 use std::iter::ExactSizeIterator;
@@ -38,7 +41,26 @@ struct ToR1cs {
     cache: TermMap<EmbeddedTerm>,
     values: Option<FxHashMap<String, Value>>,
     public_inputs: FxHashSet<String>,
+    /// Inputs that an assertion `(= input term)` defines, with their terms.
+    ///
+    /// Witness generation computes these from their terms, so that the prover need not supply
+    /// them.
+    definitions: FxHashMap<String, Term>,
+    /// Memories whose reads are prover inputs (see [CheckedMemory]), each with its index in the
+    /// witness generator (once added) and the number of its writes added so far.
+    memories: Vec<(CheckedMemory, Option<usize>, usize)>,
+    /// The reads of those memories, by input: memory, number of writes before, and key.
+    memory_reads: FxHashMap<String, (usize, usize, Term)>,
     next_idx: usize,
+    /// The (index, value) pairs looked up in each table, so far.
+    lookups: FxHashMap<Arc<Vec<Value>>, Vec<(Lc, Lc)>>,
+    /// The index of each table in the witness generator.
+    tables: FxHashMap<Arc<Vec<Value>>, usize>,
+    wit_gen: WitnessGenerator,
+    /// The term that each constraint was generated for, if any.
+    origins: Vec<Option<Term>>,
+    /// The number of bit-vector comparisons that each term is an operand of.
+    cmp_uses: TermMap<usize>,
 }
 
 impl ToR1cs {
@@ -48,21 +70,113 @@ impl ToR1cs {
         public_inputs: FxHashSet<String>,
     ) -> Self {
         Self {
+            wit_gen: WitnessGenerator::new(modulus.clone()),
             r1cs: R1cs::new(modulus, values.is_some()),
             cache: TermMap::new(),
             values,
             public_inputs,
+            definitions: FxHashMap::default(),
+            memories: Vec::new(),
+            memory_reads: FxHashMap::default(),
             next_idx: 0,
+            lookups: FxHashMap::default(),
+            tables: FxHashMap::default(),
+            origins: Vec::new(),
+            cmp_uses: TermMap::new(),
+        }
+    }
+
+    /// Record that all constraints added since the last call were generated for `t`.
+    fn tag_constraints(&mut self, t: Option<&Term>) {
+        let n = self.r1cs.constraints.len();
+        self.origins.resize(n, t.cloned());
+    }
+
+    /// Get new variables, with names dependent on `d`, whose values are computed by `op`.
+    fn fresh_vars<D: Display + ?Sized>(&mut self, ctx: &D, op: WitOp) -> Vec<Lc> {
+        let n_outputs = op.n_outputs();
+        let values = self
+            .wit_gen
+            .push(op, self.values.as_ref(), self.r1cs.values.as_ref());
+        (0..n_outputs)
+            .map(|i| {
+                let n = format!("{}_v{}", ctx, self.next_idx);
+                self.next_idx += 1;
+                self.r1cs
+                    .add_signal(n.clone(), values.as_ref().map(|v| v[i].clone()));
+                self.r1cs.signal_lc(&n)
+            })
+            .collect()
+    }
+
+    /// Get a new variable, with name dependent on `d`, whose value is computed by `op`.
+    fn fresh_var<D: Display + ?Sized>(&mut self, ctx: &D, op: WitOp) -> Lc {
+        self.fresh_vars(ctx, op).pop().unwrap()
+    }
+
+    /// Get a new variable for the input `name`.
+    ///
+    /// If the input is public, the signal is public too, and is named `name` exactly, so that a
+    /// verifier can find it.
+    fn input_var(&mut self, name: &str) -> Lc {
+        let op = if let Some(def) = self.definitions.remove(name) {
+            WitOp::Lc(WitLc::from(&self.embed_scalar(def)))
+        } else if let Some((mem, n_writes, key)) = self.memory_reads.remove(name) {
+            let wit_mem = self.embed_writes(mem, n_writes);
+            WitOp::MemRead(wit_mem, n_writes, WitLc::from(&self.embed_scalar(key)))
+        } else {
+            WitOp::Input(name.to_owned())
+        };
+        if self.public_inputs.contains(name) {
+            let value = self
+                .wit_gen
+                .push(op, self.values.as_ref(), self.r1cs.values.as_ref())
+                .map(|mut v| v.pop().unwrap());
+            self.next_idx += 1;
+            let s = name.to_owned();
+            self.r1cs.add_signal(s.clone(), value);
+            self.r1cs.publicize(&s);
+            self.r1cs.signal_lc(&s)
+        } else {
+            self.fresh_var(name, op)
         }
     }
 
-    /// Get a new variable, with name dependent on `d`.
-    /// If values are being recorded, `value` must be provided.
-    fn fresh_var<D: Display + ?Sized>(&mut self, ctx: &D, value: Option<Integer>) -> Lc {
-        let n = format!("{}_v{}", ctx, self.next_idx);
-        self.next_idx += 1;
-        self.r1cs.add_signal(n.clone(), value);
-        self.r1cs.signal_lc(&n)
+    /// Embed the scalar `t` (e.g., the definition of an input), getting its value.
+    fn embed_scalar(&mut self, t: Term) -> Lc {
+        self.embed(t.clone());
+        match check(&t) {
+            Sort::Bool => self.get_bool(&t).clone(),
+            Sort::BitVector(_) => self.get_bv_uint(&t),
+            Sort::Field(_) => self.get_pf(&t).clone(),
+            s => panic!("Unsupported sort for a scalar: {:?}", s),
+        }
+    }
+
+    /// Add memory `mem`, and its first `n_writes` writes, to the witness generator, getting its
+    /// index there.
+    ///
+    /// A write's terms only contain reads that precede it, so embedding them adds no later
+    /// writes.
+    fn embed_writes(&mut self, mem: usize, n_writes: usize) -> usize {
+        let wit_mem = match self.memories[mem].1 {
+            Some(i) => i,
+            None => {
+                let default = self.embed_scalar(self.memories[mem].0.default.clone());
+                let i = self.wit_gen.add_memory(&default);
+                self.memories[mem].1 = Some(i);
+                i
+            }
+        };
+        while self.memories[mem].2 < n_writes {
+            let (key, val, cond) = self.memories[mem].0.writes[self.memories[mem].2].clone();
+            let key = self.embed_scalar(key);
+            let val = self.embed_scalar(val);
+            let cond = cond.map(|c| self.embed_scalar(c));
+            self.wit_gen.add_write(wit_mem, &key, &val, cond.as_ref());
+            self.memories[mem].2 += 1;
+        }
+        wit_mem
     }
 
     /// Enforce `x` to be bit-valued
@@ -70,10 +184,10 @@ impl ToR1cs {
         self.r1cs.constraint(b.clone(), b - 1, self.r1cs.zero());
     }
 
-    /// Get a new bit-valued variable, with name dependent on `d`.
-    /// If values are being recorded, `value` must be provided.
-    fn fresh_bit<D: Display + ?Sized>(&mut self, ctx: &D, value: Option<Integer>) -> Lc {
-        let v = self.fresh_var(ctx, value);
+    /// Get a new bit-valued variable, with name dependent on `d`, whose value is computed by
+    /// `op`.
+    fn fresh_bit<D: Display + ?Sized>(&mut self, ctx: &D, op: WitOp) -> Lc {
+        let v = self.fresh_var(ctx, op);
         //debug!("Fresh bit: {}", self.r1cs.format_lc(&v));
         self.enforce_bit(v.clone());
         v
@@ -83,17 +197,8 @@ impl ToR1cs {
     fn is_zero(&mut self, x: Lc) -> Lc {
         // m * x - 1 + is_zero == 0
         // is_zero * x == 0
-        let m = self.fresh_var(
-            "is_zero_inv",
-            self.r1cs.eval(&x).map(|x| {
-                if x == 0 {
-                    Integer::from(0)
-                } else {
-                    Integer::from(x.invert(&self.r1cs.modulus()).unwrap())
-                }
-            }),
-        );
-        let is_zero = self.fresh_var("is_zero", self.r1cs.eval(&x).map(|x| Integer::from(x == 0)));
+        let m = self.fresh_var("is_zero_inv", WitOp::InvOrZero(WitLc::from(&x)));
+        let is_zero = self.fresh_var("is_zero", WitOp::IsZero(WitLc::from(&x)));
         self.r1cs.constraint(m, x.clone(), -is_zero.clone() + 1);
         self.r1cs.constraint(is_zero.clone(), x, self.r1cs.zero());
         is_zero
@@ -109,51 +214,18 @@ impl ToR1cs {
         self.mul(x.clone() * 2, y.clone()) - x - y + 1
     }
 
-    /// Evaluate `var`'s value as an (integer-casted) boolean.
-    /// Returns `None` if values are not stored.
-    fn eval_bool(&self, var: &str) -> Option<Integer> {
-        self.values
-            .as_ref()
-            .map(|vs| match vs.get(var).expect("missing value") {
-                Value::Bool(b) => Integer::from(*b),
-                v => panic!("{} should be a bool, but is {:?}", var, v),
-            })
-    }
-
-    /// Evaluate `var`'s value as an (integer-casted) bit-vector.
-    /// Returns `None` if values are not stored.
-    fn eval_bv(&self, var: &str) -> Option<Integer> {
-        self.values
-            .as_ref()
-            .map(|vs| match vs.get(var).expect("missing value") {
-                Value::BitVector(b) => b.uint().clone(),
-                v => panic!("{} should be a bit-vector, but is {:?}", var, v),
-            })
-    }
-
-    /// Evaluate `var`'s value as an (integer-casted) field element
-    /// Returns `None` if values are not stored.
-    fn eval_pf(&self, var: &str) -> Option<Integer> {
-        self.values
-            .as_ref()
-            .map(|vs| match vs.get(var).expect("missing value") {
-                Value::Field(b) => b.i().clone(),
-                v => panic!("{} should be a field element, but is {:?}", var, v),
-            })
-    }
-
     /// Given wire `x`, returns a vector of `n` wires which are the bits of `x`.
     /// They *have not* been constrained to sum to `x`.
     /// They have values according the the (infinite) two's complement representation of `x`.
     /// The LSB is at index 0.
     fn decomp<D: Display + ?Sized>(&mut self, d: &D, x: &Lc, n: usize) -> Vec<Lc> {
-        let x_val = self.r1cs.eval(x);
+        let x = WitLc::from(x);
         (0..n)
             .map(|i| {
                 self.fresh_bit(
                     // We get the right repr here because of infinite two's complement.
                     &format!("{}_b{}", d, i),
-                    x_val.as_ref().map(|x| Integer::from(x.get_bit(i as u32))),
+                    WitOp::Bit(x.clone(), i as u32),
                 )
             })
             .collect::<Vec<_>>()
@@ -170,11 +242,17 @@ impl ToR1cs {
         bits
     }
 
-    /// Given wire `x`, returns whether `x` fits in `n` `signed` bits.
-    fn fits_in_bits<D: Display + ?Sized>(&mut self, d: &D, x: &Lc, n: usize, signed: bool) -> Lc {
-        let bits = self.decomp(d, x, n);
-        let sum = self.debitify(bits.iter().cloned(), signed);
-        self.are_equal(sum, x)
+    /// Given wire `x` in `[-2^n, 2^n)`, returns whether `x` is in `[0, 2^n)`.
+    ///
+    /// `x + 2^n` is in `[0, 2^(n+1))`, so we decompose it once; its top bit is the answer.
+    fn fits_in_bits<D: Display + ?Sized>(&mut self, d: &D, x: &Lc, n: usize) -> Lc {
+        assert!(
+            n + 1 < self.r1cs.modulus().significant_bits() as usize,
+            "Cannot range-check {} bits in this field",
+            n
+        );
+        let shifted = x.clone() + &Integer::from(2).pow(n as u32);
+        self.bitify(d, &shifted, n + 1, false).pop().unwrap()
     }
 
     /// Given a sequence of `bits`, returns a wire which represents their sum,
@@ -210,12 +288,7 @@ impl ToR1cs {
 
     /// Return the product of `a` and `b`.
     fn mul(&mut self, a: Lc, b: Lc) -> Lc {
-        let c = self.fresh_var(
-            "mul",
-            self.r1cs
-                .eval(&a)
-                .and_then(|a| self.r1cs.eval(&b).map(|b| a * b)),
-        );
+        let c = self.fresh_var("mul", WitOp::Mul(WitLc::from(&a), WitLc::from(&b)));
         self.r1cs.constraint(a, b, c.clone());
         c
     }
@@ -285,6 +358,7 @@ impl ToR1cs {
                     s => panic!("Unsupported sort in embed: {:?}", s),
                 }
             }
+            self.tag_constraints(Some(&c));
         }
     }
 
@@ -345,7 +419,7 @@ impl ToR1cs {
         if !self.cache.contains_key(&c) {
             let lc = match &c.op {
                 Op::Var(name, Sort::Bool) => {
-                    let v = self.fresh_var(name, self.eval_bool(name));
+                    let v = self.input_var(name);
                     if !self.public_inputs.contains(name) {
                         self.enforce_bit(v.clone());
                     }
@@ -395,6 +469,7 @@ impl ToR1cs {
                     let a = self.get_bv_bits(&c.cs[0]);
                     a[*i].clone()
                 }
+                Op::Lookup(_) => self.embed_lookup(&c),
                 Op::BvBinPred(o) => {
                     let n = check(&c.cs[0]).as_bv();
                     use BvBinPred::*;
@@ -421,15 +496,38 @@ impl ToR1cs {
         self.get_bool(&c)
     }
 
-    /// Returns whether `a - b` fits in `size` non-negative bits.
-    /// i.e. is in `{0, 1, ..., 2^n-1}`.
+    /// Returns whether `a >= b`, given that `a - b` is in `[-2^size, 2^size)`.
     fn bv_ge(&mut self, a: Lc, b: &Lc, size: usize) -> Lc {
-        self.fits_in_bits("ge", &(a - b), size, false)
+        self.fits_in_bits("ge", &(a - b), size)
     }
 
     /// Returns whether `a` is (`strict`ly) (`signed`ly) greater than `b`.
     /// Assumes they are each `w`-bit bit-vectors.
+    ///
+    /// Picks the cheapest of:
+    /// * a range-check on `a - b` (see [ToR1cs::bv_ge]), costing `w + 2` constraints,
+    /// * if one side is constant, a scan of the other's bits (see [ToR1cs::ge_const_bits]), which
+    ///   is cheap if those bits already exist, or are shared with other comparisons,
+    /// * if `w` is too wide to range-check, a scan of both sides' bits.
     fn bv_cmp(&mut self, w: usize, signed: bool, strict: bool, a: &Term, b: &Term) -> Lc {
+        let f_width = self.r1cs.modulus().significant_bits() as usize;
+        let range_cost = if w + 1 < f_width { w + 2 } else { usize::MAX };
+        if let Some(c) = self.get_bv_uint(b).as_const().cloned() {
+            if let Some(ge) = self.bv_cmp_const(w, signed, strict, a, c, range_cost) {
+                return ge;
+            }
+        }
+        if let Some(c) = self.get_bv_uint(a).as_const().cloned() {
+            // a > b <=> !(b >= a), and a >= b <=> !(b > a)
+            if let Some(ge) = self.bv_cmp_const(w, signed, !strict, b, c, range_cost) {
+                return self.bool_not(&ge);
+            }
+        }
+        if range_cost == usize::MAX {
+            let a = self.get_bv_order_bits(a, signed);
+            let b = self.get_bv_order_bits(b, signed);
+            return self.ge_bits(&a, &b, strict);
+        }
         let a = if signed {
             self.get_bv_signed_int(a)
         } else {
@@ -444,6 +542,69 @@ impl ToR1cs {
         self.bv_ge(if strict { a - 1 } else { a }, &b, w)
     }
 
+    /// Returns whether `a` is (`strict`ly) (`signed`ly) greater than the constant with unsigned
+    /// value `c`, by scanning the bits of `a`, if that costs less than `max_cost` constraints.
+    fn bv_cmp_const(
+        &mut self,
+        w: usize,
+        signed: bool,
+        strict: bool,
+        a: &Term,
+        c: Integer,
+        max_cost: usize,
+    ) -> Option<Lc> {
+        // Map the constant into the unsigned order of [ToR1cs::get_bv_order_bits].
+        let c = if signed {
+            c ^ Integer::from(2).pow(w as u32 - 1)
+        } else {
+            c
+        };
+        // a > c <=> a >= c + 1
+        let c = if strict { c + 1 } else { c };
+        if c.significant_bits() as usize > w {
+            return Some(self.r1cs.zero());
+        }
+        // The decomposition of `a` is shared by all of its comparisons.
+        let decomp_cost = if self.bv_has_bits(a) {
+            0
+        } else {
+            (w + 1) / self.cmp_uses.get(a).cloned().unwrap_or(1).max(1)
+        };
+        if decomp_cost + ge_const_cost(&c, w) >= max_cost {
+            return None;
+        }
+        let bits = self.get_bv_order_bits(a, signed);
+        Some(self.ge_const_bits(&bits, &c))
+    }
+
+    /// Given the `bits` of an unsigned `a`, returns whether `a >= c`.
+    ///
+    /// Scanning from the LSB, the low `i + 1` bits of `a` are at least those of `c` iff
+    /// `a_i > c_i`, or `a_i = c_i` and the low `i` bits are at least those of `c`. So a run of ones
+    /// in `c` becomes an AND, and a run of zeros becomes an OR.
+    fn ge_const_bits(&mut self, bits: &[Lc], c: &Integer) -> Lc {
+        let mut ge: Option<Lc> = None;
+        for (one, run) in bit_runs(c, bits.len()) {
+            let args: Vec<Lc> = bits[run].iter().cloned().chain(ge.take()).collect();
+            ge = Some(if one {
+                self.nary_and(args.into_iter())
+            } else {
+                self.nary_or(args.into_iter())
+            });
+        }
+        ge.unwrap_or_else(|| self.r1cs.zero() + 1)
+    }
+
+    /// Given the bits of unsigned `a` and `b`, returns whether `a` is (`strict`ly) greater than
+    /// `b`, scanning from the LSB.
+    fn ge_bits(&mut self, a: &[Lc], b: &[Lc], strict: bool) -> Lc {
+        let init = self.r1cs.zero() + !strict as isize;
+        a.iter().zip(b).fold(init, |ge, (a_i, b_i)| {
+            let eq = self.bits_are_equal(a_i, b_i);
+            self.ite(eq, ge, a_i)
+        })
+    }
+
     /// Shift `x` left by `2^y`, if bit-valued `c` is true.
     fn const_pow_shift_bv(&mut self, x: &Lc, y: usize, c: Lc) -> Lc {
         self.ite(c, x.clone() * (1 << (1 << y)), x)
@@ -479,8 +640,7 @@ impl ToR1cs {
             if !self.cache.contains_key(&bv) {
                 match &bv.op {
                     Op::Var(name, Sort::BitVector(_)) => {
-                        let val = self.eval_bv(name);
-                        let var = self.fresh_var(name, val);
+                        let var = self.input_var(name);
                         self.set_bv_uint(bv.clone(), var, n);
                         if !self.public_inputs.contains(name) {
                             self.get_bv_bits(&bv);
@@ -539,6 +699,10 @@ impl ToR1cs {
                         let b = self.get_bool(&bv.cs[0]).clone();
                         self.set_bv_bits(bv, vec![b]);
                     }
+                    Op::Lookup(_) => {
+                        let v = self.embed_lookup(&bv);
+                        self.set_bv_uint(bv, v, n);
+                    }
                     Op::BvNaryOp(o) => match o {
                         BvNaryOp::Xor | BvNaryOp::Or | BvNaryOp::And => {
                             let mut bits_by_bv = bv
@@ -615,22 +779,10 @@ impl ToR1cs {
                                 let b = b.clone();
                                 let a = a.clone();
                                 let is_zero = self.is_zero(b.clone());
-                                let (q_v, r_v) = self
-                                    .r1cs
-                                    .eval(&a)
-                                    .and_then(|a| {
-                                        self.r1cs.eval(&b).map(|b| {
-                                            if b == 0 {
-                                                ((Integer::from(1) << n as u32) - 1, a)
-                                            } else {
-                                                (a.clone() / &b, a % b)
-                                            }
-                                        })
-                                    })
-                                    .map(|(a, b)| (Some(a), Some(b)))
-                                    .unwrap_or((None, None));
-                                let q = self.fresh_var("div_q", q_v);
-                                let r = self.fresh_var("div_q", r_v);
+                                let (a_w, b_w) = (WitLc::from(&a), WitLc::from(&b));
+                                let q = self
+                                    .fresh_var("div_q", WitOp::Quot(a_w.clone(), b_w.clone(), n));
+                                let r = self.fresh_var("div_q", WitOp::Rem(a_w, b_w));
                                 let qb = self.bitify("div_q", &q, n, false);
                                 let rb = self.bitify("div_r", &r, n, false);
                                 self.r1cs.constraint(q.clone(), b.clone(), a - &r);
@@ -773,6 +925,18 @@ impl ToR1cs {
         self.debitify(bits.into_iter(), true)
     }
 
+    /// Get the bits of `t`, with the sign bit flipped if `signed`.
+    ///
+    /// As unsigned numbers, these are ordered as `t` is (`signed`ly).
+    fn get_bv_order_bits(&mut self, t: &Term, signed: bool) -> Vec<Lc> {
+        let mut bits = self.get_bv_bits(t);
+        if signed {
+            let sign = bits.pop().unwrap();
+            bits.push(self.bool_not(&sign));
+        }
+        bits
+    }
+
     fn get_bv_bits(&mut self, t: &Term) -> Vec<Lc> {
         let entry_rc = self.get_bv(t);
         let mut entry = entry_rc.borrow_mut();
@@ -798,7 +962,7 @@ impl ToR1cs {
         // TODO: skip if already embedded
         if !self.cache.contains_key(&c) {
             let lc = match &c.op {
-                Op::Var(name, Sort::Field(_)) => self.fresh_var(name, self.eval_pf(name)),
+                Op::Var(name, Sort::Field(_)) => self.input_var(name),
                 Op::Const(Value::Field(r)) => self.r1cs.zero() + r.i(),
                 Op::Ite => {
                     let cond = self.get_bool(&c.cs[0]).clone();
@@ -819,10 +983,11 @@ impl ToR1cs {
                     }
                 }
                 Op::UbvToPf(_) => self.get_bv_uint(&c.cs[0]).clone(),
+                Op::Lookup(_) => self.embed_lookup(&c),
                 Op::PfUnOp(PfUnOp::Neg) => -self.get_pf(&c.cs[0]).clone(),
                 Op::PfUnOp(PfUnOp::Recip) => {
                     let x = self.get_pf(&c.cs[0]).clone();
-                    let inv_x = self.fresh_var("recip", self.r1cs.eval(&x));
+                    let inv_x = self.fresh_var("recip", WitOp::InvOrZero(WitLc::from(&x)));
                     self.r1cs.constraint(x, inv_x.clone(), self.r1cs.zero() + 1);
                     inv_x
                 }
@@ -833,6 +998,127 @@ impl ToR1cs {
         self.get_pf(&c)
     }
 
+    /// Embed a table lookup, `t`, as a fresh wire. Its value is checked later, by
+    /// [ToR1cs::check_lookups].
+    fn embed_lookup(&mut self, t: &Term) -> Lc {
+        let table = match &t.op {
+            Op::Lookup(table) => table.clone(),
+            _ => unreachable!(),
+        };
+        let idx = self.get_bv_uint(&t.cs[0]);
+        let wit_gen = &mut self.wit_gen;
+        let table_idx = *self
+            .tables
+            .entry(table.clone())
+            .or_insert_with(|| wit_gen.add_table(table.iter().map(table_entry).collect()));
+        let v = self.fresh_var("lookup", WitOp::Lookup(WitLc::from(&idx), table_idx));
+        self.lookups
+            .entry(table)
+            .or_insert_with(Vec::new)
+            .push((idx, v.clone()));
+        v
+    }
+
+    /// A switch, controlled by bit `swap`, which outputs `(a, b)` or `(b, a)`.
+    fn switch(&mut self, swap: Lc, a: &(Lc, Lc), b: &(Lc, Lc)) -> ((Lc, Lc), (Lc, Lc)) {
+        let d0 = self.mul(swap.clone(), b.0.clone() - &a.0);
+        let d1 = self.mul(swap, b.1.clone() - &a.1);
+        (
+            (a.0.clone() + &d0, a.1.clone() + &d1),
+            (b.0.clone() - &d0, b.1.clone() - &d1),
+        )
+    }
+
+    /// Route `xs` through a Beneš network, whose switch bits are drawn from `switches`, in the
+    /// order of `wit::benes_route`.
+    ///
+    /// The length of `xs` must be a power of two.
+    fn benes<I: Iterator<Item = Lc>>(
+        &mut self,
+        xs: Vec<(Lc, Lc)>,
+        switches: &mut I,
+    ) -> Vec<(Lc, Lc)> {
+        let n = xs.len();
+        assert!(n.is_power_of_two());
+        if n == 1 {
+            return xs;
+        }
+        if n == 2 {
+            let (a, b) = self.switch(switches.next().unwrap(), &xs[0], &xs[1]);
+            return vec![a, b];
+        }
+        let mut ups = Vec::new();
+        let mut downs = Vec::new();
+        for i in 0..n / 2 {
+            let (up, down) = self.switch(switches.next().unwrap(), &xs[2 * i], &xs[2 * i + 1]);
+            ups.push(up);
+            downs.push(down);
+        }
+        let ups = self.benes(ups, switches);
+        let downs = self.benes(downs, switches);
+        let mut outs = Vec::new();
+        for j in 0..n / 2 {
+            let (a, b) = self.switch(switches.next().unwrap(), &ups[j], &downs[j]);
+            outs.push(a);
+            outs.push(b);
+        }
+        outs
+    }
+
+    /// Check all table lookups, one batch per table.
+    ///
+    /// Plookup-style: we append the lookups to the table, and the prover sorts the result by index
+    /// using a permutation network. Then, it suffices to check that the sorted indices start at 0,
+    /// end at the last index, and increase by 0 or 1 at each step, and that the value stays the
+    /// same whenever the index does. Every (index, value) pair looked up then matches some table
+    /// row.
+    fn check_lookups(&mut self) {
+        let mut tables: Vec<_> = std::mem::take(&mut self.lookups).into_iter().collect();
+        // Determinism
+        tables.sort_by(|a, b| a.0.cmp(&b.0));
+        for (table, accesses) in tables {
+            debug!(
+                "{} lookups in table of size {}",
+                accesses.len(),
+                table.len()
+            );
+            let mut entries: Vec<(Lc, Lc)> = table
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    (
+                        self.r1cs.zero() + &Integer::from(i),
+                        self.r1cs.zero() + &table_entry(v),
+                    )
+                })
+                .collect();
+            entries.extend(accesses);
+            // Pad with copies of the first row
+            let n = entries.len().next_power_of_two();
+            let first = entries[0].clone();
+            entries.resize(n, first);
+            let keys = entries.iter().map(|e| WitLc::from(&e.0)).collect();
+            let switches = self.fresh_vars("benes", WitOp::Route(keys));
+            for s in &switches {
+                self.enforce_bit(s.clone());
+            }
+            let sorted = self.benes(entries, &mut switches.into_iter());
+            self.assert_zero(sorted[0].0.clone());
+            for w in sorted.windows(2) {
+                let d = w[1].0.clone() - &w[0].0;
+                self.r1cs
+                    .constraint(d.clone(), d.clone() - 1, self.r1cs.zero());
+                self.r1cs.constraint(
+                    self.r1cs.zero() + 1 - &d,
+                    w[1].1.clone() - &w[0].1,
+                    self.r1cs.zero(),
+                );
+            }
+            let last = sorted.last().unwrap().0.clone();
+            self.assert_zero(last - &Integer::from(table.len() - 1));
+        }
+    }
+
     fn assert_zero(&mut self, x: Lc) {
         self.r1cs.constraint(self.r1cs.zero(), self.r1cs.zero(), x);
     }
@@ -841,11 +1127,44 @@ impl ToR1cs {
         self.embed(t.clone());
         let lc = self.get_bool(&t).clone();
 	self.assert_zero(lc - 1);
+        self.tag_constraints(Some(&t));
     }
 }
 
 /// Convert this (IR) constraint system `cs` to R1CS, over a prime field defined by `modulus`.
 pub fn to_r1cs(cs: Computation, modulus: Integer) -> R1cs<String> {
+    to_r1cs_and_wit_gen(cs, modulus).0
+}
+
+/// Convert this (IR) constraint system `cs` to R1CS, over a prime field defined by `modulus`.
+///
+/// Also returns a [WitnessGenerator], which computes the values of all R1CS signals from the
+/// values of the inputs to `cs`.
+pub fn to_r1cs_and_wit_gen(cs: Computation, modulus: Integer) -> (R1cs<String>, WitnessGenerator) {
+    let (r1cs, wit_gen, _) = convert(cs, modulus);
+    (r1cs, wit_gen)
+}
+
+/// Convert this (IR) constraint system `cs` to R1CS, over a prime field defined by `modulus`.
+///
+/// Also returns a [WitnessGenerator] and a [ConstraintProfile], which attributes each R1CS
+/// constraint to the IR term (and source location) that it came from.
+pub fn to_r1cs_with_profile(
+    cs: Computation,
+    modulus: Integer,
+) -> (R1cs<String>, WitnessGenerator, ConstraintProfile) {
+    let assertions = cs.outputs.clone();
+    let term_locs = cs.metadata.term_locs.clone();
+    let (r1cs, wit_gen, origins) = convert(cs, modulus);
+    let profile = ConstraintProfile::new(&origins, &assertions, &term_locs);
+    (r1cs, wit_gen, profile)
+}
+
+/// Convert `cs` to R1CS, also returning the term that each constraint was generated for.
+fn convert(
+    cs: Computation,
+    modulus: Integer,
+) -> (R1cs<String>, WitnessGenerator, Vec<Option<Term>>) {
     let Computation {
         outputs: assertions,
         metadata,
@@ -853,6 +1172,21 @@ pub fn to_r1cs(cs: Computation, modulus: Integer) -> R1cs<String> {
     } = cs;
     let public_inputs = metadata.public_inputs().map(ToOwned::to_owned).collect();
     let mut converter = ToR1cs::new(modulus, values, public_inputs);
+    converter.definitions = definitions(&assertions, &metadata);
+    for (i, mem) in metadata.checked_memories.iter().enumerate() {
+        for (name, n_writes, key) in &mem.reads {
+            converter
+                .memory_reads
+                .insert(name.clone(), (i, *n_writes, key.clone()));
+        }
+    }
+    converter.memories = metadata
+        .checked_memories
+        .into_iter()
+        .map(|mem| (mem, None, 0))
+        .collect();
+    converter.wit_gen.set_user_names(metadata.user_names);
+    converter.cmp_uses = count_comparisons(&assertions);
     debug!(
         "Term count: {}",
         assertions
@@ -863,8 +1197,100 @@ pub fn to_r1cs(cs: Computation, modulus: Integer) -> R1cs<String> {
     for c in assertions {
         converter.assert(c);
     }
-    
-    converter.r1cs
+    converter.check_lookups();
+    converter.tag_constraints(None);
+    (converter.r1cs, converter.wit_gen, converter.origins)
+}
+
+/// Find the inputs that `assertions` define: those asserted equal to a term that does not contain
+/// them.
+///
+/// Inputs that the user gives values for (see [ComputationMetadata::user_names]) are never
+/// definitions; the front-ends create the others, as names for terms.
+fn definitions(assertions: &[Term], metadata: &ComputationMetadata) -> FxHashMap<String, Term> {
+    let mut defs = FxHashMap::default();
+    let mut stack: Vec<&Term> = assertions.iter().collect();
+    while let Some(a) = stack.pop() {
+        match &a.op {
+            Op::BoolNaryOp(BoolNaryOp::And) => stack.extend(a.cs.iter()),
+            Op::Eq => {
+                for (var, def) in &[(&a.cs[0], &a.cs[1]), (&a.cs[1], &a.cs[0])] {
+                    if let Op::Var(name, sort) = &var.op {
+                        let scalar =
+                            matches!(sort, Sort::Bool | Sort::BitVector(_) | Sort::Field(_));
+                        if scalar
+                            && !metadata.user_names.contains_key(name)
+                            && !defs.contains_key(name)
+                            && !PostOrderIter::new((*def).clone()).any(|t| &t == *var)
+                        {
+                            defs.insert(name.clone(), (*def).clone());
+                            break;
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    defs
+}
+
+/// Counts, for each term, the number of bit-vector comparisons that it is an operand of.
+fn count_comparisons(assertions: &[Term]) -> TermMap<usize> {
+    let mut uses = TermMap::new();
+    let mut visited = TermSet::new();
+    for a in assertions {
+        for t in PostOrderIter::new(a.clone()) {
+            if visited.insert(t.clone()) {
+                if let Op::BvBinPred(_) = &t.op {
+                    for c in &t.cs {
+                        *uses.entry(c.clone()).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+    }
+    uses
+}
+
+/// The runs of equal bits in the low `w` bits of `c`, from the LSB, skipping its trailing zeros.
+fn bit_runs(c: &Integer, w: usize) -> Vec<(bool, std::ops::Range<usize>)> {
+    let mut runs: Vec<(bool, std::ops::Range<usize>)> = Vec::new();
+    for i in (c.find_one(0).unwrap_or(w as u32) as usize)..w {
+        let bit = c.get_bit(i as u32);
+        match runs.last_mut() {
+            Some((b, run)) if *b == bit => run.end = i + 1,
+            _ => runs.push((bit, i..i + 1)),
+        }
+    }
+    runs
+}
+
+/// The number of constraints that [ToR1cs::ge_const_bits] uses to compare with `c`.
+fn ge_const_cost(c: &Integer, w: usize) -> usize {
+    bit_runs(c, w)
+        .into_iter()
+        .enumerate()
+        .map(|(i, (_, run))| {
+            // AND/OR of up to three bits is a chain of products; otherwise, a zero-test.
+            let n = run.len() + (i > 0) as usize;
+            if n <= 3 {
+                n - 1
+            } else {
+                2
+            }
+        })
+        .sum()
+}
+
+/// The field element representing table entry `v`.
+fn table_entry(v: &Value) -> Integer {
+    match v {
+        Value::Bool(b) => Integer::from(*b),
+        Value::BitVector(b) => b.uint().clone(),
+        Value::Field(f) => f.i().clone(),
+        v => panic!("Cannot look up {} in R1CS", v),
+    }
 }
 
 /// Returns the number of bits needed to hold `n`.
@@ -976,7 +1402,7 @@ pub mod test {
         let cs = Computation::from_constraint_system_parts(vec![t], FxHashSet::default(), Some(values));
         let r1cs = to_r1cs(cs, Integer::from(crate::ir::term::field::TEST_FIELD));
         r1cs.check_all();
-        let r1cs2 = reduce_linearities(r1cs);
+        let r1cs2 = reduce_linearities(r1cs, None);
         r1cs2.check_all();
     }
 
@@ -988,7 +1414,7 @@ pub mod test {
         let cs = crate::ir::opt::tuple::eliminate_tuples(cs);
         let r1cs = to_r1cs(cs, Integer::from(crate::ir::term::field::TEST_FIELD));
         r1cs.check_all();
-        let r1cs2 = reduce_linearities(r1cs);
+        let r1cs2 = reduce_linearities(r1cs, None);
         r1cs2.check_all();
     }
 
@@ -1023,7 +1449,7 @@ pub mod test {
         let cs = Computation::from_constraint_system_parts(vec![t], FxHashSet::default(), Some(values));
         let r1cs = to_r1cs(cs, Integer::from(crate::ir::term::field::TEST_FIELD));
         r1cs.check_all();
-        let r1cs2 = reduce_linearities(r1cs);
+        let r1cs2 = reduce_linearities(r1cs, None);
         r1cs2.check_all();
     }
 
@@ -1146,6 +1572,139 @@ pub mod test {
         ]);
     }
 
+    /// Asserts every comparison between 8-bit `a`, `b`, and constant `c` (with the values they
+    /// have), and checks the resulting R1CS over the field of size `modulus`.
+    fn check_cmps(a: u8, b: u8, c: u8, modulus: usize) {
+        use BvBinPred::*;
+        let var = |n: &str| leaf_term(Op::Var(n.to_owned(), Sort::BitVector(8)));
+        let values: FxHashMap<String, Value> = vec![("a", a), ("b", b)]
+            .into_iter()
+            .map(|(n, v)| {
+                (
+                    n.to_owned(),
+                    Value::BitVector(BitVector::new(Integer::from(v), 8)),
+                )
+            })
+            .collect();
+        let pairs = vec![
+            (var("a"), var("b")),
+            (var("a"), bv(c as usize, 8)),
+            (bv(c as usize, 8), var("b")),
+            (var("a"), bv(0, 8)),
+            (bv(255, 8), var("a")),
+        ];
+        let mut assertions = Vec::new();
+        for (x, y) in pairs {
+            for p in &[Sge, Sgt, Uge, Ugt, Sle, Slt, Ule, Ult] {
+                let t = term![Op::BvBinPred(p.clone()); x.clone(), y.clone()];
+                let v = eval(&t, &values);
+                assertions.push(term![Op::Eq; t, leaf_term(Op::Const(v))]);
+            }
+        }
+        let cs =
+            Computation::from_constraint_system_parts(assertions, FxHashSet::default(), Some(values));
+        let r1cs = to_r1cs(cs, Integer::from(modulus));
+        r1cs.check_all();
+    }
+
+    #[quickcheck]
+    fn cmp(a: u8, b: u8, c: u8) {
+        check_cmps(a, b, c, crate::ir::term::field::TEST_FIELD);
+    }
+
+    #[quickcheck]
+    fn cmp_too_wide_to_range_check(a: u8, b: u8, c: u8) {
+        check_cmps(a, b, c, 257);
+    }
+
+    #[test]
+    fn shared_cmp_is_cheaper_than_range_checks() {
+        let x = leaf_term(Op::Var("x".to_owned(), Sort::BitVector(16)));
+        let assertions: Vec<Term> = (0..8)
+            .map(|i| term![Op::BvBinPred(BvBinPred::Ult); x.clone(), bv(1000 * i + 7, 16)])
+            .collect();
+        let cs = Computation::from_constraint_system_parts(assertions, FxHashSet::default(), None);
+        let n = to_r1cs(cs, Integer::from(crate::ir::term::field::TEST_FIELD))
+            .constraints()
+            .len();
+        assert!(n < 8 * (16 + 2), "{} constraints", n);
+    }
+
+    /// A table of `2^w` pseudo-random 8-bit values.
+    fn table(w: usize) -> Arc<Vec<Value>> {
+        Arc::new(
+            (0..(1usize << w))
+                .map(|i| Value::BitVector(BitVector::new(Integer::from((i * 167 + 13) % 256), 8)))
+                .collect(),
+        )
+    }
+
+    #[quickcheck]
+    fn lookup(i: u8, j: u8, k: u8) {
+        let var = |n: &str| leaf_term(Op::Var(n.to_owned(), Sort::BitVector(4)));
+        let t = term![BV_ADD;
+            term![Op::Lookup(table(4)); var("i")],
+            term![Op::Lookup(table(4)); var("j")],
+            term![Op::Lookup(table(4)); term![BV_XOR; var("i"), var("k")]]
+        ];
+        let values: FxHashMap<String, Value> = vec![("i", i), ("j", j), ("k", k)]
+            .into_iter()
+            .map(|(n, v)| {
+                (
+                    n.to_owned(),
+                    Value::BitVector(BitVector::new(Integer::from(v % 16), 4)),
+                )
+            })
+            .collect();
+        let v = eval(&t, &values);
+        let t = term![Op::Eq; t, leaf_term(Op::Const(v))];
+        let cs =
+            Computation::from_constraint_system_parts(vec![t], FxHashSet::default(), Some(values));
+        let r1cs = to_r1cs(cs, Integer::from(crate::ir::term::field::TEST_FIELD));
+        r1cs.check_all();
+    }
+
+    #[test]
+    fn lookup_batch_is_cheaper_than_scans() {
+        use crate::ir::opt::cfold::fold;
+        let array = (0..256).fold(
+            term![Op::ConstArray(Sort::BitVector(8), 256); bv(0, 8)],
+            |a, i| term![Op::Store; a, bv(i, 8), leaf_term(Op::Const(table(8)[i].clone()))],
+        );
+        let assertions: Vec<Term> = (0..64)
+            .map(|i| {
+                let idx = leaf_term(Op::Var(format!("i{}", i), Sort::BitVector(8)));
+                let out = leaf_term(Op::Var(format!("o{}", i), Sort::BitVector(8)));
+                term![Op::Eq; term![Op::Select; array.clone(), idx], out]
+            })
+            .collect();
+        let count = |assertions: Vec<Term>| {
+            let cs =
+                Computation::from_constraint_system_parts(assertions, FxHashSet::default(), None);
+            to_r1cs(cs, Integer::from(crate::ir::term::field::TEST_FIELD))
+                .constraints()
+                .len()
+        };
+        let with_lookups = count(
+            assertions
+                .iter()
+                .map(|a| crate::ir::opt::mem::lookup::lookups(a, 0))
+                .collect(),
+        );
+        let with_scans = count(
+            assertions
+                .iter()
+                .map(|a| fold(&crate::ir::opt::mem::lin::linearize(a, usize::MAX)))
+                .collect(),
+        );
+        assert!(
+            2 * with_lookups < with_scans,
+            "{} vs {}",
+            with_lookups,
+            with_scans
+        );
+    }
+
     #[test]
     fn tuple() {
         let cs = Computation::from_constraint_system_parts(
@@ -0,0 +1,645 @@
+//! Witness generation
+//!
+//! While lowering to R1CS, [super::trans] records how the value of each signal is computed: a
+//! straight-line program from the computation's inputs, the [WitnessGenerator]. It can be saved
+//! at compile time, and then run on each new input assignment, without re-running the front-end
+//! or the lowering.
+
+use super::{Lc, R1cs};
+use crate::ir::term::Value;
+
+use fxhash::FxHashMap as HashMap;
+use rug::ops::RemRounding;
+use rug::Integer;
+use serde::de::{self, Deserializer};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+use std::hash::Hash;
+use std::io::{Read, Write};
+use std::rc::Rc;
+
+#[derive(Clone, Debug, PartialEq)]
+/// An integer, serialized in hex.
+struct Int(Integer);
+
+impl Serialize for Int {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&self.0.to_string_radix(16))
+    }
+}
+
+impl<'de> Deserialize<'de> for Int {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(d)?;
+        Integer::from_str_radix(&s, 16)
+            .map(Int)
+            .map_err(de::Error::custom)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// A linear combination of signals, by index.
+pub(super) struct WitLc {
+    constant: Int,
+    monomials: Vec<(usize, Int)>,
+}
+
+impl From<&Lc> for WitLc {
+    fn from(lc: &Lc) -> Self {
+        let mut monomials: Vec<(usize, Int)> = lc
+            .monomials
+            .iter()
+            .map(|(i, c)| (*i, Int(c.clone())))
+            .collect();
+        monomials.sort_by_key(|(i, _)| *i);
+        WitLc {
+            constant: Int(lc.constant.clone()),
+            monomials,
+        }
+    }
+}
+
+impl WitLc {
+    /// The combination, in a system mod `modulus`.
+    fn to_lc(&self, modulus: &Rc<Integer>) -> Lc {
+        Lc {
+            modulus: modulus.clone(),
+            constant: self.constant.0.clone(),
+            monomials: self
+                .monomials
+                .iter()
+                .map(|(i, c)| (*i, c.0.clone()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// A step of witness generation, which computes the values of one or more new signals.
+pub(super) enum WitOp {
+    /// The input with this name. Booleans are 0 or 1, and bit-vectors are unsigned.
+    Input(String),
+    /// The value of a combination
+    Lc(WitLc),
+    /// The product of two combinations
+    Mul(WitLc, WitLc),
+    /// The inverse of a combination, or zero if it is zero
+    InvOrZero(WitLc),
+    /// Whether a combination is zero
+    IsZero(WitLc),
+    /// This bit of a combination
+    Bit(WitLc, u32),
+    /// The quotient of two `n`-bit combinations. All ones if the divisor is zero.
+    Quot(WitLc, WitLc, usize),
+    /// The remainder of two combinations. The dividend if the divisor is zero.
+    Rem(WitLc, WitLc),
+    /// The entry of a table (by index into the generator's tables) at a combination
+    Lookup(WitLc, usize),
+    /// The switches of a Beneš network which sorts these combinations.
+    ///
+    /// Has one output per switch, in the order that [benes_route] returns them.
+    Route(Vec<WitLc>),
+    /// A read, at a combination, of a memory (by index into the generator's memories), after
+    /// this many of its writes
+    MemRead(usize, usize, WitLc),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// A memory whose reads are computed by witness generation (see
+/// [crate::ir::term::CheckedMemory]).
+pub(super) struct WitMemory {
+    default: WitLc,
+    /// Key, value, and condition (none if unconditional)
+    writes: Vec<(WitLc, WitLc, Option<WitLc>)>,
+}
+
+impl WitOp {
+    /// The number of signals this step computes.
+    pub(super) fn n_outputs(&self) -> usize {
+        match self {
+            WitOp::Route(keys) => benes_size(keys.len()),
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// A program that computes the values of all signals in an R1CS, from its inputs.
+///
+/// Signal `i` is the `i`th output of the program.
+pub struct WitnessGenerator {
+    modulus: Int,
+    tables: Vec<Vec<Int>>,
+    memories: Vec<WitMemory>,
+    steps: Vec<WitOp>,
+    /// The names that the user gives inputs by, where they differ from the inputs' own names.
+    user_names: HashMap<String, String>,
+}
+
+impl WitnessGenerator {
+    /// Create an empty program, over the field of size `modulus`.
+    pub(super) fn new(modulus: Integer) -> Self {
+        Self {
+            modulus: Int(modulus),
+            tables: Vec::new(),
+            memories: Vec::new(),
+            steps: Vec::new(),
+            user_names: HashMap::default(),
+        }
+    }
+
+    /// Set the names that the user gives inputs by (see [crate::ir::term::ComputationMetadata::user_names]).
+    pub(super) fn set_user_names(&mut self, user_names: HashMap<String, String>) {
+        self.user_names = user_names;
+    }
+
+    /// Add a lookup table, getting its index.
+    pub(super) fn add_table(&mut self, table: Vec<Integer>) -> usize {
+        self.tables.push(table.into_iter().map(Int).collect());
+        self.tables.len() - 1
+    }
+
+    /// Add a memory whose value at every key is `default`, getting its index.
+    pub(super) fn add_memory(&mut self, default: &Lc) -> usize {
+        self.memories.push(WitMemory {
+            default: WitLc::from(default),
+            writes: Vec::new(),
+        });
+        self.memories.len() - 1
+    }
+
+    /// Add a write to memory `mem`, after its others. Reads of the memory must come after all
+    /// the writes that they see.
+    pub(super) fn add_write(&mut self, mem: usize, key: &Lc, val: &Lc, cond: Option<&Lc>) {
+        self.memories[mem]
+            .writes
+            .push((WitLc::from(key), WitLc::from(val), cond.map(WitLc::from)));
+    }
+
+    /// Append `op` to the program.
+    ///
+    /// If `inputs` are given, also evaluate `op`, using the prior signal values `values`.
+    pub(super) fn push(
+        &mut self,
+        op: WitOp,
+        inputs: Option<&HashMap<String, Value>>,
+        values: Option<&HashMap<usize, Integer>>,
+    ) -> Option<Vec<Integer>> {
+        let outputs = inputs.map(|inputs| {
+            self.eval(
+                &op,
+                inputs,
+                values.expect("Missing values in witness generation"),
+            )
+        });
+        self.steps.push(op);
+        outputs
+    }
+
+    /// The number of signals this program computes.
+    pub fn num_signals(&self) -> usize {
+        self.steps.iter().map(|s| s.n_outputs()).sum()
+    }
+
+    fn eval_lc(&self, lc: &WitLc, values: &HashMap<usize, Integer>) -> Integer {
+        let mut acc = lc.constant.0.clone();
+        for (i, c) in &lc.monomials {
+            acc +=
+                Integer::from(values.get(i).expect("Missing value in witness generation") * &c.0);
+        }
+        acc.rem_floor(&self.modulus.0)
+    }
+
+    fn eval(
+        &self,
+        op: &WitOp,
+        inputs: &HashMap<String, Value>,
+        values: &HashMap<usize, Integer>,
+    ) -> Vec<Integer> {
+        let m = &self.modulus.0;
+        let lc = |lc: &WitLc| self.eval_lc(lc, values);
+        match op {
+            WitOp::Input(name) => vec![match inputs.get(name) {
+                Some(Value::Bool(b)) => Integer::from(*b),
+                Some(Value::BitVector(b)) => b.uint().clone(),
+                Some(Value::Field(f)) => f.i().clone(),
+                Some(Value::Int(i)) => i.clone().rem_floor(m),
+                Some(v) => panic!("Input {} has non-scalar value {}", name, v),
+                None => panic!("Missing input {}", name),
+            }],
+            WitOp::Lc(a) => vec![lc(a)],
+            WitOp::Mul(a, b) => vec![(lc(a) * lc(b)).rem_floor(m)],
+            WitOp::InvOrZero(a) => {
+                let a = lc(a);
+                vec![if a == 0 {
+                    a
+                } else {
+                    a.invert(m).expect("non-invertible")
+                }]
+            }
+            WitOp::IsZero(a) => vec![Integer::from(lc(a) == 0)],
+            WitOp::Bit(a, i) => vec![Integer::from(lc(a).get_bit(*i))],
+            WitOp::Quot(a, b, n) => {
+                let b = lc(b);
+                vec![if b == 0 {
+                    (Integer::from(1) << *n as u32) - 1
+                } else {
+                    lc(a) / b
+                }]
+            }
+            WitOp::Rem(a, b) => {
+                let b = lc(b);
+                vec![if b == 0 { lc(a) } else { lc(a) % b }]
+            }
+            WitOp::Lookup(a, table) => {
+                vec![self.tables[*table][lc(a).to_usize().expect("big index")]
+                    .0
+                    .clone()]
+            }
+            WitOp::Route(keys) => {
+                let keys: Vec<Integer> = keys.iter().map(lc).collect();
+                benes_route(&sorting_perm(&keys))
+                    .into_iter()
+                    .map(Integer::from)
+                    .collect()
+            }
+            WitOp::MemRead(mem, n_writes, key) => {
+                let mem = &self.memories[*mem];
+                let key = lc(key);
+                let mut v = lc(&mem.default);
+                for (k, val, cond) in &mem.writes[..*n_writes] {
+                    if lc(k) == key && cond.as_ref().map(|c| lc(c) != 0).unwrap_or(true) {
+                        v = lc(val);
+                    }
+                }
+                vec![v]
+            }
+        }
+    }
+
+    /// Compute the value of every signal, from these `inputs`.
+    pub fn generate(&self, inputs: &HashMap<String, Value>) -> HashMap<usize, Integer> {
+        let mut values = HashMap::default();
+        for op in &self.steps {
+            for v in self.eval(op, inputs, &values) {
+                values.insert(values.len(), v);
+            }
+        }
+        values
+    }
+
+    /// The inputs to this program, from the values in a user's input file.
+    ///
+    /// The file names inputs as the front-end's input files do. Returns an error naming any
+    /// input that the file has no value for.
+    pub fn user_inputs(
+        &self,
+        user_values: &HashMap<String, Integer>,
+    ) -> Result<HashMap<String, Value>, String> {
+        self.steps
+            .iter()
+            .filter_map(|op| match op {
+                WitOp::Input(name) => {
+                    let user_name = self.user_names.get(name).unwrap_or(name);
+                    Some(
+                        user_values
+                            .get(user_name)
+                            .or_else(|| user_values.get(name))
+                            .map(|v| (name.clone(), Value::Int(v.clone())))
+                            .ok_or_else(|| format!("Missing input {}", user_name)),
+                    )
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Compute the value of every signal in `r1cs`, from these `inputs`.
+    pub fn generate_into<S: Clone + Hash + Eq + std::fmt::Display>(
+        &self,
+        r1cs: &mut R1cs<S>,
+        inputs: &HashMap<String, Value>,
+    ) {
+        assert_eq!(&self.modulus.0, r1cs.modulus());
+        r1cs.set_values(self.generate(inputs));
+    }
+
+    /// Serialize this program to `w`.
+    pub fn write<W: Write>(&self, w: W) -> bincode::Result<()> {
+        bincode::serialize_into(w, self)
+    }
+
+    /// Deserialize a program from `r`.
+    pub fn read<R: Read>(r: R) -> bincode::Result<Self> {
+        bincode::deserialize_from(r)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+/// An [R1cs], as saved at setup time, so that it can be proven later without recompiling.
+struct SavedR1cs {
+    modulus: Int,
+    /// Signals, by index
+    signals: Vec<(usize, String)>,
+    next_idx: usize,
+    public: Vec<usize>,
+    constraints: Vec<(WitLc, WitLc, WitLc)>,
+}
+
+impl R1cs<String> {
+    /// Serialize this system, without its values, to `w`.
+    pub fn write<W: Write>(&self, w: W) -> bincode::Result<()> {
+        let mut signals: Vec<(usize, String)> = self
+            .idxs_signals
+            .iter()
+            .map(|(i, s)| (*i, s.clone()))
+            .collect();
+        signals.sort();
+        let mut public: Vec<usize> = self.public_idxs.iter().cloned().collect();
+        public.sort_unstable();
+        let saved = SavedR1cs {
+            modulus: Int((*self.modulus).clone()),
+            signals,
+            next_idx: self.next_idx,
+            public,
+            constraints: self
+                .constraints
+                .iter()
+                .map(|(a, b, c)| (WitLc::from(a), WitLc::from(b), WitLc::from(c)))
+                .collect(),
+        };
+        bincode::serialize_into(w, &saved)
+    }
+
+    /// Deserialize a system from `r`. It has no values; see [WitnessGenerator::generate_into].
+    pub fn read<R: Read>(r: R) -> bincode::Result<Self> {
+        let saved: SavedR1cs = bincode::deserialize_from(r)?;
+        let mut r1cs = R1cs::new(saved.modulus.0, false);
+        for (i, s) in saved.signals {
+            r1cs.signal_idxs.insert(s.clone(), i);
+            r1cs.idxs_signals.insert(i, s);
+        }
+        r1cs.next_idx = saved.next_idx;
+        r1cs.public_idxs = saved.public.into_iter().collect();
+        let m = r1cs.modulus.clone();
+        r1cs.constraints = saved
+            .constraints
+            .iter()
+            .map(|(a, b, c)| (a.to_lc(&m), b.to_lc(&m), c.to_lc(&m)))
+            .collect();
+        Ok(r1cs)
+    }
+}
+
+/// The permutation that (stably) sorts `keys`: key `i` goes to position `perm[i]`.
+fn sorting_perm(keys: &[Integer]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..keys.len()).collect();
+    order.sort_by(|a, b| keys[*a].cmp(&keys[*b]));
+    let mut perm = vec![0; keys.len()];
+    for (pos, i) in order.into_iter().enumerate() {
+        perm[i] = pos;
+    }
+    perm
+}
+
+/// The number of switches in a Beneš network with `n` inputs.
+pub(super) fn benes_size(n: usize) -> usize {
+    match n {
+        0 | 1 => 0,
+        2 => 1,
+        _ => n + 2 * benes_size(n / 2),
+    }
+}
+
+/// Switch settings for a Beneš network (on a power-of-two number of inputs) that routes input `i`
+/// to output `perm[i]`.
+///
+/// The switches of the first column come first, then those of the upper and lower sub-networks,
+/// and then those of the last column. A switch is set if it swaps its inputs.
+pub(super) fn benes_route(perm: &[usize]) -> Vec<bool> {
+    let mut switches = Vec::new();
+    route(perm, &mut switches);
+    switches
+}
+
+fn route(perm: &[usize], switches: &mut Vec<bool>) {
+    let n = perm.len();
+    match n {
+        0 | 1 => return,
+        2 => {
+            switches.push(perm[0] == 1);
+            return;
+        }
+        _ => {}
+    }
+    let mut inv = vec![0; n];
+    for (i, o) in perm.iter().enumerate() {
+        inv[*o] = i;
+    }
+    // Which inputs are routed through the lower sub-network. Inputs 2i and 2i+1 must use
+    // different sub-networks, as must the inputs bound for outputs 2j and 2j+1. We walk the
+    // cycles formed by these constraints, alternating sub-networks.
+    let mut lower: Vec<Option<bool>> = vec![None; n];
+    for start in (0..n).step_by(2) {
+        let mut i = start;
+        while lower[i].is_none() {
+            lower[i] = Some(false);
+            lower[i ^ 1] = Some(true);
+            i = inv[perm[i ^ 1] ^ 1];
+        }
+    }
+    let lower: Vec<bool> = lower.into_iter().map(Option::unwrap).collect();
+    let mut up_perm = Vec::new();
+    let mut down_perm = Vec::new();
+    for i in 0..n / 2 {
+        switches.push(lower[2 * i]);
+        let up = if lower[2 * i] { 2 * i + 1 } else { 2 * i };
+        up_perm.push(perm[up] / 2);
+        down_perm.push(perm[up ^ 1] / 2);
+    }
+    route(&up_perm, switches);
+    route(&down_perm, switches);
+    for j in 0..n / 2 {
+        switches.push(lower[inv[2 * j]]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ir::proof::Constraints;
+    use crate::ir::term::dist::test::*;
+    use crate::ir::term::*;
+    use crate::target::r1cs::opt::reduce_linearities;
+    use crate::target::r1cs::trans::{to_r1cs, to_r1cs_and_wit_gen};
+    use fxhash::FxHashSet;
+    use quickcheck_macros::quickcheck;
+
+    /// Apply Beneš switch settings to `xs`, as [crate::target::r1cs::trans] does.
+    fn apply<T: Clone>(xs: Vec<T>, switches: &mut impl Iterator<Item = bool>) -> Vec<T> {
+        let n = xs.len();
+        let swap = |s: bool, a: &T, b: &T| {
+            if s {
+                (b.clone(), a.clone())
+            } else {
+                (a.clone(), b.clone())
+            }
+        };
+        if n <= 1 {
+            return xs;
+        }
+        if n == 2 {
+            let (a, b) = swap(switches.next().unwrap(), &xs[0], &xs[1]);
+            return vec![a, b];
+        }
+        let (ups, downs): (Vec<T>, Vec<T>) = (0..n / 2)
+            .map(|i| swap(switches.next().unwrap(), &xs[2 * i], &xs[2 * i + 1]))
+            .unzip();
+        let ups = apply(ups, switches);
+        let downs = apply(downs, switches);
+        (0..n / 2)
+            .flat_map(|j| {
+                let (a, b) = swap(switches.next().unwrap(), &ups[j], &downs[j]);
+                vec![a, b]
+            })
+            .collect()
+    }
+
+    #[quickcheck]
+    fn benes_sorts(mut xs: Vec<u8>) {
+        let n = xs.len().next_power_of_two();
+        xs.resize(n, 0);
+        let keys: Vec<Integer> = xs.iter().map(|x| Integer::from(*x)).collect();
+        let switches = benes_route(&sorting_perm(&keys));
+        assert_eq!(switches.len(), benes_size(n));
+        let mut expected = xs.clone();
+        expected.sort();
+        assert_eq!(apply(xs, &mut switches.into_iter()), expected);
+    }
+
+    #[quickcheck]
+    fn generated_witness(ArbitraryTermEnv(t, values): ArbitraryTermEnv) {
+        let v = eval(&t, &values);
+        let t = term![Op::Eq; t, leaf_term(Op::Const(v))];
+        let modulus = Integer::from(crate::ir::term::field::TEST_FIELD);
+        let without_values =
+            Computation::from_constraint_system_parts(vec![t.clone()], FxHashSet::default(), None);
+        let without_values = crate::ir::opt::tuple::eliminate_tuples(without_values);
+        let (mut r1cs, wit_gen) = to_r1cs_and_wit_gen(without_values, modulus.clone());
+        assert_eq!(wit_gen.num_signals(), r1cs.next_idx);
+
+        // Round-trip
+        let mut bytes = Vec::new();
+        wit_gen.write(&mut bytes).unwrap();
+        let wit_gen = WitnessGenerator::read(&bytes[..]).unwrap();
+
+        wit_gen.generate_into(&mut r1cs, &values);
+        r1cs.check_all();
+
+        // The same values as compiling with values
+        let with_values = Computation::from_constraint_system_parts(
+            vec![t],
+            FxHashSet::default(),
+            Some(values.clone()),
+        );
+        let with_values = crate::ir::opt::tuple::eliminate_tuples(with_values);
+        let expected = to_r1cs(with_values, modulus);
+        assert_eq!(r1cs.values, expected.values);
+    }
+
+    #[test]
+    fn prove_from_saved_r1cs() {
+        // As the front-ends do, `return` is an input named for a term, and `x` is the user's.
+        let x = leaf_term(Op::Var("main_x".to_owned(), Sort::BitVector(8)));
+        let ret = leaf_term(Op::Var("return".to_owned(), Sort::BitVector(8)));
+        let t = term![Op::Eq; ret, term![BV_ADD; x.clone(), x]];
+        let public = vec!["return".to_owned()].into_iter().collect();
+        let mut cs = Computation::from_constraint_system_parts(vec![t], public, None);
+        cs.metadata
+            .set_user_name("main_x".to_owned(), "x".to_owned());
+        let modulus = Integer::from(crate::ir::term::field::TEST_FIELD);
+        let (r1cs, wit_gen) = to_r1cs_and_wit_gen(cs, modulus);
+        let r1cs = reduce_linearities(r1cs, None);
+
+        let mut bytes = Vec::new();
+        r1cs.write(&mut bytes).unwrap();
+        let mut r1cs = R1cs::read(&bytes[..]).unwrap();
+        assert!(!r1cs.has_values());
+
+        let user_values = vec![("x".to_owned(), Integer::from(200))]
+            .into_iter()
+            .collect();
+        wit_gen.generate_into(&mut r1cs, &wit_gen.user_inputs(&user_values).unwrap());
+        r1cs.check_all();
+        let ret_idx = r1cs.signal_idxs.get("return").unwrap();
+        assert_eq!(r1cs.values.unwrap().get(ret_idx), Some(&Integer::from(144)));
+    }
+
+    #[quickcheck]
+    fn prove_with_checked_memory(i: u8, j: u8, x: u8, y: u8, c: bool) {
+        use crate::ir::opt::{opt, Opt};
+        let v_bv = |n: &str| leaf_term(Op::Var(n.to_owned(), Sort::BitVector(3)));
+        let a0 = term![Op::ConstArray(Sort::BitVector(3), 8); bv_lit(5, 3)];
+        let a1 = term![Op::Store; a0.clone(), v_bv("i"), v_bv("x")];
+        let a2 = term![ITE; leaf_term(Op::Var("c".to_owned(), Sort::Bool)),
+            term![Op::Store; a1.clone(), v_bv("j"), v_bv("y")], a1.clone()];
+        let sum = term![BV_ADD;
+            term![Op::Select; a0, v_bv("j")],
+            term![Op::Select; a2.clone(), v_bv("i")],
+            term![Op::Select; a2, term![Op::Select; a1, v_bv("i")]]
+        ];
+        let t = term![Op::Eq; v_bv("out"), sum.clone()];
+        let public = vec!["out".to_owned()].into_iter().collect();
+        let cs = Computation::from_constraint_system_parts(vec![t], public, None);
+        let cs = opt(
+            cs,
+            vec![
+                Opt::PermMem(8),
+                Opt::Flatten,
+                Opt::ConstantFold,
+                Opt::Inline,
+            ],
+        );
+        assert!(!cs.metadata.checked_memories.is_empty());
+        let modulus = Integer::from(crate::ir::term::field::TEST_FIELD);
+        let (r1cs, wit_gen) = to_r1cs_and_wit_gen(cs, modulus);
+        let r1cs = reduce_linearities(r1cs, None);
+        let mut bytes = Vec::new();
+        r1cs.write(&mut bytes).unwrap();
+        let mut r1cs = R1cs::read(&bytes[..]).unwrap();
+
+        let mut user_values: HashMap<String, Integer> = vec![
+            ("i".to_owned(), Integer::from(i % 8)),
+            ("j".to_owned(), Integer::from(j % 8)),
+            ("x".to_owned(), Integer::from(x % 8)),
+            ("y".to_owned(), Integer::from(y % 8)),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            wit_gen.user_inputs(&user_values),
+            Err("Missing input c".to_owned())
+        );
+        user_values.insert("c".to_owned(), Integer::from(c));
+        wit_gen.generate_into(&mut r1cs, &wit_gen.user_inputs(&user_values).unwrap());
+        r1cs.check_all();
+
+        let values: HashMap<String, Value> = user_values
+            .iter()
+            .map(|(n, v)| {
+                let v = if n == "c" {
+                    Value::Bool(*v == 1)
+                } else {
+                    Value::BitVector(BitVector::new(v.clone(), 3))
+                };
+                (n.clone(), v)
+            })
+            .collect();
+        let out_idx = r1cs.signal_idxs.get("out").unwrap();
+        assert_eq!(
+            r1cs.values.unwrap().get(out_idx),
+            Some(eval(&sum, &values).as_bv().uint())
+        );
+    }
+}
@@ -17,6 +17,8 @@ use std::str::FromStr;
 
 use ieee754::Ieee754;
 
+pub mod underconstrained;
+
 struct SmtDisp<'a, T>(pub &'a T);
 
 impl<'a, T: Expr2Smt<()> + 'a> Display for SmtDisp<'a, T> {
@@ -134,6 +136,28 @@ impl Expr2Smt<()> for TermData {
                 write!(w, "(select")?;
                 true
             }
+            Op::Lookup(table) => {
+                // As a select from the table, as a constant array
+                let w = check(&self.cs[0]).as_bv();
+                let arr_sort = Sort::Array(
+                    Box::new(Sort::BitVector(w)),
+                    Box::new(table[0].sort()),
+                    table.len(),
+                );
+                let entries = table
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| {
+                        (
+                            Value::BitVector(BitVector::new(Integer::from(i), w)),
+                            v.clone(),
+                        )
+                    })
+                    .collect();
+                let arr = Value::Array(arr_sort, Box::new(table[0].clone()), entries, table.len());
+                write!(w, "(select {}", SmtDisp(&arr))?;
+                true
+            }
             Op::Tuple => {
                 write!(w, "(mkTuple")?;
                 true
@@ -0,0 +1,266 @@
+//! Detection of under-constrained witnesses
+//!
+//! A proof circuit is *under-constrained* if, for some assignment to its public inputs, there are
+//! two distinct assignments to the prover's private inputs that both satisfy every assertion. The
+//! verifier cannot distinguish these witnesses, which is usually a soundness bug.
+//!
+//! We search for such pairs with the SMT solver. Every private variable `x` is duplicated as
+//! `x'`, the outputs (assertions) are asserted over both copies, and then we ask for a model in
+//! which some `x` differs from `x'`. Public inputs are shared between the copies, so any model is
+//! a pair of witnesses that agree on the public inputs.
+//!
+//! Since the SMT back-end does not support prime fields, the computation must be field-free. Run
+//! [crate::ir::opt::Opt::Tuple] first if it contains tuples.
+
+use super::find_model;
+use crate::ir::term::extras::substitute_cache;
+use crate::ir::term::*;
+
+use fxhash::{FxHashMap, FxHashSet};
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+
+/// Suffix appended to the name of the second copy of each private variable.
+const COPY_SUFFIX: &str = "__uc_copy";
+
+#[derive(Clone, Debug, PartialEq)]
+/// Two distinct witnesses that agree on all public inputs and both satisfy the computation.
+pub struct WitnessPair {
+    /// Values of the public inputs, shared by both witnesses.
+    pub public: FxHashMap<String, Value>,
+    /// The first witness (private variable values).
+    pub first: FxHashMap<String, Value>,
+    /// The second witness (private variable values).
+    pub second: FxHashMap<String, Value>,
+}
+
+impl WitnessPair {
+    /// The private variables on which the two witnesses differ, sorted by name.
+    pub fn differing(&self) -> Vec<String> {
+        let mut vars: Vec<String> = self
+            .first
+            .iter()
+            .filter(|(name, val)| self.second.get(*name) != Some(*val))
+            .map(|(name, _)| name.clone())
+            .collect();
+        vars.sort();
+        vars
+    }
+}
+
+impl Display for WitnessPair {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut public: Vec<_> = self.public.iter().collect();
+        public.sort_by(|a, b| a.0.cmp(b.0));
+        for (name, val) in public {
+            writeln!(f, "  public  {}: {}", name, val)?;
+        }
+        for name in self.differing() {
+            writeln!(
+                f,
+                "  private {}: {} vs {}",
+                name,
+                self.first.get(&name).unwrap(),
+                self.second.get(&name).unwrap()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The computation's assertions, asserted over both copies of the private variables.
+struct Duplicated {
+    /// The private variables, in order of first occurence.
+    private: Vec<(String, Sort)>,
+    /// The names of the private variables.
+    private_names: FxHashSet<String>,
+    /// The conjunction of all outputs, over both copies.
+    both: Term,
+}
+
+fn copy_name(name: &str) -> String {
+    format!("{}{}", name, COPY_SUFFIX)
+}
+
+fn var(name: String, sort: Sort) -> Term {
+    leaf_term(Op::Var(name, sort))
+}
+
+/// Conjoin `ts`, handling the empty and singleton cases.
+fn conj(mut ts: Vec<Term>) -> Term {
+    match ts.len() {
+        0 => leaf_term(Op::Const(Value::Bool(true))),
+        1 => ts.pop().unwrap(),
+        _ => term(AND, ts),
+    }
+}
+
+/// Disjoin `ts`, handling the empty and singleton cases.
+fn disj(mut ts: Vec<Term>) -> Term {
+    match ts.len() {
+        0 => leaf_term(Op::Const(Value::Bool(false))),
+        1 => ts.pop().unwrap(),
+        _ => term(OR, ts),
+    }
+}
+
+/// Is `name` private? Variables unknown to the metadata (e.g. intermediate witnesses) are
+/// considered private.
+fn is_private(md: &ComputationMetadata, name: &str) -> bool {
+    md.inputs.get(name).map(|p| p.is_some()).unwrap_or(true)
+}
+
+fn duplicate(cs: &Computation) -> Duplicated {
+    let mut private = Vec::new();
+    let mut private_names = FxHashSet::default();
+    let mut subs = TermMap::new();
+    for o in &cs.outputs {
+        for t in PostOrderIter::new(o.clone()) {
+            if let Op::Var(name, sort) = &t.op {
+                assert!(
+                    !name.ends_with(COPY_SUFFIX),
+                    "Variable {} clashes with the copy suffix",
+                    name
+                );
+                if is_private(&cs.metadata, name) && private_names.insert(name.clone()) {
+                    subs.insert(t.clone(), var(copy_name(name), sort.clone()));
+                    private.push((name.clone(), sort.clone()));
+                }
+            }
+        }
+    }
+    let copies: Vec<Term> = cs
+        .outputs
+        .iter()
+        .map(|o| substitute_cache(o, &mut subs))
+        .collect();
+    let both = conj(cs.outputs.iter().cloned().chain(copies).collect());
+    Duplicated {
+        private,
+        private_names,
+        both,
+    }
+}
+
+/// A term that holds iff private variable `name` differs from its copy.
+fn differs(name: &str, sort: &Sort) -> Term {
+    term![NOT; term![EQ; var(name.to_owned(), sort.clone()), var(copy_name(name), sort.clone())]]
+}
+
+/// Find a model of the duplicated assertions and `extra`, and split it into a [WitnessPair].
+fn query(d: &Duplicated, extra: Term) -> Option<WitnessPair> {
+    let t = term![AND; d.both.clone(), extra];
+    find_model(&t).map(|model| {
+        let mut pair = WitnessPair {
+            public: FxHashMap::default(),
+            first: FxHashMap::default(),
+            second: FxHashMap::default(),
+        };
+        for (name, val) in model {
+            if let Some(orig) = name.strip_suffix(COPY_SUFFIX) {
+                pair.second.insert(orig.to_owned(), val);
+            } else if d.private_names.contains(&name) {
+                pair.first.insert(name, val);
+            } else {
+                pair.public.insert(name, val);
+            }
+        }
+        pair
+    })
+}
+
+/// Search for two distinct witnesses for `cs` that agree on its public inputs.
+///
+/// Returns [None] if the private inputs are uniquely determined by the public ones.
+pub fn find_underconstrained(cs: &Computation) -> Option<WitnessPair> {
+    let d = duplicate(cs);
+    let any_differs = disj(d.private.iter().map(|(n, s)| differs(n, s)).collect());
+    query(&d, any_differs)
+}
+
+/// Find every private variable of `cs` that is not uniquely determined by the public inputs.
+///
+/// Returns a map from each such variable to a pair of witnesses that differ on it. Issues one SMT
+/// query per private variable, though variables already shown to differ in an earlier
+/// counterexample are not queried again.
+pub fn underconstrained_vars(cs: &Computation) -> BTreeMap<String, WitnessPair> {
+    let d = duplicate(cs);
+    let mut found: BTreeMap<String, WitnessPair> = BTreeMap::new();
+    for (name, sort) in &d.private {
+        if found.contains_key(name) {
+            continue;
+        }
+        if let Some(pair) = query(&d, differs(name, sort)) {
+            for v in pair.differing() {
+                found.entry(v).or_insert_with(|| pair.clone());
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ir::proof::Constraints;
+
+    fn bv_var(n: &str, w: usize) -> Term {
+        var(n.to_owned(), Sort::BitVector(w))
+    }
+
+    fn cs(outputs: Vec<Term>, public: &[&str]) -> Computation {
+        Computation::from_constraint_system_parts(
+            outputs,
+            public.iter().map(|s| (*s).to_owned()).collect(),
+            None,
+        )
+    }
+
+    #[test]
+    fn determined() {
+        // y = x + 1
+        let c = cs(
+            vec![term![EQ; bv_var("y", 4), term![BV_ADD; bv_var("x", 4), bv_lit(1, 4)]]],
+            &["y"],
+        );
+        assert_eq!(find_underconstrained(&c), None);
+        assert!(underconstrained_vars(&c).is_empty());
+    }
+
+    #[test]
+    fn doubling_loses_msb() {
+        // y = x + x: the high bit of x is free
+        let c = cs(
+            vec![term![EQ; bv_var("y", 4), term![BV_ADD; bv_var("x", 4), bv_var("x", 4)]]],
+            &["y"],
+        );
+        let pair = find_underconstrained(&c).unwrap();
+        assert_eq!(pair.differing(), vec!["x".to_owned()]);
+        let x0 = pair.first.get("x").unwrap().as_bv().clone();
+        let x1 = pair.second.get("x").unwrap().as_bv().clone();
+        assert_eq!(x0.clone() + x0, x1.clone() + x1);
+        let vars = underconstrained_vars(&c);
+        assert_eq!(
+            vars.keys().cloned().collect::<Vec<_>>(),
+            vec!["x".to_owned()]
+        );
+    }
+
+    #[test]
+    fn only_some_vars() {
+        // a is pinned by the public b, but c is unconstrained.
+        let a = var("a".to_owned(), Sort::Bool);
+        let b = var("b".to_owned(), Sort::Bool);
+        let c = var("c".to_owned(), Sort::Bool);
+        let comp = cs(
+            vec![term![EQ; a, b], term![OR; c.clone(), term![NOT; c]]],
+            &["b"],
+        );
+        let vars = underconstrained_vars(&comp);
+        assert_eq!(
+            vars.keys().cloned().collect::<Vec<_>>(),
+            vec!["c".to_owned()]
+        );
+    }
+}